@@ -1,4 +1,15 @@
 fn main() {
+    // embed the git commit hash if it's available, reported in chess::build_info()
+    if let Ok(output) = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+    {
+        if output.status.success() {
+            let git_hash = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=CHESS_OXIDE_GIT_HASH={}", git_hash.trim());
+        }
+    }
+
     let config = slint_build::CompilerConfiguration::new().with_style("fluent".into());
     slint_build::compile_with_config("ui/main.slint", config).unwrap();
 }
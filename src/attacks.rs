@@ -0,0 +1,301 @@
+use static_init::dynamic;
+
+use crate::bitboard::{bishop_ray_attacks, rook_ray_attacks};
+
+// sliding piece attacks looked up with magic bitboards -> https://www.chessprogramming.org/Magic_Bitboards
+// the squares that can block a slider (its mask) are multiplied by a magic number, which maps every arrangement of
+// blockers to its own slot, or one with the same attacks, in a table of attacks filled in at startup like the zobrist
+// table. The magics below were found with a seeded random search for numbers with few bits set
+#[dynamic]
+static MAGIC_TABLES: MagicTables = MagicTables::new();
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+const RANK_8: u64 = 0xff;
+const RANK_1: u64 = RANK_8 << 56;
+
+const ROOK_MAGICS: [u64; 64] = [
+    0x1080008020400010,
+    0x42401002c000a003,
+    0x2080200010008008,
+    0x218014b000080080,
+    0x01800c00c8008012,
+    0x0200040200081001,
+    0x0300620001000094,
+    0x008000304b000180,
+    0x2100800040008020,
+    0x0040400040201008,
+    0x0100802000100082,
+    0x4000800800801000,
+    0x2018800400080280,
+    0x0200800400020080,
+    0x4404003001448208,
+    0x0020800100104080,
+    0x1028208008400082,
+    0x1002830040030020,
+    0x1000808010002000,
+    0x8a52210010020900,
+    0x0103010004110800,
+    0x0000818014000200,
+    0x0500040010484102,
+    0x0000020001008064,
+    0x3000400280008230,
+    0x8800400040201004,
+    0x0004120200208640,
+    0x400021010010000a,
+    0x0000040080800800,
+    0x1001000900040002,
+    0x4241000100040200,
+    0x0100008a0021004c,
+    0x000480400480042a,
+    0x0008200348401000,
+    0x0001004011002001,
+    0x1000080084801000,
+    0x0004000800800480,
+    0x0802040080800200,
+    0x340528021c001069,
+    0x0001001041002482,
+    0x5061208240028010,
+    0x2028500020004000,
+    0x009000a001828012,
+    0x9023001000210008,
+    0x0404008008008004,
+    0x008200081c260090,
+    0x0001011008040002,
+    0x0000004100820004,
+    0x0808428001043100,
+    0x4040200180400980,
+    0x2442084410208200,
+    0x0010081022004200,
+    0x1200080004008280,
+    0x4011000204002900,
+    0x0188088210410400,
+    0x024004014b008600,
+    0x000a512080010041,
+    0x0040842100184001,
+    0x0000208040081202,
+    0x0001002098100065,
+    0x4023000204080011,
+    0x0003000804000201,
+    0x8800008108100204,
+    0x0000108408402102,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x0044880881040100,
+    0x8120820402428200,
+    0x0408484044814402,
+    0x09020a1200522400,
+    0x0002021003250044,
+    0x430088a008000811,
+    0x1801083110089500,
+    0x2500240104905002,
+    0x000061a001810102,
+    0x8024100142008202,
+    0x0000100400644000,
+    0x1204040410900004,
+    0x1c00011040008019,
+    0x0000820210051800,
+    0x91004100c22020a0,
+    0x0000211082012044,
+    0x0420004408506120,
+    0x2003802444040400,
+    0x0040803104010040,
+    0x0804001804200801,
+    0x4013000090400022,
+    0x8100800040602004,
+    0x000480042a013020,
+    0x0051000821180203,
+    0x0108090404200804,
+    0x2804101802228821,
+    0x0114280110008020,
+    0x0004200804010004,
+    0x0040840008802000,
+    0x002181a00a021010,
+    0x0041010002009000,
+    0x8038902088820820,
+    0x000410083e400238,
+    0x1002061020209184,
+    0x810c040e04440820,
+    0x1040020080880080,
+    0x0040208020020020,
+    0x0002604300020080,
+    0x0018110501040084,
+    0x0184006202044100,
+    0x9081182050000400,
+    0x0000441420400504,
+    0xc80202202c000800,
+    0x0001084202012020,
+    0x003044010a010401,
+    0x2002020042040900,
+    0x00a481940404c102,
+    0x0208082900402028,
+    0x00020904a0041010,
+    0x0241012802022018,
+    0x0028810401040400,
+    0x0000a00084240028,
+    0x1000404603042040,
+    0x2004101090612018,
+    0x0220604400809010,
+    0x1260084100408800,
+    0x0001420210824000,
+    0x0908004048041183,
+    0x0400201024024803,
+    0x820800000504a800,
+    0x0000d23020042402,
+    0x08400089b1010600,
+    0x30100aa004040040,
+    0x0090041818042020,
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize, // where this square's attacks start in MagicTables::attacks
+}
+
+impl Magic {
+    #[inline(always)]
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+impl MagicTables {
+    fn new() -> Self {
+        let mut attacks = Vec::new();
+        let rook = std::array::from_fn(|i| {
+            fill_attacks(i, ROOK_MAGICS[i], rook_ray_attacks, &mut attacks)
+        });
+        let bishop = std::array::from_fn(|i| {
+            fill_attacks(i, BISHOP_MAGICS[i], bishop_ray_attacks, &mut attacks)
+        });
+        Self {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn rook_attacks(i: usize, occupied: u64) -> u64 {
+    let tables = &*MAGIC_TABLES;
+    tables.attacks[tables.rook[i].index(occupied)]
+}
+
+#[inline(always)]
+pub(crate) fn bishop_attacks(i: usize, occupied: u64) -> u64 {
+    let tables = &*MAGIC_TABLES;
+    tables.attacks[tables.bishop[i].index(occupied)]
+}
+
+// adds the attacks of the slider on square i, whose attacks are ray_attacks, to the end of the table for every
+// arrangement of blockers
+fn fill_attacks(
+    i: usize,
+    magic: u64,
+    ray_attacks: fn(usize, u64) -> u64,
+    table: &mut Vec<u64>,
+) -> Magic {
+    // a piece on the last square of a ray can't block anything, so the edges are only in the mask along the slider's own
+    // rank or file
+    let edges =
+        ((RANK_1 | RANK_8) & !(RANK_8 << (i / 8 * 8))) | ((FILE_A | FILE_H) & !(FILE_A << (i % 8)));
+    let mask = ray_attacks(i, 0) & !edges;
+    let bits = mask.count_ones();
+    let entry = Magic {
+        mask,
+        magic,
+        shift: 64 - bits,
+        offset: table.len(),
+    };
+    table.resize(table.len() + (1 << bits), 0);
+
+    // walk every subset of the mask
+    let mut blockers: u64 = 0;
+    loop {
+        let attacks = ray_attacks(i, blockers);
+        let idx = entry.index(blockers);
+        debug_assert!(
+            table[idx] == 0 || table[idx] == attacks,
+            "magic for square {} maps different attacks to the same slot",
+            i
+        );
+        table[idx] = attacks;
+        blockers = blockers.wrapping_sub(mask) & mask;
+        if blockers == 0 {
+            break;
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::mailbox;
+
+    // slider attacks walking the mailbox, the way movegen generated them before bitboards
+    fn mailbox_attacks(i: usize, occupied: u64, offsets: [i32; 4]) -> u64 {
+        let mut attacks = 0;
+        for j in offsets {
+            let mut mv = mailbox::next_mailbox_number(i, j);
+            let mut slide_idx = j;
+            while mv >= 0 {
+                attacks |= 1 << mv;
+                if occupied & (1 << mv) != 0 {
+                    break;
+                }
+                slide_idx += j;
+                mv = mailbox::next_mailbox_number(i, slide_idx);
+            }
+        }
+        attacks
+    }
+
+    #[test]
+    fn test_magic_attacks_match_mailbox() {
+        const ROOK_OFFSETS: [i32; 4] = [-10, -1, 1, 10];
+        const BISHOP_OFFSETS: [i32; 4] = [-11, -9, 9, 11];
+        let mut rng = StdRng::seed_from_u64(0);
+        for i in 0..64 {
+            // empty and full boards, then sparse, half full and crowded boards. The slider's own square doesn't matter
+            let mut occupancies = vec![0, u64::MAX];
+            for _ in 0..100 {
+                let (a, b, c) = (rng.gen::<u64>(), rng.gen::<u64>(), rng.gen::<u64>());
+                occupancies.extend([a & b & c, a & b, a, a | b]);
+            }
+            for occupied in occupancies {
+                assert_eq!(
+                    rook_attacks(i, occupied),
+                    mailbox_attacks(i, occupied, ROOK_OFFSETS),
+                    "rook on {} with {:#x}",
+                    i,
+                    occupied
+                );
+                assert_eq!(
+                    bishop_attacks(i, occupied),
+                    mailbox_attacks(i, occupied, BISHOP_OFFSETS),
+                    "bishop on {} with {:#x}",
+                    i,
+                    occupied
+                );
+            }
+        }
+        // 4096 slots for rooks in the corners, 512 for bishops in the centre
+        assert_eq!(MAGIC_TABLES.rook[0].mask.count_ones(), 12);
+        assert_eq!(MAGIC_TABLES.bishop[27].mask.count_ones(), 9);
+    }
+}
@@ -8,7 +8,6 @@ use std::sync::{Arc, Mutex};
 use env_logger::{Builder, Env, Target};
 use slint::{ComponentHandle, SharedString};
 
-use chess::fen::FEN;
 use chess::pgn::PGN;
 use chess::{eval_to_string, hash_to_string, PieceColour};
 
@@ -57,8 +56,10 @@ fn main() -> Result<(), slint::PlatformError> {
     };
     builder.target(Target::Stdout);
     builder.init();
+    log::info!("{}", chess::build_info());
 
-    let board = Arc::new(Mutex::new(chess::Board::new()));
+    // the GUI has no way to claim a draw, so threefold repetition and the fifty move rule end the game
+    let board = Arc::new(Mutex::new(chess::Board::new().with_auto_claim_draws(true)));
 
     let ui = Board_UI::new()?;
     let settings_dialog = SettingsDialog_UI::new()?;
@@ -71,25 +72,17 @@ fn main() -> Result<(), slint::PlatformError> {
         let ui = ui_weak_get_gamestate.upgrade().unwrap();
         let board = board_get_gamestate.lock().unwrap();
         let side_to_move = board.get_side_to_move();
-        match board.get_game_over_state() {
-            Some(state) => match state {
-                chess::GameOverState::WhiteResign => {
-                    ui.set_gamestate("White resigned".into());
-                }
-                chess::GameOverState::BlackResign => {
-                    ui.set_gamestate("Black resigned".into());
-                }
-                chess::GameOverState::AgreedDraw => {
-                    ui.set_gamestate("Draw agreed".into());
-                }
-                chess::GameOverState::Forced(gs) => {
-                    if gs.is_win() {
-                        ui.set_gamestate(format!("{} wins: {}", !side_to_move, gs).into());
-                    } else {
-                        ui.set_gamestate(format!("Draw: {}", gs).into());
-                    }
-                }
-            },
+        match board.result() {
+            Some(result) => {
+                ui.set_gamestate(
+                    format!(
+                        "{}: {}",
+                        result,
+                        board.termination_reason().unwrap_or_default()
+                    )
+                    .into(),
+                );
+            }
             None => {
                 ui.set_gamestate(
                     format!(
@@ -107,7 +100,13 @@ fn main() -> Result<(), slint::PlatformError> {
     let board_new_game = board.clone();
     ui.on_new_game(move || {
         let ui = ui_weak_new_game.upgrade().unwrap();
-        *board_new_game.lock().unwrap() = chess::board::Board::new();
+        let mut board = board_new_game.lock().unwrap();
+        // keep the engine's transposition table warm between games
+        let tt = board.take_transposition_table();
+        *board = chess::board::Board::new()
+            .with_transposition_table(tt)
+            .with_auto_claim_draws(true);
+        drop(board);
         ui.invoke_refresh_position();
     });
 
@@ -115,7 +114,13 @@ fn main() -> Result<(), slint::PlatformError> {
     let board_new_chess960_game = board.clone();
     ui.on_new_chess960_game(move || {
         let ui = ui_weak_new_chess960_game.upgrade().unwrap();
-        *board_new_chess960_game.lock().unwrap() = chess::board::Board::new_chess960();
+        let mut board = board_new_chess960_game.lock().unwrap();
+        // keep the engine's transposition table warm between games
+        let tt = board.take_transposition_table();
+        *board = chess::board::Board::new_chess960()
+            .with_transposition_table(tt)
+            .with_auto_claim_draws(true);
+        drop(board);
         ui.invoke_refresh_position();
     });
 
@@ -125,10 +130,11 @@ fn main() -> Result<(), slint::PlatformError> {
         let ui = ui_select_legal_moves.upgrade().unwrap();
         let board = board_select_legal_moves.lock().unwrap();
         let mut legal_moves = [false; 64];
-        for mv in board.get_current_state().get_legal_moves().unwrap() {
-            if mv.from as i32 == from_square {
-                legal_moves[mv.to as usize] = true;
-            }
+        for mv in board
+            .get_current_state()
+            .legal_moves_from(from_square as usize)
+        {
+            legal_moves[mv.to] = true;
         }
         ui.set_selected_legal_moves(
             std::rc::Rc::new(slint::VecModel::from(legal_moves.to_vec())).into(),
@@ -219,41 +225,16 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let ui_weak_find_state = ui.as_weak();
     let board_find_state = board.clone();
-    ui.on_find_state(move |notation| {
+    ui.on_find_state(move |ply| {
         let ui = ui_weak_find_state.upgrade().unwrap();
-        // get states that match notation and clone them into owned vector
-        let states = board_find_state
+        // the move history gives the ply of the move, as the same notation can be played more than once
+        let state = board_find_state
             .lock()
             .unwrap()
-            .find_states_by_notation(notation.as_str())
-            .into_iter()
+            .find_state_at_ply(ply as usize)
             .cloned()
-            .collect::<Vec<chess::BoardState>>();
-
-        let state = if states.len() > 1 {
-            let selected_move_number = ui.get_selected_move_number();
-            let selected_halfmove = ui.get_selected_halfmove();
-            log::debug!("Multiple states found for notation: {}. Disambiguating based on selected move number: {} and selected halfmove: {}", 
-                notation, selected_move_number, selected_halfmove);
-            // find state with matching move number and halfmove
-            states
-                .iter()
-                .find(|s| {
-                    if s.side_to_move == PieceColour::White {
-                        s.move_count() as i32 == selected_move_number + 1
-                            && selected_halfmove == 2
-                    } else {
-                        s.move_count() as i32 == selected_move_number
-                            && selected_halfmove == 1
-                    }
-                })
-                .unwrap()
-                .clone()
-        } else {
-            // guaranteed to have at least one state as notation is valid from gui
-            log::debug!("Single state found for notation: {}", notation);
-            states[0].clone()
-        };
+            .unwrap(); // ply comes from the move history, so the state exists
+        log::debug!("State found for ply: {}", ply);
 
         // unwrap is safe as state was found
         board_find_state
@@ -266,6 +247,27 @@ fn main() -> Result<(), slint::PlatformError> {
         ui.invoke_refresh_position();
     });
 
+    let ui_weak_takeback = ui.as_weak();
+    let board_takeback = board.clone();
+    ui.on_takeback(move || {
+        let ui = ui_weak_takeback.upgrade().unwrap();
+        let mut board = board_takeback.lock().unwrap();
+        board.checkout_latest_state();
+        // take back the engine's reply too, so it's the player's move again
+        let player_to_move =
+            ui_convert_piece_colour(board.get_side_to_move()) == ui.get_player_colour();
+        let n = if player_to_move { 2 } else { 1 };
+        match board.undo_moves(n) {
+            Ok(moves) => log::debug!("Took back {} moves", moves.len()),
+            Err(e) => log::error!("BoardStateError on takeback: {e}"),
+        }
+        ui.set_selected_move_notation(board.last_move_string_notation().into());
+        ui.set_detached_state(false);
+        // refresh_position locks the board
+        drop(board);
+        ui.invoke_refresh_position();
+    });
+
     let ui_weak_refresh_position = ui.as_weak();
     let export_dialog_weak_refresh_position = export_dialog.as_weak();
     let board_refresh_position = board.clone();
@@ -326,11 +328,7 @@ fn main() -> Result<(), slint::PlatformError> {
         ui.invoke_get_gamestate();
 
         // set current BoardState FEN
-        export_dialog.set_fen(
-            FEN::from(board_refresh_position.lock().unwrap().get_current_state())
-                .to_string()
-                .into(),
-        );
+        export_dialog.set_fen(board_refresh_position.lock().unwrap().to_fen().into());
         log::debug!(
             "FEN: {} generated from boardstate with hash: {}",
             export_dialog.get_fen(),
@@ -358,6 +356,12 @@ fn main() -> Result<(), slint::PlatformError> {
                 .to_string()
                 .into(),
         );
+        export_dialog.set_pgn_at_current(
+            PGN::from_board_at_current_state(board_refresh_position.lock().unwrap().deref())
+                .to_string()
+                .into(),
+        );
+        export_dialog.set_detatched(board_refresh_position.lock().unwrap().is_detatched());
         log::debug!(
             "PGN generated from board with current boardstate hash: {}",
             hash_to_string(
@@ -369,12 +373,7 @@ fn main() -> Result<(), slint::PlatformError> {
             )
         );
 
-        if let Some(last_move) = board_refresh_position
-            .lock()
-            .unwrap()
-            .get_current_state()
-            .last_move
-        {
+        if let Some(last_move) = board_refresh_position.lock().unwrap().last_move() {
             if ui.get_player_colour() == PieceColour_UI::Black {
                 // reverse index if player is black as the board is flipped
                 ui.set_last_move(Move_UI {
@@ -465,21 +464,39 @@ fn main() -> Result<(), slint::PlatformError> {
             .to_string()
             .parse::<i32>()
             .unwrap();
-        std::thread::spawn(
-            move || match bmem.lock().unwrap().make_engine_move(depth as u8) {
-                Ok((_, eval)) => {
+        // the board is only locked to start the search and to make its move, so the ui can redraw while the engine
+        // thinks. The search deepens one depth at a time up to the chosen depth, so the move can differ from what
+        // make_engine_move at that depth would play, as the table is filled by the shallower iterations first
+        let mut handle = match bmem
+            .lock()
+            .unwrap()
+            .start_engine_search(chess::SearchLimits::new(depth as u8))
+        {
+            Ok(handle) => handle,
+            Err(e) => {
+                log::error!("BoardStateError on starting engine search: {e}");
+                return;
+            }
+        };
+        std::thread::spawn(move || {
+            handle.wait();
+            match bmem.lock().unwrap().apply_engine_result(handle) {
+                Ok(result) => {
                     slint::invoke_from_event_loop(move || {
                         ui.upgrade().unwrap().invoke_refresh_position();
                         ui.upgrade().unwrap().set_engine_made_move(true);
-                        ui.upgrade().unwrap().set_eval(eval_to_string(eval).into())
+                        // eval is white perspective
+                        ui.upgrade()
+                            .unwrap()
+                            .set_eval(eval_to_string(result.eval).into())
                     })
                     .unwrap();
                 }
                 Err(e) => {
                     log::error!("BoardStateError on making engine move: {e}");
                 }
-            },
-        );
+            }
+        });
     });
 
     let import_dialog_weak_run = import_dialog.as_weak();
@@ -515,18 +532,18 @@ fn main() -> Result<(), slint::PlatformError> {
         let import_dialog = import_dialog_weak_import_fen.upgrade().unwrap();
         let ui = ui_weak_import_fen.upgrade().unwrap();
 
-        let new_board = chess::board::Board::from(match fen.parse::<FEN>() {
-            Ok(f) => {
+        let new_board = match chess::board::Board::from_fen(&fen) {
+            Ok(b) => {
                 import_dialog.set_fen_error(false);
                 import_dialog.set_fen_str("".into());
-                f
+                b.with_auto_claim_draws(true)
             }
             Err(e) => {
                 import_dialog.set_fen_error(true);
                 import_dialog.set_fen_error_message(e.to_string().into());
                 return;
             }
-        });
+        };
 
         let side_to_move = ui_convert_piece_colour(new_board.get_current_state().side_to_move);
         let player_side = if import_dialog.get_as_white() {
@@ -585,7 +602,7 @@ fn main() -> Result<(), slint::PlatformError> {
                         import_dialog.set_pgn_error_message("".into());
                         log::debug!("Resetting UI properties and refreshing position");
                         let side = b.get_side_to_move();
-                        *board_import_pgn.lock().unwrap() = b;
+                        *board_import_pgn.lock().unwrap() = b.with_auto_claim_draws(true);
                         // TODO for now set both to sidetomove so engine doesnt make move
                         ui.invoke_reset_properties(
                             ui_convert_piece_colour(side),
@@ -1,11 +1,34 @@
+use std::env;
+
 use env_logger::{Builder, Env, Target};
 
-use chess::perft;
+use chess::fen::FEN;
+use chess::{diagnostics, perft, perft_divide, perft_divide_to_string, BoardState};
 
 fn main() {
     // initialise logger
     let mut builder = Builder::from_env(Env::default().default_filter_or("off"));
     builder.target(Target::Stdout);
     builder.init();
-    perft(10, 5);
+
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        // 'bench' runs the fixed diagnostics workload instead of the default perft
+        Some("bench") => println!("{}", diagnostics::movegen_benchmark()),
+        // 'divide <depth> [fen]' prints node counts per root move, from the starting position if no FEN is given
+        Some("divide") => {
+            let depth = args
+                .get(2)
+                .and_then(|d| d.parse::<u8>().ok())
+                .expect("usage: chess-perft divide <depth> [fen]");
+            let bs = if args.len() > 3 {
+                let fen = args[3..].join(" ").parse::<FEN>().expect("invalid FEN");
+                BoardState::try_from(fen).expect("invalid position")
+            } else {
+                BoardState::new_starting()
+            };
+            println!("{}", perft_divide_to_string(&perft_divide(&bs, depth)));
+        }
+        _ => perft(10, 5),
+    }
 }
@@ -0,0 +1,284 @@
+use crate::attacks::{bishop_attacks, rook_attacks};
+use crate::movegen::{Piece, PieceColour, PieceType};
+use crate::position::{colour_idx, square_set_indexes};
+
+// square sets (bitboards) kept alongside the mailbox Pos64 for attack lookups, bit i is set for index i (0 = a8, 63 = h1).
+// Knight, king and pawn attacks come from tables built at compile time. Sliding attacks are looked up in the magic
+// tables in attacks.rs, which are built from the rays here that are cut off at their first blocker
+// -> https://www.chessprogramming.org/Classical_Approach
+
+// (file step, row step) of each ray, rows count down from the 8th rank like the indexes do. The first four rays lower the
+// index as they go, so their first blocker is the highest set bit, and the last four raise it
+const RAY_STEPS: [(i32, i32); 8] = [
+    (0, -1),  // north
+    (-1, 0),  // west
+    (-1, -1), // north west
+    (1, -1),  // north east
+    (0, 1),   // south
+    (1, 0),   // east
+    (1, 1),   // south east
+    (-1, 1),  // south west
+];
+const ROOK_RAYS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_RAYS: [usize; 4] = [2, 3, 6, 7];
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (0, -1),
+    (-1, 0),
+    (-1, -1),
+    (1, -1),
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (-1, 1),
+];
+const WHITE_PAWN_STEPS: [(i32, i32); 2] = [(-1, -1), (1, -1)];
+const BLACK_PAWN_STEPS: [(i32, i32); 2] = [(-1, 1), (1, 1)];
+
+pub(crate) const KNIGHT_ATTACKS: [u64; 64] = step_table(&KNIGHT_STEPS);
+pub(crate) const KING_ATTACKS: [u64; 64] = step_table(&KING_STEPS);
+// indexed by the colour of the attacking pawn
+pub(crate) const PAWN_ATTACKS: [[u64; 64]; 2] =
+    [step_table(&WHITE_PAWN_STEPS), step_table(&BLACK_PAWN_STEPS)];
+// every square along each ray to the edge of the board, not including the square the ray starts on
+const RAYS: [[u64; 64]; 8] = ray_table();
+
+// index of the square (file_step, row_step) away from i, if it is on the board
+const fn step(i: usize, (file_step, row_step): (i32, i32)) -> Option<usize> {
+    let file = (i % 8) as i32 + file_step;
+    let row = (i / 8) as i32 + row_step;
+    if file >= 0 && file < 8 && row >= 0 && row < 8 {
+        Some((row * 8 + file) as usize)
+    } else {
+        None
+    }
+}
+
+const fn step_table(steps: &[(i32, i32)]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        let mut j = 0;
+        while j < steps.len() {
+            if let Some(to) = step(i, steps[j]) {
+                table[i] |= 1 << to;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    table
+}
+
+const fn ray_table() -> [[u64; 64]; 8] {
+    let mut table = [[0; 64]; 8];
+    let mut ray = 0;
+    while ray < 8 {
+        let mut i = 0;
+        while i < 64 {
+            let mut square = step(i, RAY_STEPS[ray]);
+            while let Some(to) = square {
+                table[ray][i] |= 1 << to;
+                square = step(to, RAY_STEPS[ray]);
+            }
+            i += 1;
+        }
+        ray += 1;
+    }
+    table
+}
+
+// squares along the ray from i up to and including the first occupied square
+#[inline(always)]
+fn ray_attacks(ray: usize, i: usize, occupied: u64) -> u64 {
+    let attacks = RAYS[ray][i];
+    let blockers = attacks & occupied;
+    if blockers == 0 {
+        return attacks;
+    }
+    let first_blocker = if ray < 4 {
+        63 - blockers.leading_zeros()
+    } else {
+        blockers.trailing_zeros()
+    };
+    attacks ^ RAYS[ray][first_blocker as usize]
+}
+
+pub(crate) fn rook_ray_attacks(i: usize, occupied: u64) -> u64 {
+    ROOK_RAYS
+        .iter()
+        .fold(0, |attacks, ray| attacks | ray_attacks(*ray, i, occupied))
+}
+
+pub(crate) fn bishop_ray_attacks(i: usize, occupied: u64) -> u64 {
+    BISHOP_RAYS
+        .iter()
+        .fold(0, |attacks, ray| attacks | ray_attacks(*ray, i, occupied))
+}
+
+// squares piece attacks from i, whatever is on them
+#[inline(always)]
+pub(crate) fn piece_attacks(piece: Piece, i: usize, occupied: u64) -> u64 {
+    match piece.ptype {
+        PieceType::Pawn => PAWN_ATTACKS[colour_idx(piece.pcolour)][i],
+        PieceType::Knight => KNIGHT_ATTACKS[i],
+        PieceType::Bishop => bishop_attacks(i, occupied),
+        PieceType::Rook => rook_attacks(i, occupied),
+        PieceType::Queen => bishop_attacks(i, occupied) | rook_attacks(i, occupied),
+        PieceType::King => KING_ATTACKS[i],
+    }
+}
+
+// pieces pinned to the king on king_idx by sliders in own's way, and the sliders checking it. checks holds the
+// squares of each checking ray, from the square next to the king up to and including the checker
+#[inline(always)]
+pub(crate) fn slider_pins_and_checks(
+    bitboards: &Bitboards,
+    king_idx: usize,
+    king_colour: PieceColour,
+) -> (u64, u64, u8) {
+    let occupied = bitboards.occupied();
+    let own = bitboards.colour(king_colour);
+    let enemy = |ptype: PieceType| {
+        bitboards.pieces(Piece {
+            pcolour: !king_colour,
+            ptype,
+        })
+    };
+    let queens = enemy(PieceType::Queen);
+    let (mut pinned, mut checks, mut checkers) = (0, 0, 0);
+    for (rays, sliders) in [
+        (ROOK_RAYS, enemy(PieceType::Rook) | queens),
+        (BISHOP_RAYS, enemy(PieceType::Bishop) | queens),
+    ] {
+        for ray in rays {
+            let attacks = ray_attacks(ray, king_idx, occupied);
+            let blocker = attacks & occupied;
+            if blocker & sliders != 0 {
+                checks |= attacks;
+                checkers += 1;
+            } else if blocker & own != 0 {
+                // the ray carries on past the king's own piece, which is pinned if a slider is the next piece
+                let xray = ray_attacks(ray, king_idx, occupied ^ blocker);
+                if xray & occupied & sliders != 0 {
+                    pinned |= blocker;
+                }
+            }
+        }
+    }
+    (pinned, checks, checkers)
+}
+
+// square sets of the pieces on the board by colour and piece type, updated with the board
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) struct Bitboards {
+    pieces: [[u64; 6]; 2],
+    colours: [u64; 2],
+}
+
+impl Bitboards {
+    #[inline(always)]
+    pub fn add(&mut self, piece: Piece, i: usize) {
+        let c = colour_idx(piece.pcolour);
+        self.pieces[c][piece.ptype as usize] |= 1 << i;
+        self.colours[c] |= 1 << i;
+    }
+
+    #[inline(always)]
+    pub fn remove(&mut self, piece: Piece, i: usize) {
+        let c = colour_idx(piece.pcolour);
+        self.pieces[c][piece.ptype as usize] &= !(1 << i);
+        self.colours[c] &= !(1 << i);
+    }
+
+    #[inline(always)]
+    pub fn pieces(&self, piece: Piece) -> u64 {
+        self.pieces[colour_idx(piece.pcolour)][piece.ptype as usize]
+    }
+
+    #[inline(always)]
+    pub fn colour(&self, colour: PieceColour) -> u64 {
+        self.colours[colour_idx(colour)]
+    }
+
+    #[inline(always)]
+    pub fn occupied(&self) -> u64 {
+        self.colours[0] | self.colours[1]
+    }
+
+    // pieces of colour 'by' attacking idx when the occupied squares are occupied, which can differ from the board's to
+    // see through pieces that are moving. Pieces that have been captured have to be removed from the result
+    #[inline(always)]
+    pub fn attackers_to_with(&self, idx: usize, by: PieceColour, occupied: u64) -> u64 {
+        let piece = |ptype| Piece { pcolour: by, ptype };
+        let queens = self.pieces(piece(PieceType::Queen));
+        (PAWN_ATTACKS[colour_idx(!by)][idx] & self.pieces(piece(PieceType::Pawn)))
+            | (KNIGHT_ATTACKS[idx] & self.pieces(piece(PieceType::Knight)))
+            | (KING_ATTACKS[idx] & self.pieces(piece(PieceType::King)))
+            | (bishop_attacks(idx, occupied) & (self.pieces(piece(PieceType::Bishop)) | queens))
+            | (rook_attacks(idx, occupied) & (self.pieces(piece(PieceType::Rook)) | queens))
+    }
+
+    #[inline(always)]
+    pub fn attackers_to(&self, idx: usize, by: PieceColour) -> u64 {
+        self.attackers_to_with(idx, by, self.occupied())
+    }
+
+    // every square a piece of colour 'by' attacks, whatever is on it
+    pub fn attacked_squares(&self, by: PieceColour) -> u64 {
+        let occupied = self.occupied();
+        let mut attacked = 0;
+        for ptype in [
+            PieceType::Pawn,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+            PieceType::King,
+        ] {
+            let piece = Piece { pcolour: by, ptype };
+            for i in square_set_indexes(self.pieces(piece)) {
+                attacked |= piece_attacks(piece, i, occupied);
+            }
+        }
+        attacked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attack_tables() {
+        // a1 knight attacks b3 and c2, h8 king attacks g8, g7 and h7
+        assert_eq!(KNIGHT_ATTACKS[56], 1 << 41 | 1 << 50);
+        assert_eq!(KING_ATTACKS[7], 1 << 6 | 1 << 14 | 1 << 15);
+        // e4 pawns attack d5 and f5 for white, d3 and f3 for black
+        assert_eq!(PAWN_ATTACKS[0][36], 1 << 27 | 1 << 29);
+        assert_eq!(PAWN_ATTACKS[1][36], 1 << 43 | 1 << 45);
+        // pawns on the a and h files only attack one square
+        assert_eq!(PAWN_ATTACKS[0][48].count_ones(), 1);
+        assert_eq!(PAWN_ATTACKS[1][15].count_ones(), 1);
+
+        // a d4 rook on an empty board attacks the whole d file and 4th rank, blocked by d6 and f4 it stops on them
+        assert_eq!(rook_attacks(35, 0).count_ones(), 14);
+        let blocked = rook_attacks(35, 1 << 19 | 1 << 37);
+        assert_eq!(blocked & (1 << 11 | 1 << 38), 0);
+        assert_eq!(blocked & (1 << 19 | 1 << 37), 1 << 19 | 1 << 37);
+        assert_eq!(blocked.count_ones(), 10);
+        // an a1 bishop attacks the long diagonal, a bishop in the centre 13 squares
+        assert_eq!(bishop_attacks(56, 0).count_ones(), 7);
+        assert_eq!(bishop_attacks(35, 0).count_ones(), 13);
+    }
+}
@@ -1,19 +1,34 @@
 use core::fmt;
+use std::cell::OnceCell;
+use std::cmp;
+use std::io::{Read, Write};
 use std::string;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 
 use ahash;
 use log;
+use rand::seq::SliceRandom;
+use rand::Rng;
 
+use crate::clock::Clock;
 use crate::engine;
 use crate::errors::BoardStateError;
+use crate::errors::FenParseError;
 use crate::errors::PGNParseError;
+use crate::errors::PositionError;
+use crate::errors::SaveGameError;
 use crate::fen::FEN;
 use crate::log_and_return_error;
 use crate::movegen::*;
+use crate::packed_move::PackedMove;
 use crate::pgn;
 use crate::pgn::notation::Notation;
-use crate::pgn::tag::Tag;
+use crate::pgn::tag::{PgnDate, Tag, Termination};
+use crate::player::Player;
 use crate::position::*;
+use crate::save::SavedGame;
+use crate::search;
 use crate::transposition;
 use crate::util;
 use crate::zobrist;
@@ -21,8 +36,10 @@ use crate::zobrist::PositionHash;
 
 const DEFAULT_HALFMOVE_COUNT: u32 = 0;
 const DEFAULT_MOVE_COUNT: u32 = 1; // movecount starts at 1
+const MATE_SEARCH_TT_SIZE_MB: usize = 16;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameState {
     Check,
     Checkmate,
@@ -31,16 +48,29 @@ pub enum GameState {
     FiftyMove,
     InsufficientMaterial,
     Active,
+    FivefoldRepetition,
+    SeventyFiveMove,
 }
 impl GameState {
-    // gamestates that are draws
+    // gamestates that are draws, including the ones that are only draws if claimed
     #[inline]
     pub fn is_draw(&self) -> bool {
         matches!(
             self,
-            Self::Stalemate | Self::FiftyMove | Self::Repetition | Self::InsufficientMaterial
+            Self::Stalemate
+                | Self::FiftyMove
+                | Self::Repetition
+                | Self::InsufficientMaterial
+                | Self::FivefoldRepetition
+                | Self::SeventyFiveMove
         )
     }
+    // threefold repetition and the fifty move rule let a player claim a draw, but the game goes on until they do. See
+    // Board::claim_draw
+    #[inline]
+    pub fn is_claimable_draw(&self) -> bool {
+        matches!(self, Self::Repetition | Self::FiftyMove)
+    }
     // gamestates that are wins
     #[inline]
     pub fn is_win(&self) -> bool {
@@ -49,7 +79,40 @@ impl GameState {
     // gamestates that end game
     #[inline]
     pub fn is_game_over(&self) -> bool {
-        self.is_win() || self.is_draw()
+        self.is_win() || (self.is_draw() && !self.is_claimable_draw())
+    }
+    // the state of a position from everything it depends on, occurences is only called if no other state applies first
+    #[inline(always)]
+    fn from_parts(
+        is_in_check: bool,
+        legal_moves_empty: bool,
+        material: &MaterialCount,
+        halfmove_count: u32,
+        occurences: impl FnOnce() -> u8,
+    ) -> Self {
+        // checkmate has to be checked for first, as it supercedes other states like the 50 move rule
+        if is_in_check && legal_moves_empty {
+            GameState::Checkmate
+        } else if !is_in_check && legal_moves_empty {
+            GameState::Stalemate
+        } else if material.is_insufficient() {
+            GameState::InsufficientMaterial
+        } else if halfmove_count >= 150 {
+            GameState::SeventyFiveMove
+        } else {
+            let occurences = occurences();
+            if occurences >= 5 {
+                GameState::FivefoldRepetition
+            } else if halfmove_count >= 100 {
+                GameState::FiftyMove
+            } else if occurences >= 3 {
+                GameState::Repetition
+            } else if is_in_check {
+                GameState::Check
+            } else {
+                GameState::Active
+            }
+        }
     }
 }
 // String representation of GameState
@@ -63,23 +126,306 @@ impl fmt::Display for GameState {
             Self::FiftyMove => "Fifty Move Draw",
             Self::InsufficientMaterial => "Insufficient Material",
             Self::Active => "",
+            Self::FivefoldRepetition => "Fivefold Repetition",
+            Self::SeventyFiveMove => "Seventy-five Move Draw",
         };
         write!(f, "{}", state_str)
     }
 }
 
+// light squares as a square set, a8 (index 0) is a light square
+const LIGHT_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+
+// piece counts for one side, bishops are split by square colour for insufficient material detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideMaterial {
+    pub pawns: u8,
+    pub knights: u8,
+    pub light_bishops: u8,
+    pub dark_bishops: u8,
+    pub rooks: u8,
+    pub queens: u8,
+    // see value
+    value: i32,
+}
+
+impl SideMaterial {
+    pub fn bishops(&self) -> u8 {
+        self.light_bishops + self.dark_bishops
+    }
+
+    // in centipawns, using the engine's piece values
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    fn from_totals(totals: &PieceTotals, colour: PieceColour) -> Self {
+        let count = |ptype| {
+            totals.squares(Piece {
+                pcolour: colour,
+                ptype,
+            })
+        };
+        let bishops = count(PieceType::Bishop);
+        Self {
+            pawns: count(PieceType::Pawn).count_ones() as u8,
+            knights: count(PieceType::Knight).count_ones() as u8,
+            light_bishops: (bishops & LIGHT_SQUARES).count_ones() as u8,
+            dark_bishops: (bishops & !LIGHT_SQUARES).count_ones() as u8,
+            rooks: count(PieceType::Rook).count_ones() as u8,
+            queens: count(PieceType::Queen).count_ones() as u8,
+            value: totals.material(colour) - engine::get_piece_value(&PieceType::King),
+        }
+    }
+
+    #[inline(always)]
+    fn minors(&self) -> u8 {
+        self.knights + self.light_bishops + self.dark_bishops
+    }
+
+    #[inline(always)]
+    fn has_major_or_pawn(&self) -> bool {
+        self.pawns > 0 || self.rooks > 0 || self.queens > 0
+    }
+}
+
+// material on the board, counted from the piece square sets the Position keeps up to date as moves are made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaterialCount {
+    pub white: SideMaterial,
+    pub black: SideMaterial,
+}
+
+impl MaterialCount {
+    pub fn side(&self, colour: PieceColour) -> &SideMaterial {
+        match colour {
+            PieceColour::White => &self.white,
+            PieceColour::Black => &self.black,
+        }
+    }
+
+    // white's material minus black's in centipawns, positive when white is ahead
+    pub fn balance(&self) -> i32 {
+        self.white.value() - self.black.value()
+    }
+
+    pub(crate) fn from_totals(totals: &PieceTotals) -> Self {
+        Self {
+            white: SideMaterial::from_totals(totals, PieceColour::White),
+            black: SideMaterial::from_totals(totals, PieceColour::Black),
+        }
+    }
+
+    // neither side can possibly checkmate: K vs K, K + minor vs K, or only bishops left that are all on the same square colour
+    fn is_insufficient(&self) -> bool {
+        let (w, b) = (&self.white, &self.black);
+        if w.has_major_or_pawn() || b.has_major_or_pawn() {
+            return false;
+        }
+        let (w_minors, b_minors) = (w.minors(), b.minors());
+        if w_minors + b_minors <= 1 {
+            return true;
+        }
+        let knights = w.knights + b.knights;
+        let light_bishops = w.light_bishops + b.light_bishops;
+        let dark_bishops = w.dark_bishops + b.dark_bishops;
+        knights == 0 && (light_bishops == 0 || dark_bishops == 0)
+    }
+
+    // whether colour could checkmate by any series of legal moves, however unlikely (FIDE article 6.9). A lone king
+    // never can, and otherwise it's only impossible when neither side can, so e.g. K + N vs K + Q can still be won
+    // because the queen side can block its own king in
+    fn can_mate(&self, colour: PieceColour) -> bool {
+        let side = self.side(colour);
+        (side.has_major_or_pawn() || side.minors() > 0) && !self.is_insufficient()
+    }
+}
+
+// halfmove count after mv is made, pawn moves and captures reset it
+#[inline(always)]
+fn next_halfmove_count(halfmove_count: u32, mv: &Move) -> u32 {
+    let halfmove_reset = matches!(
+        mv.move_type,
+        MoveType::PawnPush
+            | MoveType::DoublePawnPush
+            | MoveType::Capture(_)
+            | MoveType::EnPassant(_)
+            | MoveType::Promotion(_, _)
+    );
+    if halfmove_reset {
+        0
+    } else {
+        halfmove_count + 1
+    }
+}
+
+// which castles each side still has the right to, whether or not they are legal in the current position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CastlingRights {
+    pub white_short: bool,
+    pub white_long: bool,
+    pub black_short: bool,
+    pub black_long: bool,
+}
+
+impl CastlingRights {
+    pub fn can_castle(&self, colour: PieceColour, side: CastleSide) -> bool {
+        match (colour, side) {
+            (PieceColour::White, CastleSide::Short) => self.white_short,
+            (PieceColour::White, CastleSide::Long) => self.white_long,
+            (PieceColour::Black, CastleSide::Short) => self.black_short,
+            (PieceColour::Black, CastleSide::Long) => self.black_long,
+        }
+    }
+}
+
+// FEN letter for a piece, upper case for white
+fn piece_letter(piece: Piece) -> char {
+    let letter = match piece.ptype {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+    match piece.pcolour {
+        PieceColour::White => letter.to_ascii_uppercase(),
+        PieceColour::Black => letter,
+    }
+}
+
+fn piece_symbol(piece: Piece) -> char {
+    match (piece.pcolour, piece.ptype) {
+        (PieceColour::White, PieceType::Pawn) => '♙',
+        (PieceColour::White, PieceType::Knight) => '♘',
+        (PieceColour::White, PieceType::Bishop) => '♗',
+        (PieceColour::White, PieceType::Rook) => '♖',
+        (PieceColour::White, PieceType::Queen) => '♕',
+        (PieceColour::White, PieceType::King) => '♔',
+        (PieceColour::Black, PieceType::Pawn) => '♟',
+        (PieceColour::Black, PieceType::Knight) => '♞',
+        (PieceColour::Black, PieceType::Bishop) => '♝',
+        (PieceColour::Black, PieceType::Rook) => '♜',
+        (PieceColour::Black, PieceType::Queen) => '♛',
+        (PieceColour::Black, PieceType::King) => '♚',
+    }
+}
+
+// the positions of a game, newest first. Each BoardState holds the entry for its own position, so the states of a game
+// share one list rather than each owning a copy of every position before it
+#[derive(Debug)]
+struct PositionHistory {
+    position_hash: PositionHash,
+    halfmove_count: u32,
+    // times this position has occurred, including this time
+    occurences: u8,
+    prev: Option<Arc<PositionHistory>>,
+}
+
+impl PositionHistory {
+    fn new(
+        position_hash: PositionHash,
+        halfmove_count: u32,
+        prev: Option<Arc<PositionHistory>>,
+    ) -> Arc<Self> {
+        let occurences = prev
+            .as_ref()
+            .map_or(0, |prev| prev.occurences_of(position_hash))
+            .saturating_add(1);
+        Arc::new(Self {
+            position_hash,
+            halfmove_count,
+            occurences,
+            prev,
+        })
+    }
+
+    // positions of the game from this one back to the last pawn move or capture. Positions from before it can't be
+    // repeated, so they never need to be looked at
+    fn repeatable(&self) -> impl Iterator<Item = &PositionHistory> {
+        let mut next = Some(self);
+        std::iter::from_fn(move || {
+            let entry = next?;
+            next = match entry.halfmove_count {
+                0 => None,
+                _ => entry.prev.as_deref(),
+            };
+            Some(entry)
+        })
+    }
+
+    // times position_hash has occurred up to and including this position
+    fn occurences_of(&self, position_hash: PositionHash) -> u8 {
+        self.repeatable()
+            .find(|entry| entry.position_hash == position_hash)
+            .map_or(0, |entry| entry.occurences)
+    }
+
+    // every position of the game with the number of times it occurred
+    #[cfg(feature = "serde")]
+    fn all_occurences(&self) -> ahash::AHashMap<PositionHash, u8> {
+        let mut occurences = ahash::AHashMap::default();
+        let mut next = Some(self);
+        while let Some(entry) = next {
+            // the newest entry for a position has its full count
+            occurences
+                .entry(entry.position_hash)
+                .or_insert(entry.occurences);
+            next = entry.prev.as_deref();
+        }
+        occurences
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BoardState {
     pub side_to_move: PieceColour,
     pub last_move: Option<Move>,
-    legal_moves: Vec<Move>,
+    // generated when first needed, which is straight away unless the state is lazy. Board only keeps them for its current
+    // state, see BoardState::for_history
+    legal_moves: OnceCell<Vec<Move>>,
+    // the Position keeps its pseudo legal moves packed, they are unpacked the first time they're asked for
+    pseudo_legal_moves: OnceCell<Vec<Move>>,
+    // position_hash combined with the halfmove count and number of occurences of the position, used for BoardState
+    // equality. The engine's transposition table is keyed on position_hash, so transpositions reached with a different
+    // halfmove count still share an entry
     pub board_hash: u64,
     pub position_hash: u64,
     position: Position,
     move_count: u32,
     halfmove_count: u32,
-    position_occurences: ahash::AHashMap<PositionHash, u8>,
+    history: Arc<PositionHistory>,
     lazy_legal_moves: bool,
+    // a BoardState doesn't change once created, so its gamestate is only worked out once. Set when the state is created,
+    // except for next_state_unchecked states where it's worked out on the first get_gamestate call
+    gamestate: OnceCell<GameState>,
+}
+
+/// Board diagram from white's side with FEN letters for the pieces, upper case for white, and the side to move,
+/// castling rights and en passant square underneath:
+///
+/// ```
+/// use chess::BoardState;
+///
+/// assert_eq!(
+///     BoardState::new_starting().to_string(),
+///     "8 r n b q k b n r\n\
+///      7 p p p p p p p p\n\
+///      6 . . . . . . . .\n\
+///      5 . . . . . . . .\n\
+///      4 . . . . . . . .\n\
+///      3 . . . . . . . .\n\
+///      2 P P P P P P P P\n\
+///      1 R N B Q K B N R\n  \
+///        a b c d e f g h\n\
+///      White to move, castling KQkq, en passant -"
+/// );
+/// ```
+impl fmt::Display for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagram(false, piece_letter))
+    }
 }
 
 impl PartialEq for BoardState {
@@ -88,10 +434,16 @@ impl PartialEq for BoardState {
     }
 }
 
-impl From<FEN> for BoardState {
-    fn from(fen: FEN) -> Self {
-        let pos = Position::from(fen);
-        Self::from_parts(pos, fen.halfmove_count(), fen.move_count())
+impl TryFrom<FEN> for BoardState {
+    type Error = PositionError;
+
+    fn try_from(fen: FEN) -> Result<Self, Self::Error> {
+        let pos = Position::try_from(fen)?;
+        Ok(Self::from_parts(
+            pos,
+            fen.halfmove_count(),
+            fen.move_count(),
+        ))
     }
 }
 
@@ -133,10 +485,8 @@ impl BoardState {
         let position_hash: PositionHash = position.pos_hash();
         let board_hash = zobrist::board_state_hash(position_hash, 1, halfmove_count);
         let side_to_move = position.side;
-        // deref all legal moves, performance isn't as important here, so avoid lifetime specifiers to make things easier to look at
-        let legal_moves = position.get_legal_moves().into_iter().cloned().collect();
-        let mut position_occurences = ahash::AHashMap::default();
-        position_occurences.insert(position_hash, 1);
+        let legal_moves = position.get_legal_moves();
+        let history = PositionHistory::new(position_hash, halfmove_count, None);
         log::info!(
             "New BoardState created from position: {} halfmove_count: {} move_count: {}",
             util::hash_to_string(position_hash),
@@ -151,9 +501,38 @@ impl BoardState {
             board_hash,
             side_to_move,
             last_move: None,
-            legal_moves,
-            position_occurences,
+            legal_moves: OnceCell::from(legal_moves),
+            pseudo_legal_moves: OnceCell::new(),
+            history,
             lazy_legal_moves: false,
+            gamestate: OnceCell::new(),
+        }
+        .with_gamestate()
+    }
+
+    // sets the cached gamestate
+    fn with_gamestate(self) -> Self {
+        self.get_gamestate();
+        self
+    }
+
+    // copy of the state to keep in Board's state history, without its legal or pseudo legal moves. They are generated
+    // again if the state is checked out, which doesn't happen often enough to be worth keeping them for every move of the
+    // game
+    pub(crate) fn for_history(&self) -> Self {
+        Self {
+            side_to_move: self.side_to_move,
+            last_move: self.last_move,
+            legal_moves: OnceCell::new(),
+            pseudo_legal_moves: OnceCell::new(),
+            board_hash: self.board_hash,
+            position_hash: self.position_hash,
+            position: self.position.clone(),
+            move_count: self.move_count,
+            halfmove_count: self.halfmove_count,
+            history: Arc::clone(&self.history),
+            lazy_legal_moves: self.lazy_legal_moves,
+            gamestate: self.gamestate.clone(),
         }
     }
 
@@ -161,6 +540,20 @@ impl BoardState {
         &self.position
     }
 
+    // whether colour could still checkmate by any series of legal moves, a side that can't doesn't win on time
+    pub fn has_mating_material(&self, colour: PieceColour) -> bool {
+        self.material().can_mate(colour)
+    }
+
+    pub fn material(&self) -> MaterialCount {
+        MaterialCount::from_totals(self.position.piece_totals())
+    }
+
+    // see MaterialCount::balance
+    pub fn material_balance(&self) -> i32 {
+        self.material().balance()
+    }
+
     pub fn halfmove_count(&self) -> u32 {
         self.halfmove_count
     }
@@ -170,26 +563,111 @@ impl BoardState {
     }
 
     pub fn get_pseudo_legal_moves(&self) -> &Vec<Move> {
-        self.position.get_pseudo_legal_moves()
+        self.pseudo_legal_moves.get_or_init(|| {
+            self.position
+                .get_pseudo_legal_moves()
+                .iter()
+                .map(|mv| mv.unpack())
+                .collect()
+        })
     }
 
     // checks if a move would create a legal position, does not check for boardstate legality
     pub fn is_move_legal_position(&self, mv: &Move) -> bool {
-        self.position.is_move_legal(mv)
+        PackedMove::try_pack(mv).is_some_and(|mv| self.position.is_move_legal(mv))
+    }
+
+    // whether any piece of colour 'by' attacks the square at idx (0 = a8, 63 = h1), whatever is on it
+    pub fn is_square_attacked(&self, idx: usize, by: PieceColour) -> bool {
+        self.position.is_square_attacked(idx, by)
+    }
+
+    // every piece of colour 'by' attacking the square at idx, as (index, piece) pairs
+    pub fn attackers_to(&self, idx: usize, by: PieceColour) -> Vec<(usize, Piece)> {
+        self.position.attackers_to(idx, by)
+    }
+
+    // square set (bit i for index i) of the squares attacked by colour, whatever is on them
+    pub fn attacked_squares(&self, colour: PieceColour) -> u64 {
+        self.position.attacked_squares(colour)
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        let flags = &self.position.movegen_flags;
+        CastlingRights {
+            white_short: flags.white_castle_short,
+            white_long: flags.white_castle_long,
+            black_short: flags.black_castle_short,
+            black_long: flags.black_castle_long,
+        }
+    }
+
+    // see Position::en_passant_square
+    pub fn en_passant_square(&self) -> Option<usize> {
+        self.position.en_passant_square()
+    }
+
+    pub fn king_square(&self, colour: PieceColour) -> usize {
+        self.position.king_idx(colour)
+    }
+
+    // legal moves of the piece on idx (0 = a8, 63 = h1), empty if it isn't a piece of the side to move
+    pub fn legal_moves_from(&self, idx: usize) -> Vec<Move> {
+        self.position.moves_from(idx)
+    }
+
+    // see Position::expand_short_move
+    pub fn expand_short_move(&self, short_mv: ShortMove) -> Option<Move> {
+        self.position.expand_short_move(short_mv)
+    }
+
+    // the legal move for coordinate notation like "e2e4" or "a7a8q", see Move's Display. Castling is the king's from and to
+    // squares, or king takes rook as chess960 UCI sends it. None if s is malformed or the move isn't legal
+    pub fn find_move_uci(&self, s: &str) -> Option<Move> {
+        self.find_move_short(ShortMove::from_coordinate_notation(s)?)
+    }
+
+    fn find_move_short(&self, short_mv: ShortMove) -> Option<Move> {
+        let mut castle = None;
+        for mv in self.lazy_get_legal_moves() {
+            match mv.move_type {
+                MoveType::Castle(castle_mv) => {
+                    let king_takes_rook = ShortMove {
+                        to: castle_mv.rook_from as u8,
+                        ..mv.short_move()
+                    };
+                    if mv.short_move() == short_mv || king_takes_rook == short_mv {
+                        castle = Some(*mv);
+                    }
+                }
+                // a chess960 king move can have the same squares as a castle, the castle would be sent as king takes
+                // rook so the king move comes first
+                _ if mv.short_move() == short_mv => return Some(*mv),
+                _ => {}
+            }
+        }
+        castle
     }
 
     // lazily do legality check on pseudo legal moves as the iterator is used
     pub fn lazy_get_legal_moves(&self) -> impl Iterator<Item = &Move> {
-        self.position
-            .get_pseudo_legal_moves()
+        // the packed moves are in the same order, and are quicker to check
+        self.get_pseudo_legal_moves()
             .iter()
-            .filter(|mv| self.position.is_move_legal(mv))
+            .zip(self.position.get_pseudo_legal_moves())
+            .filter(|(_, packed)| self.position.is_move_legal(**packed))
+            .map(|(mv, _)| mv)
     }
 
-    // next state without legality and gamestate checks done (legal_moves is empty), may panic if unreachable code is hit e.g. in zobrist hash generation if position occurrences ever gets above 3
+    // next state without legality and gamestate checks done (legal_moves is empty)
     // USERS MUST CHECK IF GAMESTATE IS VALID (E.G THREEFOLD REPETITION, 50 MOVE RULE) AS THIS FUNCTION DOES NOT
     pub fn next_state_unchecked(&self, mv: &Move) -> Self {
         let position = self.position.new_position(mv);
+        self.next_state_from_parts(mv, position)
+    }
+
+    // the lazy state after mv, from its position
+    fn next_state_from_parts(&self, mv: &Move, position: Position) -> Self {
         log::trace!("New Position created from move: {:?}", mv);
         let position_hash = zobrist::pos_next_hash(
             &self.position.movegen_flags,
@@ -197,13 +675,13 @@ impl BoardState {
             self.position_hash,
             mv,
         );
+        Self::debug_assert_position_hash(&position, position_hash, mv);
         log::trace!(
             "New position hash generated: {}",
             util::hash_to_string(position_hash)
         );
         let side_to_move = position.side;
         let last_move = Some(*mv);
-        let legal_moves = Vec::with_capacity(0); // empty vec as we don't need to generate legal moves ahead of time
 
         let move_count = if side_to_move == PieceColour::White {
             self.move_count + 1
@@ -211,21 +689,16 @@ impl BoardState {
             self.move_count
         };
 
-        let halfmove_reset = matches!(
-            mv.move_type,
-            MoveType::PawnPush | MoveType::DoublePawnPush | MoveType::Capture(_)
-        );
-        let halfmove_count = if halfmove_reset {
-            0
-        } else {
-            self.halfmove_count + 1
-        };
+        let halfmove_count = next_halfmove_count(self.halfmove_count, mv);
 
-        let mut position_occurences = self.position_occurences.clone();
-        let po = position_occurences.entry(position_hash).or_insert(0);
-        *po += 1;
+        let history = PositionHistory::new(
+            position_hash,
+            halfmove_count,
+            Some(Arc::clone(&self.history)),
+        );
 
-        let board_hash = zobrist::board_state_hash(position_hash, *po, halfmove_count);
+        let board_hash =
+            zobrist::board_state_hash(position_hash, history.occurences, halfmove_count);
         //let board_hash = position_hash ^ (*po as u64) ^ (halfmove_count as u64);
         log::trace!("Board hash: {}", util::hash_to_string(board_hash));
 
@@ -233,14 +706,16 @@ impl BoardState {
         Self {
             side_to_move,
             last_move,
-            legal_moves,
+            legal_moves: OnceCell::new(),
+            pseudo_legal_moves: OnceCell::new(),
             position,
             board_hash,
             position_hash,
             move_count,
             halfmove_count,
-            position_occurences,
+            history,
             lazy_legal_moves: true,
+            gamestate: OnceCell::new(),
         }
     }
 
@@ -255,17 +730,18 @@ impl BoardState {
             let err = BoardStateError::LazyIncompatiblity("next_state called on BoardState with lazy_legal_moves flag set, cannot generate next state without all legal moves being generated.".to_string());
             log_and_return_error!(err)
         }
-        if !self.legal_moves.contains(mv) {
+        if !self.legal_moves().contains(mv) {
             let err = BoardStateError::IllegalMove(format!("{:?} is not a legal move", mv));
             log_and_return_error!(err)
         }
 
         let current_game_state = self.get_gamestate();
 
+        // claimable draws don't stop the game, Board decides if they have been claimed
         if current_game_state == GameState::Checkmate
             || current_game_state == GameState::Stalemate
-            || current_game_state == GameState::FiftyMove
-            || current_game_state == GameState::Repetition
+            || current_game_state == GameState::SeventyFiveMove
+            || current_game_state == GameState::FivefoldRepetition
         {
             let err = BoardStateError::NoLegalMoves(current_game_state);
             log_and_return_error!(err)
@@ -279,14 +755,14 @@ impl BoardState {
             self.position_hash,
             mv,
         );
+        Self::debug_assert_position_hash(&position, position_hash, mv);
         log::trace!(
             "New position hash generated: {}",
             util::hash_to_string(position_hash)
         );
         let side_to_move = position.side;
         let last_move = Some(*mv);
-        // deref all legal moves
-        let legal_moves = position.get_legal_moves().into_iter().cloned().collect();
+        let legal_moves = position.get_legal_moves();
         log::trace!("Legal moves generated: {legal_moves:?}");
 
         let move_count = if side_to_move == PieceColour::White {
@@ -295,21 +771,16 @@ impl BoardState {
             self.move_count
         };
 
-        let halfmove_reset = matches!(
-            mv.move_type,
-            MoveType::PawnPush | MoveType::DoublePawnPush | MoveType::Capture(_)
-        );
-        let halfmove_count = if halfmove_reset {
-            0
-        } else {
-            self.halfmove_count + 1
-        };
+        let halfmove_count = next_halfmove_count(self.halfmove_count, mv);
 
-        let mut position_occurences = self.position_occurences.clone();
-        let po = position_occurences.entry(position_hash).or_insert(0);
-        *po += 1;
+        let history = PositionHistory::new(
+            position_hash,
+            halfmove_count,
+            Some(Arc::clone(&self.history)),
+        );
 
-        let board_hash = zobrist::board_state_hash(position_hash, *po, halfmove_count);
+        let board_hash =
+            zobrist::board_state_hash(position_hash, history.occurences, halfmove_count);
         //let board_hash = position_hash ^ (*po as u64) ^ (halfmove_count as u64);
         log::trace!("Board hash: {}", util::hash_to_string(board_hash));
 
@@ -317,15 +788,18 @@ impl BoardState {
         Ok(Self {
             side_to_move,
             last_move,
-            legal_moves,
+            legal_moves: OnceCell::from(legal_moves),
+            pseudo_legal_moves: OnceCell::new(),
             position,
             board_hash,
             position_hash,
             move_count,
             halfmove_count,
-            position_occurences,
+            history,
             lazy_legal_moves: false,
-        })
+            gamestate: OnceCell::new(),
+        }
+        .with_gamestate())
     }
 
     // fn gen_legal_moves(&mut self) {
@@ -342,41 +816,104 @@ impl BoardState {
             let err = BoardStateError::LazyIncompatiblity("get_legal_moves called on BoardState with lazy_legal_moves flag set, legal_moves vec is empty".to_string());
             log_and_return_error!(err)
         }
-        Ok(&self.legal_moves)
+        Ok(self.legal_moves())
+    }
+
+    // only for states that aren't lazy
+    fn legal_moves(&self) -> &[Move] {
+        self.legal_moves
+            .get_or_init(|| self.position.get_legal_moves())
+    }
+
+    // incrementally updated position hash must always match a full rehash of the new position
+    #[cfg(debug_assertions)]
+    fn debug_assert_position_hash(position: &Position, position_hash: PositionHash, mv: &Move) {
+        let full_hash = zobrist::pos_hash(position);
+        if full_hash != position_hash {
+            log::error!(
+                "Incremental position hash {} doesn't match full hash {} after move {:?}, FEN: {}",
+                util::hash_to_string(position_hash),
+                util::hash_to_string(full_hash),
+                mv,
+                FEN::from(position)
+            );
+        }
+        assert_eq!(
+            position_hash, full_hash,
+            "incremental position hash doesn't match a full rehash after move {:?}",
+            mv
+        );
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn debug_assert_position_hash(_position: &Position, _position_hash: PositionHash, _mv: &Move) {}
+
+    // uniformly random legal move, None if the game is over
+    pub fn random_legal_move(&self, rng: &mut impl Rng) -> Option<Move> {
+        if self.get_gamestate().is_game_over() {
+            return None;
+        }
+        if self.lazy_legal_moves {
+            self.lazy_get_legal_moves()
+                .collect::<Vec<&Move>>()
+                .choose(rng)
+                .map(|mv| **mv)
+        } else {
+            self.legal_moves().choose(rng).copied()
+        }
     }
 
     pub fn get_occurences_of_current_position(&self) -> u8 {
-        *self
-            .position_occurences
-            .get(&self.position_hash)
-            .unwrap_or(&1)
+        self.history.occurences
     }
-    // TODO add check for insufficient material
-    pub fn get_gamestate(&self) -> GameState {
-        let legal_moves_empty = if self.lazy_legal_moves {
-            self.lazy_get_legal_moves().peekable().peek().is_none()
+
+    // number of times the position with position_hash has occurred in the game up to and including this state
+    pub fn occurences_of(&self, position_hash: u64) -> u8 {
+        self.history.occurences_of(position_hash)
+    }
+
+    // number of times the position resulting from mv would have occurred (including the new occurrence), or None if mv is not legal
+    // only the position hash is computed, so this is much cheaper than generating the next state
+    pub fn would_repeat(&self, mv: &Move) -> Option<u8> {
+        let packed = PackedMove::try_pack(mv)?;
+        if !self.position.get_pseudo_legal_moves().contains(&packed)
+            || !self.position.is_move_legal(packed)
+        {
+            return None;
+        }
+        let position_hash = zobrist::pos_next_hash(
+            &self.position.movegen_flags,
+            &self.position.next_movegen_flags(mv),
+            self.position_hash,
+            mv,
+        );
+        // a pawn move or capture leads to a position that can't have occurred before
+        let occurences = if next_halfmove_count(self.halfmove_count, mv) == 0 {
+            0
         } else {
-            self.legal_moves.is_empty()
+            self.history.occurences_of(position_hash)
         };
-        let is_in_check = self.position.is_in_check();
+        Some(occurences.saturating_add(1))
+    }
 
-        // checkmate has to be checked for first, as it supercedes other states like the 50 move rule
-        if is_in_check && legal_moves_empty {
-            GameState::Checkmate
-        } else if !is_in_check && legal_moves_empty {
-            GameState::Stalemate
-        } else if self.halfmove_count >= 100 {
-            GameState::FiftyMove
-        } else if self.get_occurences_of_current_position() >= 3 {
-            GameState::Repetition
-        } else if is_in_check {
-            GameState::Check
-        } else if false {
-            //placeholder
-            GameState::InsufficientMaterial
+    pub fn get_gamestate(&self) -> GameState {
+        *self.gamestate.get_or_init(|| self.compute_gamestate())
+    }
+
+    fn compute_gamestate(&self) -> GameState {
+        let legal_moves_empty = if self.lazy_legal_moves {
+            !self.position.has_legal_move()
         } else {
-            GameState::Active
-        }
+            self.legal_moves().is_empty()
+        };
+        GameState::from_parts(
+            self.position.is_in_check(),
+            legal_moves_empty,
+            &self.material(),
+            self.halfmove_count,
+            || self.get_occurences_of_current_position(),
+        )
     }
 
     // fn is_in_check(&self) -> bool {
@@ -417,84 +954,641 @@ impl BoardState {
     pub fn get_pos64(&self) -> &Pos64 {
         &self.position.pos64
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum GameOverState {
-    WhiteResign,
-    BlackResign,
-    AgreedDraw,
-    Forced(GameState),
-}
+    /// FEN string of this state, including its halfmove and move counts:
+    ///
+    /// ```
+    /// use chess::BoardState;
+    ///
+    /// let fen = BoardState::new_starting().to_fen();
+    /// assert_eq!(fen, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    /// ```
+    pub fn to_fen(&self) -> String {
+        FEN::from(self).to_string()
+    }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum Variant {
-    #[default]
-    Standard,
-    Chess960,
-    FromPosition,
-}
+    /// Board diagram like Display's, with chess piece symbols instead of letters. Flipped shows the board from black's
+    /// side, with h1 at the top left:
+    ///
+    /// ```
+    /// use chess::BoardState;
+    ///
+    /// let diagram = BoardState::new_starting().to_unicode_string(true);
+    /// assert_eq!(
+    ///     diagram,
+    ///     "1 ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖\n\
+    ///      2 ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙\n\
+    ///      3 . . . . . . . .\n\
+    ///      4 . . . . . . . .\n\
+    ///      5 . . . . . . . .\n\
+    ///      6 . . . . . . . .\n\
+    ///      7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟\n\
+    ///      8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜\n  \
+    ///        h g f e d c b a\n\
+    ///      White to move, castling KQkq, en passant -"
+    /// );
+    /// ```
+    pub fn to_unicode_string(&self, flipped: bool) -> String {
+        self.diagram(flipped, piece_symbol)
+    }
 
-impl fmt::Display for Variant {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let variant_str = match self {
-            Self::Standard => "Standard",
-            Self::Chess960 => "Chess960",
-            Self::FromPosition => "From Position",
+    // ranks from 8 down to 1 with a file letter row underneath, reversed when flipped. The footer uses the FEN fields so
+    // chess960 castling rights are written the same way
+    fn diagram(&self, flipped: bool, piece_char: fn(Piece) -> char) -> String {
+        let mut diagram = String::new();
+        let squares: Vec<usize> = if flipped {
+            (0..64).rev().collect()
+        } else {
+            (0..64).collect()
         };
-        write!(f, "{}", variant_str)
+        for rank in squares.chunks(8) {
+            diagram.push_str(&(8 - rank[0] / 8).to_string());
+            for &idx in rank {
+                diagram.push(' ');
+                diagram.push(match self.position.pos64[idx] {
+                    Square::Piece(p) => piece_char(p),
+                    Square::Empty => '.',
+                });
+            }
+            diagram.push('\n');
+        }
+        diagram.push(' ');
+        for &idx in &squares[..8] {
+            diagram.push(' ');
+            diagram.push((b'a' + (idx % 8) as u8) as char);
+        }
+        let fen = self.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        diagram.push_str(&format!(
+            "\n{} to move, castling {}, en passant {}",
+            self.side_to_move, fields[2], fields[3]
+        ));
+        diagram
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct PlayerData {
-    pub name: Option<String>,
-    pub elo: Option<u16>,
+// the parts of a BoardState that aren't in its Position, for each position on a SearchBoard
+#[derive(Debug, Clone, Copy)]
+struct SearchPly {
+    position_hash: PositionHash,
+    halfmove_count: u32,
+    last_move: Option<Move>, // None after a null move
+    // index of the earliest ply this position can repeat, a null move isn't a move in the game so positions from before
+    // it don't count
+    repeatable_from: usize,
 }
 
-impl Default for PlayerData {
-    fn default() -> Self {
+// the engine's view of the game during a search. Moves are made and unmade on a single Position in place instead of
+// creating a BoardState for every node, with a stack of the hash and halfmove count of each position since
+// the root so draws can still be detected, along with the positions before the search
+pub(crate) struct SearchBoard {
+    // occurences of the positions before the search that can still be repeated, see PositionHistory::repeatable
+    root_occurences: ahash::AHashMap<PositionHash, u8>,
+    position: Position,
+    plies: Vec<SearchPly>, // the root first, the current position last
+}
+
+impl SearchBoard {
+    // max_plies is how many moves deep the search can go, so the ply stack never has to grow
+    pub(crate) fn new(root: &BoardState, max_plies: usize) -> Self {
+        let mut plies = Vec::with_capacity(max_plies + 1);
+        plies.push(SearchPly {
+            position_hash: root.position_hash,
+            halfmove_count: root.halfmove_count,
+            last_move: root.last_move,
+            repeatable_from: 0,
+        });
+        let mut root_occurences = ahash::AHashMap::default();
+        for entry in root.history.repeatable() {
+            root_occurences
+                .entry(entry.position_hash)
+                .or_insert(entry.occurences);
+        }
         Self {
-            name: None,
-            elo: None,
+            root_occurences,
+            position: root.position.clone(),
+            plies,
         }
     }
-}
-
-pub struct EngineAnalysis {
-    pub board_hash: u64,
-    pub position_hash: u64,
-    pub eval: i32,
-    pub best_move: Option<Move>,
-    pub best_move_notation: Option<Notation>,
-}
 
-impl fmt::Display for EngineAnalysis {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "[Board hash: {}, Position hash: {}] Best move: {:?}, Notation: {}, Raw eval: {}, Str Eval: {}",
-            util::hash_to_string(self.board_hash),
-            util::hash_to_string(self.position_hash),
-            self.best_move,
-            self.best_move_notation.as_ref().map_or_else(|| "None".into(), |n| n.to_string()),
-            self.eval,
-            util::eval_to_string(self.eval)
-        )
+    #[inline(always)]
+    fn current(&self) -> &SearchPly {
+        // the root is never popped
+        self.plies.last().unwrap()
     }
-}
+
+    #[inline(always)]
+    pub(crate) fn position(&self) -> &Position {
+        &self.position
+    }
+
+    #[inline(always)]
+    pub(crate) fn position_hash(&self) -> PositionHash {
+        self.current().position_hash
+    }
+
+    #[inline(always)]
+    pub(crate) fn last_move(&self) -> Option<Move> {
+        self.current().last_move
+    }
+
+    // makes mv on the current position in place, the position's new attack map is generated into attack_map. Like
+    // BoardState::next_state_unchecked, no legality or gamestate checks are done
+    pub(crate) fn make_move(&mut self, mv: &Move, attack_map: AttackMap) -> Undo {
+        let last = *self.current();
+        let last_flags = self.position.movegen_flags;
+        let undo = self.position.make_move_reusing(mv, attack_map);
+        let position_hash = zobrist::pos_next_hash(
+            &last_flags,
+            &self.position.movegen_flags,
+            last.position_hash,
+            mv,
+        );
+        BoardState::debug_assert_position_hash(&self.position, position_hash, mv);
+        self.plies.push(SearchPly {
+            position_hash,
+            halfmove_count: next_halfmove_count(last.halfmove_count, mv),
+            last_move: Some(*mv),
+            repeatable_from: last.repeatable_from,
+        });
+        undo
+    }
+
+    // passes the turn to the other side (see Position::make_null_move), None if the side to move is in check. Undone
+    // with unmake_move like any other move
+    pub(crate) fn make_null_move(&mut self, attack_map: AttackMap) -> Option<Undo> {
+        let last = *self.current();
+        let last_flags = self.position.movegen_flags;
+        let undo = self.position.make_null_move_reusing(attack_map)?;
+        let position_hash = zobrist::null_move_hash(&last_flags, last.position_hash);
+        BoardState::debug_assert_position_hash(&self.position, position_hash, &NULL_MOVE);
+        self.plies.push(SearchPly {
+            position_hash,
+            halfmove_count: last.halfmove_count + 1,
+            last_move: None,
+            repeatable_from: self.plies.len(),
+        });
+        Some(undo)
+    }
+
+    // whether the side to move has anything other than pawns, without which passing is often better than any move
+    pub(crate) fn side_to_move_has_pieces(&self) -> bool {
+        let material = MaterialCount::from_totals(self.position.piece_totals());
+        let side = material.side(self.position.side);
+        side.minors() > 0 || side.rooks > 0 || side.queens > 0
+    }
+
+    // takes back the last move made, returning the attack map of the position it led to so it can be reused
+    pub(crate) fn unmake_move(&mut self, undo: Undo) -> AttackMap {
+        debug_assert!(self.plies.len() > 1, "unmake_move called on the root");
+        self.plies.pop();
+        self.position.unmake_move_reusing(undo)
+    }
+
+    // number of times the current position has occurred, the same as the BoardState it stands for would count
+    fn get_occurences_of_current_position(&self) -> u8 {
+        let current = self.current();
+        // positions from before the last pawn move, capture or null move can't be repeated, so only the plies since then
+        // are checked
+        let first = cmp::max(
+            self.plies
+                .len()
+                .saturating_sub(current.halfmove_count as usize + 1),
+            current.repeatable_from,
+        );
+        // the root's occurences are counted in root_occurences, along with the rest of the game before the search
+        let in_search = self.plies[cmp::max(first, 1)..]
+            .iter()
+            .filter(|ply| ply.position_hash == current.position_hash)
+            .count() as u8;
+        let before_search = if first == 0 {
+            self.root_occurences
+                .get(&current.position_hash)
+                .copied()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        in_search + before_search
+    }
+
+    pub(crate) fn get_gamestate(&self) -> GameState {
+        let current = self.current();
+        GameState::from_parts(
+            self.position.is_in_check(),
+            !self.position.has_legal_move(),
+            &MaterialCount::from_totals(self.position.piece_totals()),
+            current.halfmove_count,
+            || self.get_occurences_of_current_position(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameOverState {
+    WhiteResign,
+    BlackResign,
+    AgreedDraw,
+    Forced(GameState),
+    // a claimable GameState (threefold repetition or the fifty move rule) that the side to move claimed
+    DrawClaimed(GameState),
+    // the side that ran out of time, it's a draw instead of a loss if the other side can't checkmate
+    Timeout(PieceColour),
+    // a result decided by an arbiter or a game server, rather than on the board
+    Adjudication(GameResult),
+}
+
+impl GameOverState {
+    // game overs that depend on the position, so they no longer apply when the moves leading to it are taken back
+    fn is_from_position(&self) -> bool {
+        matches!(self, Self::Forced(_) | Self::DrawClaimed(_))
+    }
+}
+
+// why a game ended, claimed and automatic draws of the same kind share a reason (see GameOverState for which one)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResultReason {
+    Checkmate,
+    Resignation,
+    Stalemate,
+    InsufficientMaterial,
+    Agreement,
+    Repetition,
+    FiftyMove,
+    FivefoldRepetition,
+    SeventyFiveMove,
+    Timeout,
+    // the side that ran out of time doesn't lose if its opponent couldn't have checkmated
+    TimeoutVsInsufficientMaterial,
+    Adjudication,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameResult {
+    WhiteWins(ResultReason),
+    BlackWins(ResultReason),
+    Draw(ResultReason),
+}
+
+impl GameResult {
+    pub fn reason(&self) -> ResultReason {
+        match self {
+            Self::WhiteWins(reason) | Self::BlackWins(reason) | Self::Draw(reason) => *reason,
+        }
+    }
+
+    pub fn winner(&self) -> Option<PieceColour> {
+        match self {
+            Self::WhiteWins(_) => Some(PieceColour::White),
+            Self::BlackWins(_) => Some(PieceColour::Black),
+            Self::Draw(_) => None,
+        }
+    }
+
+    // PGN result string
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::WhiteWins(_) => "1-0",
+            Self::BlackWins(_) => "0-1",
+            Self::Draw(_) => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    #[default]
+    Standard,
+    Chess960,
+    FromPosition,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let variant_str = match self {
+            Self::Standard => "Standard",
+            Self::Chess960 => "Chess960",
+            Self::FromPosition => "From Position",
+        };
+        write!(f, "{}", variant_str)
+    }
+}
+
+// parses a PGN Variant tag value, ignoring case, spaces and hyphens so "chess 960" and "From Position" are accepted
+impl std::str::FromStr for Variant {
+    type Err = PGNParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalised: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match normalised.as_str() {
+            "standard" => Ok(Self::Standard),
+            "chess960" | "fischerandom" | "fischerrandom" => Ok(Self::Chess960),
+            "fromposition" => Ok(Self::FromPosition),
+            _ => Err(PGNParseError::InvalidTag(format!("Unknown variant: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayerData {
+    pub name: Option<String>,
+    pub elo: Option<u16>,
+}
+
+impl Default for PlayerData {
+    fn default() -> Self {
+        Self {
+            name: None,
+            elo: None,
+        }
+    }
+}
+
+// who played the game and where, written to the tags of exported PGNs. Fields that aren't set fall back to defaults when
+// exporting
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GameMetadata {
+    pub event: Option<String>,
+    pub site: Option<String>,
+    pub round: Option<String>,
+    pub date: Option<PgnDate>,
+    pub white: PlayerData,
+    pub black: PlayerData,
+    // tags without a field of their own e.g. TimeControl or ECO, one per tag name
+    pub extra_tags: Vec<Tag>,
+}
+
+impl GameMetadata {
+    // tags for the metadata that is set
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        tags.extend(self.event.clone().map(Tag::Event));
+        tags.extend(self.site.clone().map(Tag::Site));
+        tags.extend(self.date.map(Tag::Date));
+        tags.extend(self.round.clone().map(Tag::Round));
+        tags.extend(self.white.name.clone().map(Tag::White));
+        tags.extend(self.black.name.clone().map(Tag::Black));
+        if self.white.elo.is_some() {
+            tags.push(Tag::WhiteElo(self.white.elo));
+        }
+        if self.black.elo.is_some() {
+            tags.push(Tag::BlackElo(self.black.elo));
+        }
+        tags.extend(self.extra_tags.iter().cloned());
+        tags
+    }
+
+    // stores the tag in its field, replacing the value already there. Tags that describe the game itself, like Result and
+    // FEN, are written from the board and aren't stored, returning false
+    pub fn set_tag(&mut self, tag: Tag) -> bool {
+        match tag {
+            Tag::Event(event) => self.event = Some(event),
+            Tag::Site(site) => self.site = Some(site),
+            Tag::Date(date) => self.date = Some(date),
+            Tag::Round(round) => self.round = Some(round),
+            Tag::White(name) => self.white.name = Some(name),
+            Tag::Black(name) => self.black.name = Some(name),
+            Tag::WhiteElo(elo) => self.white.elo = elo,
+            Tag::BlackElo(elo) => self.black.elo = elo,
+            Tag::Result(_)
+            | Tag::Variant(_)
+            | Tag::SetUp(_)
+            | Tag::FEN(_)
+            | Tag::Termination(_)
+            | Tag::Annotator(_)
+            | Tag::PlyCount(_) => return false,
+            tag => match self
+                .extra_tags
+                .iter_mut()
+                .find(|extra| extra.name() == tag.name())
+            {
+                Some(extra) => *extra = tag,
+                None => self.extra_tags.push(tag),
+            },
+        }
+        true
+    }
+}
+
+// result of Board::make_engine_move. eval is in centipawns from white's perspective (positive is good for white),
+// regardless of which side the engine moved for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineResult {
+    pub game_state: GameState,
+    pub eval: i32,
+}
+
+impl EngineResult {
+    // eval from the perspective of colour, positive is good for colour
+    pub const fn eval_for(&self, colour: PieceColour) -> i32 {
+        white_eval_for(self.eval, colour)
+    }
+}
+
+// converts between a white perspective eval and colour's perspective (the conversion is its own inverse)
+#[inline(always)]
+pub(crate) const fn white_eval_for(eval: i32, colour: PieceColour) -> i32 {
+    match colour {
+        PieceColour::White => eval,
+        PieceColour::Black => -eval,
+    }
+}
+
+pub struct EngineAnalysis {
+    pub board_hash: u64,
+    pub position_hash: u64,
+    pub eval: i32, // white perspective, same as EngineResult
+    pub best_move: Option<Move>,
+    pub best_move_notation: Option<Notation>,
+}
+
+impl fmt::Display for EngineAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[Board hash: {}, Position hash: {}] Best move: {:?}, Notation: {}, Raw eval: {}, Str Eval: {}",
+            util::hash_to_string(self.board_hash),
+            util::hash_to_string(self.position_hash),
+            self.best_move,
+            self.best_move_notation.as_ref().map_or_else(|| "None".into(), |n| n.to_string()),
+            self.eval,
+            util::eval_to_string(self.eval)
+        )
+    }
+}
+
+// mate evals are capped to this many centipawns when working out a move's centipawn loss, so swapping one forced mate for
+// another is no loss but throwing one away (or walking into one) is a blunder
+const ANNOTATION_MATE_CP: i32 = 10_000;
+
+// how a played move compares to the engine's best move, by the centipawns it lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveClassification {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveClassification {
+    pub fn from_centipawn_loss(loss: i32) -> Self {
+        match loss {
+            ..=0 => Self::Best,
+            1..=49 => Self::Good,
+            50..=99 => Self::Inaccuracy,
+            100..=299 => Self::Mistake,
+            _ => Self::Blunder,
+        }
+    }
+}
+
+impl fmt::Display for MoveClassification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let classification_str = match self {
+            Self::Best => "Best",
+            Self::Good => "Good",
+            Self::Inaccuracy => "Inaccuracy",
+            Self::Mistake => "Mistake",
+            Self::Blunder => "Blunder",
+        };
+        write!(f, "{}", classification_str)
+    }
+}
+
+// a position that occurred more than once in a game, see Board::repeated_positions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepeatedPosition {
+    pub position_hash: u64,
+    pub count: u8,
+    // plies of the states the position occurred in, in the order they were played
+    pub plies: Vec<usize>,
+}
+
+// a position to look for with Board::find_plies_with_position, by FEN or by position hash (see BoardState::position_hash)
+#[derive(Debug, Clone)]
+pub enum PositionQuery {
+    Fen(Box<FEN>),
+    Hash(u64),
+}
+
+impl From<FEN> for PositionQuery {
+    fn from(fen: FEN) -> Self {
+        Self::Fen(Box::new(fen))
+    }
+}
+
+impl From<u64> for PositionQuery {
+    fn from(hash: u64) -> Self {
+        Self::Hash(hash)
+    }
+}
+
+// engine review of one move of a game, see Board::annotate_with_engine. Evals are from white's perspective like
+// EngineAnalysis
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlyAnnotation {
+    // index of the move in the move history
+    pub ply: usize,
+    pub mv: Move,
+    pub notation: Notation,
+    pub eval_before: i32,
+    pub best_move: Option<Move>,
+    pub eval_after: i32,
+    pub classification: MoveClassification,
+}
+
+impl PlyAnnotation {
+    // how much worse the position got for the side that moved, never negative
+    pub fn centipawn_loss(&self) -> i32 {
+        let capped = |eval: i32| {
+            if engine::is_eval_checkmate(eval) {
+                eval.signum() * ANNOTATION_MATE_CP
+            } else {
+                eval.clamp(-ANNOTATION_MATE_CP, ANNOTATION_MATE_CP)
+            }
+        };
+        let colour = self.mv.piece.pcolour;
+        let loss = capped(white_eval_for(self.eval_before, colour))
+            - capped(white_eval_for(self.eval_after, colour));
+        loss.max(0)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Board {
     variant: Variant,
-    white_player: PlayerData,
-    black_player: PlayerData,
+    metadata: GameMetadata,
     current_state: BoardState,
     state_history: Vec<BoardState>,
     move_history: Vec<Move>,
+    // notation of each move in move_history, worked out once in make_move
+    notation_history: Vec<Notation>,
     game_over_state: Option<GameOverState>,
-    transposition_table: transposition::TranspositionTable,
+    // locked by engine searches running on their own thread (see Board::start_engine_search) while they use it. Clones
+    // of the board share it
+    transposition_table: Arc<Mutex<transposition::TranspositionTable>>,
     detatched_idx: Option<usize>,
+    // alternative lines branching off this game, see Board::start_variation_from_current
+    variations: Vec<BoardVariation>,
+    // end the game as soon as a draw can be claimed, instead of waiting for claim_draw
+    auto_claim_draws: bool,
+    // None for untimed games, see Board::set_time_control
+    clock: Option<Clock>,
+}
+
+// diagram of current_state, see BoardState's Display
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.current_state)
+    }
+}
+
+// a line branching off a Board's game, played on its own Board that starts with a copy of the game up to branch_ply
+#[derive(Debug, Clone)]
+pub struct BoardVariation {
+    branch_ply: usize,
+    board: Board,
+}
+
+impl BoardVariation {
+    // ply of the mainline state the variation branches from, the variation's first move is an alternative to the
+    // mainline move at this index of its move history
+    pub fn branch_ply(&self) -> usize {
+        self.branch_ply
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn board_mut(&mut self) -> &mut Board {
+        &mut self.board
+    }
+
+    // the moves played in the variation after the branch point, empty if the variation's board has been taken back
+    // to before it
+    pub fn moves(&self) -> &[Move] {
+        self.board
+            .move_history
+            .get(self.branch_ply..)
+            .unwrap_or(&[])
+    }
 }
 
 impl Default for Board {
@@ -503,46 +1597,75 @@ impl Default for Board {
     }
 }
 
-impl From<FEN> for Board {
-    fn from(fen: FEN) -> Self {
-        let current_state = BoardState::from(fen);
-        let state_history: Vec<BoardState> = vec![current_state.clone()];
-        let transposition_table = transposition::TranspositionTable::new();
+impl TryFrom<FEN> for Board {
+    type Error = PositionError;
+
+    fn try_from(fen: FEN) -> Result<Self, Self::Error> {
+        let current_state = BoardState::try_from(fen)?;
+        let state_history: Vec<BoardState> = vec![current_state.for_history()];
+        let transposition_table = Arc::new(Mutex::new(transposition::TranspositionTable::new()));
         // TODO gos
         log::info!("New Board created from FEN: {}", fen.to_string());
-        Board {
-            variant: Variant::FromPosition,
-            white_player: PlayerData::default(),
-            black_player: PlayerData::default(),
+        let variant = if fen.is_chess960() {
+            Variant::Chess960
+        } else {
+            Variant::FromPosition
+        };
+        Ok(Board {
+            variant,
+            metadata: GameMetadata::default(),
             current_state,
             state_history,
             move_history: Vec::new(),
+            notation_history: Vec::new(),
             game_over_state: None,
             transposition_table,
             detatched_idx: None,
-        }
+            variations: Vec::new(),
+            auto_claim_draws: false,
+            clock: None,
+        })
     }
 }
 
 impl TryFrom<pgn::PGN> for Board {
     type Error = PGNParseError;
+    // contradictions in the PGN are logged as warnings, see Board::try_from_pgn_with_warnings to get them
     fn try_from(pgn: pgn::PGN) -> Result<Self, PGNParseError> {
+        let (board, warnings) = Board::try_from_pgn_with_warnings(pgn)?;
+        for warning in warnings {
+            log::warn!("PGN import: {}", warning);
+        }
+        Ok(board)
+    }
+}
+
+impl Board {
+    // the game of a PGN, and the things in it that contradict the game its moves play out. Moves after the game ended
+    // by checkmate or a draw rule aren't played, and its Result tag doesn't replace that ending
+    pub fn try_from_pgn_with_warnings(
+        pgn: pgn::PGN,
+    ) -> Result<(Self, Vec<pgn::ImportWarning>), PGNParseError> {
         let fen_tag = pgn.tags().iter().find(|tag| matches!(tag, Tag::FEN(_)));
-        let variant_tag = pgn.tags().iter().find(|tag| matches!(tag, Tag::Variant(_)));
+        // variants other than the ones supported are ignored and the game is read as standard chess
+        let variant = pgn.tags().iter().find_map(|tag| match tag {
+            Tag::Variant(v) => v.parse::<Variant>().ok(),
+            _ => None,
+        });
         let mut board = match fen_tag {
             Some(Tag::FEN(fen_str)) => {
                 let fen = fen_str.parse::<FEN>();
                 match fen {
                     Ok(fen) => {
-                        // default variant is set to FromPosition
-                        let mut board = Board::from(fen);
-                        if let Some(Tag::Variant(v)) = variant_tag {
-                            // set variant to Chess960 if tag is present, as both FromPosition and Chess960 will have FEN tag
-                            if v == "Chess960" {
-                                board.variant = Variant::Chess960;
+                        // variant is FromPosition unless the castling rights can only be Chess960 ones
+                        match Board::try_from(fen) {
+                            Ok(board) => board,
+                            Err(e) => {
+                                log_and_return_error!(PGNParseError::NotationParseError(
+                                    e.to_string()
+                                ))
                             }
                         }
-                        board
                     }
                     Err(e) => {
                         log_and_return_error!(PGNParseError::NotationParseError(e.to_string()))
@@ -551,45 +1674,22 @@ impl TryFrom<pgn::PGN> for Board {
             }
             _ => Board::new(),
         };
-
-        // unwrap is safe as white and black tags are guarenteed to be present in PGN
-        let white_tag = pgn
-            .tags()
-            .iter()
-            .find(|tag| matches!(tag, Tag::White(_)))
-            .unwrap();
-        let white_elo = pgn
-            .tags()
-            .iter()
-            .find(|tag| matches!(tag, Tag::WhiteElo(_)));
-        let black_tag = pgn
-            .tags()
-            .iter()
-            .find(|tag| matches!(tag, Tag::Black(_)))
-            .unwrap();
-        let black_elo = pgn
-            .tags()
-            .iter()
-            .find(|tag| matches!(tag, Tag::BlackElo(_)));
-
-        if let Tag::White(name) = white_tag {
-            board.white_player.name = Some(name.clone());
+        // both FromPosition and Chess960 games have a FEN tag, and a Chess960 game can start from the standard position
+        if variant == Some(Variant::Chess960) {
+            board.variant = Variant::Chess960;
         }
-        if let Some(Tag::WhiteElo(elo)) = white_elo {
-            if let Ok(elo) = elo.parse::<u16>() {
-                board.white_player.elo = Some(elo);
-            }
-        }
-        if let Tag::Black(name) = black_tag {
-            board.black_player.name = Some(name.clone());
-        }
-        if let Some(Tag::BlackElo(elo)) = black_elo {
-            if let Ok(elo) = elo.parse::<u16>() {
-                board.black_player.elo = Some(elo);
-            }
+
+        // players, event and any other tags are kept for exporting the game again
+        for tag in pgn.tags() {
+            board.metadata.set_tag(tag.clone());
         }
 
-        for notation in pgn.moves() {
+        let mut warnings = Vec::new();
+        for (ply, notation) in pgn.moves().enumerate() {
+            if board.get_game_over_state().is_some() {
+                warnings.push(pgn::ImportWarning::MovesAfterGameOver { ply });
+                break;
+            }
             let mv = notation.to_move_with_context(board.get_current_state())?;
             match board.make_move(&mv) {
                 Ok(_) => {}
@@ -597,18 +1697,52 @@ impl TryFrom<pgn::PGN> for Board {
             }
         }
 
+        let termination = pgn.tags().iter().find_map(|tag| match tag {
+            Tag::Termination(termination) => Some(termination),
+            _ => None,
+        });
         for tag in pgn.tags() {
             if let Tag::Result(result) = tag {
-                match result.as_str() {
+                let side_to_move = board.get_side_to_move();
+                match (result.as_str(), termination) {
                     // these will be ignored if game over state is already set in Board, priority is given to Forced(GameState) FIXME this needs to be clearer
-                    "1-0" => board.set_resign(PieceColour::Black),
-                    "0-1" => board.set_resign(PieceColour::White),
-                    "1/2-1/2" => board.set_draw(),
+                    ("1-0", Some(Termination::TimeForfeit)) if board.game_over_state.is_none() => {
+                        board.game_over_state = Some(GameOverState::Timeout(PieceColour::Black))
+                    }
+                    ("0-1", Some(Termination::TimeForfeit)) if board.game_over_state.is_none() => {
+                        board.game_over_state = Some(GameOverState::Timeout(PieceColour::White))
+                    }
+                    // a drawn time forfeit is the side to move flagging when the other side couldn't have checkmated
+                    ("1/2-1/2", Some(Termination::TimeForfeit))
+                        if board.game_over_state.is_none()
+                            && !board.opponent_can_win_on_time(side_to_move) =>
+                    {
+                        board.game_over_state = Some(GameOverState::Timeout(side_to_move))
+                    }
+                    ("1-0", Some(Termination::Adjudication)) => {
+                        board.adjudicate(GameResult::WhiteWins(ResultReason::Adjudication))
+                    }
+                    ("0-1", Some(Termination::Adjudication)) => {
+                        board.adjudicate(GameResult::BlackWins(ResultReason::Adjudication))
+                    }
+                    ("1/2-1/2", Some(Termination::Adjudication)) => {
+                        board.adjudicate(GameResult::Draw(ResultReason::Adjudication))
+                    }
+                    ("1-0", _) => board.set_resign(PieceColour::Black),
+                    ("0-1", _) => board.set_resign(PieceColour::White),
+                    // a draw that could be claimed at the end of the game was most likely claimed
+                    ("1/2-1/2", _) => match board.current_state.get_gamestate() {
+                        gs if gs.is_claimable_draw() && board.game_over_state.is_none() => {
+                            board.game_over_state = Some(GameOverState::DrawClaimed(gs))
+                        }
+                        _ => board.set_draw(),
+                    },
                     _ => {}
                 }
             }
         }
-        Ok(board)
+        warnings.extend(pgn.verify_result(&board));
+        Ok((board, warnings))
     }
 }
 
@@ -617,21 +1751,24 @@ impl Board {
         let current_state = BoardState::new_starting();
         let mut state_history: Vec<BoardState> = Vec::new();
         log::info!("State history created");
-        state_history.push(current_state.clone());
+        state_history.push(current_state.for_history());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Arc::new(Mutex::new(transposition::TranspositionTable::new()));
         log::info!("Transposition table created");
         log::info!("New Board created");
         Board {
             variant: Variant::Standard,
-            white_player: PlayerData::default(),
-            black_player: PlayerData::default(),
+            metadata: GameMetadata::default(),
             current_state,
             state_history,
             move_history: Vec::new(),
+            notation_history: Vec::new(),
             game_over_state: None,
             transposition_table,
             detatched_idx: None,
+            variations: Vec::new(),
+            auto_claim_draws: false,
+            clock: None,
         }
     }
 
@@ -639,21 +1776,24 @@ impl Board {
         let current_state = BoardState::new_chess960();
         let mut state_history: Vec<BoardState> = Vec::new();
         log::info!("State history created");
-        state_history.push(current_state.clone());
+        state_history.push(current_state.for_history());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Arc::new(Mutex::new(transposition::TranspositionTable::new()));
         log::info!("Transposition table created");
         log::info!("New Chess960 variant Board created");
         Board {
             variant: Variant::Chess960,
-            white_player: PlayerData::default(),
-            black_player: PlayerData::default(),
+            metadata: GameMetadata::default(),
             current_state,
             state_history,
             move_history: Vec::new(),
+            notation_history: Vec::new(),
             game_over_state: None,
             transposition_table,
             detatched_idx: None,
+            variations: Vec::new(),
+            auto_claim_draws: false,
+            clock: None,
         }
     }
 
@@ -661,9 +1801,9 @@ impl Board {
         let current_state = BoardState::new_chess960_from_num(position_number)?;
         let mut state_history: Vec<BoardState> = Vec::new();
         log::info!("State history created");
-        state_history.push(current_state.clone());
+        state_history.push(current_state.for_history());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Arc::new(Mutex::new(transposition::TranspositionTable::new()));
         log::info!("Transposition table created");
         log::info!(
             "New Chess960 variant Board created from position number: {}",
@@ -671,37 +1811,463 @@ impl Board {
         );
         Ok(Board {
             variant: Variant::Chess960,
-            white_player: PlayerData::default(),
-            black_player: PlayerData::default(),
+            metadata: GameMetadata::default(),
             current_state,
             state_history,
             move_history: Vec::new(),
+            notation_history: Vec::new(),
             game_over_state: None,
             transposition_table,
             detatched_idx: None,
+            variations: Vec::new(),
+            auto_claim_draws: false,
+            clock: None,
         })
     }
 
-    pub fn set_resign(&mut self, side: PieceColour) {
-        let gos = match side {
-            PieceColour::White => GameOverState::WhiteResign,
-            PieceColour::Black => GameOverState::BlackResign,
-        };
-        if self.game_over_state.is_none() {
-            self.game_over_state = Some(gos);
-        } else {
-            log::warn!("Game over state already set, ignoring set_resign");
+    /// Board starting from the position in a FEN string, the FEN must describe a position that can be reached in a game:
+    ///
+    /// ```
+    /// use chess::board::Board;
+    ///
+    /// let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    /// assert_eq!(board.get_current_state().get_legal_moves().unwrap().len(), 6);
+    /// // the side not to move can't be in check
+    /// assert!(Board::from_fen("4k3/8/8/8/8/8/4R3/4K3 w - - 0 1").is_err());
+    /// ```
+    pub fn from_fen(fen: &str) -> Result<Self, FenParseError> {
+        let fen = fen.parse::<FEN>()?;
+        Self::try_from(fen).map_err(FenParseError::InvalidPosition)
+    }
+
+    /// Board with a list of moves played from the starting position. Each move can be SAN ("Nf3") or coordinate
+    /// notation ("g1f3"), and the index of a move that can't be played is given in the error:
+    ///
+    /// ```
+    /// use chess::board::Board;
+    ///
+    /// let board = Board::from_moves(&["e4", "e7e5", "Nf3"]).unwrap();
+    /// assert_eq!(board.move_history_string_notation(), ["e4", "e5", "Nf3"]);
+    /// let err = Board::from_moves(&["e4", "e4"]).unwrap_err();
+    /// assert!(err.to_string().starts_with("Error at move 1"));
+    /// ```
+    pub fn from_moves(moves: &[&str]) -> Result<Self, BoardStateError> {
+        Self::new().with_moves(moves)
+    }
+
+    // from_moves starting from a FEN position, see from_fen
+    pub fn from_fen_and_moves(fen: &str, moves: &[&str]) -> Result<Self, BoardStateError> {
+        match Self::from_fen(fen) {
+            Ok(board) => board.with_moves(moves),
+            Err(e) => {
+                let err = BoardStateError::InvalidInput(format!("Invalid FEN {}: {}", fen, e));
+                log_and_return_error!(err)
+            }
+        }
+    }
+
+    fn with_moves(mut self, moves: &[&str]) -> Result<Self, BoardStateError> {
+        for (i, s) in moves.iter().enumerate() {
+            // coordinate notation never looks like SAN, so the format can be told from the string
+            let result = if ShortMove::from_coordinate_notation(s).is_some() {
+                self.make_move_uci(s)
+            } else {
+                self.make_move_san(s)
+            };
+            if let Err(e) = result {
+                let err = BoardStateError::AtMove(i, Box::new(e));
+                log_and_return_error!(err)
+            }
+        }
+        Ok(self)
+    }
+
+    /// FEN string of the current state, which follows checking out earlier states:
+    ///
+    /// ```
+    /// use chess::board::Board;
+    ///
+    /// let mut board = Board::new();
+    /// board.make_move_uci("e2e4").unwrap();
+    /// assert_eq!(
+    ///     board.to_fen(),
+    ///     "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+    /// );
+    /// ```
+    pub fn to_fen(&self) -> String {
+        self.current_state.to_fen()
+    }
+
+    // FEN after ply moves (0 is the starting position) of the whole game, whichever state is checked out. None if fewer
+    // moves have been made
+    pub fn fen_at_ply(&self, ply: usize) -> Option<String> {
+        self.state_history
+            .get(ply)
+            .map(|state| FEN::from(state).to_string())
+    }
+
+    // FEN of every state in the game, from the starting position to the latest move
+    pub fn fen_history(&self) -> Vec<String> {
+        self.state_history
+            .iter()
+            .map(|state| FEN::from(state).to_string())
+            .collect()
+    }
+
+    // writes the game in the native save format (see save.rs): the starting FEN, players and every move made, whichever
+    // state is checked out
+    pub fn save(&self, mut w: impl Write) -> Result<(), SaveGameError> {
+        let tags = self
+            .metadata
+            .tags()
+            .iter()
+            .map(|tag| (tag.name().to_string(), tag.value()))
+            .collect();
+        SavedGame {
+            variant: self.variant,
+            starting_fen: self.get_starting_state().to_fen(),
+            tags,
+            moves: self.move_history.iter().map(Move::short_move).collect(),
+            game_over_state: self.game_over_state,
+        }
+        .write(&mut w)
+    }
+
+    // reads a game written by save, replaying its moves so every state is rebuilt as if they were just made
+    pub fn load(mut r: impl Read) -> Result<Self, SaveGameError> {
+        let saved = SavedGame::read(&mut r)?;
+        let mut board = match Self::from_fen(&saved.starting_fen) {
+            Ok(board) => board,
+            Err(e) => log_and_return_error!(SaveGameError::InvalidFormat(e.to_string())),
+        };
+        board.variant = saved.variant;
+        // tags with values this version can't read are skipped
+        for (name, value) in saved.tags {
+            if let Ok(tag) = Tag::from_name_value(&name, &value) {
+                board.metadata.set_tag(tag);
+            }
+        }
+
+        for (ply, short_mv) in saved.moves.iter().enumerate() {
+            let Some(mv) = board.current_state.expand_short_move(*short_mv) else {
+                log_and_return_error!(SaveGameError::IllegalMove(format!(
+                    "{} at ply {}",
+                    short_mv,
+                    ply + 1
+                )))
+            };
+            if let Err(e) = board.make_move(&mv) {
+                log_and_return_error!(SaveGameError::IllegalMove(format!(
+                    "{} at ply {}: {}",
+                    short_mv,
+                    ply + 1,
+                    e
+                )))
+            }
+        }
+
+        // forced game overs are found again by replaying, anything else recorded has to agree with them
+        match (board.game_over_state, saved.game_over_state) {
+            (replayed, recorded) if replayed == recorded => {}
+            (None, Some(GameOverState::Forced(_))) | (Some(_), _) => {
+                log_and_return_error!(SaveGameError::InvalidFormat(format!(
+                    "Recorded game over state {:?} does not match the replayed game's {:?}",
+                    saved.game_over_state, board.game_over_state
+                )))
+            }
+            (None, recorded) => board.game_over_state = recorded,
+        }
+        Ok(board)
+    }
+
+    // sets the size of the engine's transposition table, e.g. Board::new().with_tt_size_mb(64)
+    pub fn with_tt_size_mb(mut self, size_mb: usize) -> Self {
+        self.set_tt_size_mb(size_mb);
+        self
+    }
+
+    // resizing clears all transposition table entries. A size of 0 disables the transposition table
+    pub fn set_tt_size_mb(&mut self, size_mb: usize) {
+        *self.tt() = transposition::TranspositionTable::with_capacity_mb(size_mb);
+        log::info!("Transposition table resized to {}MB", size_mb);
+    }
+
+    // use an existing (possibly warm) transposition table, e.g. one taken from the previous game's Board
+    pub fn with_transposition_table(self, tt: transposition::TranspositionTable) -> Self {
+        *self.tt() = tt;
+        self
+    }
+
+    // removes the transposition table, leaving an empty one of the same size in its place
+    pub fn take_transposition_table(&mut self) -> transposition::TranspositionTable {
+        let mut tt = self.tt();
+        let empty = transposition::TranspositionTable::with_capacity_mb(tt.size_mb());
+        std::mem::replace(&mut tt, empty)
+    }
+
+    // the lock is only held by an engine search while it takes the table out and puts it back. A search that panicked
+    // can't have left the table in a state that matters, the entries are only a cache
+    fn tt(&self) -> MutexGuard<'_, transposition::TranspositionTable> {
+        self.transposition_table
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // removes all entries but keeps the table allocated
+    pub fn clear_transposition_table(&mut self) {
+        self.tt().clear();
+        log::info!("Transposition table cleared");
+    }
+
+    pub fn set_resign(&mut self, side: PieceColour) {
+        let gos = match side {
+            PieceColour::White => GameOverState::WhiteResign,
+            PieceColour::Black => GameOverState::BlackResign,
+        };
+        if self.game_over_state.is_none() {
+            self.end_game(gos);
+        } else {
+            log::warn!("Game over state already set, ignoring set_resign");
         }
     }
 
     pub fn set_draw(&mut self) {
         if self.game_over_state.is_none() {
-            self.game_over_state = Some(GameOverState::AgreedDraw);
+            self.end_game(GameOverState::AgreedDraw);
         } else {
             log::warn!("Game over state already set, ignoring set_draw");
         }
     }
 
+    pub fn adjudicate(&mut self, result: GameResult) {
+        if self.game_over_state.is_none() {
+            self.end_game(GameOverState::Adjudication(result));
+        } else {
+            log::warn!("Game over state already set, ignoring adjudicate");
+        }
+    }
+
+    // sets the game over state and stops the clock
+    fn end_game(&mut self, gos: GameOverState) {
+        self.game_over_state = Some(gos);
+        if let Some(clock) = &mut self.clock {
+            clock.stop();
+        }
+    }
+
+    // claim a draw for the side to move by threefold repetition or the fifty move rule, which unlike fivefold
+    // repetition and the seventy-five move rule don't end the game by themselves
+    pub fn claim_draw(&mut self) -> Result<(), BoardStateError> {
+        self.check_can_claim_draw()?;
+        let game_state = self.current_state.get_gamestate();
+        if !game_state.is_claimable_draw() {
+            let err = BoardStateError::DrawNotClaimable(game_state);
+            log_and_return_error!(err)
+        }
+        self.end_game(GameOverState::DrawClaimed(game_state));
+        log::info!("Draw claimed: {}", game_state);
+        Ok(())
+    }
+
+    // claim a draw by playing mv, which has to reach a position where a draw can be claimed. The move isn't made if the
+    // claim is wrong
+    pub fn claim_draw_with_move(&mut self, mv: &Move) -> Result<(), BoardStateError> {
+        self.check_can_claim_draw()?;
+        let next_state = self.current_state.next_state(mv)?;
+        let game_state = next_state.get_gamestate();
+        if !game_state.is_claimable_draw() {
+            let err = BoardStateError::DrawNotClaimable(game_state);
+            log_and_return_error!(err)
+        }
+        self.push_state(mv, next_state);
+        // with auto_claim_draws the move has already ended the game
+        if self.game_over_state.is_none() {
+            self.end_game(GameOverState::DrawClaimed(game_state));
+        }
+        log::info!("Draw claimed with move {:?}: {}", mv, game_state);
+        Ok(())
+    }
+
+    fn check_can_claim_draw(&self) -> Result<(), BoardStateError> {
+        if let Some(idx) = self.detatched_idx {
+            let err = BoardStateError::Detatched(format!(
+                "Detatched from current boardstate at index {}. Cannot claim draw",
+                idx
+            ));
+            log_and_return_error!(err)
+        }
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        Ok(())
+    }
+
+    // claimable draws end the game as soon as they happen, the way every draw used to be handled
+    pub fn with_auto_claim_draws(mut self, auto_claim_draws: bool) -> Self {
+        self.auto_claim_draws = auto_claim_draws;
+        self
+    }
+
+    pub fn auto_claim_draws(&self) -> bool {
+        self.auto_claim_draws
+    }
+
+    // number of times the position after ply moves had occurred in the game by then, whichever state is checked out.
+    // None if fewer moves have been made
+    pub fn repetition_count_at_ply(&self, ply: usize) -> Option<u8> {
+        self.state_history
+            .get(ply)
+            .map(|state| state.get_occurences_of_current_position())
+    }
+
+    // whether the side to move can claim a draw by threefold repetition, either as the current position has occurred
+    // three times or by playing a move that repeats a position for the third time (see claim_draw_with_move)
+    pub fn is_threefold_claimable(&self) -> bool {
+        if self.is_detatched() || self.game_over_state.is_some() {
+            return false;
+        }
+        self.current_state.get_occurences_of_current_position() >= 3
+            || self
+                .current_state
+                .lazy_get_legal_moves()
+                .any(|mv| matches!(self.current_state.would_repeat(mv), Some(3 | 4)))
+    }
+
+    // every position that occurred more than once in the game, whichever state is checked out, in the order of their
+    // first occurrence
+    pub fn repeated_positions(&self) -> Vec<RepeatedPosition> {
+        let mut positions: Vec<RepeatedPosition> = Vec::new();
+        // index in positions of each position hash
+        let mut indexes: ahash::AHashMap<PositionHash, usize> = ahash::AHashMap::default();
+        for (ply, state) in self.state_history.iter().enumerate() {
+            let idx = *indexes.entry(state.position_hash).or_insert_with(|| {
+                positions.push(RepeatedPosition {
+                    position_hash: state.position_hash,
+                    count: 0,
+                    plies: Vec::new(),
+                });
+                positions.len() - 1
+            });
+            let position = &mut positions[idx];
+            position.count = position.count.saturating_add(1);
+            position.plies.push(ply);
+        }
+        positions.retain(|position| position.count >= 2);
+        positions
+    }
+
+    // every ply (0 is the starting position) of the game whose position matches, whichever state is checked out. More
+    // than one for a repetition or a transposition back to an earlier position. A FEN matches on its pieces, side to
+    // move, castling rights and en passant square but not its move counters
+    pub fn find_plies_with_position(&self, position: impl Into<PositionQuery>) -> Vec<usize> {
+        let position_hash = match position.into() {
+            PositionQuery::Hash(hash) => hash,
+            PositionQuery::Fen(fen) => match Position::try_from(*fen) {
+                Ok(pos) => zobrist::pos_hash(&pos),
+                // FEN parsing already checks the position is valid, and an invalid one can't be in the game anyway
+                Err(e) => {
+                    log::warn!("FEN {} is not a valid position: {}", fen, e);
+                    return Vec::new();
+                }
+            },
+        };
+        self.state_history
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.position_hash == position_hash)
+            .map(|(ply, _)| ply)
+            .collect()
+    }
+
+    // times the game with base time for each side and increment added after each of their moves, replacing any clock
+    // already set. Time doesn't run until start_clock
+    pub fn set_time_control(&mut self, base: Duration, increment: Duration) {
+        self.clock = Some(Clock::new(base, increment));
+        log::info!(
+            "Time control set: {}s + {}s",
+            base.as_secs_f64(),
+            increment.as_secs_f64()
+        );
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    // starts the side to move's time. While the clock runs, make_move charges the side moving for its turn, adds its
+    // increment and starts the other side's time
+    pub fn start_clock(&mut self) -> Result<(), BoardStateError> {
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        // the game's side to move, even if an earlier state is checked out
+        let side_to_move = self.state_history.last().unwrap().side_to_move;
+        let clock = self.clock_mut()?;
+        clock.start(side_to_move);
+        Ok(())
+    }
+
+    // pauses the clock until start_clock is called again
+    pub fn stop_clock(&mut self) -> Result<(), BoardStateError> {
+        self.clock_mut()?.stop();
+        Ok(())
+    }
+
+    // charges side with time kept outside of the board, e.g. by a game server, returning its remaining time. The game
+    // is lost on time if it runs out
+    pub fn apply_elapsed(
+        &mut self,
+        side: PieceColour,
+        elapsed: Duration,
+    ) -> Result<Duration, BoardStateError> {
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        let remaining = self.clock_mut()?.apply_elapsed(side, elapsed);
+        if remaining.is_zero() {
+            self.flag(side);
+        }
+        Ok(remaining)
+    }
+
+    // time left for side, None for untimed games
+    pub fn remaining(&self, side: PieceColour) -> Option<Duration> {
+        self.clock.as_ref().map(|clock| clock.remaining(side))
+    }
+
+    // ends the game if the side whose time is running has run out, returning the game over state. make_move checks
+    // this before every move, call it to notice a flag while waiting for one
+    pub fn check_flag(&mut self) -> Option<GameOverState> {
+        if self.game_over_state.is_some() {
+            return None;
+        }
+        let clock = self.clock.as_ref()?;
+        let side = clock.running_side()?;
+        if !clock.is_flagged(side) {
+            return None;
+        }
+        self.flag(side);
+        self.game_over_state
+    }
+
+    fn flag(&mut self, side: PieceColour) {
+        log::info!("{} ran out of time", side);
+        self.end_game(GameOverState::Timeout(side));
+    }
+
+    fn clock_mut(&mut self) -> Result<&mut Clock, BoardStateError> {
+        match &mut self.clock {
+            Some(clock) => Ok(clock),
+            None => {
+                let err = BoardStateError::NotFound("No time control set".to_string());
+                log_and_return_error!(err)
+            }
+        }
+    }
+
     pub fn get_starting_state(&self) -> &BoardState {
         // first element in state_history is guarenteed to be initialised as starting BoardState
         &self.state_history[0]
@@ -727,6 +2293,103 @@ impl Board {
         &self.state_history
     }
 
+    // how the game ended for the PGN Termination tag, Unterminated while it's still being played
+    pub fn termination(&self) -> Termination {
+        match self.get_game_over_state() {
+            Some(GameOverState::Timeout(_)) => Termination::TimeForfeit,
+            Some(GameOverState::Adjudication(_)) => Termination::Adjudication,
+            Some(_) => Termination::Normal,
+            None => Termination::Unterminated,
+        }
+    }
+
+    // why the game ended, for displaying to the user. None while the game is still being played
+    pub fn termination_reason(&self) -> Option<&'static str> {
+        let reason = match self.get_game_over_state()? {
+            GameOverState::WhiteResign => "White resigned",
+            GameOverState::BlackResign => "Black resigned",
+            GameOverState::AgreedDraw => "Draw agreed",
+            // the side to move is the one that was checkmated
+            GameOverState::Forced(GameState::Checkmate) => match self.get_side_to_move() {
+                PieceColour::White => "Black wins by checkmate",
+                PieceColour::Black => "White wins by checkmate",
+            },
+            GameOverState::Forced(GameState::Stalemate) => "Draw by stalemate",
+            GameOverState::Forced(GameState::Repetition) => "Draw by repetition",
+            GameOverState::Forced(GameState::FiftyMove) => "Draw by the fifty move rule",
+            GameOverState::Forced(GameState::InsufficientMaterial) => {
+                "Draw by insufficient material"
+            }
+            GameOverState::Forced(GameState::FivefoldRepetition) => "Draw by fivefold repetition",
+            GameOverState::Forced(GameState::SeventyFiveMove) => {
+                "Draw by the seventy-five move rule"
+            }
+            GameOverState::DrawClaimed(GameState::FiftyMove) => {
+                "Draw claimed by the fifty move rule"
+            }
+            GameOverState::DrawClaimed(_) => "Draw claimed by threefold repetition",
+            GameOverState::Timeout(side) => match (side, self.opponent_can_win_on_time(side)) {
+                (_, false) => "Draw by timeout vs insufficient material",
+                (PieceColour::White, true) => "Black wins on time",
+                (PieceColour::Black, true) => "White wins on time",
+            },
+            GameOverState::Adjudication(result) => match result {
+                GameResult::WhiteWins(_) => "White wins by adjudication",
+                GameResult::BlackWins(_) => "Black wins by adjudication",
+                GameResult::Draw(_) => "Draw by adjudication",
+            },
+            GameOverState::Forced(GameState::Check | GameState::Active) => return None,
+        };
+        Some(reason)
+    }
+
+    // None while the game is ongoing or the board is detatched
+    pub fn result(&self) -> Option<GameResult> {
+        let result = match self.get_game_over_state()? {
+            GameOverState::WhiteResign => GameResult::BlackWins(ResultReason::Resignation),
+            GameOverState::BlackResign => GameResult::WhiteWins(ResultReason::Resignation),
+            GameOverState::AgreedDraw => GameResult::Draw(ResultReason::Agreement),
+            GameOverState::Timeout(side) => match (side, self.opponent_can_win_on_time(side)) {
+                (_, false) => GameResult::Draw(ResultReason::TimeoutVsInsufficientMaterial),
+                (PieceColour::White, true) => GameResult::BlackWins(ResultReason::Timeout),
+                (PieceColour::Black, true) => GameResult::WhiteWins(ResultReason::Timeout),
+            },
+            GameOverState::Adjudication(result) => result,
+            // the side to move is the one that was checkmated
+            GameOverState::Forced(GameState::Checkmate) => match self.get_side_to_move() {
+                PieceColour::White => GameResult::BlackWins(ResultReason::Checkmate),
+                PieceColour::Black => GameResult::WhiteWins(ResultReason::Checkmate),
+            },
+            GameOverState::Forced(gs) | GameOverState::DrawClaimed(gs) => {
+                let reason = match gs {
+                    GameState::Stalemate => ResultReason::Stalemate,
+                    GameState::InsufficientMaterial => ResultReason::InsufficientMaterial,
+                    GameState::Repetition => ResultReason::Repetition,
+                    GameState::FiftyMove => ResultReason::FiftyMove,
+                    GameState::FivefoldRepetition => ResultReason::FivefoldRepetition,
+                    GameState::SeventyFiveMove => ResultReason::SeventyFiveMove,
+                    GameState::Checkmate | GameState::Check | GameState::Active => return None,
+                };
+                GameResult::Draw(reason)
+            }
+        };
+        Some(result)
+    }
+
+    pub fn winner(&self) -> Option<PieceColour> {
+        self.result()?.winner()
+    }
+
+    // whether the opponent of the side that ran out of time could have checkmated, otherwise the timeout is a draw
+    fn opponent_can_win_on_time(&self, flagged: PieceColour) -> bool {
+        self.current_state.has_mating_material(!flagged)
+    }
+
+    // PGN result string, "*" while the game is ongoing
+    pub fn result_string(&self) -> &'static str {
+        self.result().map_or("*", |result| result.as_str())
+    }
+
     pub fn get_game_over_state(&self) -> Option<GameOverState> {
         if self.is_detatched() {
             return None;
@@ -735,12 +2398,59 @@ impl Board {
         }
     }
 
+    // uniformly random legal move in the current state, None if the game is over or the board is detatched
+    pub fn random_legal_move(&self, rng: &mut impl Rng) -> Option<Move> {
+        if self.is_detatched() || self.game_over_state.is_some() {
+            return None;
+        }
+        self.current_state.random_legal_move(rng)
+    }
+
+    // plays the game on from the current state with each side's moves chosen by its player, until it's over or
+    // max_plies more moves have been made, when it's adjudicated a draw. A move that isn't legal is returned as an error,
+    // leaving the game as it was before that move
+    pub fn play_game(
+        &mut self,
+        white: &mut dyn Player,
+        black: &mut dyn Player,
+        max_plies: usize,
+    ) -> Result<GameOverState, BoardStateError> {
+        for _ in 0..max_plies {
+            if self.game_over_state.is_some() {
+                break;
+            }
+            let mv = match self.get_side_to_move() {
+                PieceColour::White => white.get_move(&self.current_state),
+                PieceColour::Black => black.get_move(&self.current_state),
+            };
+            self.make_move(&mv)?;
+        }
+        if self.game_over_state.is_none() {
+            log::info!("No result after {} plies, adjudicating a draw", max_plies);
+            self.adjudicate(GameResult::Draw(ResultReason::Adjudication));
+        }
+        // set above if it wasn't already
+        Ok(self.game_over_state.unwrap())
+    }
+
     pub fn get_white_player(&self) -> &PlayerData {
-        &self.white_player
+        &self.metadata.white
     }
 
     pub fn get_black_player(&self) -> &PlayerData {
-        &self.black_player
+        &self.metadata.black
+    }
+
+    pub fn metadata(&self) -> &GameMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut GameMetadata {
+        &mut self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: GameMetadata) {
+        self.metadata = metadata;
     }
 
     pub fn variant(&self) -> Variant {
@@ -763,97 +2473,477 @@ impl Board {
             ));
             log_and_return_error!(err)
         }
-        if let Some(gos) = self.game_over_state {
+        if let Some(gos) = self.game_over_state.or_else(|| self.check_flag()) {
             let err = BoardStateError::GameOver(gos);
             log_and_return_error!(err)
         }
         let next_state = self.current_state.next_state(mv)?;
+        Ok(self.push_state(mv, next_state))
+    }
+
+    // make_move that also works when detatched, by discarding every move after the checked out state and playing mv
+    // from there instead. A forced game over or claimed draw from the discarded moves is cleared, resignations and
+    // agreed draws stand
+    pub fn make_move_from_here(&mut self, mv: &Move) -> Result<GameState, BoardStateError> {
+        let Some(idx) = self.detatched_idx else {
+            return self.make_move(mv);
+        };
+        if let Some(gos) = self.game_over_state {
+            if !gos.is_from_position() {
+                let err = BoardStateError::GameOver(gos);
+                log_and_return_error!(err)
+            }
+        }
+        // nothing is discarded if mv is illegal
+        let next_state = self.current_state.next_state(mv)?;
+        log::info!(
+            "Discarding {} moves after index {}",
+            self.move_history.len() - idx,
+            idx
+        );
+        // the same as undoing the discarded moves, so their increments are taken back
+        if let Some(clock) = &mut self.clock {
+            for (ply, mv) in self.move_history.iter().enumerate().skip(idx).rev() {
+                clock.remove_increment(mv.piece.pcolour, ply + 1);
+            }
+        }
+        self.state_history.truncate(idx + 1);
+        self.move_history.truncate(idx);
+        self.notation_history.truncate(idx);
+        self.game_over_state = None;
+        self.detatched_idx = None;
+        self.drop_orphaned_variations();
+        Ok(self.push_state(mv, next_state))
+    }
+
+    // starts a new variation from current_state (the checked out state if detatched) and returns its board. Moves made
+    // on it don't touch this game, and the copied states keep their repetition counts so draws within the variation
+    // are still found
+    pub fn start_variation_from_current(&mut self) -> &mut Board {
+        let ply = self.detatched_idx.unwrap_or(self.move_history.len());
+        let game_state = self.current_state.get_gamestate();
+        let board = Board {
+            variant: self.variant,
+            metadata: self.metadata.clone(),
+            current_state: self.current_state.clone(),
+            state_history: self.state_history[..=ply].to_vec(),
+            move_history: self.move_history[..ply].to_vec(),
+            notation_history: self.notation_history[..ply].to_vec(),
+            game_over_state: self.forced_game_over_state(game_state),
+            // the engine's table isn't shared, a variation is usually only a few moves of analysis
+            transposition_table: Arc::new(Mutex::new(transposition::TranspositionTable::new())),
+            detatched_idx: None,
+            variations: Vec::new(),
+            auto_claim_draws: self.auto_claim_draws,
+            clock: None,
+        };
+        log::info!("Variation started at ply {}", ply);
+        self.variations.push(BoardVariation {
+            branch_ply: ply,
+            board,
+        });
+        &mut self.variations.last_mut().unwrap().board
+    }
+
+    pub fn variations(&self) -> &[BoardVariation] {
+        &self.variations
+    }
+
+    pub fn variation_mut(&mut self, idx: usize) -> Option<&mut Board> {
+        self.variations.get_mut(idx).map(BoardVariation::board_mut)
+    }
+
+    pub fn remove_variation(&mut self, idx: usize) -> Option<BoardVariation> {
+        (idx < self.variations.len()).then(|| self.variations.remove(idx))
+    }
+
+    // variations branching from a state that is no longer in the game are dropped
+    fn drop_orphaned_variations(&mut self) {
+        let plies = self.move_history.len();
+        self.variations.retain(|v| v.branch_ply <= plies);
+    }
+
+    // adds next_state, reached by playing the legal move mv from current_state, to the game
+    fn push_state(&mut self, mv: &Move, next_state: BoardState) -> GameState {
+        // move is legal, so unwrap is safe
+        let notation =
+            Notation::from_mv_with_next_state(&self.current_state, mv, &next_state).unwrap();
         self.current_state = next_state;
-        self.state_history.push(self.current_state.clone());
+        self.state_history.push(self.current_state.for_history());
         self.move_history.push(*mv);
+        self.notation_history.push(notation);
 
         let game_state = self.current_state.get_gamestate();
-        if game_state.is_game_over() {
-            self.game_over_state = Some(GameOverState::Forced(game_state));
+        if let Some(gos) = self.forced_game_over_state(game_state) {
+            self.end_game(gos);
+        } else if let Some(clock) = self.clock.as_mut().filter(|clock| clock.is_running()) {
+            clock.start(self.current_state.side_to_move);
+            clock.add_increment(mv.piece.pcolour, self.move_history.len());
         }
         log::info!("Move made: {:?}", mv);
-        Ok(game_state)
+        game_state
+    }
+
+    // the game over state game_state ends the game with on its own, claimable draws only do if they are claimed
+    // automatically
+    fn forced_game_over_state(&self, game_state: GameState) -> Option<GameOverState> {
+        if game_state.is_game_over() || (self.auto_claim_draws && game_state.is_claimable_draw()) {
+            Some(GameOverState::Forced(game_state))
+        } else {
+            None
+        }
     }
 
-    pub fn make_engine_move(&mut self, depth: u8) -> Result<(GameState, i32), BoardStateError> {
+    // takes back the last move made, returning it. A forced game over (checkmate, stalemate, etc) caused by the move or a
+    // claimed draw is cleared, but resignations and agreed draws stand as they weren't decided by the moves
+    pub fn undo_move(&mut self) -> Result<Move, BoardStateError> {
         if let Some(idx) = self.detatched_idx {
             let err = BoardStateError::Detatched(format!(
-                "Detatched from current boardstate at index {}. Cannot make engine move",
+                "Detatched from current boardstate at index {}. Cannot undo move",
                 idx
             ));
             log_and_return_error!(err)
         }
-        if let Some(gos) = self.game_over_state {
-            let err = BoardStateError::GameOver(gos);
+        let ply = self.move_history.len();
+        let Some(mv) = self.move_history.pop() else {
+            let err = BoardStateError::NotFound("No moves to undo".to_string());
             log_and_return_error!(err)
+        };
+        self.state_history.pop();
+        self.notation_history.pop();
+        // state_history always holds the starting state, so there is a previous state whenever there was a move
+        self.current_state = self.state_history.last().unwrap().clone();
+        if self
+            .game_over_state
+            .is_some_and(|gos| gos.is_from_position())
+        {
+            self.game_over_state = None;
         }
-        let (eval, engine_move) =
-            engine::choose_move(&self.current_state, depth, &mut self.transposition_table);
-        let mv = *engine_move;
-        match self.make_move(&mv) {
-            Ok(gs) => Ok((gs, eval)),
-            Err(e) => Err(e),
+        // the time spent on the move isn't given back, only the turn is. The increment for it is taken back though
+        if let Some(clock) = &mut self.clock {
+            clock.remove_increment(mv.piece.pcolour, ply);
+            if clock.is_running() {
+                clock.start(self.current_state.side_to_move);
+            }
         }
+        self.drop_orphaned_variations();
+        log::info!("Move undone: {:?}", mv);
+        Ok(mv)
     }
 
-    // analyse current_state and return analysis struct
-    pub fn engine_analyse(&mut self, depth: u8) -> EngineAnalysis {
-        let (eval, mv) =
-            engine::choose_move(&self.current_state, depth, &mut self.transposition_table);
-        EngineAnalysis {
-            board_hash: self.current_state.board_hash,
-            position_hash: self.current_state.position_hash,
-            eval,
-            best_move: if mv != &NULL_MOVE { Some(*mv) } else { None },
-            best_move_notation: if mv != &NULL_MOVE {
-                // should be guaranteed to be Some if we get here. if not, it will fail silently by returning None
-                self.get_move_notation(mv).ok()
-            } else {
-                None
-            },
+    // undo_move n times, returning the moves taken back in the order they were played. Nothing is undone if there
+    // are fewer than n moves
+    pub fn undo_moves(&mut self, n: usize) -> Result<Vec<Move>, BoardStateError> {
+        if n > self.move_history.len() {
+            let err = BoardStateError::NotFound(format!(
+                "Cannot undo {} moves, only {} have been made",
+                n,
+                self.move_history.len()
+            ));
+            log_and_return_error!(err)
+        }
+        let mut undone = Vec::with_capacity(n);
+        for _ in 0..n {
+            undone.push(self.undo_move()?);
         }
+        undone.reverse();
+        Ok(undone)
     }
 
-    pub fn get_move_notation(&self, mv: &Move) -> Result<Notation, PGNParseError> {
-        // mv needs to be a legal move for current_state. If not PGNParseError is returned
-        Notation::from_mv_with_context(&self.current_state, mv)
+    // make_move for coordinate notation like "e2e4", see BoardState::find_move_uci
+    pub fn make_move_uci(&mut self, s: &str) -> Result<GameState, BoardStateError> {
+        let Some(short_mv) = ShortMove::from_coordinate_notation(s) else {
+            let err = BoardStateError::InvalidInput(format!("Malformed UCI move: {}", s));
+            log_and_return_error!(err)
+        };
+        let Some(mv) = self.current_state.find_move_short(short_mv) else {
+            let err = BoardStateError::IllegalMove(format!("{} is not a legal move", s));
+            log_and_return_error!(err)
+        };
+        self.make_move(&mv)
     }
 
-    pub fn move_history_string_notation(&self) -> Vec<String> {
-        let mut notations_string = Vec::new();
-        let notations = self.move_history_notation();
-        for n in notations {
-            notations_string.push(n.to_string());
-        }
-        notations_string
+    // make_move for standard algebraic notation like "Nf3", the notation must be exact (see Notation::from_str)
+    pub fn make_move_san(&mut self, san: &str) -> Result<GameState, BoardStateError> {
+        let notation = match san.parse::<Notation>() {
+            Ok(n) => n,
+            Err(e) => {
+                let err =
+                    BoardStateError::InvalidInput(format!("Malformed SAN move {}: {}", san, e));
+                log_and_return_error!(err)
+            }
+        };
+        let mv = match notation.to_move_with_context(&self.current_state) {
+            Ok(mv) => mv,
+            Err(PGNParseError::AmbiguousMove(s)) => {
+                let err = BoardStateError::AmbiguousMove(s);
+                log_and_return_error!(err)
+            }
+            Err(_) => {
+                let err = BoardStateError::IllegalMove(format!("{} is not a legal move", san));
+                log_and_return_error!(err)
+            }
+        };
+        self.make_move(&mv)
     }
 
-    pub fn move_history_notation(&self) -> Vec<Notation> {
-        let mut notations = Vec::new();
-        for (state, mv) in self.state_history.iter().zip(self.move_history.iter()) {
-            // move will all be legal, so unwrap is safe
-            let notation = Notation::from_mv_with_context(state, mv).unwrap();
-            notations.push(notation);
+    // make_move_san for each move in turn, returning the GameState after the last one. If any move fails the moves
+    // already made by this call are taken back, so the board is unchanged
+    pub fn make_moves_san(&mut self, sans: &[&str]) -> Result<GameState, BoardStateError> {
+        let mut game_state = self.current_state.get_gamestate();
+        for (i, san) in sans.iter().enumerate() {
+            match self.make_move_san(san) {
+                Ok(gs) => game_state = gs,
+                Err(e) => {
+                    // the moves before i were made from an attached board, so they can be undone
+                    self.undo_moves(i)?;
+                    return Err(e);
+                }
+            }
         }
-        notations
+        Ok(game_state)
     }
 
-    pub fn last_move_notation(&self) -> Option<Notation> {
+    // eval in the returned EngineResult is from white's perspective, see EngineResult::eval_for for other perspectives
+    pub fn make_engine_move(&mut self, depth: u8) -> Result<EngineResult, BoardStateError> {
+        self.make_engine_move_with_options(engine::EngineOptions::new(depth))
+    }
+
+    // make_engine_move with a limited engine strength and/or a fixed seed for the move choice, see EngineOptions
+    pub fn make_engine_move_with_options(
+        &mut self,
+        options: engine::EngineOptions,
+    ) -> Result<EngineResult, BoardStateError> {
         if let Some(idx) = self.detatched_idx {
-            if idx == 0 {
-                return None;
-            } else {
-                return Some(self.move_history_notation()[idx - 1].clone());
+            let err = BoardStateError::Detatched(format!(
+                "Detatched from current boardstate at index {}. Cannot make engine move",
+                idx
+            ));
+            log_and_return_error!(err)
+        }
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        let side = self.current_state.side_to_move;
+        let result = {
+            let mut tt = self.tt();
+            tt.new_search();
+            engine::choose_move_with_options(&self.current_state, &options, &mut tt)
+        };
+        // game_over_state is always set when current_state is game over, so this should be unreachable
+        let Some(mv) = result.best_move else {
+            let err = BoardStateError::NoLegalMoves(self.current_state.get_gamestate());
+            log_and_return_error!(err)
+        };
+        match self.make_move(&mv) {
+            Ok(game_state) => Ok(EngineResult {
+                game_state,
+                // choose_move evals are relative to the side that moved
+                eval: white_eval_for(result.eval, side),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    // searches current_state on another thread, so the board can still be read (e.g. drawn by a GUI) while the engine
+    // thinks. The search works on a copy of current_state and takes the transposition table out, leaving an empty one
+    // of the same size in its place. Other engine calls on this board don't wait for the search, they use the empty
+    // table, and whatever they put in it is dropped when the search puts its table back. That also overwrites a table
+    // set with set_tt_size_mb or with_transposition_table during the search. Make the move with apply_engine_result
+    pub fn start_engine_search(
+        &self,
+        limits: search::SearchLimits,
+    ) -> Result<search::SearchHandle, BoardStateError> {
+        if let Some(idx) = self.detatched_idx {
+            let err = BoardStateError::Detatched(format!(
+                "Detatched from current boardstate at index {}. Cannot start engine search",
+                idx
+            ));
+            log_and_return_error!(err)
+        }
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        Ok(search::SearchHandle::spawn(
+            self.current_state.clone(),
+            limits,
+            Arc::clone(&self.transposition_table),
+        ))
+    }
+
+    // waits for the search to finish and makes its best move. Fails if the board has moved on from the searched state
+    pub fn apply_engine_result(
+        &mut self,
+        mut handle: search::SearchHandle,
+    ) -> Result<EngineResult, BoardStateError> {
+        let result = handle.join();
+        if self.detatched_idx.is_some() || self.current_state.board_hash != handle.board_hash {
+            let err = BoardStateError::InvalidInput(
+                "Engine search result is for a different state than the current one".to_string(),
+            );
+            log_and_return_error!(err)
+        }
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        let side = self.current_state.side_to_move;
+        let Some(mv) = result.best_move else {
+            let err = BoardStateError::NoLegalMoves(self.current_state.get_gamestate());
+            log_and_return_error!(err)
+        };
+        let game_state = self.make_move(&mv)?;
+        Ok(EngineResult {
+            game_state,
+            eval: white_eval_for(result.eval, side),
+        })
+    }
+
+    // forced checkmate for the side to move in the current state within max_ply plies, see engine::find_mate. Uses its
+    // own transposition table, as mate search entries aren't compatible with the engine's
+    pub fn find_mate(&self, max_ply: u8) -> Option<Vec<Move>> {
+        let mut tt = transposition::TranspositionTable::with_capacity_mb(MATE_SEARCH_TT_SIZE_MB);
+        engine::find_mate(&self.current_state, max_ply, &mut tt)
+    }
+
+    // analyse current_state and return analysis struct
+    pub fn engine_analyse(&mut self, depth: u8) -> EngineAnalysis {
+        let result = {
+            let mut tt = self.tt();
+            tt.new_search();
+            engine::choose_move(&self.current_state, depth, &mut tt)
+        };
+        EngineAnalysis {
+            board_hash: self.current_state.board_hash,
+            position_hash: self.current_state.position_hash,
+            eval: white_eval_for(result.eval, self.current_state.side_to_move),
+            best_move: result.best_move,
+            // should be guaranteed to be Some if there is a best move. if not, it will fail silently by returning None
+            best_move_notation: result
+                .best_move
+                .and_then(|mv| self.get_move_notation(&mv).ok()),
+        }
+    }
+
+    // searches every state of the game to depth and compares each move played with the engine's best move. tt is shared
+    // by the searches, which is where most of the speed comes from as neighbouring states have most of their trees in
+    // common. The eval after a move is the search of the next state, so each state is searched once. A state the game
+    // ended in isn't searched, its eval comes from how it ended
+    pub fn annotate_with_engine(
+        &self,
+        depth: u8,
+        tt: &mut transposition::TranspositionTable,
+    ) -> Vec<PlyAnnotation> {
+        // eval from white's perspective and best move of each state
+        let searched: Vec<(i32, Option<Move>)> = self
+            .state_history
+            .iter()
+            .map(|state| match state.get_gamestate() {
+                GameState::Checkmate => (
+                    white_eval_for(-engine::checkmate_eval(0), state.side_to_move),
+                    None,
+                ),
+                gs if gs.is_game_over() => (0, None),
+                _ => {
+                    tt.new_search();
+                    let result = engine::choose_move(state, depth, tt);
+                    (
+                        white_eval_for(result.eval, state.side_to_move),
+                        result.best_move,
+                    )
+                }
+            })
+            .collect();
+
+        let annotations: Vec<PlyAnnotation> = self
+            .move_history
+            .iter()
+            .enumerate()
+            .map(|(ply, mv)| {
+                let (eval_before, best_move) = searched[ply];
+                let mut annotation = PlyAnnotation {
+                    ply,
+                    mv: *mv,
+                    notation: self.notation_history[ply].clone(),
+                    eval_before,
+                    best_move,
+                    eval_after: searched[ply + 1].0,
+                    classification: MoveClassification::Best,
+                };
+                if best_move != Some(*mv) {
+                    annotation.classification =
+                        MoveClassification::from_centipawn_loss(annotation.centipawn_loss());
+                }
+                annotation
+            })
+            .collect();
+        log::info!("Annotated {} moves at depth {}", annotations.len(), depth);
+        annotations
+    }
+
+    pub fn get_move_notation(&self, mv: &Move) -> Result<Notation, PGNParseError> {
+        // mv needs to be a legal move for current_state. If not PGNParseError is returned
+        Notation::from_mv_with_context(&self.current_state, mv)
+    }
+
+    pub fn move_history_string_notation(&self) -> Vec<String> {
+        self.notation_history
+            .iter()
+            .map(Notation::to_string)
+            .collect()
+    }
+
+    pub fn move_history_notation(&self) -> Vec<Notation> {
+        self.notation_history.clone()
+    }
+
+    // every move of the game in the order they were played, including the ones after a checked out state
+    pub fn move_history(&self) -> &[Move] {
+        &self.move_history
+    }
+
+    // move_history in UCI's long algebraic notation, e.g. for a "position startpos moves ..." command
+    pub fn moves_uci(&self) -> Vec<String> {
+        self.move_history.iter().map(Move::uci_string).collect()
+    }
+
+    // number of moves (half moves) played in the game
+    pub fn ply_count(&self) -> usize {
+        self.move_history.len()
+    }
+
+    // pieces captured by white and by black, in the order they were taken. Only the moves up to current_state count,
+    // so a checked out state shows what had been captured by then
+    pub fn captured_pieces(&self) -> (Vec<PieceType>, Vec<PieceType>) {
+        let plies = self.detatched_idx.unwrap_or(self.move_history.len());
+        let (mut by_white, mut by_black) = (Vec::new(), Vec::new());
+        for mv in &self.move_history[..plies] {
+            let captured = match mv.move_type {
+                MoveType::Capture(ptype) | MoveType::Promotion(_, Some(ptype)) => ptype,
+                MoveType::EnPassant(_) => PieceType::Pawn,
+                _ => continue,
+            };
+            match mv.piece.pcolour {
+                PieceColour::White => by_white.push(captured),
+                PieceColour::Black => by_black.push(captured),
             }
-        } else if self.move_history_notation().is_empty() {
-            return None;
         }
-        Some(self.move_history_notation().last().unwrap().clone())
+        (by_white, by_black)
+    }
+
+    // the move that led to current_state, so the checked out state's when detatched
+    pub fn last_move(&self) -> Option<Move> {
+        let plies = self.detatched_idx.unwrap_or(self.move_history.len());
+        plies.checked_sub(1).map(|idx| self.move_history[idx])
+    }
+
+    pub fn last_move_notation(&self) -> Option<Notation> {
+        let plies = self.detatched_idx.unwrap_or(self.notation_history.len());
+        plies
+            .checked_sub(1)
+            .map(|idx| self.notation_history[idx].clone())
     }
 
     pub fn last_move_string_notation(&self) -> String {
@@ -928,11 +3018,36 @@ impl Board {
         self.current_state = self.state_history[0].clone();
     }
 
+    // state after the first ply moves of the game, the starting state is ply 0. The same index as detatched_idx
+    pub fn find_state_at_ply(&self, ply: usize) -> Option<&BoardState> {
+        self.state_history.get(ply)
+    }
+
+    // state after colour's move with this move number, numbered as in FEN and PGN so a game from a position can start
+    // at a later move number, or with black's move
+    pub fn find_state_by_move_number(
+        &self,
+        move_number: u32,
+        colour: PieceColour,
+    ) -> Option<&BoardState> {
+        // counting white's move of move number n as halfmove 2n and black's as 2n + 1
+        let halfmove = |move_number: u32, colour: PieceColour| {
+            move_number as usize * 2 + usize::from(colour == PieceColour::Black)
+        };
+        let start = self.get_starting_state();
+        let ply = halfmove(move_number, colour)
+            .checked_sub(halfmove(start.move_count, start.side_to_move))?
+            + 1;
+        self.find_state_at_ply(ply)
+    }
+
+    // every state after a move with this notation, see find_state_at_ply and find_state_by_move_number to find a single
+    // one as the same notation can be played more than once
     pub fn find_states_by_notation(&self, notation: &str) -> Vec<&BoardState> {
         let mut state_iter = self.state_history.iter();
         state_iter.next(); // skip starting state
         let mut states = Vec::new();
-        for (state, n) in state_iter.zip(self.move_history_notation()) {
+        for (state, n) in state_iter.zip(&self.notation_history) {
             if n.to_string() == notation {
                 states.push(state);
             }
@@ -944,3 +3059,2729 @@ impl Board {
         self.current_state.get_gamestate()
     }
 }
+
+// BoardState is serialized as its FEN with the position occurences behind it, and Board as its starting FEN and moves,
+// which are replayed on deserializing so every hash and occurence count is rebuilt by make_move rather than trusted
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct BoardStateData {
+        fen: FEN,
+        last_move: Option<Move>,
+        position_occurences: Vec<(PositionHash, u8)>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct BoardData {
+        variant: Variant,
+        white_player: PlayerData,
+        black_player: PlayerData,
+        // the rest of the metadata as tag names and values
+        #[serde(default)]
+        tags: Vec<(String, String)>,
+        starting_fen: FEN,
+        moves: Vec<Move>,
+        game_over_state: Option<GameOverState>,
+        detatched_idx: Option<usize>,
+        #[serde(default)]
+        auto_claim_draws: bool,
+    }
+
+    impl Serialize for BoardState {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            // sorted so the same state always serializes the same way
+            let mut position_occurences: Vec<(PositionHash, u8)> =
+                self.history.all_occurences().into_iter().collect();
+            position_occurences.sort_unstable();
+            BoardStateData {
+                fen: FEN::from(self),
+                last_move: self.last_move,
+                position_occurences,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for BoardState {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let data = BoardStateData::deserialize(deserializer)?;
+            let mut state = BoardState::try_from(data.fen).map_err(D::Error::custom)?;
+            let Some(&(_, occurences)) = data
+                .position_occurences
+                .iter()
+                .find(|(hash, _)| *hash == state.position_hash)
+            else {
+                return Err(D::Error::custom(
+                    "Position occurences do not include the current position",
+                ));
+            };
+            // the order the earlier positions occurred in isn't kept, so they are all treated as repeatable. Only the
+            // newest entry for a position is looked at, which has its full count
+            let mut prev = None;
+            for (hash, count) in data.position_occurences {
+                if hash != state.position_hash {
+                    prev = Some(Arc::new(PositionHistory {
+                        position_hash: hash,
+                        halfmove_count: state.halfmove_count,
+                        occurences: count,
+                        prev,
+                    }));
+                }
+            }
+            state.history = Arc::new(PositionHistory {
+                position_hash: state.position_hash,
+                halfmove_count: state.halfmove_count,
+                occurences,
+                prev,
+            });
+            state.last_move = data.last_move;
+            state.board_hash = zobrist::board_state_hash(
+                state.position_hash,
+                state.get_occurences_of_current_position(),
+                state.halfmove_count,
+            );
+            // the gamestate cached by try_from didn't know about the position occurences
+            state.gamestate = OnceCell::new();
+            Ok(state.with_gamestate())
+        }
+    }
+
+    impl Serialize for Board {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            BoardData {
+                variant: self.variant,
+                white_player: self.metadata.white.clone(),
+                black_player: self.metadata.black.clone(),
+                tags: GameMetadata {
+                    white: PlayerData::default(),
+                    black: PlayerData::default(),
+                    ..self.metadata.clone()
+                }
+                .tags()
+                .iter()
+                .map(|tag| (tag.name().to_string(), tag.value()))
+                .collect(),
+                starting_fen: FEN::from(self.get_starting_state()),
+                moves: self.move_history.clone(),
+                game_over_state: self.game_over_state,
+                detatched_idx: self.detatched_idx,
+                auto_claim_draws: self.auto_claim_draws,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Board {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let data = BoardData::deserialize(deserializer)?;
+            let mut board = Board::try_from(data.starting_fen)
+                .map_err(D::Error::custom)?
+                .with_auto_claim_draws(data.auto_claim_draws);
+            for mv in &data.moves {
+                board.make_move(mv).map_err(D::Error::custom)?;
+            }
+            board.variant = data.variant;
+            for (name, value) in &data.tags {
+                let tag = Tag::from_name_value(name, value).map_err(D::Error::custom)?;
+                board.metadata.set_tag(tag);
+            }
+            board.metadata.white = data.white_player;
+            board.metadata.black = data.black_player;
+            // forced game overs are found again by make_move, resignations and agreed or claimed draws have to be restored
+            if board.game_over_state.is_none() {
+                board.game_over_state = data.game_over_state;
+            }
+            if let Some(idx) = data.detatched_idx {
+                if idx + 1 >= board.state_history.len() {
+                    return Err(D::Error::custom(format!(
+                        "Detatched index {} is out of range of the state history",
+                        idx
+                    )));
+                }
+                board.current_state = board.state_history[idx].clone();
+                board.detatched_idx = Some(idx);
+            }
+            Ok(board)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notation_move(board: &Board, notation: &str) -> Move {
+        notation
+            .parse::<Notation>()
+            .unwrap()
+            .to_move_with_context(board.get_current_state())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_make_engine_move_white_perspective_eval() {
+        // black is a queen up and to move
+        let mut board =
+            Board::try_from("4k3/8/8/8/8/8/q7/4K3 b - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let result = board.make_engine_move(2).unwrap();
+        assert!(result.eval < 0);
+        assert!(result.eval_for(PieceColour::Black) > 0);
+        assert_eq!(result.eval_for(PieceColour::White), result.eval);
+
+        // white to move in the mirrored position
+        let mut board =
+            Board::try_from("4k3/Q7/8/8/8/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let result = board.make_engine_move(2).unwrap();
+        assert!(result.eval > 0);
+        assert!(result.eval_for(PieceColour::Black) < 0);
+    }
+
+    #[test]
+    fn test_set_tt_size_mb() {
+        let mut board = Board::new().with_tt_size_mb(1);
+        assert_eq!(board.tt().size_mb(), 1);
+        board.make_engine_move(3).unwrap();
+        assert!(!board.tt().is_empty());
+
+        // resizing clears the table
+        board.set_tt_size_mb(2);
+        assert_eq!(board.tt().size_mb(), 2);
+        assert!(board.tt().is_empty());
+
+        // disabled table, the engine still works without it
+        board.set_tt_size_mb(0);
+        board.make_engine_move(3).unwrap();
+        assert!(board.tt().is_empty());
+        assert_eq!(board.tt().heap_alloc_size(), 0);
+    }
+
+    #[test]
+    fn test_reuse_transposition_table() {
+        let mut board = Board::new().with_tt_size_mb(1);
+        board.make_engine_move(3).unwrap();
+        let len = board.tt().len();
+
+        let mut next_game = Board::new().with_transposition_table(board.take_transposition_table());
+        assert_eq!(next_game.tt().len(), len);
+        assert!(board.tt().is_empty());
+        assert_eq!(board.tt().size_mb(), 1);
+
+        next_game.clear_transposition_table();
+        assert!(next_game.tt().is_empty());
+        assert_eq!(next_game.tt().size_mb(), 1);
+    }
+
+    #[test]
+    fn test_engine_analyse_game_over() {
+        let mut board = Board::try_from(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let analysis = board.engine_analyse(2);
+        assert_eq!(analysis.best_move, None);
+        assert_eq!(analysis.best_move_notation, None);
+        assert!(analysis.eval < 0);
+        assert!(board.make_engine_move(2).is_err());
+    }
+
+    #[test]
+    fn test_engine_search_in_background() {
+        // white mates with Qg8
+        let mut board =
+            Board::try_from("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let handle = board
+            .start_engine_search(search::SearchLimits::new(4))
+            .unwrap();
+        // the board can be read while the engine thinks
+        while !handle.is_finished() {
+            assert_eq!(board.ply_count(), 0);
+            assert!(board.to_string().contains("White to move"));
+            assert_eq!(board.get_current_state().side_to_move, PieceColour::White);
+            std::thread::yield_now();
+        }
+        let progress = handle.progress();
+        assert_eq!(progress.depth, 4);
+        assert!(engine::is_eval_checkmate(progress.eval));
+
+        let result = board.apply_engine_result(handle).unwrap();
+        assert_eq!(result.game_state, GameState::Checkmate);
+        assert_eq!(board.ply_count(), 1);
+        assert_eq!(board.last_move(), progress.best_move);
+        assert!(board
+            .start_engine_search(search::SearchLimits::new(2))
+            .is_err());
+    }
+
+    #[test]
+    fn test_engine_search_stop() {
+        let mut board = Board::new().with_tt_size_mb(1);
+        let mut handle = board
+            .start_engine_search(search::SearchLimits::new(64))
+            .unwrap();
+        // the table is the search's until it's done, using the board's meanwhile doesn't wait for it
+        std::thread::sleep(Duration::from_millis(100));
+        board.clear_transposition_table();
+        assert!(board.engine_analyse(1).best_move.is_some());
+        board.set_tt_size_mb(2);
+        // the iteration the search is in is abandoned, rather than searched to the end
+        handle.stop();
+        let stopped = std::time::Instant::now();
+        let progress = handle.wait();
+        assert!(stopped.elapsed() < Duration::from_secs(2));
+        assert!(progress.depth >= 1 && progress.depth < 64);
+        assert!(progress.best_move.is_some());
+        board.apply_engine_result(handle).unwrap();
+        assert_eq!(board.ply_count(), 1);
+        // the search's table is put back, over the resized one
+        assert!(!board.tt().is_empty());
+        assert_eq!(board.tt().size_mb(), 1);
+
+        // a result for a state the board has moved on from isn't applied
+        let handle = board
+            .start_engine_search(search::SearchLimits::new(2).with_movetime(Duration::ZERO))
+            .unwrap();
+        board.make_move_uci("e7e5").unwrap();
+        assert!(board.apply_engine_result(handle).is_err());
+        assert_eq!(board.ply_count(), 2);
+    }
+
+    #[test]
+    fn test_annotate_with_engine() {
+        // white's queen takes a pawn with check and is taken back
+        let board = Board::from_moves(&["e4", "e5", "Qh5", "Nc6", "Qxe5+", "Nxe5"]).unwrap();
+        let mut tt = transposition::TranspositionTable::new();
+        let annotations = board.annotate_with_engine(3, &mut tt);
+        assert_eq!(annotations.len(), board.ply_count());
+        for (ply, annotation) in annotations.iter().enumerate() {
+            assert_eq!(annotation.ply, ply);
+            assert_eq!(annotation.mv, board.move_history()[ply]);
+            assert_eq!(
+                annotation.notation.to_string(),
+                board.move_history_string_notation()[ply]
+            );
+            if let Some(next) = annotations.get(ply + 1) {
+                assert_eq!(annotation.eval_after, next.eval_before);
+            }
+        }
+        let hung_queen = &annotations[4];
+        assert_eq!(hung_queen.classification, MoveClassification::Blunder);
+        assert!(hung_queen.centipawn_loss() >= 500);
+        assert!(hung_queen.eval_after < -500);
+        let recapture = &annotations[5];
+        assert_eq!(recapture.best_move, Some(recapture.mv));
+        assert_eq!(recapture.classification, MoveClassification::Best);
+
+        // the checkmated state isn't searched, walking into mate is a blunder and mating is the best move
+        let board = Board::from_moves(&["f3", "e5", "g4", "Qh4#"]).unwrap();
+        let annotations = board.annotate_with_engine(3, &mut tt);
+        assert_eq!(annotations.len(), 4);
+        assert_eq!(annotations[2].classification, MoveClassification::Blunder);
+        assert!(engine::is_eval_checkmate(annotations[2].eval_after));
+        assert_eq!(annotations[3].best_move, Some(annotations[3].mv));
+        assert_eq!(annotations[3].classification, MoveClassification::Best);
+        assert_eq!(annotations[3].eval_after, -engine::checkmate_eval(0));
+        assert_eq!(annotations[3].centipawn_loss(), 0);
+
+        assert_eq!(
+            MoveClassification::from_centipawn_loss(-20),
+            MoveClassification::Best
+        );
+        assert_eq!(
+            MoveClassification::from_centipawn_loss(49),
+            MoveClassification::Good
+        );
+        assert_eq!(
+            MoveClassification::from_centipawn_loss(50),
+            MoveClassification::Inaccuracy
+        );
+        assert_eq!(
+            MoveClassification::from_centipawn_loss(100),
+            MoveClassification::Mistake
+        );
+        assert_eq!(
+            MoveClassification::from_centipawn_loss(300),
+            MoveClassification::Blunder
+        );
+    }
+
+    #[test]
+    fn test_make_engine_move_with_options_seeded() {
+        let options = engine::EngineOptions::new(3)
+            .with_skill_level(0)
+            .with_seed(42);
+        let mut board = Board::new();
+        let mut other = Board::new();
+        for _ in 0..6 {
+            let legal_moves = board
+                .get_current_state()
+                .get_legal_moves()
+                .unwrap()
+                .to_vec();
+            board.make_engine_move_with_options(options).unwrap();
+            other.make_engine_move_with_options(options).unwrap();
+            assert!(legal_moves.contains(board.move_history.last().unwrap()));
+        }
+        assert_eq!(board.move_history, other.move_history);
+    }
+
+    #[test]
+    fn test_cached_gamestate() {
+        for (fen, expected) in [
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                GameState::Checkmate,
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", GameState::Stalemate),
+            ("4k3/8/8/8/8/8/8/4K2R b - - 0 1", GameState::Active),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                GameState::InsufficientMaterial,
+            ),
+            ("4k3/4R3/8/8/8/8/8/4K3 b - - 99 80", GameState::Check),
+        ] {
+            let state = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            assert_eq!(state.gamestate.get(), Some(&expected), "{}", fen);
+            assert_eq!(state.get_gamestate(), state.compute_gamestate());
+            assert_eq!(state.get_gamestate(), state.get_gamestate());
+        }
+
+        let start = BoardState::new_starting();
+        let e2e4 = start.find_move_uci("e2e4").unwrap();
+        let next = start.next_state(&e2e4).unwrap();
+        assert_eq!(next.gamestate.get(), Some(&GameState::Active));
+
+        // lazy states work it out on first use
+        let lazy = start.next_state_unchecked(&e2e4);
+        assert_eq!(lazy.gamestate.get(), None);
+        assert_eq!(lazy.get_gamestate(), GameState::Active);
+        assert_eq!(lazy.gamestate.get(), Some(&GameState::Active));
+        assert_eq!(lazy.get_gamestate(), lazy.compute_gamestate());
+
+        let mut board = Board::new();
+        for mv in [
+            "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+        ] {
+            board.make_move_uci(mv).unwrap();
+        }
+        for state in board.get_state_history() {
+            assert_eq!(state.get_gamestate(), state.compute_gamestate());
+        }
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+    }
+
+    #[test]
+    fn test_state_history_storage() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::new();
+        for mv in ["e2e4", "e7e5"]
+            .iter()
+            .chain(shuffle.iter().cycle().take(14))
+        {
+            board.make_move_uci(mv).unwrap();
+        }
+        let history = board.get_state_history();
+        assert_eq!(history.len(), 17);
+        // one position history entry per state, shared along the game rather than copied into every state
+        assert!(history[0].history.prev.is_none());
+        for pair in history.windows(2) {
+            let prev = pair[1].history.prev.as_ref().unwrap();
+            assert!(Arc::ptr_eq(prev, &pair[0].history));
+        }
+        // legal moves are only kept for the current state
+        assert!(history
+            .iter()
+            .all(|state| state.legal_moves.get().is_none()));
+        assert!(board.current_state.legal_moves.get().is_some());
+        // but are still there when asked for
+        assert_eq!(
+            history[10].get_legal_moves().unwrap(),
+            history[10].position.get_legal_moves()
+        );
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            4
+        );
+        for (ply, state) in history.iter().enumerate().skip(2) {
+            assert_eq!(
+                state.get_occurences_of_current_position() as usize,
+                (ply - 2) / 4 + 1,
+                "ply {}",
+                ply
+            );
+        }
+    }
+
+    #[test]
+    fn test_repetition_after_checkout_and_undo() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::new();
+        for mv in shuffle.iter().cycle().take(6) {
+            board.make_move_uci(mv).unwrap();
+        }
+        let moved_on = board.get_current_state().clone();
+
+        // the starting position has occurred twice at ply 4, playing the shuffle again from there repeats it a third
+        // time
+        board
+            .checkout_state(&board.get_state_history()[4].clone())
+            .unwrap();
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            2
+        );
+        assert_eq!(
+            board.get_current_state().get_legal_moves().unwrap().len(),
+            20
+        );
+        for mv in shuffle {
+            let mv = board.get_current_state().find_move_uci(mv).unwrap();
+            board.make_move_from_here(&mv).unwrap();
+        }
+        assert_eq!(board.ply_count(), 8);
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            3
+        );
+
+        // taking the moves back and playing them again gets to the same states
+        board.undo_moves(4).unwrap();
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            2
+        );
+        for mv in ["g1f3", "g8f6"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(board.get_current_state(), &moved_on);
+        assert_eq!(board.get_current_state().board_hash, moved_on.board_hash);
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            moved_on.get_gamestate()
+        );
+        assert_eq!(
+            board
+                .get_current_state()
+                .would_repeat(&board.get_current_state().find_move_uci("f3g1").unwrap()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    #[ignore = "micro-benchmark, run with --release -- --ignored --nocapture"]
+    fn bench_cached_gamestate() {
+        const ITERATIONS: u32 = 1_000_000;
+        let start = BoardState::try_from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        // lazy, so working the gamestate out walks the legal move iterator
+        let state = start.next_state_unchecked(&start.find_move_uci("a2a3").unwrap());
+
+        let timer = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(std::hint::black_box(&state).compute_gamestate());
+        }
+        let uncached = timer.elapsed();
+
+        let timer = std::time::Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(std::hint::black_box(&state).get_gamestate());
+        }
+        let cached = timer.elapsed();
+
+        println!(
+            "get_gamestate x{}: uncached {:?}, cached {:?}",
+            ITERATIONS, uncached, cached
+        );
+        assert_eq!(state.get_gamestate(), state.compute_gamestate());
+    }
+
+    #[test]
+    fn test_castling_en_passant_and_king_accessors() {
+        for fen_str in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let bs = BoardState::try_from(fen_str.parse::<FEN>().unwrap()).unwrap();
+            let fen_out = FEN::from(&bs).to_string();
+            assert_eq!(fen_out, fen_str);
+            let fields: Vec<&str> = fen_out.split(' ').collect();
+
+            let rights = bs.castling_rights();
+            for (c, colour, side) in [
+                ('K', PieceColour::White, CastleSide::Short),
+                ('Q', PieceColour::White, CastleSide::Long),
+                ('k', PieceColour::Black, CastleSide::Short),
+                ('q', PieceColour::Black, CastleSide::Long),
+            ] {
+                assert_eq!(
+                    rights.can_castle(colour, side),
+                    fields[2].contains(c),
+                    "{}",
+                    fen_str
+                );
+            }
+
+            let ep_square = bs.en_passant_square().map(util::square_name_unchecked);
+            assert_eq!(
+                ep_square.as_deref().unwrap_or("-"),
+                fields[3],
+                "{}",
+                fen_str
+            );
+
+            for colour in [PieceColour::White, PieceColour::Black] {
+                assert_eq!(
+                    bs.get_pos64()[bs.king_square(colour)],
+                    Square::Piece(Piece {
+                        pcolour: colour,
+                        ptype: PieceType::King
+                    })
+                );
+            }
+        }
+        let bs = BoardState::new_starting();
+        let bs = bs.next_state(&bs.find_move_uci("e2e4").unwrap()).unwrap();
+        assert_eq!(
+            bs.en_passant_square(),
+            Some(crate::util::square_index("e3").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_find_move_uci() {
+        let state = |fen: &str| BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        let uci = |bs: &BoardState, s: &str| bs.find_move_uci(s).map(|mv| mv.to_string());
+
+        let bs = BoardState::new_starting();
+        assert_eq!(uci(&bs, "e2e4").as_deref(), Some("e2e4"));
+        assert_eq!(uci(&bs, "g1f3").as_deref(), Some("g1f3"));
+        // illegal or malformed
+        for s in [
+            "e2e5", "e7e5", "e2e4q", "e2e", "e2e4e5", "i2i4", "0000", "E2E4",
+        ] {
+            assert_eq!(bs.find_move_uci(s), None, "{}", s);
+        }
+
+        // promotion suffixes pick the piece, no suffix isn't a promotion
+        let bs = state("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P1P1P/R2Q1RK1 b kq - 0 1");
+        for s in ["b2a1q", "b2a1r", "b2a1b", "b2a1n", "b2b1q"] {
+            assert_eq!(uci(&bs, s).as_deref(), Some(s));
+        }
+        assert_eq!(bs.find_move_uci("b2a1"), None);
+
+        let bs = state("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        let mv = bs.find_move_uci("e5f6").unwrap();
+        assert_eq!(mv.move_type, MoveType::EnPassant(29));
+
+        // castling as the king's squares or king takes rook
+        let bs = state("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        for (s, side) in [
+            ("e1g1", CastleSide::Short),
+            ("e1h1", CastleSide::Short),
+            ("e1c1", CastleSide::Long),
+            ("e1a1", CastleSide::Long),
+        ] {
+            match bs.find_move_uci(s).unwrap().move_type {
+                MoveType::Castle(castle_mv) => assert_eq!(castle_mv.get_castle_side(), side),
+                move_type => panic!("{} is {:?}", s, move_type),
+            }
+        }
+        let bs = state("rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1");
+        assert_eq!(uci(&bs, "b1h1").as_deref(), Some("b1g1"));
+        assert_eq!(uci(&bs, "b1a1").as_deref(), Some("b1c1"));
+        // the king stepping to g1 isn't the castle that puts it there
+        let bs = state("1r3k1r/8/8/8/8/8/8/1R3K1R w HBhb - 0 1");
+        assert_eq!(
+            bs.find_move_uci("f1g1").unwrap().move_type,
+            MoveType::Normal
+        );
+        assert!(matches!(
+            bs.find_move_uci("f1h1").unwrap().move_type,
+            MoveType::Castle(_)
+        ));
+    }
+
+    #[test]
+    fn test_make_move_uci() {
+        let mut board = Board::new();
+        assert!(matches!(board.make_move_uci("e2e4"), Ok(GameState::Active)));
+        assert!(matches!(
+            board.make_move_uci("e2e4"),
+            Err(BoardStateError::IllegalMove(_))
+        ));
+        assert!(matches!(
+            board.make_move_uci("e7"),
+            Err(BoardStateError::InvalidInput(_))
+        ));
+        for s in ["e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"] {
+            board.make_move_uci(s).unwrap();
+        }
+        assert_eq!(board.move_history.len(), 7);
+        assert_eq!(board.move_history.last().unwrap().to_string(), "e1g1");
+    }
+
+    #[test]
+    fn test_move_history() {
+        let board = Board::new();
+        assert_eq!(board.ply_count(), 0);
+        assert!(board.move_history().is_empty());
+        assert_eq!(board.last_move(), None);
+
+        let moves = ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "O-O"];
+        let mut board = Board::from_moves(&moves).unwrap();
+        assert_eq!(board.ply_count(), moves.len());
+        assert_eq!(board.move_history().len(), board.ply_count());
+        assert_eq!(board.move_history_notation().len(), board.ply_count());
+        assert_eq!(board.move_history_string_notation(), moves);
+        assert_eq!(
+            board.moves_uci(),
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5", "e1g1"]
+        );
+        assert_eq!(board.last_move(), board.move_history().last().copied());
+        assert_eq!(board.last_move(), board.get_current_state().last_move);
+
+        // the UCI moves replay the same game
+        let uci = board.moves_uci();
+        let uci = uci.iter().map(String::as_str).collect::<Vec<_>>();
+        let replayed = Board::from_moves(&uci).unwrap();
+        assert_eq!(replayed.move_history(), board.move_history());
+        assert_eq!(replayed.to_fen(), board.to_fen());
+
+        // a checked out state has the move that led to it, the history is still the whole game's
+        board.checkout_prev();
+        board.checkout_prev();
+        assert_eq!(board.last_move(), Some(board.move_history()[4]));
+        assert_eq!(board.last_move(), board.get_current_state().last_move);
+        assert_eq!(
+            board.last_move_notation().unwrap().to_string(),
+            moves[4].to_string()
+        );
+        assert_eq!(board.ply_count(), moves.len());
+        board.checkout_latest_state();
+        board.undo_move().unwrap();
+        assert_eq!(board.ply_count(), moves.len() - 1);
+        assert_eq!(board.move_history_notation().len(), board.ply_count());
+        assert_eq!(board.last_move(), Some(board.move_history()[5]));
+    }
+
+    #[test]
+    fn test_captured_pieces() {
+        let board = Board::new();
+        assert_eq!(board.captured_pieces(), (vec![], vec![]));
+        assert_eq!(board.get_current_state().material_balance(), 0);
+
+        // white's e pawn takes three pawns and underpromotes taking the a8 rook, black takes g2 en passant
+        let mut board = Board::from_moves(&[
+            "e2e4", "d7d5", "e4d5", "c7c6", "d5c6", "h7h5", "c6b7", "h5h4", "b7a8n", "g8f6",
+            "g2g4", "h4g3", "h2g3", "d8d2", "c1d2",
+        ])
+        .unwrap();
+        use PieceType::*;
+        assert_eq!(
+            board.captured_pieces(),
+            (vec![Pawn, Pawn, Pawn, Rook, Pawn, Queen], vec![Pawn, Pawn])
+        );
+        let material = board.get_current_state().material();
+        assert_eq!(
+            (
+                material.white.pawns,
+                material.white.knights,
+                material.white.bishops(),
+                material.white.rooks,
+                material.white.queens
+            ),
+            (5, 3, 2, 2, 1)
+        );
+        assert_eq!(
+            (
+                material.black.pawns,
+                material.black.knights,
+                material.black.bishops(),
+                material.black.rooks,
+                material.black.queens
+            ),
+            (4, 2, 2, 1, 0)
+        );
+        assert_eq!(material.side(PieceColour::Black), &material.black);
+        // the captures are worth 1800 - 200 and the promotion turned a pawn into a knight
+        assert_eq!(board.get_current_state().material_balance(), 1600 + 220);
+
+        // a checked out state only counts the captures made by then
+        let state = board.get_state_history()[9].clone();
+        board.checkout_state(&state).unwrap();
+        assert_eq!(
+            board.captured_pieces(),
+            (vec![Pawn, Pawn, Pawn, Rook], vec![])
+        );
+        assert_eq!(
+            board.get_current_state().material_balance(),
+            300 + 500 + 220
+        );
+    }
+
+    #[test]
+    fn test_board_diagram() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let state = board.get_current_state();
+        let lines = |diagram: String| diagram.lines().map(str::to_string).collect::<Vec<String>>();
+
+        let diagram = lines(state.to_string());
+        assert_eq!(diagram[0], "8 . . . . k . . .");
+        assert_eq!(diagram[7], "1 R . . . K . . .");
+        assert_eq!(diagram[8], "  a b c d e f g h");
+        assert_eq!(diagram[9], "White to move, castling Q, en passant -");
+        assert_eq!(board.to_string(), state.to_string());
+        assert_eq!(
+            lines(state.to_unicode_string(false))[7],
+            "1 ♖ . . . ♔ . . ."
+        );
+
+        // from black's side a1 is the top right square
+        let flipped = lines(state.to_unicode_string(true));
+        assert_eq!(flipped[0], "1 . . . ♔ . . . ♖");
+        assert!(flipped[0].ends_with('♖'));
+        assert_eq!(flipped[7], "8 . . . ♚ . . . .");
+        assert_eq!(flipped[8], "  h g f e d c b a");
+
+        let mut board = Board::from_fen("4k3/8/8/8/5p2/8/4P3/4K3 w - - 0 1").unwrap();
+        board.make_move_uci("e2e4").unwrap();
+        assert_eq!(
+            lines(board.to_string())[9],
+            "Black to move, castling -, en passant e3"
+        );
+        // a checked out state is shown
+        board.checkout_prev();
+        assert_eq!(lines(board.to_string())[6], "2 . . . . P . . .");
+    }
+
+    #[test]
+    fn test_make_move_san() {
+        let mut board = Board::new();
+        assert_eq!(
+            board
+                .make_moves_san(&["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"])
+                .unwrap(),
+            GameState::Active
+        );
+        assert_eq!(
+            board.to_fen(),
+            "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5"
+        );
+
+        // the knights on b1 and f3 can both go to d2
+        let mut board = Board::new();
+        board.make_moves_san(&["d4", "d5", "Nf3", "Nf6"]).unwrap();
+        let fen = board.to_fen();
+        let err = board.make_move_san("Nd2").unwrap_err();
+        assert!(matches!(err, BoardStateError::AmbiguousMove(_)));
+        let msg = err.to_string();
+        assert!(msg.contains("Nbd2") && msg.contains("Nfd2"), "{}", msg);
+        board.make_move_san("Nbd2").unwrap();
+        board.undo_move().unwrap();
+
+        // failed moves leave the board as it was
+        assert!(matches!(
+            board.make_move_san("Ke3"),
+            Err(BoardStateError::IllegalMove(_))
+        ));
+        assert!(matches!(
+            board.make_move_san("Zz9"),
+            Err(BoardStateError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            board.make_moves_san(&["e3", "e6", "Bd3", "Bd3"]),
+            Err(BoardStateError::IllegalMove(_))
+        ));
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(
+            board.move_history_string_notation(),
+            ["d4", "d5", "Nf3", "Nf6"]
+        );
+    }
+
+    #[test]
+    fn test_board_from_moves() {
+        let san = Board::from_moves(&["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4"]).unwrap();
+        let uci =
+            Board::from_moves(&["e2e4", "c7c5", "g1f3", "d7d6", "d2d4", "c5d4", "f3d4"]).unwrap();
+        assert_eq!(san.state_history, uci.state_history);
+        assert_eq!(
+            uci.move_history_string_notation(),
+            ["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4"]
+        );
+        assert_eq!(
+            uci.to_fen(),
+            "rnbqkbnr/pp2pppp/3p4/8/3NP3/8/PPP2PPP/RNBQKB1R b KQkq - 0 4"
+        );
+
+        for (moves, idx) in [
+            (&["e4", "e5", "Ke3"][..], 2),
+            (&["e2e4", "e7e5", "e4e5"][..], 2),
+            (&["Zz9"][..], 0),
+        ] {
+            let Err(BoardStateError::AtMove(i, _)) = Board::from_moves(moves) else {
+                panic!("{:?} should fail", moves);
+            };
+            assert_eq!(i, idx);
+        }
+        assert!(matches!(
+            Board::from_fen_and_moves("not a fen", &[]),
+            Err(BoardStateError::InvalidInput(_))
+        ));
+
+        // chess960 castling long with the rook on b1 in SAN, then short as king takes rook
+        let board =
+            Board::from_fen_and_moves("r3k2r/8/8/8/8/8/8/RR2K2R w BHah - 0 1", &["O-O-O", "e8h8"])
+                .unwrap();
+        assert_eq!(board.variant(), Variant::Chess960);
+        assert_eq!(board.to_fen(), "r4rk1/8/8/8/8/8/8/R1KR3R w - - 2 2");
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let round_trip = |board: &Board| {
+            let fen = board.to_fen();
+            let from_fen = Board::from_fen(&fen).unwrap();
+            assert_eq!(from_fen.to_fen(), fen);
+            assert_eq!(from_fen.get_current_state(), board.get_current_state());
+            assert_eq!(
+                from_fen.get_current_state().castling_rights(),
+                board.get_current_state().castling_rights()
+            );
+        };
+
+        let mut board = Board::new();
+        round_trip(&board);
+        for s in ["e2e4", "c7c5", "g1f3", "d7d6", "f1b5", "c8d7", "e1g1"] {
+            board.make_move_uci(s).unwrap();
+            round_trip(&board);
+        }
+        assert_eq!(
+            board.to_fen(),
+            "rn1qkbnr/pp1bpppp/3p4/1Bp5/4P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 4"
+        );
+        // the current state after checking out an earlier one
+        board.checkout_starting_state();
+        assert_eq!(board.to_fen(), crate::fen::STD_STARTING_FEN_STR);
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 40 60",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.to_fen(), fen);
+            round_trip(&board);
+        }
+
+        // Chess960 starting positions, which have the outer rooks so use KQkq
+        for position_number in [0, 1, 518, 959] {
+            let board = Board::new_chess960_from_num(position_number).unwrap();
+            round_trip(&board);
+        }
+        // an inner rook keeps its file, shredder-FEN files for outer rooks are written as KQkq
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/RR2K2R w BHa - 0 1").unwrap();
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/RR2K2R w KBq - 0 1");
+        round_trip(&board);
+        let board =
+            Board::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        round_trip(&board);
+
+        assert!(matches!(
+            Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 x - - 0 1"),
+            Err(FenParseError::InvalidFen(_))
+        ));
+        assert!(matches!(
+            Board::from_fen("4k3/8/8/8/8/8/8/4K2P w - - 0 1"),
+            Err(FenParseError::InvalidPosition(_))
+        ));
+    }
+
+    #[test]
+    fn test_fen_at_ply() {
+        let mut board = Board::new();
+        assert_eq!(
+            board.fen_at_ply(0).unwrap(),
+            crate::fen::STD_STARTING_FEN_STR
+        );
+        for s in ["e2e4", "c7c5", "g1f3"] {
+            board.make_move_uci(s).unwrap();
+        }
+        assert_eq!(
+            board.fen_at_ply(2).unwrap(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2"
+        );
+        assert_eq!(board.fen_at_ply(4), None);
+        let history = board.fen_history();
+        assert_eq!(history.len(), 4);
+        assert_eq!(
+            history.last().unwrap(),
+            &FEN::from(board.get_current_state()).to_string()
+        );
+        // plies count from the start of the game, not the checked out state
+        board.checkout_prev();
+        board.checkout_prev();
+        assert_eq!(board.fen_at_ply(3).unwrap(), history[3]);
+        assert_eq!(board.fen_at_ply(1).unwrap(), board.to_fen());
+        assert_eq!(board.fen_history(), history);
+
+        // boards that don't start from the standard position
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        board.make_move_uci("e1g1").unwrap();
+        assert_eq!(board.fen_at_ply(0).unwrap(), fen);
+        assert_eq!(
+            board.fen_history().last().unwrap(),
+            &FEN::from(board.get_current_state()).to_string()
+        );
+        let board = Board::new_chess960_from_num(100).unwrap();
+        assert_eq!(
+            board.fen_at_ply(0).unwrap(),
+            board.get_starting_state().to_fen()
+        );
+        assert_eq!(board.fen_history(), [board.to_fen()]);
+    }
+
+    #[test]
+    fn test_termination() {
+        let termination_tag = |board: &Board| {
+            pgn::PGN::from(board)
+                .tags()
+                .iter()
+                .find_map(|tag| match tag {
+                    Tag::Termination(termination) => Some(termination.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let board = Board::new();
+        assert_eq!(board.termination_reason(), None);
+        assert_eq!(board.termination(), Termination::Unterminated);
+        assert_eq!(termination_tag(&board), Termination::Unterminated);
+
+        let mut boards = Vec::new();
+        for (side, gos, reason) in [
+            (
+                PieceColour::White,
+                GameOverState::WhiteResign,
+                "White resigned",
+            ),
+            (
+                PieceColour::Black,
+                GameOverState::BlackResign,
+                "Black resigned",
+            ),
+        ] {
+            let mut board = Board::new();
+            board.set_resign(side);
+            boards.push((board, gos, reason));
+        }
+        let mut board = Board::new();
+        board.set_draw();
+        boards.push((board, GameOverState::AgreedDraw, "Draw agreed"));
+
+        let forced = [
+            (
+                Board::new(),
+                vec!["f2f3", "e7e5", "g2g4", "d8h4"],
+                GameState::Checkmate,
+                "Black wins by checkmate",
+            ),
+            (
+                Board::from_fen("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1").unwrap(),
+                vec!["f1f7"],
+                GameState::Stalemate,
+                "Draw by stalemate",
+            ),
+            (
+                Board::new().with_auto_claim_draws(true),
+                vec![
+                    "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+                ],
+                GameState::Repetition,
+                "Draw by repetition",
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80")
+                    .unwrap()
+                    .with_auto_claim_draws(true),
+                vec!["a1a2"],
+                GameState::FiftyMove,
+                "Draw by the fifty move rule",
+            ),
+            (
+                Board::new(),
+                [
+                    "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6",
+                    "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+                ]
+                .to_vec(),
+                GameState::FivefoldRepetition,
+                "Draw by fivefold repetition",
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 80").unwrap(),
+                vec!["a1a2"],
+                GameState::SeventyFiveMove,
+                "Draw by the seventy-five move rule",
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/3rK3 w - - 0 1").unwrap(),
+                vec!["e1d1"],
+                GameState::InsufficientMaterial,
+                "Draw by insufficient material",
+            ),
+        ];
+        for (mut board, moves, gs, reason) in forced {
+            for mv in moves {
+                board.make_move_uci(mv).unwrap();
+            }
+            boards.push((board, GameOverState::Forced(gs), reason));
+        }
+
+        let claimed = [
+            (
+                Board::new(),
+                vec![
+                    "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+                ],
+                GameState::Repetition,
+                "Draw claimed by threefold repetition",
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap(),
+                vec!["a1a2"],
+                GameState::FiftyMove,
+                "Draw claimed by the fifty move rule",
+            ),
+        ];
+        for (mut board, moves, gs, reason) in claimed {
+            for mv in moves {
+                board.make_move_uci(mv).unwrap();
+            }
+            board.claim_draw().unwrap();
+            boards.push((board, GameOverState::DrawClaimed(gs), reason));
+        }
+
+        for (board, gos, reason) in boards {
+            assert_eq!(board.get_game_over_state(), Some(gos));
+            assert_eq!(board.termination_reason(), Some(reason));
+            assert_eq!(board.termination(), Termination::Normal);
+            assert_eq!(termination_tag(&board), Termination::Normal);
+        }
+
+        // a finished game checked out at an earlier move is being looked at, not played
+        let mut board = Board::new();
+        for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        board.checkout_prev();
+        assert_eq!(board.termination_reason(), None);
+    }
+
+    #[test]
+    fn test_result() {
+        let result_tag = |board: &Board| {
+            pgn::PGN::from(board)
+                .tags()
+                .iter()
+                .find_map(|tag| match tag {
+                    Tag::Result(result) => Some(result.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let board = Board::new();
+        assert_eq!(board.result(), None);
+        assert_eq!(board.winner(), None);
+        assert_eq!(board.result_string(), "*");
+        assert_eq!(result_tag(&board), "*");
+
+        let mut boards = Vec::new();
+        let mut board = Board::new();
+        board.set_resign(PieceColour::White);
+        boards.push((board, GameResult::BlackWins(ResultReason::Resignation)));
+        let mut board = Board::new();
+        board.set_resign(PieceColour::Black);
+        boards.push((board, GameResult::WhiteWins(ResultReason::Resignation)));
+        let mut board = Board::new();
+        board.set_draw();
+        boards.push((board, GameResult::Draw(ResultReason::Agreement)));
+
+        let played = [
+            (
+                Board::new(),
+                vec!["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"],
+                GameResult::WhiteWins(ResultReason::Checkmate),
+            ),
+            (
+                Board::new(),
+                vec!["f2f3", "e7e5", "g2g4", "d8h4"],
+                GameResult::BlackWins(ResultReason::Checkmate),
+            ),
+            (
+                Board::from_fen("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1").unwrap(),
+                vec!["f1f7"],
+                GameResult::Draw(ResultReason::Stalemate),
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/3rK3 w - - 0 1").unwrap(),
+                vec!["e1d1"],
+                GameResult::Draw(ResultReason::InsufficientMaterial),
+            ),
+            (
+                Board::new().with_auto_claim_draws(true),
+                vec![
+                    "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+                ],
+                GameResult::Draw(ResultReason::Repetition),
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80")
+                    .unwrap()
+                    .with_auto_claim_draws(true),
+                vec!["a1a2"],
+                GameResult::Draw(ResultReason::FiftyMove),
+            ),
+            (
+                Board::new(),
+                [
+                    "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6",
+                    "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+                ]
+                .to_vec(),
+                GameResult::Draw(ResultReason::FivefoldRepetition),
+            ),
+            (
+                Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 80").unwrap(),
+                vec!["a1a2"],
+                GameResult::Draw(ResultReason::SeventyFiveMove),
+            ),
+        ];
+        for (mut board, moves, result) in played {
+            for mv in moves {
+                board.make_move_uci(mv).unwrap();
+            }
+            boards.push((board, result));
+        }
+
+        // claimed draws have the same result as automatic ones
+        let mut board = Board::new();
+        for mv in [
+            "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+        ] {
+            board.make_move_uci(mv).unwrap();
+        }
+        board.claim_draw().unwrap();
+        boards.push((board, GameResult::Draw(ResultReason::Repetition)));
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        board.make_move_uci("a1a2").unwrap();
+        board.claim_draw().unwrap();
+        boards.push((board, GameResult::Draw(ResultReason::FiftyMove)));
+
+        for (board, result) in boards {
+            assert_eq!(board.result(), Some(result));
+            assert_eq!(board.result().unwrap().reason(), result.reason());
+            assert_eq!(board.winner(), result.winner());
+            let expected = match result {
+                GameResult::WhiteWins(_) => "1-0",
+                GameResult::BlackWins(_) => "0-1",
+                GameResult::Draw(_) => "1/2-1/2",
+            };
+            assert_eq!(board.result_string(), expected);
+            assert_eq!(result_tag(&board), expected);
+        }
+
+        // the game goes on after a claimable draw that wasn't claimed, and a board looking at an earlier move has
+        // no result
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        board.make_move_uci("a1a2").unwrap();
+        assert_eq!(board.result(), None);
+        let mut board = Board::new();
+        for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(board.winner(), Some(PieceColour::Black));
+        board.checkout_prev();
+        assert_eq!(board.result(), None);
+        assert_eq!(board.result_string(), "*");
+    }
+
+    #[test]
+    fn test_clock_timeout() {
+        let mut board = Board::new();
+        assert_eq!(board.remaining(PieceColour::White), None);
+        assert!(board.start_clock().is_err());
+
+        board.set_time_control(Duration::from_secs(10), Duration::ZERO);
+        board.start_clock().unwrap();
+        assert_eq!(
+            board.clock().unwrap().running_side(),
+            Some(PieceColour::White)
+        );
+        board.make_move_uci("e2e4").unwrap();
+        assert_eq!(
+            board.clock().unwrap().running_side(),
+            Some(PieceColour::Black)
+        );
+        assert_eq!(
+            board
+                .apply_elapsed(PieceColour::Black, Duration::from_secs(11))
+                .unwrap(),
+            Duration::ZERO
+        );
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Timeout(PieceColour::Black))
+        );
+        assert!(!board.clock().unwrap().is_running());
+        assert_eq!(
+            board.result(),
+            Some(GameResult::WhiteWins(ResultReason::Timeout))
+        );
+        assert_eq!(board.termination(), Termination::TimeForfeit);
+        assert_eq!(board.termination_reason(), Some("White wins on time"));
+        assert!(board.make_move_uci("e7e5").is_err());
+
+        // time running out is noticed by the next move, or by polling check_flag
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_millis(1), Duration::ZERO);
+        board.start_clock().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(board.make_move_uci("e2e4").is_err());
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Timeout(PieceColour::White))
+        );
+        assert_eq!(board.check_flag(), None);
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_millis(1), Duration::ZERO);
+        board.start_clock().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(
+            board.check_flag(),
+            Some(GameOverState::Timeout(PieceColour::White))
+        );
+
+        // a stopped clock doesn't run out
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_millis(1), Duration::ZERO);
+        assert_eq!(board.check_flag(), None);
+        board.make_move_uci("e2e4").unwrap();
+
+        // the result and termination survive a PGN round trip
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_secs(10), Duration::ZERO);
+        board.make_move_uci("e2e4").unwrap();
+        board
+            .apply_elapsed(PieceColour::Black, Duration::from_secs(10))
+            .unwrap();
+        let exported = pgn::PGN::from(&board).to_string();
+        assert!(exported.contains("[Termination \"time forfeit\"]"));
+        let imported = Board::try_from(exported.parse::<pgn::PGN>().unwrap()).unwrap();
+        assert_eq!(imported.get_game_over_state(), board.get_game_over_state());
+    }
+
+    #[test]
+    fn test_clock_timeout_insufficient_material() {
+        let timeout = |fen: &str, side: PieceColour| {
+            let mut board = Board::from_fen(fen).unwrap();
+            board.set_time_control(Duration::from_secs(1), Duration::ZERO);
+            board.apply_elapsed(side, Duration::from_secs(1)).unwrap();
+            assert_eq!(board.winner(), board.result().unwrap().winner());
+            board.result().unwrap()
+        };
+        let draw = GameResult::Draw(ResultReason::TimeoutVsInsufficientMaterial);
+        // a lone king can't win on time, and a king and minor piece only can when the other side has something to
+        // block its own king in with
+        for (fen, white_flags, black_flags) in [
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+                draw,
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", draw, draw),
+            ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", draw, draw),
+            (
+                "4k3/8/8/8/8/8/8/3QK1n1 w - - 0 1",
+                GameResult::BlackWins(ResultReason::Timeout),
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            (
+                "4k3/p7/8/8/8/8/8/2B1K3 w - - 0 1",
+                GameResult::BlackWins(ResultReason::Timeout),
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            (
+                "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+                GameResult::BlackWins(ResultReason::Timeout),
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            ("3bk3/8/8/8/8/8/8/2B1K3 w - - 0 1", draw, draw),
+            // two bishops can only mate when they are on different square colours
+            (
+                "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+                draw,
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            ("4k3/8/8/8/8/4B3/8/2B1K3 w - - 0 1", draw, draw),
+            (
+                "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+                draw,
+                GameResult::WhiteWins(ResultReason::Timeout),
+            ),
+            (
+                "4k3/p7/8/8/8/8/8/4K3 w - - 0 1",
+                GameResult::BlackWins(ResultReason::Timeout),
+                draw,
+            ),
+        ] {
+            assert_eq!(timeout(fen, PieceColour::White), white_flags, "{}", fen);
+            assert_eq!(timeout(fen, PieceColour::Black), black_flags, "{}", fen);
+        }
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        board.set_time_control(Duration::from_secs(1), Duration::ZERO);
+        board
+            .apply_elapsed(PieceColour::Black, Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(board.winner(), None);
+        assert_eq!(board.result_string(), "1/2-1/2");
+        assert_eq!(
+            board.termination_reason(),
+            Some("Draw by timeout vs insufficient material")
+        );
+        let exported = pgn::PGN::from(&board).to_string();
+        let imported = Board::try_from(exported.parse::<pgn::PGN>().unwrap()).unwrap();
+        assert_eq!(imported.result(), Some(draw));
+    }
+
+    #[test]
+    fn test_clock_increment() {
+        // the time actually spent on each move is a few microseconds on top of the time applied
+        let assert_remaining = |board: &Board, side: PieceColour, secs: u64| {
+            let remaining = board.remaining(side).unwrap();
+            let expected = Duration::from_secs(secs);
+            assert!(
+                remaining <= expected && remaining > expected - Duration::from_millis(100),
+                "{:?} {:?}",
+                remaining,
+                expected
+            );
+        };
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_secs(60), Duration::from_secs(2));
+        board.start_clock().unwrap();
+        board
+            .apply_elapsed(PieceColour::White, Duration::from_secs(10))
+            .unwrap();
+        board.make_move_uci("e2e4").unwrap();
+        assert_remaining(&board, PieceColour::White, 52);
+        assert_remaining(&board, PieceColour::Black, 60);
+        board
+            .apply_elapsed(PieceColour::Black, Duration::from_secs(5))
+            .unwrap();
+        board.make_move_uci("e7e5").unwrap();
+        assert_remaining(&board, PieceColour::Black, 57);
+        assert_eq!(
+            board.clock().unwrap().running_side(),
+            Some(PieceColour::White)
+        );
+
+        // undoing gives the turn back but not the time, and takes the increment back
+        board.undo_move().unwrap();
+        assert_eq!(
+            board.clock().unwrap().running_side(),
+            Some(PieceColour::Black)
+        );
+        assert_remaining(&board, PieceColour::Black, 55);
+
+        // no increments while the clock is stopped, so there are none to take back either
+        board.stop_clock().unwrap();
+        board.make_move_uci("e7e5").unwrap();
+        assert!(!board.clock().unwrap().is_running());
+        assert_remaining(&board, PieceColour::Black, 55);
+        board.undo_move().unwrap();
+        assert_remaining(&board, PieceColour::Black, 55);
+        board.undo_move().unwrap();
+        assert_remaining(&board, PieceColour::White, 50);
+
+        // going back and playing a different move takes back the increments of the moves discarded, like undoing them
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_secs(60), Duration::from_secs(2));
+        board.start_clock().unwrap();
+        for mv in ["e2e4", "e7e5", "g1f3"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_remaining(&board, PieceColour::White, 64);
+        assert_remaining(&board, PieceColour::Black, 62);
+        board.checkout_prev();
+        board.checkout_prev();
+        board
+            .make_move_from_here(&notation_move(&board, "c5"))
+            .unwrap();
+        assert_remaining(&board, PieceColour::White, 62);
+        assert_remaining(&board, PieceColour::Black, 62);
+        assert_eq!(board.ply_count(), 2);
+
+        // the clock stops when the game ends, without adding the increment for the last move
+        let mut board = Board::new();
+        board.set_time_control(Duration::from_secs(60), Duration::from_secs(2));
+        board.start_clock().unwrap();
+        for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert!(!board.clock().unwrap().is_running());
+        assert_remaining(&board, PieceColour::Black, 62);
+    }
+
+    #[test]
+    fn test_adjudication() {
+        let mut board = Board::new();
+        board.make_move_uci("e2e4").unwrap();
+        board.adjudicate(GameResult::WhiteWins(ResultReason::Adjudication));
+        assert_eq!(board.winner(), Some(PieceColour::White));
+        assert_eq!(board.termination(), Termination::Adjudication);
+        assert_eq!(
+            board.termination_reason(),
+            Some("White wins by adjudication")
+        );
+        // a game that is already over isn't adjudicated
+        board.set_draw();
+        assert_eq!(board.result_string(), "1-0");
+
+        for result in ["1-0", "0-1", "1/2-1/2"] {
+            let pgn = format!(
+                "[Result \"{}\"]\n[Termination \"adjudication\"]\n\n1. e4 {}",
+                result, result
+            );
+            let board = Board::try_from(pgn.parse::<pgn::PGN>().unwrap()).unwrap();
+            assert!(matches!(
+                board.get_game_over_state(),
+                Some(GameOverState::Adjudication(_))
+            ));
+            assert_eq!(board.result_string(), result);
+            assert_eq!(board.result().unwrap().reason(), ResultReason::Adjudication);
+        }
+    }
+
+    #[test]
+    fn test_claimable_draws() {
+        // the rook walks up the a file and the black king shuffles between d8 and e8, so no position is repeated
+        let quiet = ["a1a2", "e8d8", "a2a3", "d8e8", "a3a4", "e8d8"];
+        let gamestates = |fen: &str| {
+            let mut board = Board::from_fen(fen).unwrap();
+            quiet
+                .iter()
+                .map(|mv| board.make_move_uci(mv).unwrap())
+                .collect::<Vec<GameState>>()
+        };
+        // halfmoves 99 to 104, the game goes on after the fifty move rule
+        assert_eq!(
+            gamestates("4k3/8/8/8/8/8/8/R3K3 w - - 98 80"),
+            [
+                GameState::Active,
+                GameState::FiftyMove,
+                GameState::FiftyMove,
+                GameState::FiftyMove,
+                GameState::FiftyMove,
+                GameState::FiftyMove
+            ]
+        );
+        // halfmoves 149 and 150, which ends the game
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 148 80").unwrap();
+        assert_eq!(board.make_move_uci("a1a2").unwrap(), GameState::FiftyMove);
+        assert_eq!(board.get_game_over_state(), None);
+        assert_eq!(
+            board.make_move_uci("e8d8").unwrap(),
+            GameState::SeventyFiveMove
+        );
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::SeventyFiveMove))
+        );
+        assert!(board.claim_draw().is_err());
+
+        // the starting position occurs for the 2nd to 5th time after each cycle
+        let cycle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::new();
+        let mut after_cycles = Vec::new();
+        for _ in 0..4 {
+            for mv in cycle {
+                board.make_move_uci(mv).unwrap();
+            }
+            after_cycles.push((
+                board.get_current_state().get_gamestate(),
+                board.get_game_over_state(),
+            ));
+        }
+        assert_eq!(
+            after_cycles,
+            [
+                (GameState::Active, None),
+                (GameState::Repetition, None),
+                (GameState::Repetition, None),
+                (
+                    GameState::FivefoldRepetition,
+                    Some(GameOverState::Forced(GameState::FivefoldRepetition))
+                ),
+            ]
+        );
+        // a repetition (or fifty move) state is still a draw for the result once claimed
+        assert!(GameState::Repetition.is_draw() && !GameState::Repetition.is_game_over());
+        assert!(GameState::FivefoldRepetition.is_game_over());
+    }
+
+    #[test]
+    fn test_claim_draw() {
+        let cycle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::new();
+        assert!(matches!(
+            board.claim_draw(),
+            Err(BoardStateError::DrawNotClaimable(GameState::Active))
+        ));
+        for mv in cycle.iter().chain(&cycle[..3]) {
+            board.make_move_uci(mv).unwrap();
+        }
+
+        // Ng8 would repeat the starting position a third time, a claim with any other move is wrong and isn't played
+        let e5 = notation_move(&board, "e5");
+        assert!(matches!(
+            board.claim_draw_with_move(&e5),
+            Err(BoardStateError::DrawNotClaimable(GameState::Active))
+        ));
+        assert_eq!(board.move_history_notation().len(), 7);
+        let ng8 = notation_move(&board, "Ng8");
+        let mut claimed_with_move = board.clone();
+        claimed_with_move.claim_draw_with_move(&ng8).unwrap();
+        assert_eq!(claimed_with_move.move_history_notation().len(), 8);
+        assert_eq!(
+            claimed_with_move.get_game_over_state(),
+            Some(GameOverState::DrawClaimed(GameState::Repetition))
+        );
+        assert!(pgn::PGN::from(&claimed_with_move)
+            .to_string()
+            .trim_end()
+            .ends_with("1/2-1/2"));
+
+        // after the move the draw is there to claim, but the other side can also play on
+        board.make_move(&ng8).unwrap();
+        let mut played_on = board.clone();
+        played_on.make_move_uci("g1f3").unwrap();
+        assert_eq!(played_on.get_game_over_state(), None);
+        board.claim_draw().unwrap();
+        assert!(matches!(
+            board.claim_draw(),
+            Err(BoardStateError::GameOver(_))
+        ));
+        assert!(board.make_move_uci("g1f3").is_err());
+        // taking back the repeating move takes back the claim too
+        board.undo_move().unwrap();
+        assert_eq!(board.get_game_over_state(), None);
+
+        // the old behaviour, where the game ends as soon as a draw can be claimed
+        let mut board = Board::new().with_auto_claim_draws(true);
+        assert!(board.auto_claim_draws());
+        for mv in cycle.iter().chain(&cycle) {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::Repetition))
+        );
+        let variation = board.start_variation_from_current();
+        assert!(variation.auto_claim_draws());
+        assert!(variation.get_game_over_state().is_some());
+    }
+
+    #[test]
+    fn test_repetition_queries() {
+        // e4 e5, then the knights go out and back, and out again with black's knight going to e7 instead
+        let mut board = Board::new();
+        for mv in [
+            "e2e4", "e7e5", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8e7",
+        ] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let counts: Vec<u8> = (0..=8)
+            .map(|ply| board.repetition_count_at_ply(ply).unwrap())
+            .collect();
+        assert_eq!(counts, [1, 1, 1, 1, 1, 1, 2, 2, 1]);
+        assert_eq!(board.repetition_count_at_ply(9), None);
+        let after_e5 = board.get_state_history()[2].position_hash;
+        assert_eq!(board.get_current_state().occurences_of(after_e5), 2);
+        assert_eq!(
+            board.repeated_positions(),
+            [
+                RepeatedPosition {
+                    position_hash: after_e5,
+                    count: 2,
+                    plies: vec![2, 6],
+                },
+                RepeatedPosition {
+                    position_hash: board.get_state_history()[3].position_hash,
+                    count: 2,
+                    plies: vec![3, 7],
+                },
+            ]
+        );
+        // no white move repeats a position, as black's knight is on e7
+        assert!(!board.is_threefold_claimable());
+
+        // Ng8 would repeat the position after e5 a third time
+        board.make_move_uci("f3g1").unwrap();
+        assert!(board.is_threefold_claimable());
+        board.make_move_uci("e7g8").unwrap();
+        assert_eq!(board.repetition_count_at_ply(10), Some(3));
+        assert!(board.is_threefold_claimable());
+        let repeated = board.repeated_positions();
+        assert_eq!(repeated.len(), 2);
+        assert_eq!(repeated[0].plies, [2, 6, 10]);
+        assert_eq!(repeated[0].count, 3);
+        assert_eq!(board.get_current_state().occurences_of(after_e5), 3);
+
+        // nothing to claim from a checked out state or once the draw is claimed
+        let mut checked_out = board.clone();
+        checked_out.checkout_prev();
+        assert!(!checked_out.is_threefold_claimable());
+        board.claim_draw().unwrap();
+        assert!(!board.is_threefold_claimable());
+        assert_eq!(board.repeated_positions(), repeated);
+    }
+
+    #[test]
+    fn test_find_plies_with_position() {
+        // both sides' knights come out, go back and come out again in the other order
+        let mut board = Board::new();
+        for mv in [
+            "g1f3", "g8f6", "b1c3", "b8c6", "f3g1", "f6g8", "g1f3", "g8f6",
+        ] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let four_knights = board.get_state_history()[4].to_fen();
+        // the move counters don't have to match
+        let query = "r1bqkb1r/pppppppp/2n2n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R w KQkq - 0 1";
+        assert_eq!(
+            board.find_plies_with_position(query.parse::<FEN>().unwrap()),
+            [4, 8]
+        );
+        assert_eq!(
+            board.find_plies_with_position(four_knights.parse::<FEN>().unwrap()),
+            [4, 8]
+        );
+        let start_hash = board.get_starting_state().position_hash;
+        assert_eq!(board.find_plies_with_position(start_hash), [0]);
+        // black to move in the same position is a different position
+        let black_to_move = "r1bqkb1r/pppppppp/2n2n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R b KQkq - 0 1";
+        assert!(board
+            .find_plies_with_position(black_to_move.parse::<FEN>().unwrap())
+            .is_empty());
+        // the whole game is searched from a checked out state
+        board.checkout_starting_state();
+        assert_eq!(
+            board.find_plies_with_position(query.parse::<FEN>().unwrap()),
+            [4, 8]
+        );
+
+        // a game from a FEN counts its starting position as ply 0
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 10 40";
+        let mut board = Board::from_fen(fen).unwrap();
+        for mv in ["a1a2", "e8d8", "a2a1", "d8e8"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            board.find_plies_with_position(fen.parse::<FEN>().unwrap()),
+            [0, 4]
+        );
+    }
+
+    #[test]
+    fn test_metadata_pgn_round_trip() {
+        let mut board = Board::new();
+        for mv in ["e2e4", "c7c5", "g1f3"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        // defaults when nothing is set
+        let tags = pgn::PGN::from(&board).tags().to_vec();
+        assert!(tags.contains(&Tag::Event("Chess Oxide".to_string())));
+        assert!(tags.contains(&Tag::White("?".to_string())));
+
+        let metadata = GameMetadata {
+            event: Some("Club championship".to_string()),
+            site: Some("Leeds ENG".to_string()),
+            round: Some("3".to_string()),
+            date: Some("2024.05.??".parse().unwrap()),
+            white: PlayerData {
+                name: Some("Smith, Anna".to_string()),
+                elo: Some(1850),
+            },
+            black: PlayerData {
+                name: Some("Jones, Ben".to_string()),
+                elo: None,
+            },
+            extra_tags: vec![
+                Tag::TimeControl("5400+30".parse().unwrap()),
+                Tag::Other("Board".to_string(), "2".to_string()),
+            ],
+        };
+        board.set_metadata(metadata.clone());
+        let exported = pgn::PGN::from(&board).to_string();
+        for tag in metadata.tags() {
+            assert!(exported.contains(&tag.to_string()), "{}", tag);
+        }
+
+        // importing keeps the tags for exporting again, the ones written from the game itself aren't stored
+        let imported = Board::try_from(exported.parse::<pgn::PGN>().unwrap()).unwrap();
+        assert_eq!(imported.metadata(), &metadata);
+        assert_eq!(pgn::PGN::from(&imported).to_string(), exported);
+
+        board.metadata_mut().round = None;
+        assert!(pgn::PGN::from(&board)
+            .tags()
+            .contains(&Tag::Round("?".to_string())));
+        assert!(!board.metadata_mut().set_tag(Tag::PlyCount(3)));
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let round_trip = |board: &Board| {
+            let mut bytes = Vec::new();
+            board.save(&mut bytes).unwrap();
+            let loaded = Board::load(bytes.as_slice()).unwrap();
+            assert_eq!(loaded.variant(), board.variant());
+            assert_eq!(loaded.get_game_over_state(), board.get_game_over_state());
+            assert_eq!(loaded.metadata(), board.metadata());
+            assert_eq!(loaded.move_history, board.move_history);
+            // the latest state is loaded, with every hash and repetition count rebuilt
+            assert_eq!(loaded.get_state_history(), board.get_state_history());
+            assert_eq!(
+                loaded.get_current_state(),
+                board.get_state_history().last().unwrap()
+            );
+            assert_eq!(
+                loaded
+                    .get_current_state()
+                    .get_occurences_of_current_position(),
+                board
+                    .get_state_history()
+                    .last()
+                    .unwrap()
+                    .get_occurences_of_current_position()
+            );
+            bytes
+        };
+
+        // mid game with castling, en passant, promotion and a repeated position, checked out earlier
+        let mut board = Board::from_fen("r3k2r/pP4pp/8/3pP3/8/8/6PP/R3K2R w KQkq d6 0 1").unwrap();
+        board.metadata.white.name = Some("White".to_string());
+        board.metadata.black.elo = Some(2100);
+        board.metadata.event = Some("Casual game".to_string());
+        board
+            .metadata
+            .set_tag(Tag::Other("Opening".to_string(), "Unknown".to_string()));
+        for s in [
+            "e5d6", "e8g8", "b7a8n", "f8a8", "e1c1", "g8f8", "d1d2", "f8g8", "d2d1", "g8f8",
+        ] {
+            board.make_move_uci(s).unwrap();
+        }
+        board.checkout_prev();
+        let bytes = round_trip(&board);
+        // each move takes 2 bytes
+        board.checkout_latest_state();
+        board.make_move_uci("d1d2").unwrap();
+        assert_eq!(round_trip(&board).len(), bytes.len() + 2);
+
+        // finished games, forced and otherwise
+        let mut board = Board::new();
+        for s in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(s).unwrap();
+        }
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::Checkmate))
+        );
+        round_trip(&board);
+        let mut board = Board::new_chess960_from_num(100).unwrap();
+        board.make_move_uci("e2e4").unwrap();
+        board.set_resign(PieceColour::Black);
+        round_trip(&board);
+        let mut board = Board::new();
+        board.set_draw();
+        round_trip(&board);
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_games() {
+        let mut board = Board::new();
+        for s in ["e2e4", "e7e5", "g1f3"] {
+            board.make_move_uci(s).unwrap();
+        }
+        let saved = |board: &Board| {
+            let mut saved = SavedGame {
+                variant: board.variant,
+                starting_fen: board.get_starting_state().to_fen(),
+                tags: Vec::new(),
+                moves: board.move_history.iter().map(Move::short_move).collect(),
+                game_over_state: board.game_over_state,
+            };
+            let load = |saved: &SavedGame| {
+                let mut bytes = Vec::new();
+                saved.write(&mut bytes).unwrap();
+                Board::load(bytes.as_slice())
+            };
+            assert!(load(&saved).is_ok());
+            // a move that isn't legal in the position it was recorded in
+            saved.moves[1] = saved.moves[0];
+            assert!(matches!(load(&saved), Err(SaveGameError::IllegalMove(_))));
+            saved.moves.truncate(1);
+            // a forced game over that replaying doesn't reach
+            saved.game_over_state = Some(GameOverState::Forced(GameState::Checkmate));
+            assert!(matches!(load(&saved), Err(SaveGameError::InvalidFormat(_))));
+            // an invalid starting position
+            saved.game_over_state = None;
+            saved.starting_fen = "8/8/8/8/8/8/8/8 w - - 0 1".to_string();
+            assert!(matches!(load(&saved), Err(SaveGameError::InvalidFormat(_))));
+        };
+        saved(&board);
+
+        let mut bytes = Vec::new();
+        board.save(&mut bytes).unwrap();
+        assert!(Board::load(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Board::load(&b"PGN?"[..]).is_err());
+        assert!(Board::load(std::io::empty()).is_err());
+    }
+
+    #[test]
+    fn test_random_legal_move() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let bs = BoardState::new_starting();
+        let legal_moves = bs.get_legal_moves().unwrap();
+        let mut counts = vec![0u32; legal_moves.len()];
+        for _ in 0..2000 {
+            let mv = bs.random_legal_move(&mut rng).unwrap();
+            counts[legal_moves.iter().position(|m| *m == mv).unwrap()] += 1;
+        }
+        // 100 expected samples per move, 20 is far outside the variance of a uniform choice
+        assert_eq!(counts.len(), 20);
+        assert!(counts.iter().all(|&c| c > 20), "{:?}", counts);
+
+        // lazy states pick from the lazily generated legal moves
+        let lazy = bs.next_state_unchecked(&legal_moves[0]);
+        assert!(lazy.get_legal_moves().is_err());
+        let mv = lazy.random_legal_move(&mut rng).unwrap();
+        assert!(lazy.lazy_get_legal_moves().any(|m| *m == mv));
+
+        let mut board = Board::try_from(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(board.random_legal_move(&mut rng).is_none());
+        assert!(board
+            .get_current_state()
+            .random_legal_move(&mut rng)
+            .is_none());
+
+        board = Board::new();
+        let mv = board.random_legal_move(&mut rng).unwrap();
+        board.make_move(&mv).unwrap();
+    }
+
+    #[test]
+    fn test_move_history_notation_cached() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // a 200 move game, played again from the start if it ends sooner
+        let mut rng = StdRng::seed_from_u64(2107);
+        let mut board = Board::new();
+        while board.move_history.len() < 400 {
+            let mv = board.random_legal_move(&mut rng).unwrap();
+            if board.make_move(&mv).unwrap().is_game_over() {
+                board = Board::new();
+            }
+        }
+
+        // the same notation as generating it from each state
+        let generated: Vec<Notation> = board
+            .state_history
+            .iter()
+            .zip(&board.move_history)
+            .map(|(state, mv)| Notation::from_mv_with_context(state, mv).unwrap())
+            .collect();
+        assert_eq!(board.move_history_notation(), generated);
+        assert_eq!(
+            board.move_history_string_notation(),
+            generated
+                .iter()
+                .map(Notation::to_string)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(board.last_move_notation(), generated.last().cloned());
+        board.checkout_prev();
+        assert_eq!(board.last_move_notation(), generated.get(398).cloned());
+        board.checkout_starting_state();
+        assert_eq!(board.last_move_notation(), None);
+        board.checkout_latest_state();
+
+        // and looked up rather than generated again, generating it for every call would take seconds
+        let start = std::time::Instant::now();
+        for _ in 0..10_000 {
+            assert!(board.last_move_notation().is_some());
+        }
+        for _ in 0..100 {
+            assert_eq!(board.move_history_string_notation().len(), 400);
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_find_state_by_ply_and_move_number() {
+        // Nf3 and Ng1 are both played twice
+        let mut board = Board::new();
+        for mv in [
+            "g1f3", "e7e5", "f3g1", "b8c6", "g1f3", "g8f6", "f3g1", "f8c5",
+        ] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let nf3 = board.find_states_by_notation("Nf3");
+        assert_eq!(nf3.len(), 2);
+        assert_ne!(nf3[0].board_hash, nf3[1].board_hash);
+
+        assert_eq!(board.find_state_at_ply(0), Some(board.get_starting_state()));
+        assert_eq!(board.find_state_at_ply(1), Some(nf3[0]));
+        assert_eq!(board.find_state_at_ply(5), Some(nf3[1]));
+        assert_eq!(board.find_state_at_ply(8), Some(board.get_current_state()));
+        assert_eq!(board.find_state_at_ply(9), None);
+
+        assert_eq!(
+            board.find_state_by_move_number(1, PieceColour::White),
+            Some(nf3[0])
+        );
+        assert_eq!(
+            board.find_state_by_move_number(3, PieceColour::White),
+            Some(nf3[1])
+        );
+        let ng1 = board
+            .find_state_by_move_number(4, PieceColour::White)
+            .unwrap();
+        assert_eq!(ng1.last_move.unwrap().to_string(), "f3g1");
+        assert_eq!(board.find_states_by_notation("Ng1")[1], ng1);
+        assert_eq!(
+            board.find_state_by_move_number(4, PieceColour::Black),
+            Some(board.get_current_state())
+        );
+        assert_eq!(board.find_state_by_move_number(5, PieceColour::White), None);
+        assert_eq!(board.find_state_by_move_number(0, PieceColour::Black), None);
+
+        // a game from a position starting with black's 20th move
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 0 20").unwrap();
+        for mv in ["e8d7", "a1a7", "d7e6"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            board.find_state_by_move_number(20, PieceColour::White),
+            None
+        );
+        assert_eq!(
+            board.find_state_by_move_number(20, PieceColour::Black),
+            board.find_state_at_ply(1)
+        );
+        assert_eq!(
+            board.find_state_by_move_number(21, PieceColour::Black),
+            Some(board.get_current_state())
+        );
+        assert_eq!(
+            board
+                .find_state_at_ply(2)
+                .unwrap()
+                .last_move
+                .unwrap()
+                .to_string(),
+            "a1a7"
+        );
+    }
+
+    #[test]
+    fn test_undo_move() {
+        let mut board = Board::new();
+        assert!(matches!(
+            board.undo_move(),
+            Err(BoardStateError::NotFound(_))
+        ));
+
+        // fool's mate
+        for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::Checkmate))
+        );
+        let mated = board.get_current_state().clone();
+        let mv = board.undo_move().unwrap();
+        assert_eq!(mv.to_string(), "d8h4");
+        assert_eq!(board.get_game_over_state(), None);
+        assert_eq!(board.get_current_state().get_gamestate(), GameState::Active);
+        assert_eq!(board.move_history_string_notation(), ["f3", "e5", "g4"]);
+        assert_eq!(board.find_state_at_ply(4), None);
+        board.make_move(&mv).unwrap();
+        assert_eq!(board.get_current_state(), &mated);
+
+        // undo_moves returns moves in the order they were played, and does nothing when asked for too many
+        assert!(board.undo_moves(5).is_err());
+        assert_eq!(board.move_history_notation().len(), 4);
+        let undone: Vec<String> = board
+            .undo_moves(2)
+            .unwrap()
+            .iter()
+            .map(Move::to_string)
+            .collect();
+        assert_eq!(undone, ["g2g4", "d8h4"]);
+        assert_eq!(board.undo_moves(2).unwrap().len(), 2);
+        assert_eq!(board.get_current_state(), board.get_starting_state());
+
+        // resignations stand after an undo
+        board.make_move_uci("e2e4").unwrap();
+        board.set_resign(PieceColour::Black);
+        board.undo_move().unwrap();
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::BlackResign)
+        );
+
+        // can't undo while looking at an earlier state
+        let mut board = Board::new();
+        board.make_move_uci("e2e4").unwrap();
+        board.checkout_prev();
+        assert!(matches!(
+            board.undo_move(),
+            Err(BoardStateError::Detatched(_))
+        ));
+    }
+
+    #[test]
+    fn test_undo_move_repetition() {
+        let mut board = Board::new();
+        let cycle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for mv in cycle {
+            board.make_move_uci(mv).unwrap();
+        }
+        let twice = board.get_current_state().clone();
+        assert_eq!(twice.get_occurences_of_current_position(), 2);
+        for mv in &cycle[..3] {
+            board.make_move_uci(mv).unwrap();
+        }
+        // undo the second cycle and replay it, the occurrence counts come back the same
+        board.undo_moves(3).unwrap();
+        assert_eq!(board.get_current_state(), &twice);
+        assert_eq!(board.get_current_state().board_hash, twice.board_hash);
+        for mv in cycle {
+            board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            3
+        );
+
+        // undoing the repetition reopens the game
+        board.undo_move().unwrap();
+        assert_eq!(board.get_game_over_state(), None);
+        board.make_move_uci("f6g8").unwrap();
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+    }
+
+    #[test]
+    fn test_make_move_from_here() {
+        let find = |board: &Board, s: &str| {
+            let short = ShortMove::from_coordinate_notation(s).unwrap();
+            board.get_current_state().find_move_short(short).unwrap()
+        };
+        // fool's mate, then go back two plies and play a different line
+        let mut board = Board::new();
+        for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let mut latest = board.clone();
+        latest.undo_move().unwrap();
+        let d8h4 = find(&latest, "d8h4");
+        board.checkout_prev();
+        board.checkout_prev();
+        assert_eq!(board.detatched_idx(), Some(2));
+        let e2e4 = find(&board, "e2e4");
+        assert!(board.make_move(&e2e4).is_err());
+
+        // an illegal move discards nothing
+        assert!(board.make_move_from_here(&d8h4).is_err());
+        assert_eq!(board.detatched_idx(), Some(2));
+        assert_eq!(board.move_history_notation().len(), 4);
+
+        assert_eq!(board.make_move_from_here(&e2e4).unwrap(), GameState::Active);
+        assert!(!board.is_detatched());
+        assert_eq!(board.get_game_over_state(), None);
+        // not detatched, so the same as make_move
+        let nc6 = find(&board, "b8c6");
+        board.make_move_from_here(&nc6).unwrap();
+        assert_eq!(
+            board.move_history_string_notation(),
+            ["f3", "e5", "e4", "Nc6"]
+        );
+        assert_eq!(board.find_state_at_ply(4), Some(board.get_current_state()));
+        assert_eq!(board.find_state_at_ply(5), None);
+
+        let mut expected = Board::new();
+        for mv in ["f2f3", "e7e5", "e2e4", "b8c6"] {
+            expected.make_move_uci(mv).unwrap();
+        }
+        for ply in 0..=4 {
+            assert_eq!(
+                board.find_state_at_ply(ply).unwrap().board_hash,
+                expected.find_state_at_ply(ply).unwrap().board_hash
+            );
+        }
+        assert_eq!(
+            pgn::PGN::from(&board).to_string(),
+            pgn::PGN::from(&expected).to_string()
+        );
+
+        // a resignation isn't undone by branching
+        board.set_resign(PieceColour::White);
+        board.checkout_prev();
+        let nf6 = find(&board, "g8f6");
+        assert!(matches!(
+            board.make_move_from_here(&nf6),
+            Err(BoardStateError::GameOver(GameOverState::WhiteResign))
+        ));
+    }
+
+    #[test]
+    fn test_variations() {
+        // scholar's mate in the mainline
+        let mainline = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"];
+        let mut board = Board::new();
+        for mv in mainline {
+            board.make_move_uci(mv).unwrap();
+        }
+        let main_states = board.state_history.clone();
+        let state = board.find_state_at_ply(6).unwrap().clone();
+        board.checkout_state(&state).unwrap();
+
+        // black takes the queen instead of being mated
+        let variation = ["d2d3", "f6h5", "g1f3", "d7d6"];
+        let var_board = board.start_variation_from_current();
+        assert!(!var_board.is_detatched());
+        for mv in variation {
+            var_board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            var_board.get_current_state().get_gamestate(),
+            GameState::Active
+        );
+        assert_eq!(var_board.get_game_over_state(), None);
+
+        // the mainline is untouched
+        assert_eq!(board.detatched_idx(), Some(6));
+        board.checkout_latest_state();
+        assert_eq!(board.state_history, main_states);
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::Checkmate))
+        );
+
+        assert_eq!(board.variations().len(), 1);
+        let var = &board.variations()[0];
+        assert_eq!(var.branch_ply(), 6);
+        let moves: Vec<String> = var.moves().iter().map(Move::to_string).collect();
+        assert_eq!(moves, variation);
+        assert_eq!(var.board().find_state_at_ply(6), Some(&state));
+
+        // the variation's states are the same as playing its line from scratch
+        let mut expected = Board::new();
+        for mv in mainline[..6].iter().chain(&variation) {
+            expected.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(var.board().state_history, expected.state_history);
+        assert_eq!(
+            var.board().move_history_string_notation(),
+            expected.move_history_string_notation()
+        );
+
+        // a variation from a finished game is over too, and variations from taken back moves are dropped
+        board.start_variation_from_current();
+        assert!(board
+            .variation_mut(1)
+            .unwrap()
+            .make_move_uci("e8f7")
+            .is_err());
+        board.undo_move().unwrap();
+        assert_eq!(board.variations().len(), 1);
+        assert_eq!(board.remove_variation(0).unwrap().branch_ply(), 6);
+        assert!(board.variations().is_empty());
+    }
+
+    #[test]
+    fn test_variation_repetition() {
+        // knights out and back twice in the variation, the starting position's earlier occurrences count
+        let mut board = Board::new();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "e2e4"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        board.checkout_prev();
+        let var_board = board.start_variation_from_current();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            var_board.make_move_uci(mv).unwrap();
+        }
+        assert_eq!(
+            var_board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+        var_board.claim_draw().unwrap();
+        board.checkout_latest_state();
+        assert_eq!(board.get_game_over_state(), None);
+        assert_eq!(board.get_current_state().get_gamestate(), GameState::Active);
+    }
+
+    #[test]
+    fn test_board_find_mate() {
+        let mut board = Board::try_from(
+            "r2qk2r/pb4pp/1n2Pb2/2B2Q2/p1p5/2P5/2B2PPP/RN2R1K1 w - - 1 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let line = board.find_mate(3).unwrap();
+        for mv in &line {
+            board.make_move(mv).unwrap();
+        }
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::Checkmate))
+        );
+        assert!(board.find_mate(3).is_none());
+    }
+
+    fn material_fen(fen: &str) -> MaterialCount {
+        BoardState::try_from(fen.parse::<FEN>().unwrap())
+            .unwrap()
+            .material()
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        assert!(material_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_insufficient());
+        assert!(material_fen("4k3/8/8/8/8/8/8/3NK3 w - - 0 1").is_insufficient());
+        assert!(material_fen("4k3/8/8/8/8/8/8/3BK3 b - - 0 1").is_insufficient());
+        // same colour bishops
+        assert!(material_fen("2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1").is_insufficient());
+        // opposite colour bishops
+        assert!(!material_fen("3bk3/8/8/8/8/8/8/3BK3 w - - 0 1").is_insufficient());
+        assert!(!material_fen("4k3/8/8/8/8/8/8/2NNK3 w - - 0 1").is_insufficient());
+        assert!(!material_fen("3nk3/8/8/8/8/8/8/3NK3 w - - 0 1").is_insufficient());
+        assert!(!material_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_insufficient());
+        assert!(!material_fen("4k3/8/8/8/8/8/8/3RK3 w - - 0 1").is_insufficient());
+        assert!(!BoardState::new_starting().material().is_insufficient());
+    }
+
+    #[test]
+    fn test_material_after_promotion_capture() {
+        let board =
+            Board::try_from("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let mv = notation_move(&board, "axb8=B");
+        let next = board.get_current_state().next_state(&mv).unwrap();
+        // the promoted bishop is on b8, a dark square
+        let material = next.material();
+        assert_eq!(
+            (
+                material.white.pawns,
+                material.white.dark_bishops,
+                material.black.rooks
+            ),
+            (0, 1, 0)
+        );
+        assert_eq!(material.balance(), 330);
+        assert_eq!(next.get_gamestate(), GameState::InsufficientMaterial);
+    }
+
+    #[test]
+    fn test_make_move_insufficient_material() {
+        // Kxd2 leaves K+N vs K
+        let mut board =
+            Board::try_from("4k3/8/8/8/8/8/3r4/3NK3 w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let mv = notation_move(&board, "Kxd2");
+        assert_eq!(
+            board.make_move(&mv).unwrap(),
+            GameState::InsufficientMaterial
+        );
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(GameState::InsufficientMaterial))
+        );
+
+        // Kxd2 leaves K+N+N vs K, which isn't a dead position
+        let mut board =
+            Board::try_from("4k3/8/8/8/8/8/3r4/2NNK3 w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let mv = notation_move(&board, "Kxd2");
+        assert_eq!(board.make_move(&mv).unwrap(), GameState::Active);
+        assert_eq!(board.get_game_over_state(), None);
+    }
+
+    #[test]
+    fn test_would_repeat() {
+        let mut board = Board::new();
+        for n in ["Nf3", "Nf6", "Ng1"] {
+            let mv = notation_move(&board, n);
+            board.make_move(&mv).unwrap();
+        }
+        // Ng8 returns to the starting position for the second time
+        let mv = notation_move(&board, "Ng8");
+        assert_eq!(board.get_current_state().would_repeat(&mv), Some(2));
+        // a move to a position that hasn't been seen before
+        let novel = notation_move(&board, "e5");
+        assert_eq!(board.get_current_state().would_repeat(&novel), Some(1));
+
+        board.make_move(&mv).unwrap();
+        assert_eq!(
+            board
+                .get_current_state()
+                .get_occurences_of_current_position(),
+            2
+        );
+        for n in ["Nf3", "Nf6", "Ng1"] {
+            let mv = notation_move(&board, n);
+            board.make_move(&mv).unwrap();
+        }
+        let mv = notation_move(&board, "Ng8");
+        assert_eq!(board.get_current_state().would_repeat(&mv), Some(3));
+
+        assert_eq!(board.get_current_state().would_repeat(&NULL_MOVE), None);
+    }
+
+    #[test]
+    fn test_halfmove_count_reset_by_en_passant_and_promotion() {
+        let halfmove_count = |board: &Board| board.get_current_state().halfmove_count();
+        let last_move_type = |board: &Board| board.get_current_state().last_move.unwrap().move_type;
+
+        let mut board = Board::from_fen("4k3/3p4/8/4P3/8/8/8/4K3 b - - 20 40").unwrap();
+        board.make_move_san("d5").unwrap();
+        board.make_move_san("exd6").unwrap();
+        assert!(matches!(last_move_type(&board), MoveType::EnPassant(_)));
+        assert_eq!(halfmove_count(&board), 0);
+        board.make_move_san("Kd7").unwrap();
+        assert_eq!(halfmove_count(&board), 1);
+
+        // promotions, with and without a capture and to pieces other than a queen
+        for (san, halfmove_count_before) in [("b8=Q+", 30), ("b8=N", 41), ("bxa8=R+", 99)] {
+            let mut board = Board::from_fen(&format!(
+                "r3k3/1P6/8/8/8/8/8/4K3 w - - {} 60",
+                halfmove_count_before
+            ))
+            .unwrap();
+            board.make_move_san(san).unwrap();
+            assert!(
+                matches!(last_move_type(&board), MoveType::Promotion(_, _)),
+                "{}",
+                san
+            );
+            assert_eq!(halfmove_count(&board), 0, "{}", san);
+        }
+    }
+
+    #[test]
+    fn test_halfmove_count_past_fifty_move_rule() {
+        let bs = BoardState::try_from("4k3/8/8/8/8/8/8/R3K3 w - - 150 80".parse::<FEN>().unwrap())
+            .unwrap();
+        assert_eq!(bs.halfmove_count(), 150);
+        assert_eq!(bs.get_gamestate(), GameState::SeventyFiveMove);
+        // every halfmove count from 100 to 149 has the same hash, and so does every count from 150
+        let at = |halfmove_count: u32| {
+            BoardState::try_from(
+                format!("4k3/8/8/8/8/8/8/R3K3 w - - {} 80", halfmove_count)
+                    .parse::<FEN>()
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(at(100).board_hash, at(149).board_hash);
+        assert_eq!(bs.board_hash, at(200).board_hash);
+        assert_ne!(at(99).board_hash, at(100).board_hash);
+        assert_ne!(at(149).board_hash, bs.board_hash);
+        assert_eq!(at(99).get_gamestate(), GameState::Active);
+        assert_eq!(at(100).get_gamestate(), GameState::FiftyMove);
+        assert_eq!(at(149).get_gamestate(), GameState::FiftyMove);
+
+        // quiet moves from just before the fifty move rule to well past it, the game carries on while the draw can
+        // only be claimed. next_state rejects moves once the game is over so the engine's next_state_unchecked is used
+        // from there
+        let mut bs =
+            BoardState::try_from("4k3/8/8/8/8/8/8/R3K3 w - - 97 80".parse::<FEN>().unwrap())
+                .unwrap();
+        // the rook walks up the a file and the black king shuffles between d8 and e8, so no position is repeated
+        let squares = [48, 3, 40, 4, 32, 3, 24, 4, 16, 3, 17, 4];
+        for (i, to) in squares.into_iter().enumerate() {
+            let mv = bs.lazy_get_legal_moves().find(|mv| mv.to == to).unwrap();
+            bs = if bs.get_gamestate().is_game_over() {
+                bs.next_state_unchecked(mv)
+            } else {
+                bs.next_state(mv).unwrap()
+            };
+            assert_eq!(bs.halfmove_count(), 98 + i as u32);
+        }
+        assert_eq!(bs.get_gamestate(), GameState::FiftyMove);
+    }
+
+    #[test]
+    fn test_position_occurring_more_than_three_times() {
+        let start = BoardState::new_starting();
+        let mut bs = start.clone();
+        // Nf3, Nf6, Ng1, Ng8 five times
+        let squares = [45, 21, 62, 6];
+        for i in 0..5 {
+            for to in squares {
+                let mv = bs
+                    .lazy_get_legal_moves()
+                    .find(|mv| mv.piece.ptype == PieceType::Knight && mv.to == to)
+                    .unwrap();
+                bs = bs.next_state_unchecked(mv);
+            }
+            assert_eq!(bs.position_hash, start.position_hash);
+            assert_eq!(bs.get_occurences_of_current_position(), i + 2);
+            if i + 2 == 3 {
+                assert_eq!(bs.get_gamestate(), GameState::Repetition);
+            }
+        }
+
+        // occurrences 1 to 5 have different hashes, 5 (fivefold repetition) and above share one
+        let hashes: Vec<PositionHash> = (1..=6)
+            .map(|occurrences| zobrist::board_state_hash(start.position_hash, occurrences, 0))
+            .collect();
+        for i in 1..5 {
+            assert!(!hashes[..i].contains(&hashes[i]));
+        }
+        assert_eq!(hashes[4], hashes[5]);
+        assert_eq!(
+            zobrist::board_state_hash(start.position_hash, u8::MAX, 0),
+            hashes[4]
+        );
+    }
+
+    #[test]
+    fn test_engine_finds_repetition_draw() {
+        // white is a queen down
+        let mut board = Board::try_from("4k3/8/8/8/8/8/q7/4K1N1 b - - 0 1".parse::<FEN>().unwrap())
+            .unwrap()
+            .with_tt_size_mb(1);
+        for n in ["Kd8", "Nf3", "Ke8"] {
+            let mv = notation_move(&board, n);
+            board.make_move(&mv).unwrap();
+        }
+        // the transposition table has entries for the positions before they are repeated
+        board.engine_analyse(3);
+        for n in ["Ng1", "Kd8", "Nf3", "Ke8"] {
+            let mv = notation_move(&board, n);
+            board.make_move(&mv).unwrap();
+        }
+        // Ng1 repeats the starting position for the third time
+        let analysis = board.engine_analyse(3);
+        assert_eq!(analysis.eval, 0);
+        assert_eq!(analysis.best_move_notation.unwrap().to_string(), "Ng1");
+        board.make_engine_move(3).unwrap();
+        assert_eq!(
+            board.get_current_state().get_gamestate(),
+            GameState::Repetition
+        );
+        // black can claim the draw, or play on
+        assert_eq!(board.get_game_over_state(), None);
+        board.claim_draw().unwrap();
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::DrawClaimed(GameState::Repetition))
+        );
+    }
+
+    // plays the moves chosen by next_move on a SearchBoard and on BoardStates side by side, they must agree on every
+    // position. Unmaking all the moves must get back to the root
+    fn assert_search_board_matches(
+        root: &BoardState,
+        mut next_move: impl FnMut(&BoardState) -> Option<Move>,
+    ) {
+        let mut board = SearchBoard::new(root, 0);
+        let mut bs = root.clone();
+        let mut undos = Vec::new();
+        while let Some(mv) = next_move(&bs) {
+            undos.push(board.make_move(&mv, AttackMap::default()));
+            bs = bs.next_state_unchecked(&mv);
+            let fen = FEN::from(&bs);
+            assert_eq!(board.position(), bs.position(), "{}", fen);
+            assert_eq!(board.position_hash(), bs.position_hash, "{}", fen);
+            assert_eq!(board.last_move(), bs.last_move, "{}", fen);
+            assert_eq!(
+                board.get_occurences_of_current_position(),
+                bs.get_occurences_of_current_position(),
+                "{}",
+                fen
+            );
+            assert_eq!(board.get_gamestate(), bs.get_gamestate(), "{}", fen);
+        }
+        while let Some(undo) = undos.pop() {
+            board.unmake_move(undo);
+        }
+        assert_eq!(board.position(), root.position());
+        assert_eq!(board.position_hash(), root.position_hash);
+        assert_eq!(board.get_gamestate(), root.get_gamestate());
+    }
+
+    #[test]
+    fn test_search_board_matches_board_state() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // the starting position has already occurred twice before the search, so it is repeated on the fourth ply
+        let mut board = Board::new();
+        for n in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            let mv = notation_move(&board, n);
+            board.make_move(&mv).unwrap();
+        }
+        let mut squares = [45, 21, 62, 6, 45, 21, 62, 6].into_iter();
+        assert_search_board_matches(board.get_current_state(), |bs| {
+            let to = squares.next()?;
+            bs.lazy_get_legal_moves()
+                .find(|mv| mv.piece.ptype == PieceType::Knight && mv.to == to)
+                .copied()
+        });
+
+        // the fifty move rule is reached during the search
+        let root = BoardState::try_from("4k3/8/8/8/8/8/8/R3K3 w - - 97 80".parse::<FEN>().unwrap())
+            .unwrap();
+        let mut squares = [48, 3, 40, 4, 32, 3].into_iter();
+        assert_search_board_matches(&root, |bs| {
+            let to = squares.next()?;
+            bs.lazy_get_legal_moves().find(|mv| mv.to == to).copied()
+        });
+
+        // random games cover captures, promotions, castling, insufficient material and checkmate
+        let mut rng = StdRng::seed_from_u64(0);
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/8/8/8/3p4/4KN2 w - - 0 1",
+        ] {
+            let root = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            for _ in 0..20 {
+                let mut plies = 0;
+                assert_search_board_matches(&root, |bs| {
+                    plies += 1;
+                    if plies > 60 {
+                        return None;
+                    }
+                    bs.random_legal_move(&mut rng)
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_board_null_move() {
+        // the starting position has occurred twice before the search
+        let mut game = Board::new();
+        for n in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            let mv = notation_move(&game, n);
+            game.make_move(&mv).unwrap();
+        }
+        let root = game.get_current_state();
+        let mut board = SearchBoard::new(root, 4);
+        let mut undos = Vec::new();
+
+        // the same position as it would be with black to move
+        undos.push(board.make_null_move(AttackMap::default()).unwrap());
+        let black_to_move = BoardState::try_from(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 5 3"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(board.position(), black_to_move.position());
+        assert_eq!(board.position_hash(), black_to_move.position_hash);
+        assert_eq!(board.last_move(), None);
+
+        // passing twice gets back to the starting position, but a position reached through a null move isn't a
+        // repetition of one from before it
+        let nc6 = *board
+            .position()
+            .get_legal_moves()
+            .iter()
+            .find(|mv| mv.from == 1 && mv.to == 18)
+            .unwrap();
+        undos.push(board.make_move(&nc6, AttackMap::default()));
+        undos.push(board.make_null_move(AttackMap::default()).unwrap());
+        let nb8 = *board
+            .position()
+            .get_legal_moves()
+            .iter()
+            .find(|mv| mv.from == 18 && mv.to == 1)
+            .unwrap();
+        undos.push(board.make_move(&nb8, AttackMap::default()));
+        assert_eq!(board.position(), root.position());
+        assert_eq!(board.position_hash(), root.position_hash);
+        assert_eq!(board.get_occurences_of_current_position(), 1);
+        assert_eq!(board.get_gamestate(), GameState::Active);
+        for undo in undos.into_iter().rev() {
+            board.unmake_move(undo);
+        }
+        assert_eq!(board.position(), root.position());
+        assert_eq!(board.position_hash(), root.position_hash);
+        assert_eq!(board.get_occurences_of_current_position(), 2);
+
+        // no null move in check
+        let in_check =
+            BoardState::try_from("4k3/8/8/8/8/8/8/4K2r w - - 0 1".parse::<FEN>().unwrap()).unwrap();
+        let mut board = SearchBoard::new(&in_check, 1);
+        assert!(board.make_null_move(AttackMap::default()).is_none());
+        assert_eq!(board.position(), in_check.position());
+        assert_eq!(board.plies.len(), 1);
+    }
+}
@@ -0,0 +1,319 @@
+// Polyglot opening books -> http://hgm.nubati.net/book_format.html
+// A book is a list of 16 byte big endian entries (key, move, weight, learn) sorted by key, where the key is the
+// polyglot zobrist hash of the position, the same hash as BoardState::position_hash
+use ahash::AHashMap;
+
+use crate::board::{Board, BoardState};
+use crate::errors::{PGNParseError, PolyglotBookError};
+use crate::log_and_return_error;
+use crate::movegen::*;
+use crate::pgn::PGN;
+use crate::zobrist::PositionHash;
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: PositionHash,
+    pub mv: u16, // see polyglot_move
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    // bytes must be ENTRY_SIZE long
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+// polyglot encoding of mv: bits 0-5 to square, 6-11 from square, 12-14 promotion piece (0 none, 1 knight to 4 queen).
+// Squares are rank * 8 + file with a1 = 0, and castling is encoded as the king moving to its own rook's square
+pub fn polyglot_move(mv: &Move) -> u16 {
+    // pos64 index 0 is a8
+    let square = |i: usize| ((7 - i / 8) * 8 + i % 8) as u16;
+    let to = match mv.move_type {
+        MoveType::Castle(castle_mv) => castle_mv.rook_from,
+        _ => mv.to,
+    };
+    let promotion = match mv.move_type {
+        MoveType::Promotion(ptype, _) => match ptype {
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook => 3,
+            PieceType::Queen => 4,
+            PieceType::Pawn | PieceType::King => 0,
+        },
+        _ => 0,
+    };
+    (promotion << 12) | (square(mv.from) << 6) | square(to)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolyglotBook {
+    entries: Vec<BookEntry>,
+}
+
+impl PolyglotBook {
+    // reads the contents of a .bin book file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PolyglotBookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            let err = PolyglotBookError::InvalidBook(format!(
+                "length {} is not a multiple of the {} byte entry size",
+                bytes.len(),
+                ENTRY_SIZE
+            ));
+            log_and_return_error!(err)
+        }
+        let entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // lookups are a binary search, so entries must be sorted
+        if let Some(i) = entries.windows(2).position(|w| w[0].key > w[1].key) {
+            let err =
+                PolyglotBookError::InvalidBook(format!("entry {} is not sorted by key", i + 1));
+            log_and_return_error!(err)
+        }
+        Ok(Self { entries })
+    }
+
+    // contents of a .bin book file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.to_bytes())
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[BookEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // all entries with key, in book order
+    pub fn get_entries(&self, key: PositionHash) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // book moves for bs with their weights, entries that aren't legal moves in bs (e.g. hash collisions) are skipped
+    pub fn get_moves(&self, bs: &BoardState) -> Vec<(Move, u16)> {
+        self.get_entries(bs.position_hash)
+            .iter()
+            .filter_map(|entry| {
+                bs.lazy_get_legal_moves()
+                    .find(|mv| polyglot_move(mv) == entry.mv)
+                    .map(|mv| (*mv, entry.weight))
+            })
+            .collect()
+    }
+}
+
+// accumulates how often each move is played in each position over a collection of games, and builds a book from it
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    max_ply: usize,
+    counts: AHashMap<(PositionHash, u16), u32>,
+}
+
+impl BookBuilder {
+    // only the first max_ply plies of each game are added
+    pub fn new(max_ply: usize) -> Self {
+        Self {
+            max_ply,
+            counts: AHashMap::new(),
+        }
+    }
+
+    pub fn add_game(&mut self, pgn: PGN) -> Result<(), PGNParseError> {
+        let board = Board::try_from(pgn)?;
+        let states = board.get_state_history();
+        for (state, next_state) in states.iter().zip(states.iter().skip(1)).take(self.max_ply) {
+            // every state after the first one is reached by its last_move
+            if let Some(mv) = &next_state.last_move {
+                *self
+                    .counts
+                    .entry((state.position_hash, polyglot_move(mv)))
+                    .or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_games(&mut self, games: impl IntoIterator<Item = PGN>) -> Result<(), PGNParseError> {
+        for pgn in games {
+            self.add_game(pgn)?;
+        }
+        Ok(())
+    }
+
+    // weights are the number of times a move was played, scaled down if needed so the most played move fits in a u16.
+    // Entries are sorted by key, then highest weight first
+    pub fn build(&self) -> PolyglotBook {
+        let max_count = self.counts.values().copied().max().unwrap_or(0) as u64;
+        let mut entries: Vec<BookEntry> = self
+            .counts
+            .iter()
+            .map(|(&(key, mv), &count)| {
+                let weight = if max_count > u16::MAX as u64 {
+                    // keep rarely played moves in the book
+                    ((count as u64 * u16::MAX as u64) / max_count).max(1) as u16
+                } else {
+                    count as u16
+                };
+                BookEntry {
+                    key,
+                    mv,
+                    weight,
+                    learn: 0,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.key
+                .cmp(&b.key)
+                .then(b.weight.cmp(&a.weight))
+                .then(a.mv.cmp(&b.mv))
+        });
+        PolyglotBook { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+    use crate::pgn::notation::Notation;
+    use std::str::FromStr;
+
+    fn notation_move(bs: &BoardState, notation: &str) -> Move {
+        notation
+            .parse::<Notation>()
+            .unwrap()
+            .to_move_with_context(bs)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_polyglot_move() {
+        let bs = BoardState::new_starting();
+        // e2 = 12, e4 = 28
+        assert_eq!(polyglot_move(&notation_move(&bs, "e4")), (12 << 6) | 28);
+
+        let bs = BoardState::try_from(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        // e1h1 and e1a1
+        assert_eq!(polyglot_move(&notation_move(&bs, "O-O")), (4 << 6) | 7);
+        assert_eq!(polyglot_move(&notation_move(&bs, "O-O-O")), 4 << 6);
+
+        let bs = BoardState::try_from("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        // a7xb8=N, a7 = 48, b8 = 57
+        assert_eq!(
+            polyglot_move(&notation_move(&bs, "axb8=N")),
+            (1 << 12) | (48 << 6) | 57
+        );
+    }
+
+    #[test]
+    fn test_book_builder_round_trip() {
+        let games = [
+            "1. e4 e5 2. Nf3 Nc6 *",
+            "1. e4 c5 2. Nf3 d6 *",
+            "1. d4 d5 2. c4 e6 *",
+            "1. e4 e5 2. Bc4 Nf6 *",
+        ];
+        let mut builder = BookBuilder::new(2);
+        builder
+            .add_games(
+                games
+                    .iter()
+                    .map(|g| PGN::from_str(&format!("[Event \"Book test\"]\n\n{}", g)).unwrap()),
+            )
+            .unwrap();
+        let book = builder.build();
+        // 2 moves from the start, 2 after e4, 1 after d4
+        assert_eq!(book.len(), 5);
+
+        let bytes = book.to_bytes();
+        assert_eq!(bytes.len(), 5 * ENTRY_SIZE);
+        let path = std::env::temp_dir().join("chess_oxide_test_book.bin");
+        std::fs::write(&path, &bytes).unwrap();
+        let book = PolyglotBook::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let start = BoardState::new_starting();
+        // polyglot key of the starting position
+        assert_eq!(book.get_entries(0x463b96181691fc9c).len(), 2);
+        assert_eq!(
+            book.get_moves(&start),
+            vec![
+                (notation_move(&start, "e4"), 3),
+                (notation_move(&start, "d4"), 1)
+            ]
+        );
+
+        let after_e4 = start.next_state(&notation_move(&start, "e4")).unwrap();
+        assert_eq!(
+            book.get_moves(&after_e4),
+            vec![
+                (notation_move(&after_e4, "e5"), 2),
+                (notation_move(&after_e4, "c5"), 1)
+            ]
+        );
+        // past max_ply
+        let after_e5 = after_e4
+            .next_state(&notation_move(&after_e4, "e5"))
+            .unwrap();
+        assert!(book.get_moves(&after_e5).is_empty());
+    }
+
+    #[test]
+    fn test_book_invalid_bytes() {
+        assert!(PolyglotBook::from_bytes(&[0; 15]).is_err());
+        let unsorted = [
+            BookEntry {
+                key: 2,
+                mv: 0,
+                weight: 1,
+                learn: 0,
+            },
+            BookEntry {
+                key: 1,
+                mv: 0,
+                weight: 1,
+                learn: 0,
+            },
+        ];
+        let bytes: Vec<u8> = unsorted.iter().flat_map(|e| e.to_bytes()).collect();
+        assert!(PolyglotBook::from_bytes(&bytes).is_err());
+        assert!(PolyglotBook::from_bytes(&[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+// chess clock for timed games. Time is charged to the side whose turn it is, measured from when its turn started,
+// and can also be reported with apply_elapsed when the time is kept elsewhere, e.g. by a GUI or a game server
+use std::time::{Duration, Instant};
+
+use crate::movegen::PieceColour;
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    white: Duration,
+    black: Duration,
+    increment: Duration,
+    // side whose time is running and when its turn started (or when time was last charged to it), None while stopped
+    running: Option<(PieceColour, Instant)>,
+    // plies the increment was added after, in order, so taking a move back can take its increment back too
+    incremented_plies: Vec<usize>,
+}
+
+impl Clock {
+    pub fn new(base: Duration, increment: Duration) -> Self {
+        Self {
+            white: base,
+            black: base,
+            increment,
+            running: None,
+            incremented_plies: Vec::new(),
+        }
+    }
+
+    pub fn increment(&self) -> Duration {
+        self.increment
+    }
+
+    // side whose time is running, None while the clock is stopped
+    pub fn running_side(&self) -> Option<PieceColour> {
+        self.running.map(|(side, _)| side)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    // time left for side, including the time used so far in its current turn
+    pub fn remaining(&self, side: PieceColour) -> Duration {
+        let stored = self.stored(side);
+        match self.running {
+            Some((running, started)) if running == side => stored.saturating_sub(started.elapsed()),
+            _ => stored,
+        }
+    }
+
+    pub fn is_flagged(&self, side: PieceColour) -> bool {
+        self.remaining(side).is_zero()
+    }
+
+    // starts side's time, charging the side that was running for its turn first
+    pub fn start(&mut self, side: PieceColour) {
+        self.stop();
+        self.running = Some((side, Instant::now()));
+    }
+
+    // charges the running side for its turn and stops the clock
+    pub fn stop(&mut self) {
+        if let Some((side, started)) = self.running.take() {
+            self.charge(side, started.elapsed());
+        }
+    }
+
+    // charges side with time kept outside the clock, returning its remaining time. If side's time is running its turn
+    // is restarted, so the elapsed time isn't charged twice
+    pub fn apply_elapsed(&mut self, side: PieceColour, elapsed: Duration) -> Duration {
+        if let Some((running, started)) = &mut self.running {
+            if *running == side {
+                *started = Instant::now();
+            }
+        }
+        self.charge(side, elapsed);
+        self.remaining(side)
+    }
+
+    // adds the increment to side, for after it has made the move ending at ply
+    pub fn add_increment(&mut self, side: PieceColour, ply: usize) {
+        self.forget_plies_from(ply);
+        self.incremented_plies.push(ply);
+        let increment = self.increment;
+        let stored = self.stored_mut(side);
+        *stored += increment;
+    }
+
+    // takes back the increment side was given for the move ending at ply, if it was given one
+    pub fn remove_increment(&mut self, side: PieceColour, ply: usize) {
+        self.forget_plies_from(ply + 1);
+        if self.incremented_plies.last() == Some(&ply) {
+            self.incremented_plies.pop();
+            let increment = self.increment;
+            let stored = self.stored_mut(side);
+            *stored = stored.saturating_sub(increment);
+        }
+    }
+
+    // plies from ply on are no longer in the game, e.g. after moves were discarded to play a different one
+    fn forget_plies_from(&mut self, ply: usize) {
+        while self.incremented_plies.last().is_some_and(|&p| p >= ply) {
+            self.incremented_plies.pop();
+        }
+    }
+
+    fn charge(&mut self, side: PieceColour, elapsed: Duration) {
+        let stored = self.stored_mut(side);
+        *stored = stored.saturating_sub(elapsed);
+    }
+
+    fn stored(&self, side: PieceColour) -> Duration {
+        match side {
+            PieceColour::White => self.white,
+            PieceColour::Black => self.black,
+        }
+    }
+
+    fn stored_mut(&mut self, side: PieceColour) -> &mut Duration {
+        match side {
+            PieceColour::White => &mut self.white,
+            PieceColour::Black => &mut self.black,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::board::BoardState;
+use crate::engine;
+use crate::fen::FEN;
+use crate::movegen::PieceType;
+use crate::perft::{self, PerftNodes};
+use crate::position::Position;
+
+// fixed workload, changing any of these will change the bench signature
+const BENCH_FENS: [&str; 3] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+];
+const BENCH_PERFT_DEPTH: u8 = 4;
+// perft_hashed only pays off once there are enough transpositions, so it is compared with plain perft on a deeper search
+// of the first bench position
+const BENCH_HASHED_PERFT_DEPTH: u8 = 5;
+const BENCH_EVALUATE_ITERATIONS: u64 = 100_000;
+const BENCH_LEGALITY_ITERATIONS: u64 = 10_000;
+const BENCH_MOBILITY_ITERATIONS: u64 = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub perft_nodes: u64,
+    pub perft_time: Duration,
+    pub hashed_perft_nodes: u64,
+    pub unhashed_perft_time: Duration,
+    pub hashed_perft_time: Duration,
+    pub evaluate_calls: u64,
+    pub evaluate_time: Duration,
+    pub legality_checks: u64,
+    pub legality_time: Duration,
+    pub mobility_counts: u64,
+    pub mobility_time: Duration,
+}
+
+impl BenchReport {
+    // node count of the fixed perft workload, stays the same across machines so it doubles as a determinism check
+    pub fn signature(&self) -> u64 {
+        self.perft_nodes
+    }
+
+    pub fn perft_nps(&self) -> f64 {
+        per_second(self.perft_nodes, self.perft_time)
+    }
+
+    // how many times faster perft_hashed was than plain perft at BENCH_HASHED_PERFT_DEPTH
+    pub fn hashed_perft_speedup(&self) -> f64 {
+        if self.hashed_perft_time.is_zero() {
+            0.0
+        } else {
+            self.unhashed_perft_time.as_secs_f64() / self.hashed_perft_time.as_secs_f64()
+        }
+    }
+
+    pub fn evaluate_per_second(&self) -> f64 {
+        per_second(self.evaluate_calls, self.evaluate_time)
+    }
+
+    pub fn legality_checks_per_second(&self) -> f64 {
+        per_second(self.legality_checks, self.legality_time)
+    }
+
+    pub fn mobility_counts_per_second(&self) -> f64 {
+        per_second(self.mobility_counts, self.mobility_time)
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.perft_time
+            + self.unhashed_perft_time
+            + self.hashed_perft_time
+            + self.evaluate_time
+            + self.legality_time
+            + self.mobility_time
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Bench signature: {}", self.signature())?;
+        writeln!(
+            f,
+            " - Perft: {} nodes in {:?} ({:.0} nodes/s)",
+            self.perft_nodes,
+            self.perft_time,
+            self.perft_nps()
+        )?;
+        writeln!(
+            f,
+            " - Hashed perft: {} nodes in {:?}, {:?} unhashed ({:.1}x faster)",
+            self.hashed_perft_nodes,
+            self.hashed_perft_time,
+            self.unhashed_perft_time,
+            self.hashed_perft_speedup()
+        )?;
+        writeln!(
+            f,
+            " - Evaluate: {} calls in {:?} ({:.0} calls/s)",
+            self.evaluate_calls,
+            self.evaluate_time,
+            self.evaluate_per_second()
+        )?;
+        writeln!(
+            f,
+            " - Move legality: {} checks in {:?} ({:.0} checks/s)",
+            self.legality_checks,
+            self.legality_time,
+            self.legality_checks_per_second()
+        )?;
+        writeln!(
+            f,
+            " - Mobility: {} pieces counted in {:?} ({:.0} pieces/s)",
+            self.mobility_counts,
+            self.mobility_time,
+            self.mobility_counts_per_second()
+        )?;
+        write!(f, "Total time: {:?}", self.total_time())
+    }
+}
+
+// runs a fixed movegen/eval workload and reports timings, used to diagnose platform performance
+pub fn movegen_benchmark() -> BenchReport {
+    let mut positions: Vec<Position> = BENCH_FENS
+        .iter()
+        .map(|fen| Position::try_from(fen.parse::<FEN>().unwrap()).unwrap()) // bench FENs are valid
+        .collect();
+    let board_states: Vec<BoardState> = BENCH_FENS
+        .iter()
+        .map(|fen| BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap())
+        .collect();
+
+    let mut nodes = PerftNodes::default();
+    let start = Instant::now();
+    for pos in &mut positions {
+        perft::get_all_legal_positions(pos, BENCH_PERFT_DEPTH, &mut nodes);
+    }
+    let perft_time = start.elapsed();
+
+    let start = Instant::now();
+    black_box(perft::perft_nodes(
+        &board_states[0],
+        BENCH_HASHED_PERFT_DEPTH,
+    ));
+    let unhashed_perft_time = start.elapsed();
+    let start = Instant::now();
+    let hashed_perft_nodes = perft::perft_hashed(&board_states[0], BENCH_HASHED_PERFT_DEPTH);
+    let hashed_perft_time = start.elapsed();
+
+    let mut evaluate_calls = 0;
+    let start = Instant::now();
+    for _ in 0..BENCH_EVALUATE_ITERATIONS {
+        for bs in &board_states {
+            black_box(engine::evaluate(black_box(bs).position()));
+            evaluate_calls += 1;
+        }
+    }
+    let evaluate_time = start.elapsed();
+
+    let mut legality_checks = 0;
+    let start = Instant::now();
+    for _ in 0..BENCH_LEGALITY_ITERATIONS {
+        for pos in &positions {
+            for mv in pos.get_pseudo_legal_moves() {
+                black_box(pos.is_move_legal(black_box(*mv)));
+                legality_checks += 1;
+            }
+        }
+    }
+    let legality_time = start.elapsed();
+
+    // mobility is counted for every non-pawn, non-king piece in evaluate, so time it separately
+    let mut mobility_counts = 0;
+    let start = Instant::now();
+    for _ in 0..BENCH_MOBILITY_ITERATIONS {
+        for pos in &positions {
+            for (i, p) in pos.pos64.pieces() {
+                if p.ptype != PieceType::Pawn && p.ptype != PieceType::King {
+                    black_box(engine::get_piece_mobility(
+                        pos.piece_totals().bitboards(),
+                        p,
+                        black_box(i),
+                    ));
+                    mobility_counts += 1;
+                }
+            }
+        }
+    }
+    let mobility_time = start.elapsed();
+
+    let report = BenchReport {
+        perft_nodes: nodes.nodes,
+        perft_time,
+        hashed_perft_nodes,
+        unhashed_perft_time,
+        hashed_perft_time,
+        evaluate_calls,
+        evaluate_time,
+        legality_checks,
+        legality_time,
+        mobility_counts,
+        mobility_time,
+    };
+    log::info!("Movegen benchmark complete: {:?}", report);
+    report
+}
+
+#[inline]
+fn per_second(count: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        0.0
+    } else {
+        count as f64 / duration.as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // must be consciously updated if movegen changes the node count of the bench workload
+    const BENCH_SIGNATURE: u64 = 662852;
+
+    #[test]
+    fn test_bench_signature() {
+        let report = movegen_benchmark();
+        assert_eq!(report.signature(), BENCH_SIGNATURE);
+        assert_eq!(report.hashed_perft_nodes, 4865609);
+        assert_eq!(
+            report.evaluate_calls,
+            BENCH_EVALUATE_ITERATIONS * BENCH_FENS.len() as u64
+        );
+    }
+}
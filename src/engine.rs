@@ -1,7 +1,20 @@
 use std::cmp;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bitboard::{piece_attacks, Bitboards};
 use crate::board::*;
+use crate::epd;
+use crate::errors::FenParseError;
+use crate::log_and_return_error;
 use crate::movegen::*;
+use crate::packed_move::PackedMove;
+use crate::pgn::notation::Notation;
+use crate::position::{colour_idx, square_set_indexes, AttackMap, Pos64, Position};
 use crate::transposition::*;
 use crate::util;
 
@@ -13,6 +26,69 @@ const CHECKMATE_THRESHOLD: i32 = CHECKMATE_VALUE - 1000;
 const DRAW_VALUE: i32 = 0;
 // max depth for quiescence search, best case it should be unlimited (only stopping when there are no more captures), but in practice it takes too long
 const QUIECENCE_DEPTH: u8 = 10;
+// how much shallower than a normal move a null move is searched, on top of the ply it uses
+const NULL_MOVE_REDUCTION: u8 = 2;
+// game phase when all non-pawn material is on the board, phase decreases towards 0 as pieces are traded off
+const MAX_GAME_PHASE: i32 = 24;
+
+// highest skill level, the engine plays at full strength
+pub const MAX_SKILL_LEVEL: u8 = 20;
+// centipawns a chosen move may be worse than the best move, per skill level below MAX_SKILL_LEVEL
+const SKILL_EVAL_MARGIN_STEP: i32 = 15;
+
+// engine search settings, used by Board::make_engine_move_with_options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineOptions {
+    pub depth: u8,
+    // 0 (weakest) to MAX_SKILL_LEVEL (full strength). Below full strength the search depth is limited and the move is
+    // picked randomly from the root moves within eval_margin of the best one
+    pub skill_level: u8,
+    // seed for the random move choice below full strength, None seeds from the OS
+    pub seed: Option<u64>,
+}
+
+impl EngineOptions {
+    // full strength search to depth
+    pub const fn new(depth: u8) -> Self {
+        Self {
+            depth,
+            skill_level: MAX_SKILL_LEVEL,
+            seed: None,
+        }
+    }
+
+    pub const fn with_skill_level(mut self, skill_level: u8) -> Self {
+        self.skill_level = skill_level;
+        self
+    }
+
+    pub const fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub const fn is_full_strength(&self) -> bool {
+        self.skill_level >= MAX_SKILL_LEVEL
+    }
+
+    // depth that is actually searched, from 1 ply at skill level 0 up to the full depth
+    pub fn search_depth(&self) -> u8 {
+        if self.is_full_strength() {
+            self.depth
+        } else {
+            cmp::max(cmp::min(self.depth, 1 + self.skill_level / 4), 1)
+        }
+    }
+
+    // how many centipawns worse than the best move the chosen move can be, 0 at full strength
+    pub const fn eval_margin(&self) -> i32 {
+        if self.is_full_strength() {
+            0
+        } else {
+            (MAX_SKILL_LEVEL - self.skill_level) as i32 * SKILL_EVAL_MARGIN_STEP
+        }
+    }
+}
 
 // TODO for tt, to make sure checkmate eval is relative to the ply it was found at, maybe have a checkmate flag in the tt entry or an enum here for evals i dont know
 #[inline(always)]
@@ -26,6 +102,12 @@ pub const fn get_checkmate_ply(eval: i32) -> u8 {
     (CHECKMATE_VALUE - eval.abs()).unsigned_abs() as u8
 }
 
+// eval of a checkmate in the amount of plys, for the mating side
+#[inline(always)]
+pub const fn checkmate_eval(ply: u8) -> i32 {
+    CHECKMATE_VALUE - ply as i32
+}
+
 struct Nodes {
     negamax_nodes: u64,
     negamax_prunes: u64,
@@ -53,14 +135,32 @@ impl Nodes {
     }
 }
 
-pub fn choose_move<'a>(
-    bs: &'a BoardState,
+// result of a search, eval is relative to the side to move in the searched position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub eval: i32,
+    // None if the searched position is game over
+    pub best_move: Option<Move>,
+    pub depth: u8,
+}
+
+pub fn choose_move(bs: &BoardState, depth: u8, tt: &mut TranspositionTable) -> SearchResult {
+    // nothing else can see the flag, so the search always completes
+    choose_move_until_stopped(bs, depth, tt, &AtomicBool::new(false))
+        .expect("search without a stop flag was stopped")
+}
+
+// choose_move that gives up as soon as stop is set, returning None. Nodes that were fully searched before the stop are
+// still stored in tt, the ones left unfinished aren't
+pub fn choose_move_until_stopped(
+    bs: &BoardState,
     depth: u8,
     tt: &mut TranspositionTable,
-) -> (i32, &'a Move) {
+    stop: &AtomicBool,
+) -> Option<SearchResult> {
     let mut nodes = Nodes::new();
-    // TODO add check if position is in endgame, for different evaluation
-    let (eval, mv) = negamax_root(bs, depth, tt, &mut nodes);
+    let mut stack = SearchStack::new(bs, depth);
+    let (eval, mv) = negamax_root(bs, depth, tt, &mut nodes, &mut stack, stop)?;
 
     if cfg!(feature = "debug_engine_logging") {
         log::info!("Nodes searched: {}", nodes.total_nodes());
@@ -73,6 +173,7 @@ pub fn choose_move<'a>(
             "Transposition table hits: {}",
             nodes.transposition_table_hits
         );
+        log::info!("Transposition table stats: {}", tt.stats());
     }
     log::debug!(
         "Transposition table: Entries -> {}/{}, Size on heap -> {}",
@@ -82,34 +183,159 @@ pub fn choose_move<'a>(
     );
     if is_eval_checkmate(eval) {
         log::info!(
-            "Engine chose move: {:?} with eval: Mate in {} ply @ depth {}",
-            mv,
+            "Engine chose move: {} with eval: Mate in {} ply @ depth {}",
+            move_string(mv),
             get_checkmate_ply(eval),
             depth
         );
     } else {
         log::info!(
-            "Engine chose move: {:?} with eval: {} @ depth {}",
-            mv,
+            "Engine chose move: {} with eval: {} @ depth {}",
+            move_string(mv),
             eval,
             depth
         );
     }
-    (eval, mv)
+    Some(SearchResult {
+        eval,
+        best_move: mv.copied(),
+        depth,
+    })
+}
+
+// coordinate notation of the chosen move for logging, "(none)" when there are no legal moves
+fn move_string(mv: Option<&Move>) -> String {
+    mv.map_or_else(|| "(none)".to_string(), Move::to_string)
+}
+
+// same as choose_move, but the engine strength is limited by options.skill_level
+pub fn choose_move_with_options(
+    bs: &BoardState,
+    options: &EngineOptions,
+    tt: &mut TranspositionTable,
+) -> SearchResult {
+    if options.is_full_strength() {
+        return choose_move(bs, options.depth, tt);
+    }
+    let mut nodes = Nodes::new();
+    let candidates = root_move_candidates(
+        bs,
+        options.search_depth(),
+        options.eval_margin(),
+        tt,
+        &mut nodes,
+    );
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let (eval, mv) = candidates[rng.gen_range(0..candidates.len())];
+    log::info!(
+        "Engine chose move: {} with eval: {} @ depth {} (skill level {}, {} candidate moves)",
+        move_string(mv),
+        eval,
+        options.search_depth(),
+        options.skill_level,
+        candidates.len()
+    );
+    SearchResult {
+        eval,
+        best_move: mv.copied(),
+        depth: options.search_depth(),
+    }
+}
+
+// root moves whose eval is within margin of the best move, best move first. Other moves are searched with a window
+// from the lowest accepted eval to the best eval, so evals are exact for the accepted moves and cutoffs still apply.
+// If bs is game over the only candidate has no move
+fn root_move_candidates<'a>(
+    bs: &'a BoardState,
+    depth: u8,
+    margin: i32,
+    tt: &mut TranspositionTable,
+    nodes: &mut Nodes,
+) -> Vec<(i32, Option<&'a Move>)> {
+    let mut stack = SearchStack::new(bs, depth);
+    // never set, the candidates are always searched in full
+    let stop = AtomicBool::new(false);
+    let (best_eval, best_move) = negamax_root(bs, depth, tt, nodes, &mut stack, &stop)
+        .expect("search without a stop flag was stopped");
+    let mut candidates = vec![(best_eval, best_move)];
+    // game over or mate found, don't risk picking a worse move
+    let best_move = match best_move {
+        Some(mv) if !is_eval_checkmate(best_eval) => mv,
+        _ => return candidates,
+    };
+    let min_eval = best_eval - margin;
+    let (scratch, plies) = stack.plies.split_first_mut().expect(SEARCH_STACK_ERROR);
+    // only called after negamax_root has already returned the legal moves successfully
+    for mv in bs.get_legal_moves().unwrap_or_default() {
+        if mv == best_move {
+            continue;
+        }
+        let undo = stack
+            .board
+            .make_move(mv, std::mem::take(&mut scratch.attack_map));
+        let eval = -negamax(
+            &mut stack.board,
+            depth - 1,
+            1,
+            -(best_eval + 1),
+            -(min_eval - 1),
+            tt,
+            nodes,
+            plies,
+            &stop,
+        );
+        scratch.attack_map = stack.board.unmake_move(undo);
+        if eval >= min_eval {
+            candidates.push((eval, Some(mv)));
+        }
+    }
+    candidates
+}
+
+const SEARCH_STACK_ERROR: &str = "search stack is too small for the search depth";
+
+// scratch space for one ply of the search, reused by every node at that ply so the search doesn't allocate per node
+#[derive(Default)]
+struct PlyScratch {
+    // the attack map the position after the move currently being searched is generated into
+    attack_map: AttackMap,
+    // copy of the moves being searched, as the position's own attack map is replaced while its moves are made
+    moves: Vec<PackedMove>,
+    move_scores: Vec<(usize, i32)>,
+}
+
+// the board a search is made on, allocated once per search
+struct SearchStack {
+    board: SearchBoard,
+    // one PlyScratch for every ply below the root, negamax plies followed by the quiescence plies
+    plies: Vec<PlyScratch>,
+}
+
+impl SearchStack {
+    fn new(bs: &BoardState, depth: u8) -> Self {
+        let plies = depth as usize + QUIECENCE_DEPTH as usize;
+        Self {
+            board: SearchBoard::new(bs, plies),
+            plies: (0..plies).map(|_| PlyScratch::default()).collect(),
+        }
+    }
 }
 
 // TODO add checks (and maybe promotions) to quiescence search
 fn quiescence(
-    bs: &BoardState,
+    board: &mut SearchBoard,
     depth: u8,
     ply: u8,
     mut alpha: i32,
     beta: i32,
     nodes: &mut Nodes,
+    stack: &mut [PlyScratch],
 ) -> i32 {
-    let pseudo_legal_moves = bs.get_pseudo_legal_moves();
     // check game over conditions returning immediately, or begin quiescence search
-    match bs.get_gamestate() {
+    match board.get_gamestate() {
         GameState::Checkmate => {
             if cfg!(feature = "debug_engine_logging") {
                 nodes.quiescence_nodes += 1;
@@ -119,7 +345,9 @@ fn quiescence(
         // draw states
         GameState::Stalemate
         | GameState::Repetition
+        | GameState::FivefoldRepetition
         | GameState::FiftyMove
+        | GameState::SeventyFiveMove
         | GameState::InsufficientMaterial => {
             if cfg!(feature = "debug_engine_logging") {
                 nodes.quiescence_nodes += 1;
@@ -129,19 +357,35 @@ fn quiescence(
         _ => {}
     }
 
-    let mut max_eval = evaluate(bs);
+    let mut max_eval = evaluate(board.position());
     if max_eval >= beta || depth == 0 {
         return max_eval;
     }
     alpha = cmp::max(alpha, max_eval);
 
-    for i in sorted_move_indexes(pseudo_legal_moves, true, NULL_SHORT_MOVE, &bs.last_move) {
-        let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
+    let (scratch, stack) = stack.split_first_mut().expect(SEARCH_STACK_ERROR);
+    let PlyScratch {
+        attack_map,
+        moves,
+        move_scores,
+    } = scratch;
+    moves.clear();
+    moves.extend_from_slice(board.position().get_pseudo_legal_moves());
+    sort_move_scores(
+        moves,
+        true,
+        NULL_SHORT_MOVE,
+        &board.last_move(),
+        move_scores,
+    );
+    for &(i, _) in move_scores.iter() {
+        let mv = moves[i];
+        if !board.position().is_move_legal(mv) {
             continue; // skip illegal moves
         }
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -quiescence(&child_bs, depth - 1, ply + 1, -beta, -alpha, nodes);
+        let undo = board.make_move(&mv.unpack(), std::mem::take(attack_map));
+        let eval = -quiescence(board, depth - 1, ply + 1, -beta, -alpha, nodes, stack);
+        *attack_map = board.unmake_move(undo);
         max_eval = cmp::max(max_eval, eval);
         alpha = cmp::max(alpha, max_eval);
 
@@ -159,48 +403,73 @@ fn quiescence(
     max_eval
 }
 
+// None if the search was stopped before every root move was searched
 fn negamax_root<'a>(
     bs: &'a BoardState,
     depth: u8,
     tt: &mut TranspositionTable,
     nodes: &mut Nodes,
-) -> (i32, &'a Move) {
-    let pseudo_legal_moves = bs.get_pseudo_legal_moves();
+    stack: &mut SearchStack,
+    stop: &AtomicBool,
+) -> Option<(i32, Option<&'a Move>)> {
+    // root BoardStates come from Board, which always generates legal moves, so they are already legality checked
+    let legal_moves = match bs.get_legal_moves() {
+        Ok(legal_moves) => legal_moves,
+        Err(e) => {
+            log::error!("{}", e);
+            return Some((MIN, None));
+        }
+    };
+    let packed_moves: Vec<PackedMove> = legal_moves.iter().map(PackedMove::pack).collect();
     // check game over conditions returning immediately, or begin quiescence search
     match bs.get_gamestate() {
         GameState::Checkmate => {
             if cfg!(feature = "debug_engine_logging") {
                 nodes.negamax_nodes += 1;
             }
-            return (-CHECKMATE_VALUE, &NULL_MOVE);
+            return Some((-CHECKMATE_VALUE, None));
         }
-        // draw states
+        // draw states. A claimable draw at the root hasn't been claimed, so a move is still needed
         GameState::Stalemate
-        | GameState::Repetition
-        | GameState::FiftyMove
+        | GameState::FivefoldRepetition
+        | GameState::SeventyFiveMove
         | GameState::InsufficientMaterial => {
             if cfg!(feature = "debug_engine_logging") {
                 nodes.negamax_nodes += 1;
             }
-            return (DRAW_VALUE, &NULL_MOVE); // stalemate
+            return Some((DRAW_VALUE, None));
         }
         _ => {}
     }
     let mut alpha = MIN;
     let beta = MAX;
-    let mut best_move = &NULL_MOVE;
+    let mut best_move = None;
     let mut max_eval = MIN;
-    for i in sorted_move_indexes(pseudo_legal_moves, false, NULL_SHORT_MOVE, &bs.last_move) {
-        let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
-            continue; // skip illegal moves
+    let (scratch, plies) = stack.plies.split_first_mut().expect(SEARCH_STACK_ERROR);
+    for i in sorted_move_indexes(&packed_moves, false, NULL_SHORT_MOVE, &bs.last_move) {
+        let mv = &legal_moves[i];
+        let undo = stack
+            .board
+            .make_move(mv, std::mem::take(&mut scratch.attack_map));
+        let eval = -negamax(
+            &mut stack.board,
+            depth - 1,
+            1,
+            -beta,
+            -alpha,
+            tt,
+            nodes,
+            plies,
+            stop,
+        );
+        scratch.attack_map = stack.board.unmake_move(undo);
+        if stop.load(Ordering::Relaxed) {
+            return None;
         }
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -negamax(&child_bs, depth - 1, 1, -beta, -alpha, tt, nodes);
 
         if eval > max_eval {
             max_eval = eval;
-            best_move = mv;
+            best_move = Some(mv);
         }
         alpha = cmp::max(alpha, max_eval);
 
@@ -215,23 +484,60 @@ fn negamax_root<'a>(
         }
     }
 
-    (max_eval, best_move)
+    Some((max_eval, best_move))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn negamax(
-    bs: &BoardState,
+    board: &mut SearchBoard,
     depth: u8,
     ply: u8,
     mut alpha: i32,
     mut beta: i32,
     tt: &mut TranspositionTable,
     nodes: &mut Nodes,
+    stack: &mut [PlyScratch],
+    stop: &AtomicBool,
 ) -> i32 {
+    // the eval of a stopped search is never used
+    if stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+    // check game over conditions returning immediately, or begin quiescence search. This has to be done before the
+    // transposition table lookup, as repetition and fifty move draws depend on how the position was reached, which the
+    // TT key (position_hash) doesn't include
+    match board.get_gamestate() {
+        GameState::Checkmate => {
+            if cfg!(feature = "debug_engine_logging") {
+                nodes.negamax_nodes += 1;
+            }
+            return -CHECKMATE_VALUE + ply as i32;
+        }
+        // draw states
+        GameState::Stalemate
+        | GameState::Repetition
+        | GameState::FivefoldRepetition
+        | GameState::FiftyMove
+        | GameState::SeventyFiveMove
+        | GameState::InsufficientMaterial => {
+            if cfg!(feature = "debug_engine_logging") {
+                nodes.negamax_nodes += 1;
+            }
+            return DRAW_VALUE; // stalemate
+        }
+        _ => {}
+    }
+
     // transposition table lookup
     let alpha_orig = alpha;
     let mut best_move = NULL_SHORT_MOVE; // will be set on tt hit
-    if let Some(entry) = tt.get(bs.board_hash) {
-        //TODO does adding halfmove count to the hash make sense? test performance
+
+    // entries are only stored with a legal best move, so an entry whose move isn't legal here is a hash collision from
+    // another position, and neither its eval nor its move can be trusted
+    if let Some(entry) = tt
+        .get(board.position_hash())
+        .filter(|entry| is_tt_move_valid(board.position(), entry.mv))
+    {
         if cfg!(feature = "debug_engine_logging") {
             nodes.transposition_table_hits += 1;
         }
@@ -258,42 +564,79 @@ fn negamax(
     }
     // TODO checkmate stored in tt will have wrong eval, so the root depth should be recalculated, might need a checkmate flag in tt entry
 
-    let pseudo_legal_moves = bs.get_pseudo_legal_moves();
-    // check game over conditions returning immediately, or begin quiescence search
-    match bs.get_gamestate() {
-        GameState::Checkmate => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
+    if depth == 0 {
+        return quiescence(board, QUIECENCE_DEPTH, ply + 1, alpha, beta, nodes, stack);
+    }
+
+    let mut max_eval = MIN;
+    let (scratch, stack) = stack.split_first_mut().expect(SEARCH_STACK_ERROR);
+    let PlyScratch {
+        attack_map,
+        moves,
+        move_scores,
+    } = scratch;
+
+    // null move pruning: if this side could pass and a shallower search still fails high, a real move almost always would
+    // too. Not done in check, straight after another null move, when a mate score could be cut off, or with only pawns
+    // left, where zugzwang can make passing better than every legal move
+    if depth > NULL_MOVE_REDUCTION
+        && board.last_move().is_some()
+        && !board.position().is_in_check()
+        && beta.abs() < CHECKMATE_THRESHOLD
+        && board.side_to_move_has_pieces()
+    {
+        if let Some(undo) = board.make_null_move(std::mem::take(attack_map)) {
+            let eval = -negamax(
+                board,
+                depth - 1 - NULL_MOVE_REDUCTION,
+                ply + 1,
+                -beta,
+                -beta + 1,
+                tt,
+                nodes,
+                stack,
+                stop,
+            );
+            *attack_map = board.unmake_move(undo);
+            if stop.load(Ordering::Relaxed) {
+                return 0;
             }
-            return -CHECKMATE_VALUE + ply as i32;
-        }
-        // draw states
-        GameState::Stalemate
-        | GameState::Repetition
-        | GameState::FiftyMove
-        | GameState::InsufficientMaterial => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
+            if eval >= beta {
+                if cfg!(feature = "debug_engine_logging") {
+                    nodes.negamax_prunes += 1;
+                }
+                return beta;
             }
-            return DRAW_VALUE; // stalemate
         }
-        _ => {}
-    }
-
-    if depth == 0 {
-        return quiescence(bs, QUIECENCE_DEPTH, ply + 1, alpha, beta, nodes);
     }
 
-    let mut max_eval = MIN;
-    let moves = sorted_move_indexes(pseudo_legal_moves, false, best_move, &bs.last_move); // sort pseudo legal moves instead of consuming the lazy iterator
-    for i in moves {
-        let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
+    moves.clear();
+    moves.extend_from_slice(board.position().get_pseudo_legal_moves());
+    let last_mv = board.last_move();
+    // order pseudo legal moves instead of consuming the lazy iterator
+    for i in StagedMoves::new(moves, best_move, &last_mv, move_scores) {
+        let mv = moves[i];
+        if !board.position().is_move_legal(mv) {
             continue; // skip illegal moves
         }
 
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -negamax(&child_bs, depth - 1, ply + 1, -beta, -alpha, tt, nodes);
+        let undo = board.make_move(&mv.unpack(), std::mem::take(attack_map));
+        let eval = -negamax(
+            board,
+            depth - 1,
+            ply + 1,
+            -beta,
+            -alpha,
+            tt,
+            nodes,
+            stack,
+            stop,
+        );
+        *attack_map = board.unmake_move(undo);
+        // the move's eval is incomplete, and so is this node's
+        if stop.load(Ordering::Relaxed) {
+            return 0;
+        }
         if eval > max_eval {
             max_eval = eval;
             best_move = mv.short_move();
@@ -318,6 +661,7 @@ fn negamax(
         ply,
         eval: max_eval,
         mv: best_move,
+        generation: 0,
     };
     // set bound type to Upper or Lower, otherwise it stays Exact
     if entry.eval <= alpha_orig {
@@ -325,65 +669,454 @@ fn negamax(
     } else if entry.eval >= beta {
         entry.bound_type = BoundType::Lower;
     }
-    tt.insert(bs.board_hash, entry);
+    tt.insert(board.position_hash(), entry);
 
     max_eval
 }
 
+// cheap check that tt_mv is a legal move in pos
+fn is_tt_move_valid(pos: &Position, tt_mv: ShortMove) -> bool {
+    pos.expand_short_move(tt_mv).is_some()
+}
+
 fn sorted_move_indexes(
-    moves: &[Move],
+    moves: &[PackedMove],
     quiecense_mode: bool,
     tt_mv: ShortMove,
     last_mv: &Option<Move>,
 ) -> Vec<usize> {
     let mut move_scores: Vec<(usize, i32)> = Vec::with_capacity(moves.len());
+    sort_move_scores(moves, quiecense_mode, tt_mv, last_mv, &mut move_scores);
+
+    move_scores
+        .into_iter()
+        .unzip::<_, _, Vec<usize>, Vec<i32>>()
+        .0
+}
 
+// same as sorted_move_indexes, but the (index, score) pairs are written into move_scores so its allocation is reused
+fn sort_move_scores(
+    moves: &[PackedMove],
+    quiecense_mode: bool,
+    tt_mv: ShortMove,
+    last_mv: &Option<Move>,
+    move_scores: &mut Vec<(usize, i32)>,
+) {
+    move_scores.clear();
     for (index, mv) in moves.iter().enumerate() {
-        if quiecense_mode
-            && (!matches!(mv.move_type, MoveType::Capture(_))
-                || matches!(mv.move_type, MoveType::Promotion(_, _)))
-        {
+        // only captures are searched in quiescence (capturing promotions aren't a MoveType::Capture)
+        if quiecense_mode && mv.capture_ptype().is_none() {
             continue;
         }
         if mv == &tt_mv {
             move_scores.push((index, MAX)); // tt move should be searched first
             continue;
         }
+        move_scores.push((index, move_score(mv, last_mv)));
+    }
+
+    move_scores.sort_by(|a, b| b.1.cmp(&a.1));
+}
 
-        let mv_score = match mv.move_type {
-            MoveType::Capture(capture_type) => {
-                let mv_ptype_value = get_piece_value(&mv.piece.ptype);
-                // prioritise captures, even when capturing with a more valuable piece. After trades it could still be good, so min 1
-                cmp::max(get_piece_value(&capture_type) - mv_ptype_value, 1)
-                    + if let Some(last_mv) = last_mv {
-                        // prioritize recaptures, with least valuable piece
-                        if mv.to == last_mv.to {
-                            10000 - mv_ptype_value
-                        } else {
-                            0
+// ordering score of a move, every move that isn't a capture or promotion scores 0. Recaptures with the king score below 0
+#[inline(always)]
+fn move_score(mv: &PackedMove, last_mv: &Option<Move>) -> i32 {
+    if let Some(capture_type) = mv.capture_ptype() {
+        let mv_ptype_value = get_piece_value(&mv.ptype());
+        // prioritise captures, even when capturing with a more valuable piece. After trades it could still be good, so min 1
+        cmp::max(get_piece_value(&capture_type) - mv_ptype_value, 1)
+            + if let Some(last_mv) = last_mv {
+                // prioritize recaptures, with least valuable piece
+                if mv.to() == last_mv.to {
+                    10000 - mv_ptype_value
+                } else {
+                    0
+                }
+            } else {
+                0
+            }
+    } else if let Some(promotion_type) = mv.promotion_ptype() {
+        get_piece_value(&promotion_type) // TODO maybe potential capture should be taken into account
+    } else {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MoveStage {
+    TTMove,
+    ScoreCaptures,
+    GoodCaptures,
+    Quiets,
+    BadCaptures,
+}
+
+// picks moves in the same order as sorted_move_indexes, one stage at a time: the tt move, then captures and promotions
+// best first, then quiet moves, then the captures scoring below 0. Captures are only scored and sorted if the tt move
+// didn't cause a cutoff, and quiet moves all score 0 so they are picked in generation order without sorting. Only the
+// ordering is staged, the moves are the position's full pseudo legal list which is always generated up front
+struct StagedMoves<'a> {
+    moves: &'a [PackedMove],
+    tt_mv: ShortMove,
+    last_mv: &'a Option<Move>,
+    stage: MoveStage,
+    tt_idx: Option<usize>,
+    captures: &'a mut Vec<(usize, i32)>, // sorted best first
+    capture_idx: usize,
+    quiet_idx: usize,
+}
+
+impl<'a> StagedMoves<'a> {
+    // captures is only used as a buffer for the scored captures, so its allocation can be reused
+    fn new(
+        moves: &'a [PackedMove],
+        tt_mv: ShortMove,
+        last_mv: &'a Option<Move>,
+        captures: &'a mut Vec<(usize, i32)>,
+    ) -> Self {
+        Self {
+            moves,
+            tt_mv,
+            last_mv,
+            stage: MoveStage::TTMove,
+            tt_idx: None,
+            captures,
+            capture_idx: 0,
+            quiet_idx: 0,
+        }
+    }
+}
+
+impl Iterator for StagedMoves<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            match self.stage {
+                MoveStage::TTMove => {
+                    self.stage = MoveStage::ScoreCaptures;
+                    self.tt_idx = self.moves.iter().position(|mv| *mv == self.tt_mv);
+                    if self.tt_idx.is_some() {
+                        return self.tt_idx;
+                    }
+                }
+                MoveStage::ScoreCaptures => {
+                    self.stage = MoveStage::GoodCaptures;
+                    self.captures.clear();
+                    self.captures.extend(
+                        self.moves
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| Some(*index) != self.tt_idx)
+                            .map(|(index, mv)| (index, move_score(mv, self.last_mv)))
+                            .filter(|(_, score)| *score != 0),
+                    );
+                    self.captures.sort_by_key(|(_, score)| cmp::Reverse(*score));
+                }
+                MoveStage::GoodCaptures => match self.captures.get(self.capture_idx) {
+                    Some((index, score)) if *score > 0 => {
+                        self.capture_idx += 1;
+                        return Some(*index);
+                    }
+                    _ => self.stage = MoveStage::Quiets,
+                },
+                MoveStage::Quiets => {
+                    while self.quiet_idx < self.moves.len() {
+                        let index = self.quiet_idx;
+                        self.quiet_idx += 1;
+                        if Some(index) != self.tt_idx
+                            && move_score(&self.moves[index], self.last_mv) == 0
+                        {
+                            return Some(index);
                         }
-                    } else {
-                        0
                     }
+                    self.stage = MoveStage::BadCaptures;
+                }
+                MoveStage::BadCaptures => {
+                    let index = self.captures.get(self.capture_idx)?.0;
+                    self.capture_idx += 1;
+                    return Some(index);
+                }
             }
-            MoveType::Promotion(promotion_type, _) => get_piece_value(&promotion_type), // TODO maybe potential capture should be taken into account
-            _ => 0,
+        }
+    }
+}
+
+// searches for a forced checkmate by the side to move within max_ply plies, returning the mating line (moves for both
+// sides, the last one giving checkmate) if there is one. Shorter mates are found first, and the defending side plays the
+// reply that delays mate the longest. tt should only be used for mate searches, entries record positions that are
+// proven to have no mate within their depth
+pub fn find_mate(bs: &BoardState, max_ply: u8, tt: &mut TranspositionTable) -> Option<Vec<Move>> {
+    if bs.get_gamestate().is_game_over() {
+        return None;
+    }
+    // mates by the side to move always take an odd number of plies
+    let mut line = (1..=max_ply)
+        .step_by(2)
+        .find_map(|depth| mate_attack(bs, depth, 0, tt))?;
+    // lines are built from the mating move backwards
+    line.reverse();
+    Some(line)
+}
+
+// result of run_epd_suite, positions only counts the positions with a bm or am opcode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpdSuiteResult {
+    pub positions: usize,
+    pub solved: usize,
+    // ids of the positions that weren't solved, or their FEN if they have no id
+    pub failed: Vec<String>,
+}
+
+// searches every position of an EPD test suite (WAC, STS, Bratko-Kopec...) to depth. A position is solved if the move
+// found is one of its best moves (bm) and none of its moves to avoid (am), positions with neither are skipped
+pub fn run_epd_suite(path: impl AsRef<Path>, depth: u8) -> Result<EpdSuiteResult, FenParseError> {
+    let path = path.as_ref();
+    let epd_str = match fs::read_to_string(path) {
+        Ok(epd_str) => epd_str,
+        Err(e) => log_and_return_error!(FenParseError::InvalidEpd(format!(
+            "Error reading {}: {}",
+            path.display(),
+            e
+        ))),
+    };
+    let mut result = EpdSuiteResult {
+        positions: 0,
+        solved: 0,
+        failed: Vec::new(),
+    };
+    let mut tt = TranspositionTable::new();
+    for entry in epd::parse_epd(&epd_str)? {
+        if entry.best_moves().is_empty() && entry.avoid_moves().is_empty() {
+            continue;
+        }
+        let bs = BoardState::try_from(entry.fen())
+            .expect("position is validated when the EPD is parsed");
+        let to_moves = |sans: &[String]| -> Result<Vec<Move>, FenParseError> {
+            sans.iter()
+                .map(|san| {
+                    san.parse::<Notation>()
+                        .and_then(|notation| notation.to_move_with_context(&bs))
+                        .map_err(|e| FenParseError::InvalidEpd(format!("Move {}: {}", san, e)))
+                })
+                .collect()
         };
+        let best_moves = to_moves(entry.best_moves())?;
+        let avoid_moves = to_moves(entry.avoid_moves())?;
 
-        move_scores.push((index, mv_score));
+        tt.new_search();
+        let found = choose_move(&bs, depth, &mut tt).best_move;
+        let solved = found.is_some_and(|mv| {
+            (best_moves.is_empty() || best_moves.contains(&mv)) && !avoid_moves.contains(&mv)
+        });
+        let name = entry
+            .id()
+            .map_or_else(|| entry.fen().to_string(), str::to_string);
+        log::info!(
+            "EPD {}: found {}, {}",
+            name,
+            move_string(found.as_ref()),
+            if solved { "solved" } else { "failed" }
+        );
+        result.positions += 1;
+        if solved {
+            result.solved += 1;
+        } else {
+            result.failed.push(name);
+        }
     }
+    log::info!(
+        "EPD suite {}: solved {}/{} at depth {}",
+        path.display(),
+        result.solved,
+        result.positions,
+        depth
+    );
+    Ok(result)
+}
 
-    move_scores.sort_by(|a, b| b.1.cmp(&a.1));
+// attacking side to move, Some(line) if any move forces mate within depth plies
+fn mate_attack(
+    bs: &BoardState,
+    depth: u8,
+    ply: u8,
+    tt: &mut TranspositionTable,
+) -> Option<Vec<Move>> {
+    // unlike negamax this is keyed on board_hash, as a position proven to have no mate can have one when it is reached
+    // with a different halfmove count or number of repetitions
+    if let Some(entry) = tt.get(bs.board_hash) {
+        if entry.bound_type == BoundType::Upper && entry.depth >= depth {
+            return None;
+        }
+    }
+    // checkmate is always check, so on the last ply only checking moves need to be searched
+    for (mv, child_bs) in mate_search_children(bs, depth == 1) {
+        match child_bs.get_gamestate() {
+            GameState::Checkmate => return Some(vec![mv]),
+            // the defender would claim a claimable draw
+            gs if gs.is_draw() => continue,
+            _ => {}
+        }
+        if depth >= 3 {
+            if let Some(mut line) = mate_defend(&child_bs, depth - 1, ply + 1, tt) {
+                line.push(mv);
+                return Some(line);
+            }
+        }
+    }
+    tt.insert(
+        bs.board_hash,
+        TableEntry {
+            bound_type: BoundType::Upper,
+            depth,
+            ply,
+            eval: DRAW_VALUE,
+            mv: NULL_SHORT_MOVE,
+            generation: 0,
+        },
+    );
+    None
+}
 
-    move_scores
+// defending side to move, Some(line) if every reply still allows mate within depth plies
+fn mate_defend(
+    bs: &BoardState,
+    depth: u8,
+    ply: u8,
+    tt: &mut TranspositionTable,
+) -> Option<Vec<Move>> {
+    let mut longest: Option<Vec<Move>> = None;
+    for (mv, child_bs) in mate_search_children(bs, false) {
+        // any draw after a defending move, including ones the defender can claim, means there is no mate
+        if child_bs.get_gamestate().is_draw() {
+            return None;
+        }
+        let mut line = mate_attack(&child_bs, depth - 1, ply + 1, tt)?;
+        if longest.as_ref().is_none_or(|l| line.len() + 1 > l.len()) {
+            line.push(mv);
+            longest = Some(line);
+        }
+    }
+    longest
+}
+
+// legal moves and the states they lead to, checking moves first then captures
+fn mate_search_children(bs: &BoardState, checks_only: bool) -> Vec<(Move, BoardState)> {
+    let mut children: Vec<(i32, Move, BoardState)> = Vec::new();
+    for packed in bs.position().get_pseudo_legal_moves() {
+        if !bs.position().is_move_legal(*packed) {
+            continue;
+        }
+        let mv = packed.unpack();
+        let child_bs = bs.next_state_unchecked(&mv);
+        let is_check = child_bs.position().is_in_check();
+        if checks_only && !is_check {
+            continue;
+        }
+        let score = if is_check {
+            2
+        } else if packed.capture_ptype().is_some() || packed.promotion_ptype().is_some() {
+            1
+        } else {
+            0
+        };
+        children.push((score, mv, child_bs));
+    }
+    children.sort_by_key(|child| cmp::Reverse(child.0));
+    children
         .into_iter()
-        .unzip::<_, _, Vec<usize>, Vec<i32>>()
-        .0
+        .map(|(_, mv, child_bs)| (mv, child_bs))
+        .collect()
+}
+
+// contribution of each piece to the game phase, a full set of non-pawn material adds up to MAX_GAME_PHASE
+#[inline(always)]
+pub(crate) const fn get_piece_phase_value(ptype: &PieceType) -> i32 {
+    match ptype {
+        PieceType::Knight | PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 4,
+        PieceType::Pawn | PieceType::King => 0,
+    }
+}
+
+// positional term weights in centipawns, kept together so they can be tuned in one place
+const KNIGHT_MOBILITY_BONUS: i32 = 4;
+const BISHOP_MOBILITY_BONUS: i32 = 5;
+const ROOK_MOBILITY_BONUS: i32 = 2;
+const QUEEN_MOBILITY_BONUS: i32 = 1;
+const BISHOP_PAIR_BONUS: i32 = 30;
+const ROOK_OPEN_FILE_BONUS: i32 = 20;
+const ROOK_SEMI_OPEN_FILE_BONUS: i32 = 10;
+const KNIGHT_OUTPOST_BONUS: i32 = 20;
+
+// bonus per pseudo-legal move, kings and pawns dont get a mobility bonus
+#[inline(always)]
+const fn get_piece_mobility_value(ptype: &PieceType) -> i32 {
+    match ptype {
+        PieceType::Knight => KNIGHT_MOBILITY_BONUS,
+        PieceType::Bishop => BISHOP_MOBILITY_BONUS,
+        PieceType::Rook => ROOK_MOBILITY_BONUS,
+        PieceType::Queen => QUEEN_MOBILITY_BONUS,
+        PieceType::Pawn | PieceType::King => 0,
+    }
+}
+
+// rook bonus based on the pawns on its file, pawn_files is the pawn count per file indexed by colour_idx
+#[inline(always)]
+fn get_rook_file_value(file: usize, colour: PieceColour, pawn_files: &[[u8; 8]; 2]) -> i32 {
+    let own_pawns = pawn_files[colour_idx(colour)][file];
+    let enemy_pawns = pawn_files[colour_idx(!colour)][file];
+    match (own_pawns, enemy_pawns) {
+        (0, 0) => ROOK_OPEN_FILE_BONUS,
+        (0, _) => ROOK_SEMI_OPEN_FILE_BONUS,
+        _ => 0,
+    }
+}
+
+// knight in enemy territory (ranks 5-7 for white, 4-2 for black), protected by an own pawn, which can never be
+// attacked by an enemy pawn i.e. there are no enemy pawns ahead of it on the adjacent files
+fn is_knight_outpost(pos: &Pos64, i: usize, colour: PieceColour) -> bool {
+    let row = i / 8;
+    let file = i % 8;
+    let (protector_row, enemy_rows) = match colour {
+        PieceColour::White if (1..=3).contains(&row) => (row + 1, 1..row),
+        PieceColour::Black if (4..=6).contains(&row) => (row - 1, row + 1..7),
+        _ => return false,
+    };
+
+    let is_pawn = |idx: usize, pcolour: PieceColour| {
+        pos[idx]
+            == Square::Piece(Piece {
+                pcolour,
+                ptype: PieceType::Pawn,
+            })
+    };
+    let adjacent_files = [file.checked_sub(1), (file < 7).then_some(file + 1)];
+
+    let protected = adjacent_files
+        .iter()
+        .flatten()
+        .any(|f| is_pawn(protector_row * 8 + f, colour));
+    let attackable = adjacent_files
+        .iter()
+        .flatten()
+        .any(|f| enemy_rows.clone().any(|r| is_pawn(r * 8 + f, !colour)));
+
+    protected && !attackable
+}
+
+// number of pseudo-legal moves for the piece at index i, the squares it attacks that its own pieces aren't on. Pawns and
+// kings would also need their pushes and castling, but mobility is only used for the other pieces
+#[inline(always)]
+pub(crate) fn get_piece_mobility(bitboards: &Bitboards, piece: Piece, i: usize) -> i32 {
+    let targets = piece_attacks(piece, i, bitboards.occupied()) & !bitboards.colour(piece.pcolour);
+    targets.count_ones() as i32
 }
 
 // values in centipawns
 #[inline(always)]
-const fn get_piece_value(ptype: &PieceType) -> i32 {
+pub(crate) const fn get_piece_value(ptype: &PieceType) -> i32 {
     match ptype {
         PieceType::Pawn => 100,
         PieceType::Knight => 320,
@@ -395,13 +1128,19 @@ const fn get_piece_value(ptype: &PieceType) -> i32 {
 }
 
 #[inline(always)]
-const fn get_piece_pos_value(i: usize, piece: &Piece, is_endgame: bool) -> i32 {
+pub(crate) const fn get_piece_pos_value(i: usize, piece: &Piece, is_endgame: bool) -> i32 {
     // all pos values are from whites perspective (a8 = index 0, h1 = index 63)
     const PAWN_POS_VALUES: [i32; 64] = [
         0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5,
         5, 10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10,
         -20, -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
+    // passed and advanced pawns are worth more in the endgame
+    const PAWN_END_POS_VALUES: [i32; 64] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 80, 80, 80, 80, 80, 80, 80, 80, 50, 50, 50, 50, 50, 50, 50, 50, 30,
+        30, 30, 30, 30, 30, 30, 30, 20, 20, 20, 20, 20, 20, 20, 20, 10, 10, 10, 10, 10, 10, 10, 10,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
     const KNIGHT_POS_VALUES: [i32; 64] = [
         -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15,
         10, 0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15,
@@ -435,13 +1174,20 @@ const fn get_piece_pos_value(i: usize, piece: &Piece, is_endgame: bool) -> i32 {
         -30, -30, -30, -30, -50,
     ];
 
+    // black's tables are white's with the ranks mirrored, i ^ 56 is the same file on the mirrored rank
     let side_adjusted_idx = match piece.pcolour {
         PieceColour::White => i,
-        PieceColour::Black => 63 - i,
+        PieceColour::Black => i ^ 56,
     };
 
     match piece.ptype {
-        PieceType::Pawn => PAWN_POS_VALUES[side_adjusted_idx],
+        PieceType::Pawn => {
+            if is_endgame {
+                PAWN_END_POS_VALUES[side_adjusted_idx]
+            } else {
+                PAWN_POS_VALUES[side_adjusted_idx]
+            }
+        }
         PieceType::Knight => KNIGHT_POS_VALUES[side_adjusted_idx],
         PieceType::Bishop => BISHOP_POS_VALUES[side_adjusted_idx],
         PieceType::Rook => ROOK_POS_VALUES[side_adjusted_idx],
@@ -456,30 +1202,688 @@ const fn get_piece_pos_value(i: usize, piece: &Piece, is_endgame: bool) -> i32 {
     }
 }
 
+// contribution of each evaluation term for one side in centipawns, positive is good for that side. Terms that differ
+// between the middlegame and endgame are already interpolated by the game phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideEval {
+    pub material: i32, // not including the king
+    pub piece_square: i32,
+    pub mobility: i32,
+    pub bishop_pair: i32,
+    pub rook_files: i32,
+    pub knight_outposts: i32,
+}
+
+impl SideEval {
+    pub const fn total(&self) -> i32 {
+        self.material
+            + self.piece_square
+            + self.mobility
+            + self.bishop_pair
+            + self.rook_files
+            + self.knight_outposts
+    }
+}
+
+// static evaluation split into its terms for each side, returned by evaluate_detailed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvalBreakdown {
+    pub white: SideEval,
+    pub black: SideEval,
+    pub phase: i32, // 0 (endgame) to MAX_GAME_PHASE (all non-pawn material on the board)
+}
+
+impl EvalBreakdown {
+    pub const fn side(&self, colour: PieceColour) -> &SideEval {
+        match colour {
+            PieceColour::White => &self.white,
+            PieceColour::Black => &self.black,
+        }
+    }
+
+    // white perspective eval, same as evaluate_state
+    pub const fn eval(&self) -> i32 {
+        self.white.total() - self.black.total()
+    }
+
+    // eval from the perspective of colour, positive is good for colour
+    pub const fn eval_for(&self, colour: PieceColour) -> i32 {
+        match colour {
+            PieceColour::White => self.eval(),
+            PieceColour::Black => -self.eval(),
+        }
+    }
+}
+
+// static evaluation of bs in centipawns from white's perspective (positive is good for white), without any search.
+// Game over states aren't taken into account, a checkmated side is evaluated on its material like any other position
+pub fn evaluate_state(bs: &BoardState) -> i32 {
+    evaluate_detailed(bs).eval()
+}
+
+// same as evaluate_state, with the contribution of each term for both sides
+pub fn evaluate_detailed(bs: &BoardState) -> EvalBreakdown {
+    evaluate_position_detailed(bs.position())
+}
+
+// evaluate_detailed of a position on its own, the search has no BoardState for the positions it makes moves on
+fn evaluate_position_detailed(position: &Position) -> EvalBreakdown {
+    let pos = &position.pos64;
+    let totals = position.piece_totals();
+    // promotions can take phase above the starting material
+    let phase = cmp::min(totals.phase(), MAX_GAME_PHASE);
+    let mut pawn_files = [[0u8; 8]; 2];
+    for colour in [PieceColour::White, PieceColour::Black] {
+        let pawns = totals.squares(Piece {
+            pcolour: colour,
+            ptype: PieceType::Pawn,
+        });
+        for i in square_set_indexes(pawns) {
+            pawn_files[colour_idx(colour)][i % 8] += 1;
+        }
+    }
+
+    let mut breakdown = EvalBreakdown {
+        phase,
+        ..Default::default()
+    };
+    for colour in [PieceColour::White, PieceColour::Black] {
+        let kings = totals.squares(Piece {
+            pcolour: colour,
+            ptype: PieceType::King,
+        });
+        let mut side = SideEval {
+            material: totals.material(colour)
+                - get_piece_value(&PieceType::King) * kings.count_ones() as i32,
+            piece_square: taper(totals.mg_pst(colour), totals.eg_pst(colour), phase),
+            ..Default::default()
+        };
+
+        for ptype in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let piece = Piece {
+                pcolour: colour,
+                ptype,
+            };
+            let mobility_weight = get_piece_mobility_value(&ptype);
+            for i in square_set_indexes(totals.squares(piece)) {
+                side.mobility += mobility_weight * get_piece_mobility(totals.bitboards(), piece, i);
+                match ptype {
+                    PieceType::Rook => {
+                        side.rook_files += get_rook_file_value(i % 8, colour, &pawn_files)
+                    }
+                    PieceType::Knight if is_knight_outpost(pos, i, colour) => {
+                        side.knight_outposts += KNIGHT_OUTPOST_BONUS
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let bishops = totals.squares(Piece {
+            pcolour: colour,
+            ptype: PieceType::Bishop,
+        });
+        if bishops.count_ones() >= 2 {
+            side.bishop_pair = BISHOP_PAIR_BONUS;
+        }
+
+        match colour {
+            PieceColour::White => breakdown.white = side,
+            PieceColour::Black => breakdown.black = side,
+        }
+    }
+    breakdown
+}
+
+// interpolate between middlegame and endgame values based on the game phase (tapered eval) -> https://www.chessprogramming.org/Tapered_Eval
+#[inline(always)]
+const fn taper(mg: i32, eg: i32, phase: i32) -> i32 {
+    (mg * phase + eg * (MAX_GAME_PHASE - phase)) / MAX_GAME_PHASE
+}
+
 // adapted piece eval scores from here -> https://www.chessprogramming.org/Simplified_Evaluation_Function
-fn evaluate(bs: &BoardState) -> i32 {
-    let maxi_colour = bs.side_to_move;
-    let mut w_eval: i32 = 0;
-    let mut b_eval: i32 = 0;
-    for (i, s) in bs.get_pos64().iter().enumerate() {
-        match s {
-            Square::Empty => {
-                continue;
+// material, piece-square and phase totals are kept up to date by Position as moves are made, so only the mobility and
+// pawn structure dependent terms need to look at the pieces. Returns the eval relative to the side to move
+#[inline(always)]
+pub(crate) fn evaluate(pos: &Position) -> i32 {
+    evaluate_position_detailed(pos).eval_for(pos.side)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+
+    fn evaluate_fen(fen: &str) -> i32 {
+        evaluate(
+            BoardState::try_from(fen.parse::<FEN>().unwrap())
+                .unwrap()
+                .position(),
+        )
+    }
+
+    #[test]
+    fn test_evaluate_starting_position() {
+        // full material means middlegame tables only, and the position is symmetrical
+        assert_eq!(evaluate(BoardState::new_starting().position()), 0);
+    }
+
+    #[test]
+    fn test_evaluate_mirrored_position() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        // random games from the perft positions, mirroring swaps the colours so the white perspective eval changes sign
+        // and the side to move's eval doesn't change
+        let mut rng = StdRng::seed_from_u64(0);
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let mut pos = Position::try_from(entry.fen).unwrap();
+            for _ in 0..40 {
+                let mirrored = pos.mirrored();
+                assert_eq!(
+                    evaluate_position_detailed(&pos).eval(),
+                    -evaluate_position_detailed(&mirrored).eval(),
+                    "{}",
+                    FEN::from(&pos)
+                );
+                assert_eq!(evaluate(&pos), evaluate(&mirrored), "{}", FEN::from(&pos));
+                match pos.get_legal_moves().choose(&mut rng) {
+                    Some(mv) => pos = pos.new_position(mv),
+                    None => break,
+                }
             }
-            Square::Piece(p) => {
-                let val = get_piece_value(&p.ptype) + get_piece_pos_value(i, p, false);
-                if p.pcolour == PieceColour::White {
-                    w_eval += val;
-                } else {
-                    b_eval += val;
+        }
+    }
+
+    #[test]
+    fn test_evaluate_endgame_king_centralisation() {
+        let king_corner = evaluate_fen("7k/8/8/8/8/8/4P3/6K1 w - - 0 1");
+        let king_centre = evaluate_fen("7k/8/8/8/4K3/8/4P3/8 w - - 0 1");
+        assert!(king_centre > king_corner);
+    }
+
+    #[test]
+    fn test_piece_mobility_entombed_bishop() {
+        // c1 bishop is boxed in by its own pawns on b2 and d2
+        let bs = BoardState::try_from(
+            "4k3/8/8/8/8/8/1P1P4/2B1K3 w - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let bishop = Piece {
+            pcolour: PieceColour::White,
+            ptype: PieceType::Bishop,
+        };
+        assert_eq!(
+            get_piece_mobility(bs.position().piece_totals().bitboards(), bishop, 58),
+            0
+        );
+
+        let bs = BoardState::try_from("4k3/8/8/8/8/8/1P6/2B1K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        assert_eq!(
+            get_piece_mobility(bs.position().piece_totals().bitboards(), bishop, 58),
+            5
+        );
+    }
+
+    #[test]
+    fn test_evaluate_entombed_bishop() {
+        // same material, pawns and piece-square values, only the bishop's ability to get out differs
+        let entombed = evaluate_fen("4k3/8/8/8/8/8/1P1P4/2B1K3 w - - 0 1");
+        let free = evaluate_fen("4k3/8/8/8/8/8/1P1P4/4KB2 w - - 0 1");
+        assert!(free > entombed);
+        // eval is relative to the side to move
+        assert_eq!(
+            evaluate_fen("4k3/8/8/8/8/8/1P1P4/2B1K3 b - - 0 1"),
+            -entombed
+        );
+    }
+
+    #[test]
+    fn test_evaluate_bishop_pair() {
+        // both sides have two minor pieces, only white has the pair
+        let pair = evaluate_fen("2b1kb2/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        let no_pair = evaluate_fen("2b1kb2/8/8/8/8/8/8/2B1KN2 w - - 0 1");
+        assert_eq!(pair, 0);
+        assert!(pair - no_pair > BISHOP_PAIR_BONUS / 2);
+    }
+
+    #[test]
+    fn test_rook_file_value() {
+        let pawn_files = [[1, 0, 0, 0, 0, 0, 0, 0], [1, 1, 0, 0, 0, 0, 0, 0]];
+        assert_eq!(get_rook_file_value(0, PieceColour::White, &pawn_files), 0);
+        assert_eq!(
+            get_rook_file_value(1, PieceColour::White, &pawn_files),
+            ROOK_SEMI_OPEN_FILE_BONUS
+        );
+        assert_eq!(get_rook_file_value(1, PieceColour::Black, &pawn_files), 0);
+        assert_eq!(
+            get_rook_file_value(2, PieceColour::Black, &pawn_files),
+            ROOK_OPEN_FILE_BONUS
+        );
+    }
+
+    #[test]
+    fn test_evaluate_rook_open_file() {
+        // rook mobility is the same on d1 and e1, the only difference is the pawn on the file
+        let open = evaluate_fen("4k3/8/8/8/8/8/4P3/3RK3 w - - 0 1");
+        let closed = evaluate_fen("4k3/8/8/8/8/8/3P4/3RK3 w - - 0 1");
+        assert!(open > closed);
+    }
+
+    #[test]
+    fn test_knight_outpost() {
+        let outpost = "4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap();
+        let bs = BoardState::try_from(outpost).unwrap();
+        assert!(is_knight_outpost(bs.get_pos64(), 27, PieceColour::White));
+
+        // black c7 pawn can kick the knight with ...c6
+        let bs = BoardState::try_from(
+            "4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(!is_knight_outpost(bs.get_pos64(), 27, PieceColour::White));
+
+        // unprotected
+        let bs = BoardState::try_from("4k3/8/8/3N4/8/8/4P3/4K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        assert!(!is_knight_outpost(bs.get_pos64(), 27, PieceColour::White));
+
+        // black knight on d4 protected by e5 pawn
+        let bs = BoardState::try_from("4k3/8/8/4p3/3n4/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        assert!(is_knight_outpost(bs.get_pos64(), 35, PieceColour::Black));
+        assert!(!is_knight_outpost(bs.get_pos64(), 35, PieceColour::White));
+    }
+
+    // same position with the board flipped vertically and the colours swapped
+    fn mirror_fen(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |s: &str| {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
+        };
+        let board = swap_case(&fields[0].split('/').rev().collect::<Vec<_>>().join("/"));
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let castling = if fields[2] == "-" {
+            "-".to_string()
+        } else {
+            let swapped = swap_case(fields[2]);
+            // uppercase (white) castling rights come first
+            swapped
+                .chars()
+                .filter(|c| c.is_ascii_uppercase())
+                .chain(swapped.chars().filter(|c| c.is_ascii_lowercase()))
+                .collect()
+        };
+        let en_passant = fields[3]
+            .replace('3', "x")
+            .replace('6', "3")
+            .replace('x', "6");
+        format!(
+            "{} {} {} {} {}",
+            board,
+            side,
+            castling,
+            en_passant,
+            fields[4..].join(" ")
+        )
+    }
+
+    const EVAL_TEST_FENS: [&str; 5] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "4k3/8/8/3N4/4P3/8/8/2B1KB2 b - - 0 1",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+
+    #[test]
+    fn test_evaluate_detailed_sums_to_eval() {
+        for fen in EVAL_TEST_FENS {
+            let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            let breakdown = evaluate_detailed(&bs);
+            assert_eq!(
+                breakdown.white.total() - breakdown.black.total(),
+                evaluate_state(&bs),
+                "{}",
+                fen
+            );
+            assert_eq!(
+                breakdown.eval_for(bs.side_to_move),
+                evaluate(bs.position()),
+                "{}",
+                fen
+            );
+        }
+
+        let bs = BoardState::try_from(
+            "4k3/8/8/3N4/4P3/8/8/2B1KB2 b - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let breakdown = evaluate_detailed(&bs);
+        assert_eq!(breakdown.white.material, 320 + 330 * 2 + 100);
+        assert_eq!(breakdown.black.material, 0);
+        assert_eq!(breakdown.white.bishop_pair, BISHOP_PAIR_BONUS);
+        assert_eq!(breakdown.white.knight_outposts, KNIGHT_OUTPOST_BONUS);
+        assert_eq!(
+            breakdown.black,
+            SideEval {
+                piece_square: breakdown.black.piece_square,
+                ..Default::default()
+            }
+        );
+        assert_eq!(breakdown.phase, 3);
+    }
+
+    #[test]
+    fn test_evaluate_colour_flip_symmetry() {
+        for fen in EVAL_TEST_FENS {
+            let mirrored = mirror_fen(fen);
+            let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            let mirrored_bs = BoardState::try_from(mirrored.parse::<FEN>().unwrap()).unwrap();
+            let breakdown = evaluate_detailed(&bs);
+            let mirrored_breakdown = evaluate_detailed(&mirrored_bs);
+            assert_eq!(
+                breakdown.white, mirrored_breakdown.black,
+                "{} / {}",
+                fen, mirrored
+            );
+            assert_eq!(
+                breakdown.black, mirrored_breakdown.white,
+                "{} / {}",
+                fen, mirrored
+            );
+            assert_eq!(evaluate_state(&bs), -evaluate_state(&mirrored_bs));
+            // relative to the side to move, so the same for both
+            assert_eq!(evaluate(bs.position()), evaluate(mirrored_bs.position()));
+        }
+    }
+
+    #[test]
+    fn test_engine_options() {
+        let full = EngineOptions::new(5);
+        assert!(full.is_full_strength());
+        assert_eq!(full.search_depth(), 5);
+        assert_eq!(full.eval_margin(), 0);
+
+        let weakest = full.with_skill_level(0);
+        assert_eq!(weakest.search_depth(), 1);
+        assert_eq!(
+            weakest.eval_margin(),
+            MAX_SKILL_LEVEL as i32 * SKILL_EVAL_MARGIN_STEP
+        );
+        assert_eq!(full.with_skill_level(10).search_depth(), 3);
+    }
+
+    #[test]
+    fn test_choose_move_game_over() {
+        let options = EngineOptions::new(3).with_skill_level(0).with_seed(0);
+        // checkmate, then stalemate
+        for (fen, eval) in [
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                -CHECKMATE_VALUE,
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", DRAW_VALUE),
+        ] {
+            let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            let result = choose_move(&bs, 3, &mut TranspositionTable::with_capacity_mb(1));
+            assert_eq!(result.best_move, None, "{}", fen);
+            assert_eq!(result.eval, eval, "{}", fen);
+            let result = choose_move_with_options(
+                &bs,
+                &options,
+                &mut TranspositionTable::with_capacity_mb(1),
+            );
+            assert_eq!(result.best_move, None, "{}", fen);
+            assert_eq!(result.eval, eval, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_poisoned_tt_entry_ignored() {
+        let bs = BoardState::new_starting();
+        let expected = choose_move(&bs, 3, &mut TranspositionTable::with_capacity_mb(1));
+
+        // entries for every position after the first move, with a move that isn't on the board and an eval that would
+        // make every reply look lost for white
+        let poisoned = TableEntry {
+            bound_type: BoundType::Exact,
+            depth: u8::MAX,
+            ply: 1,
+            eval: CHECKMATE_VALUE,
+            mv: ShortMove {
+                from: 0,
+                to: 63,
+                promotion_ptype: None,
+            },
+            generation: 0,
+        };
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        for mv in bs.get_legal_moves().unwrap() {
+            tt.insert(bs.next_state(mv).unwrap().position_hash, poisoned);
+        }
+        let result = choose_move(&bs, 3, &mut tt);
+        assert!(bs
+            .get_legal_moves()
+            .unwrap()
+            .contains(&result.best_move.unwrap()));
+        assert_eq!(result.eval, expected.eval);
+    }
+
+    #[test]
+    fn test_skill_level_zero_move_within_margin() {
+        let options = EngineOptions::new(4).with_skill_level(0);
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "4k3/8/8/3N4/4P3/8/8/2B1KB2 b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            let legal_moves = bs.get_legal_moves().unwrap();
+            let best_eval = choose_move(
+                &bs,
+                options.search_depth(),
+                &mut TranspositionTable::with_capacity_mb(1),
+            )
+            .eval;
+
+            let mut chosen = Vec::new();
+            for seed in 0..20 {
+                let seeded = options.with_seed(seed);
+                let result = choose_move_with_options(
+                    &bs,
+                    &seeded,
+                    &mut TranspositionTable::with_capacity_mb(1),
+                );
+                let (eval, mv) = (result.eval, result.best_move.unwrap());
+                assert!(legal_moves.contains(&mv), "{}: {:?}", fen, mv);
+                assert!(eval <= best_eval, "{}: {:?}", fen, mv);
+                assert!(
+                    eval >= best_eval - options.eval_margin(),
+                    "{}: {:?}",
+                    fen,
+                    mv
+                );
+                // same seed, same move
+                assert_eq!(
+                    choose_move_with_options(
+                        &bs,
+                        &seeded,
+                        &mut TranspositionTable::with_capacity_mb(1)
+                    ),
+                    result
+                );
+                if !chosen.contains(&mv) {
+                    chosen.push(mv);
                 }
             }
+            // the point of lower skill levels is to not always play the same move
+            assert!(chosen.len() > 1, "{}", fen);
         }
     }
-    let eval = w_eval - b_eval;
-    if maxi_colour == PieceColour::White {
-        eval
-    } else {
-        -eval
+
+    fn find_mate_fen(fen: &str, max_ply: u8) -> Option<Vec<Move>> {
+        let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        find_mate(&bs, max_ply, &mut TranspositionTable::with_capacity_mb(1))
+    }
+
+    // plays out the line, checking every move is legal and the last one gives checkmate
+    fn assert_mating_line(fen: &str, line: &[Move]) {
+        let mut bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        for mv in line {
+            assert!(
+                bs.lazy_get_legal_moves().any(|m| m == mv),
+                "{}: {:?}",
+                fen,
+                mv
+            );
+            bs = bs.next_state_unchecked(mv);
+        }
+        assert_eq!(bs.get_gamestate(), GameState::Checkmate, "{}", fen);
+    }
+
+    #[test]
+    fn test_find_mate_in_two() {
+        // 1. Qg6+ hxg6 2. Bxg6#
+        let fen = "r2qk2r/pb4pp/1n2Pb2/2B2Q2/p1p5/2P5/2B2PPP/RN2R1K1 w - - 1 1";
+        let line = find_mate_fen(fen, 5).unwrap();
+        assert_eq!(line.len(), 3);
+        assert_eq!((line[0].from, line[0].to), (29, 22));
+        assert_mating_line(fen, &line);
+
+        // quiet first move, 1. Kb6 (or Kc7) and Rh8# or Ra1# next move
+        let fen = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+        let line = find_mate_fen(fen, 3).unwrap();
+        assert_eq!(line.len(), 3);
+        assert_eq!(line[0].piece.ptype, PieceType::King);
+        assert_mating_line(fen, &line);
+    }
+
+    #[test]
+    fn test_find_mate_in_three() {
+        // 1... Bc5+ 2. Kxc5 Qb6+ 3. Kd5 Qd6#
+        let fen = "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1";
+        let line = find_mate_fen(fen, 5).unwrap();
+        assert_eq!(line.len(), 5);
+        assert_eq!((line[0].from, line[0].to), (5, 26));
+        assert_mating_line(fen, &line);
+        // not enough plies to find it
+        assert!(find_mate_fen(fen, 4).is_none());
+    }
+
+    #[test]
+    fn test_find_mate_none() {
+        assert!(find_mate(
+            &BoardState::new_starting(),
+            5,
+            &mut TranspositionTable::with_capacity_mb(1)
+        )
+        .is_none());
+        // mate in 2 needs 3 plies
+        assert!(find_mate_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2).is_none());
+        // already checkmate
+        assert!(find_mate_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", 5).is_none());
+    }
+
+    #[test]
+    fn test_run_epd_suite() {
+        // mates in one, with every mating move given for the second, and a move to avoid that loses the queen
+        let suite = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"back rank\";
+k7/7Q/1K6/8/8/8/8/8 w - - bm Qa7# Qb7# Qg8# Qh8#; id \"four mates\";
+r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - bm Ra1#;
+4k3/8/8/8/8/2p5/8/3QK3 w - - am Qd2; id \"avoid\";
+4k3/8/8/8/8/8/8/4K3 w - - id \"no bm or am\";";
+        let path = std::env::temp_dir().join("chess_oxide_test_suite.epd");
+        std::fs::write(&path, suite).unwrap();
+        let result = run_epd_suite(&path, 2).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result,
+            EpdSuiteResult {
+                positions: 4,
+                solved: 4,
+                failed: Vec::new(),
+            }
+        );
+
+        // every bundled position is searched, unsolved ones are listed by id
+        let result = run_epd_suite("test_data/wac_sample.epd", 1).unwrap();
+        assert_eq!(result.positions, 6);
+        assert_eq!(result.solved + result.failed.len(), 6);
+        assert!(result
+            .failed
+            .iter()
+            .all(|id| id.starts_with("WAC.") || id == "start"));
+
+        assert!(run_epd_suite("test_data/missing.epd", 1).is_err());
+        // a best move that isn't legal in the position
+        let path = std::env::temp_dir().join("chess_oxide_test_suite_illegal.epd");
+        std::fs::write(&path, "4k3/8/8/8/8/8/8/4K3 w - - bm Kf3;").unwrap();
+        let result = run_epd_suite(&path, 1);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(FenParseError::InvalidEpd(_))));
+    }
+
+    #[test]
+    fn test_evaluate_endgame_pawn_advancement() {
+        let pawn_back = evaluate_fen("7k/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        let pawn_advanced = evaluate_fen("7k/8/4P3/8/8/8/8/4K3 w - - 0 1");
+        assert!(pawn_advanced > pawn_back);
+    }
+
+    #[test]
+    fn test_staged_moves_order() {
+        // staged picking has to search moves in exactly the order of the full sort, or search results would change
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let root = BoardState::try_from(entry.fen).unwrap();
+            let mut states = vec![root.clone()];
+            // children have a last move, so recaptures are scored too
+            for mv in root.get_legal_moves().unwrap() {
+                states.push(root.next_state_unchecked(mv));
+            }
+            for bs in states {
+                let moves = bs.position().get_pseudo_legal_moves();
+                let mut tt_moves = vec![NULL_SHORT_MOVE];
+                tt_moves.extend(moves.first().map(|mv| mv.short_move()));
+                tt_moves.extend(moves.last().map(|mv| mv.short_move()));
+                tt_moves.extend(
+                    moves
+                        .iter()
+                        .find(|mv| mv.capture_ptype().is_some())
+                        .map(|mv| mv.short_move()),
+                );
+                for tt_mv in tt_moves {
+                    assert_eq!(
+                        StagedMoves::new(moves, tt_mv, &bs.last_move, &mut Vec::new())
+                            .collect::<Vec<usize>>(),
+                        sorted_move_indexes(moves, false, tt_mv, &bs.last_move)
+                    );
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,278 @@
+// Extended Position Description, used by engine test suites like WAC and STS
+// -> https://www.chessprogramming.org/Extended_Position_Description
+// The first four FEN fields followed by operations, each an opcode and its operands ending with ';' e.g.
+// 2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::FenParseError;
+use crate::fen::FEN;
+use crate::log_and_return_error;
+
+// opcodes whose operands are strings, so are always quoted when written
+const STRING_OPCODES: [&str; 23] = [
+    "id", "eco", "nic", "c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7", "c8", "c9", "v0", "v1",
+    "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9",
+];
+
+#[derive(Debug, Clone)]
+pub struct Epd {
+    fen: FEN,
+    // operands of each opcode, with the quotes around strings removed
+    opcodes: BTreeMap<String, Vec<String>>,
+}
+
+impl FromStr for Epd {
+    type Err = FenParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut fen_fields = Vec::new();
+        let mut rest = s;
+        for _ in 0..4 {
+            match rest.split_once(char::is_whitespace) {
+                Some((field, remaining)) => {
+                    fen_fields.push(field);
+                    rest = remaining.trim_start();
+                }
+                None if !rest.is_empty() => {
+                    fen_fields.push(rest);
+                    rest = "";
+                }
+                None => log_and_return_error!(FenParseError::InvalidEpd(format!(
+                    "Expected 4 FEN fields before the operations: {}",
+                    s
+                ))),
+            }
+        }
+
+        let opcodes = parse_operations(rest)?;
+        // EPD has no move counters, the hmvc and fmvn opcodes give them instead
+        let counter = |opcode: &str, default: &str| -> Result<String, FenParseError> {
+            match opcodes.get(opcode).map(Vec::as_slice) {
+                None => Ok(default.to_string()),
+                Some([count]) if count.parse::<u32>().is_ok() => Ok(count.clone()),
+                Some(operands) => log_and_return_error!(FenParseError::InvalidEpd(format!(
+                    "Invalid {} operand: {}. Expected a single number",
+                    opcode,
+                    operands.join(" ")
+                ))),
+            }
+        };
+        let fen = format!(
+            "{} {} {}",
+            fen_fields.join(" "),
+            counter("hmvc", "0")?,
+            counter("fmvn", "1")?
+        )
+        .parse::<FEN>()?;
+        Ok(Self { fen, opcodes })
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the FEN without its move counters, which are kept in the hmvc and fmvn opcodes
+        let fen = self.fen.to_string();
+        let fen_fields: Vec<&str> = fen.split(' ').take(4).collect();
+        write!(f, "{}", fen_fields.join(" "))?;
+        for (opcode, operands) in &self.opcodes {
+            write!(f, " {}", opcode)?;
+            for operand in operands {
+                if STRING_OPCODES.contains(&opcode.as_str())
+                    || operand.is_empty()
+                    || operand.contains(|c: char| c.is_whitespace() || c == ';')
+                {
+                    write!(f, " \"{}\"", operand)?;
+                } else {
+                    write!(f, " {}", operand)?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+impl Epd {
+    // the position, with the move counters from the hmvc and fmvn opcodes if there are any
+    pub fn fen(&self) -> FEN {
+        self.fen
+    }
+
+    pub fn opcodes(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.opcodes
+    }
+
+    pub fn opcode(&self, opcode: &str) -> Option<&[String]> {
+        self.opcodes.get(opcode).map(Vec::as_slice)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.opcode("id")
+            .and_then(|operands| operands.first())
+            .map(String::as_str)
+    }
+
+    // SAN of the best moves (bm) and moves to avoid (am) of a test suite position
+    pub fn best_moves(&self) -> &[String] {
+        self.opcode("bm").unwrap_or_default()
+    }
+
+    pub fn avoid_moves(&self) -> &[String] {
+        self.opcode("am").unwrap_or_default()
+    }
+}
+
+// opcodes and their operands, strings in double quotes can hold spaces and semicolons. The ';' after the last operation
+// is optional
+fn parse_operations(s: &str) -> Result<BTreeMap<String, Vec<String>>, FenParseError> {
+    let mut opcodes = BTreeMap::new();
+    let mut operation: Vec<String> = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        // skip whitespace between tokens
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let token = match chars.peek() {
+            None | Some(';') => {
+                let end = chars.next().is_none();
+                if !operation.is_empty() {
+                    let opcode = operation.remove(0);
+                    if opcodes.insert(opcode.clone(), operation).is_some() {
+                        log_and_return_error!(FenParseError::InvalidEpd(format!(
+                            "Duplicate opcode: {}",
+                            opcode
+                        )))
+                    }
+                    operation = Vec::new();
+                } else if !end {
+                    log_and_return_error!(FenParseError::InvalidEpd(format!(
+                        "Empty operation in: {}",
+                        s
+                    )))
+                }
+                if end {
+                    break;
+                }
+                continue;
+            }
+            Some('"') => {
+                chars.next();
+                let string: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                if operation.is_empty() {
+                    log_and_return_error!(FenParseError::InvalidEpd(format!(
+                        "Expected an opcode, got string: \"{}\"",
+                        string
+                    )))
+                }
+                string
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';') {
+                    word.push(c);
+                }
+                if operation.is_empty()
+                    && !(word.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                {
+                    log_and_return_error!(FenParseError::InvalidEpd(format!(
+                        "Invalid opcode: {}",
+                        word
+                    )))
+                }
+                word
+            }
+        };
+        operation.push(token);
+    }
+    Ok(opcodes)
+}
+
+// parse every position in an EPD file, blank lines and lines starting with '#' are skipped
+pub fn parse_epd(epd: &str) -> Result<Vec<Epd>, FenParseError> {
+    epd.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Epd::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_SAMPLE: &str = include_str!("../test_data/wac_sample.epd");
+
+    #[test]
+    fn test_parse_epd() {
+        let entries = parse_epd(WAC_SAMPLE).unwrap();
+        assert_eq!(entries.len(), 6);
+
+        let wac1 = &entries[0];
+        assert_eq!(wac1.id(), Some("WAC.001"));
+        assert_eq!(wac1.best_moves(), ["Qg6"]);
+        assert!(wac1.avoid_moves().is_empty());
+        assert_eq!(
+            wac1.fen().to_string(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+
+        // move counters come from hmvc and fmvn, and strings can hold semicolons
+        let start = entries.last().unwrap();
+        assert_eq!(start.avoid_moves(), ["g4"]);
+        assert_eq!(
+            start.opcode("c0"),
+            Some(["avoid the Grob; it loses".to_string()].as_slice())
+        );
+        assert_eq!(start.opcode("ce"), Some(["-20".to_string()].as_slice()));
+        assert_eq!(start.fen().halfmove_count(), 3);
+        assert_eq!(start.fen().move_count(), 12);
+        // operations without operands
+        assert_eq!(start.opcode("noop"), Some([].as_slice()));
+    }
+
+    #[test]
+    fn test_epd_round_trip() {
+        for line in WAC_SAMPLE
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        {
+            let epd = line.parse::<Epd>().unwrap();
+            // opcodes are written in alphabetical order, which every line in the sample already uses
+            assert_eq!(epd.to_string(), line);
+            let reparsed = epd.to_string().parse::<Epd>().unwrap();
+            assert_eq!(reparsed.opcodes(), epd.opcodes());
+            assert_eq!(reparsed.fen().to_string(), epd.fen().to_string());
+        }
+
+        // other orders and spacing are normalised, the last ';' can be left out
+        let epd = "8/8/8/8/8/8/k7/7K b - -  id  \"test\" ;bm Kb2"
+            .parse::<Epd>()
+            .unwrap();
+        assert_eq!(
+            epd.to_string(),
+            "8/8/8/8/8/8/k7/7K b - - bm Kb2; id \"test\";"
+        );
+    }
+
+    #[test]
+    fn test_epd_from_str_invalid() {
+        for epd in [
+            // missing FEN fields, and an invalid position
+            "8/8/8/8/8/8/k7/7K b",
+            "8/8/8/8/8/8/8/7K w - - bm Kg2;",
+            // duplicate and invalid opcodes, empty operations and strings in place of opcodes
+            "8/8/8/8/8/8/k7/7K b - - id \"a\"; id \"b\";",
+            "8/8/8/8/8/8/k7/7K b - - 1bm Kb2;",
+            "8/8/8/8/8/8/k7/7K b - - ;;",
+            "8/8/8/8/8/8/k7/7K b - - \"WAC.001\";",
+            // counters that aren't a single number
+            "8/8/8/8/8/8/k7/7K b - - hmvc x;",
+            "8/8/8/8/8/8/k7/7K b - - fmvn 1 2;",
+        ] {
+            assert!(epd.parse::<Epd>().is_err(), "{}", epd);
+        }
+    }
+}
@@ -13,6 +13,9 @@ pub enum BoardStateError {
     InvalidInput(String),
     Detatched(String),
     NotFound(String),
+    AmbiguousMove(String),
+    AtMove(usize, Box<BoardStateError>),
+    DrawNotClaimable(GameState),
 }
 
 impl fmt::Display for BoardStateError {
@@ -28,6 +31,9 @@ impl fmt::Display for BoardStateError {
             Self::InvalidInput(s) => write!(f, "Invalid input: {}", s),
             Self::Detatched(s) => write!(f, "Detatched from current boardstate: {}", s),
             Self::NotFound(s) => write!(f, "Not found: {}", s),
+            Self::AmbiguousMove(s) => write!(f, "Ambiguous move: {}", s),
+            Self::AtMove(idx, e) => write!(f, "Error at move {} of the move list: {}", idx, e),
+            Self::DrawNotClaimable(gs) => write!(f, "No draw to claim in GameState: {:?}", gs),
         }
     }
 }
@@ -38,6 +44,8 @@ impl error::Error for BoardStateError {}
 pub enum FenParseError {
     InvalidFen(String),
     VariantIncompatible(String),
+    InvalidEpd(String),
+    InvalidPosition(PositionError),
 }
 
 impl fmt::Display for FenParseError {
@@ -45,18 +53,69 @@ impl fmt::Display for FenParseError {
         match self {
             Self::InvalidFen(s) => write!(f, "Invalid FEN: {}", s),
             Self::VariantIncompatible(s) => write!(f, "Variant incompatibility: {}", s),
+            Self::InvalidEpd(s) => write!(f, "Invalid EPD: {}", s),
+            Self::InvalidPosition(e) => write!(f, "Invalid position: {}", e),
         }
     }
 }
 
 impl error::Error for FenParseError {}
 
+#[derive(Debug)]
+pub enum PositionError {
+    KingCount(String),
+    TooManyPieces(String),
+    TooManyPawns(String),
+    PawnOnBackRank(String),
+    KingsAdjacent(String),
+    OpponentInCheck(String),
+    InvalidEnPassant(String),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::KingCount(s) => write!(f, "Invalid number of kings: {}", s),
+            Self::TooManyPieces(s) => write!(f, "Too many pieces: {}", s),
+            Self::TooManyPawns(s) => write!(f, "Too many pawns: {}", s),
+            Self::PawnOnBackRank(s) => write!(f, "Pawn on the first or last rank: {}", s),
+            Self::KingsAdjacent(s) => write!(f, "Kings are adjacent: {}", s),
+            Self::OpponentInCheck(s) => write!(f, "Side not to move is in check: {}", s),
+            Self::InvalidEnPassant(s) => write!(f, "Invalid en passant square: {}", s),
+        }
+    }
+}
+
+impl error::Error for PositionError {}
+
+#[derive(Debug)]
+pub enum SquareParseError {
+    InvalidNotation(String),
+    InvalidIndex(usize),
+}
+
+impl fmt::Display for SquareParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidNotation(s) => write!(f, "Invalid square notation: {}", s),
+            Self::InvalidIndex(i) => write!(f, "Invalid square index: {}", i),
+        }
+    }
+}
+
+impl error::Error for SquareParseError {}
+
 #[derive(Debug)]
 pub enum PGNParseError {
     InvalidTag(String),
     NotationParseError(String),
     FileError(String),
     MoveNotFound(String),
+    AmbiguousMove(String),
+    MultipleGames(String),
+    InGame(usize, Box<PGNParseError>),
+    InvalidVariation(String),
+    InvalidNag(String),
 }
 
 impl fmt::Display for PGNParseError {
@@ -66,8 +125,49 @@ impl fmt::Display for PGNParseError {
             Self::NotationParseError(s) => write!(f, "Error parsing notation: {}", s),
             Self::FileError(s) => write!(f, "Error reading file: {}", s),
             Self::MoveNotFound(s) => write!(f, "Move not found: {}", s),
+            Self::AmbiguousMove(s) => write!(f, "Ambiguous move: {}", s),
+            Self::MultipleGames(s) => write!(f, "Multiple games: {}", s),
+            Self::InGame(game, e) => write!(f, "Error in game {}: {}", game, e),
+            Self::InvalidVariation(s) => write!(f, "Invalid variation: {}", s),
+            Self::InvalidNag(s) => write!(f, "Invalid NAG: {}", s),
         }
     }
 }
 
 impl error::Error for PGNParseError {}
+
+#[derive(Debug)]
+pub enum PolyglotBookError {
+    InvalidBook(String),
+}
+
+impl fmt::Display for PolyglotBookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidBook(s) => write!(f, "Invalid polyglot book: {}", s),
+        }
+    }
+}
+
+impl error::Error for PolyglotBookError {}
+
+#[derive(Debug)]
+pub enum SaveGameError {
+    Io(String),
+    InvalidFormat(String),
+    VersionMismatch(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for SaveGameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(s) => write!(f, "Error reading or writing save file: {}", s),
+            Self::InvalidFormat(s) => write!(f, "Invalid save file: {}", s),
+            Self::VersionMismatch(s) => write!(f, "Incompatible save file: {}", s),
+            Self::IllegalMove(s) => write!(f, "Illegal move in save file: {}", s),
+        }
+    }
+}
+
+impl error::Error for SaveGameError {}
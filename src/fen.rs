@@ -2,10 +2,11 @@ use std::fmt;
 use std::str::FromStr;
 
 use crate::board::BoardState;
-use crate::errors::FenParseError;
+use crate::errors::{FenParseError, PositionError};
 use crate::log_and_return_error;
 use crate::movegen::{MovegenFlags, Piece, PieceColour, PieceType, Square};
 use crate::position::{Pos64, Position};
+use crate::util::{square_index, square_name_unchecked, SquareIdx};
 
 pub const STD_STARTING_FEN_STR: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 const ABOVE_BELOW: usize = 8; // 8 indexes from i is the square directly above/below in the pos64 array
@@ -45,6 +46,8 @@ impl FromStr for FEN {
         fen.parse_en_passant_flag(fen_vec[3])?;
         // set last two fields if they exist, otherwise default values are 0 and 1 already set in new()
         fen.parse_halfmove_move_count(fen_vec.get(4).copied(), fen_vec.get(5).copied())?;
+        // every field is well formed, check the position they describe could be reached in a game
+        fen.validate()?;
 
         Ok(fen)
     }
@@ -124,10 +127,8 @@ impl fmt::Display for FEN {
                 != self.movegen_flags.short_white_rook_start
             {
                 fen_str.push(
-                    index_to_notation(self.movegen_flags.short_white_rook_start)
-                        .chars()
-                        .next()
-                        .unwrap()
+                    SquareIdx::new_unchecked(self.movegen_flags.short_white_rook_start)
+                        .file_char()
                         .to_ascii_uppercase(),
                 );
             } else {
@@ -142,10 +143,8 @@ impl fmt::Display for FEN {
                 != self.movegen_flags.long_white_rook_start
             {
                 fen_str.push(
-                    index_to_notation(self.movegen_flags.long_white_rook_start)
-                        .chars()
-                        .next()
-                        .unwrap()
+                    SquareIdx::new_unchecked(self.movegen_flags.long_white_rook_start)
+                        .file_char()
                         .to_ascii_uppercase(),
                 );
             } else {
@@ -160,10 +159,8 @@ impl fmt::Display for FEN {
                 != self.movegen_flags.short_black_rook_start
             {
                 fen_str.push(
-                    index_to_notation(self.movegen_flags.short_black_rook_start)
-                        .chars()
-                        .next()
-                        .unwrap()
+                    SquareIdx::new_unchecked(self.movegen_flags.short_black_rook_start)
+                        .file_char()
                         .to_ascii_lowercase(),
                 );
             } else {
@@ -178,10 +175,8 @@ impl fmt::Display for FEN {
                 != self.movegen_flags.long_black_rook_start
             {
                 fen_str.push(
-                    index_to_notation(self.movegen_flags.long_black_rook_start)
-                        .chars()
-                        .next()
-                        .unwrap()
+                    SquareIdx::new_unchecked(self.movegen_flags.long_black_rook_start)
+                        .file_char()
                         .to_ascii_lowercase(),
                 );
             } else {
@@ -200,9 +195,9 @@ impl fmt::Display for FEN {
         match self.movegen_flags.en_passant {
             Some(idx) => {
                 if self.side == PieceColour::White {
-                    fen_str.push_str(index_to_notation(idx - ABOVE_BELOW).as_str());
+                    fen_str.push_str(&square_name_unchecked(idx - ABOVE_BELOW));
                 } else {
-                    fen_str.push_str(index_to_notation(idx + ABOVE_BELOW).as_str());
+                    fen_str.push_str(&square_name_unchecked(idx + ABOVE_BELOW));
                 }
             }
             None => {
@@ -216,6 +211,22 @@ impl fmt::Display for FEN {
     }
 }
 
+// serialized as the FEN string, so deserializing goes through the same validation as parsing
+#[cfg(feature = "serde")]
+impl serde::Serialize for FEN {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FEN {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl From<&BoardState> for FEN {
     fn from(board_state: &BoardState) -> Self {
         let mut fen = Self::from(board_state.position());
@@ -269,6 +280,24 @@ impl FEN {
         self.move_count
     }
 
+    // true if a side can castle with its king off the e file or a rook off the a or h file, which only happens in Chess960
+    pub fn is_chess960(&self) -> bool {
+        let flags = &self.movegen_flags;
+        let king = |pcolour| {
+            Square::Piece(Piece {
+                pcolour,
+                ptype: PieceType::King,
+            })
+        };
+        let white_king_moved = self.pos64[60] != king(PieceColour::White);
+        let black_king_moved = self.pos64[4] != king(PieceColour::Black);
+        (flags.white_castle_long && (white_king_moved || flags.long_white_rook_start != 56))
+            || (flags.white_castle_short
+                && (white_king_moved || flags.short_white_rook_start != 63))
+            || (flags.black_castle_long && (black_king_moved || flags.long_black_rook_start != 0))
+            || (flags.black_castle_short && (black_king_moved || flags.short_black_rook_start != 7))
+    }
+
     fn parse_pos_field(&mut self, field: &str) -> Result<(), FenParseError> {
         let mut pos = Pos64::default();
         let mut rank_start_idx = 0;
@@ -372,10 +401,10 @@ impl FEN {
         }
 
         if wking_num != 1 || bking_num != 1 {
-            let err = FenParseError::InvalidFen(format!(
-                "Incorrect number of kings (white: {}, black: {}) in FEN field: {}",
+            let err = FenParseError::InvalidPosition(PositionError::KingCount(format!(
+                "White has {}, Black has {} in FEN field: {}",
                 wking_num, bking_num, field
-            ));
+            )));
             log_and_return_error!(err)
         }
 
@@ -458,42 +487,56 @@ impl FEN {
                     let (idx, pcolour) = if x.is_ascii_uppercase() {
                         // white notation is x1
                         (
-                            notation_to_index(&format!("{}1", x.to_ascii_lowercase()))?,
+                            square_index(&format!("{}1", x.to_ascii_lowercase()))
+                                .map_err(|e| FenParseError::InvalidFen(e.to_string()))?,
                             PieceColour::White,
                         )
                     } else {
                         // black notation is x8
                         (
-                            notation_to_index(&format!("{}8", x.to_ascii_lowercase()))?,
+                            square_index(&format!("{}8", x.to_ascii_lowercase()))
+                                .map_err(|e| FenParseError::InvalidFen(e.to_string()))?,
                             PieceColour::Black,
                         )
                     };
 
-                    // if idx is the outer kingside rook it means the outer queenside rook is not the long castle rook
-                    // and vice versa for the short castle rook, if x is set t
-                    if let Some(i) = self.pos64().king_side_outer_rook_idx(pcolour) {
-                        if i == idx {
-                            if pcolour == PieceColour::White {
-                                self.movegen_flags.white_castle_long = true;
-                                self.movegen_flags.long_white_rook_start = idx;
-                            } else {
-                                self.movegen_flags.black_castle_long = true;
-                                self.movegen_flags.long_black_rook_start = idx;
-                            }
-                        }
-                        continue;
-                    }
-                    if let Some(i) = self.pos64().queen_side_outer_rook_idx(pcolour) {
-                        if i == idx {
-                            if pcolour == PieceColour::White {
-                                self.movegen_flags.white_castle_short = true;
-                                self.movegen_flags.short_white_rook_start = idx;
-                            } else {
-                                self.movegen_flags.black_castle_short = true;
-                                self.movegen_flags.short_black_rook_start = idx;
+                    // the file names the castling rook exactly (shredder-FEN, or XFEN disambiguating an inner rook), it is the
+                    // long castle rook if it is on the queen side of the king and the short castle rook otherwise
+                    let rook = Square::Piece(Piece {
+                        pcolour,
+                        ptype: PieceType::Rook,
+                    });
+                    let king = Square::Piece(Piece {
+                        pcolour,
+                        ptype: PieceType::King,
+                    });
+                    let king_file = self
+                        .pos64()
+                        .back_rank(pcolour)
+                        .iter()
+                        .position(|s| *s == king);
+                    if let Some(king_file) = king_file {
+                        if self.pos64()[idx] == rook {
+                            match (idx % 8 < king_file, pcolour) {
+                                (true, PieceColour::White) => {
+                                    self.movegen_flags.white_castle_long = true;
+                                    self.movegen_flags.long_white_rook_start = idx;
+                                }
+                                (true, PieceColour::Black) => {
+                                    self.movegen_flags.black_castle_long = true;
+                                    self.movegen_flags.long_black_rook_start = idx;
+                                }
+                                (false, PieceColour::White) => {
+                                    self.movegen_flags.white_castle_short = true;
+                                    self.movegen_flags.short_white_rook_start = idx;
+                                }
+                                (false, PieceColour::Black) => {
+                                    self.movegen_flags.black_castle_short = true;
+                                    self.movegen_flags.short_black_rook_start = idx;
+                                }
                             }
+                            continue;
                         }
-                        continue;
                     }
                     return Err(FenParseError::InvalidFen(format!(
                         "Invalid castling flag: {}. Rook disambiguation is incorrect/rook not found at file",
@@ -513,7 +556,8 @@ impl FEN {
 
     fn parse_en_passant_flag(&mut self, field: &str) -> Result<(), FenParseError> {
         if field != "-" {
-            let ep_mv_idx = notation_to_index(field)?;
+            let ep_mv_idx =
+                square_index(field).map_err(|e| FenParseError::InvalidFen(e.to_string()))?;
 
             // error if index is out of bounds. FEN defines the index behind the pawn that moved, so valid indexes are only 16->47 (excluded top and bottom two ranks)
             if !(16..=47).contains(&ep_mv_idx) {
@@ -564,55 +608,12 @@ impl FEN {
         }
         Ok(())
     }
-}
 
-#[inline]
-fn notation_to_index(n: &str) -> Result<usize, FenParseError> {
-    if n.len() != 2
-        || n.chars().next().unwrap() < 'a'
-        || n.chars().next().unwrap() > 'h'
-        || n.chars().nth(1).unwrap() < '1'
-        || n.chars().nth(1).unwrap() > '8'
-    {
-        log_and_return_error!(FenParseError::InvalidFen(format!(
-            "Invalid notation ({}) when converting to index:",
-            n
-        )))
-    }
-    let file: char = n.chars().next().unwrap();
-    let rank: char = n.chars().nth(1).unwrap();
-    let rank_starts = [56, 48, 40, 32, 24, 16, 8, 0]; // 1st to 8th rank starting indexes
-    let file_offset = match file {
-        'a' => 0,
-        'b' => 1,
-        'c' => 2,
-        'd' => 3,
-        'e' => 4,
-        'f' => 5,
-        'g' => 6,
-        'h' => 7,
-        _ => unreachable!(), // see error checking at start of function
-    };
-    let rank_digit = rank.to_digit(10).unwrap();
-    Ok(file_offset + rank_starts[(rank_digit - 1) as usize])
-}
-
-#[inline]
-fn index_to_notation(i: usize) -> String {
-    let file = match i % 8 {
-        0 => 'a',
-        1 => 'b',
-        2 => 'c',
-        3 => 'd',
-        4 => 'e',
-        5 => 'f',
-        6 => 'g',
-        7 => 'h',
-        _ => ' ',
-    };
-    let rank_num = 8 - i / 8;
-    let rank = char::from_digit(rank_num.try_into().unwrap(), 10).unwrap();
-    format!("{}{}", file, rank)
+    fn validate(&self) -> Result<(), FenParseError> {
+        Position::new_from_pub_parts(self.pos64, self.side, self.movegen_flags)
+            .validate()
+            .map_err(FenParseError::InvalidPosition)
+    }
 }
 
 #[cfg(test)]
@@ -648,6 +649,76 @@ mod tests {
     fn test_fen_from_str_invalid_castling_flags() {
         let fen_str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w zakK - 0 1";
         assert!(FEN::from_str(fen_str).is_err());
+        // no rook on the given file
+        let fen_str = "r3k2r/8/8/8/8/8/8/RR2K2R w C - 0 1";
+        assert!(FEN::from_str(fen_str).is_err());
+    }
+
+    #[test]
+    fn test_fen_castling_rook_files() {
+        // shredder-FEN files are equivalent to KQkq for outer rooks
+        let fen =
+            FEN::from_str("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        let flags = fen.movegen_flags();
+        assert!(flags.white_castle_short && flags.white_castle_long);
+        assert!(flags.black_castle_short && flags.black_castle_long);
+        assert_eq!(flags.short_white_rook_start, 63);
+        assert_eq!(flags.long_white_rook_start, 61);
+        assert_eq!(flags.short_black_rook_start, 7);
+        assert_eq!(flags.long_black_rook_start, 5);
+        assert_eq!(
+            fen.to_string(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+
+        // inner rooks keep their file when converted back to a string (XFEN)
+        let fen = FEN::from_str("r3k2r/8/8/8/8/8/8/RR2K2R w BHa - 0 1").unwrap();
+        let flags = fen.movegen_flags();
+        assert!(flags.white_castle_short && flags.white_castle_long && flags.black_castle_long);
+        assert!(!flags.black_castle_short);
+        assert_eq!(flags.long_white_rook_start, 57);
+        assert_eq!(flags.short_white_rook_start, 63);
+        assert_eq!(fen.to_string(), "r3k2r/8/8/8/8/8/8/RR2K2R w KBq - 0 1");
+        assert_eq!(
+            FEN::from_str(&fen.to_string())
+                .unwrap()
+                .movegen_flags()
+                .long_white_rook_start,
+            57
+        );
+    }
+
+    #[test]
+    fn test_fen_is_chess960() {
+        for (fen_str, is_chess960) in [
+            (STD_STARTING_FEN_STR, false),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                false,
+            ),
+            // no castling rights left, so nothing shows it is Chess960
+            ("1r4k1/8/8/8/8/8/8/1R4K1 w - - 0 1", false),
+            (
+                "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1",
+                true,
+            ),
+            // king on the e file but the rooks aren't in the corners
+            (
+                "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w KQkq - 0 1",
+                true,
+            ),
+            // only black can still castle, with the king off the e file
+            ("r1k4r/8/8/8/8/8/8/R3K2R w ah - 0 1", true),
+            ("r3k2r/8/8/8/8/8/8/RR2K2R w BHa - 0 1", true),
+        ] {
+            assert_eq!(
+                FEN::from_str(fen_str).unwrap().is_chess960(),
+                is_chess960,
+                "{}",
+                fen_str
+            );
+        }
     }
 
     #[test]
@@ -656,6 +727,66 @@ mod tests {
         assert!(FEN::from_str(fen_str).is_err());
     }
 
+    #[test]
+    fn test_fen_from_str_invalid_position() {
+        let position_error = |fen_str: &str| match FEN::from_str(fen_str) {
+            Err(FenParseError::InvalidPosition(e)) => e,
+            other => panic!("{}: expected an invalid position, got {:?}", fen_str, other),
+        };
+        assert!(matches!(
+            position_error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            PositionError::KingCount(_)
+        ));
+        assert!(matches!(
+            position_error("4k3/8/8/8/8/P7/PPPPPPPP/4K3 w - - 0 1"),
+            PositionError::TooManyPawns(_)
+        ));
+        assert!(matches!(
+            position_error("4k3/8/8/8/8/NNN5/PPPPPPPP/RNBQKBNR w KQ - 0 1"),
+            PositionError::TooManyPieces(_)
+        ));
+        assert!(matches!(
+            position_error("P3k3/8/8/8/8/8/8/4K3 b - - 0 1"),
+            PositionError::PawnOnBackRank(_)
+        ));
+        assert!(matches!(
+            position_error("8/8/8/8/8/8/8/3kK3 w - - 0 1"),
+            PositionError::KingsAdjacent(_)
+        ));
+        assert!(matches!(
+            position_error("4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1"),
+            PositionError::OpponentInCheck(_)
+        ));
+        // no pawn in front of the en passant square, one on the wrong rank, and one that couldn't have moved two squares
+        for fen_str in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 2",
+            "rnbqkb1r/pppp1ppp/4n3/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 2",
+        ] {
+            assert!(matches!(
+                position_error(fen_str),
+                PositionError::InvalidEnPassant(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_fen_from_str_exotic_positions() {
+        for fen_str in [
+            // underpromoted pieces
+            "4k3/8/8/8/8/8/NNNBBB2/4K3 w - - 0 1",
+            // double check, checkmate and stalemate for the side to move
+            "3k4/8/8/8/8/5n2/8/3qK3 w - - 0 1",
+            "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+            // an en passant square whose capture would be illegal, the pawns are pinned to the king
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 2",
+        ] {
+            assert!(FEN::from_str(fen_str).is_ok(), "{}", fen_str);
+        }
+    }
+
     #[test]
     fn test_fen_from_str_invalid_halfmove_count() {
         let fen_str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1";
@@ -687,25 +818,8 @@ mod tests {
     fn test_fen_to_board_state() {
         let fen_str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
         let fen = FEN::from_str(fen_str).unwrap();
-        let board_state: BoardState = fen.into();
+        let board_state: BoardState = fen.try_into().unwrap();
         let fen_from_board = FEN::from(&board_state);
         assert_eq!(fen_from_board.to_string(), fen_str);
     }
-
-    #[test]
-    fn test_notation_to_index() {
-        assert_eq!(notation_to_index("a1").unwrap(), 56);
-        assert_eq!(notation_to_index("h8").unwrap(), 7);
-        assert_eq!(notation_to_index("d4").unwrap(), 35);
-        assert!(notation_to_index("i9").is_err());
-        assert!(notation_to_index("a9").is_err());
-        assert!(notation_to_index("z1").is_err());
-    }
-
-    #[test]
-    fn test_index_to_notation() {
-        assert_eq!(index_to_notation(56), "a1");
-        assert_eq!(index_to_notation(7), "h8");
-        assert_eq!(index_to_notation(35), "d4");
-    }
 }
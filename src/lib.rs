@@ -1,24 +1,44 @@
+mod attacks;
+mod bitboard;
 pub mod board;
+pub mod book;
+mod clock;
+pub mod diagnostics;
 mod engine;
+pub mod epd;
 mod errors;
 pub mod fen;
 mod macros;
 mod magic;
 mod mailbox;
 mod movegen;
+mod packed_move;
 mod perft;
 pub mod pgn;
+pub mod player;
 mod position;
+mod save;
+mod search;
 mod transposition;
 mod util;
+mod version;
 mod zobrist;
 
 pub use {
     board::*,
+    clock::Clock,
+    engine::{
+        evaluate_detailed, evaluate_state, run_epd_suite, EngineOptions, EpdSuiteResult,
+        EvalBreakdown, SideEval, MAX_SKILL_LEVEL,
+    },
     movegen::{
         CastleMove, CastleSide, Move, MoveType, Piece, PieceColour, PieceType, ShortMove, Square,
         NULL_MOVE, NULL_SHORT_MOVE,
     },
     perft::*,
+    search::{SearchHandle, SearchLimits, SearchProgress},
+    transposition::{SharedTranspositionTable, TTStats, TranspositionTable},
     util::*,
+    version::*,
+    zobrist::polyglot_hash,
 };
@@ -919,13 +919,18 @@ pub const POLYGLOT_MAGIC_EN_PASSANT_TABLE: [u64; 8] = [
 pub const POLYGLOT_MAGIC_WHITE_TO_MOVE: u64 = 17930561480947107081;
 
 // Magic numbers for BoardState. Not defined in Polyglot, so custom magic numbers generated from rand::thread_rng()
-pub const MAGIC_OCCURRENCES_TABLE: [u64; 3] = [
+// one hash per number of occurrences from 1 to 4, the last one is shared by 5 or more occurrences
+pub const MAGIC_OCCURRENCES_TABLE: [u64; 5] = [
     3067485425033798816,
     10109551177187616785,
     3854914644446497806,
+    8430534862049515626,
+    8373471103228907290,
 ];
 
-pub const MAGIC_HALFMOVE_COUNT_TABLE: [u64; 100] = [
+// one hash per halfmove count from 0 to 99, then one shared by 100 to 149 (fifty move rule) and one shared by every
+// halfmove count of 150 or more (seventy-five move rule)
+pub const MAGIC_HALFMOVE_COUNT_TABLE: [u64; 102] = [
     12220966387191540788,
     5312883734740957481,
     311175233825183678,
@@ -1026,4 +1031,6 @@ pub const MAGIC_HALFMOVE_COUNT_TABLE: [u64; 100] = [
     8486805234946147017,
     1423773609666347314,
     15325857710397518336,
+    9727646837592068833,
+    16269712734703801193,
 ];
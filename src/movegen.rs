@@ -1,16 +1,12 @@
 use core::fmt;
 
+use crate::bitboard::*;
 use crate::mailbox;
 use crate::position;
+use crate::position::{colour_idx, square_set_indexes};
+use crate::util::SquareIdx;
 
 //pub const MOVE_VEC_SIZE: usize = 27; // max number of squares a queen can possibly move to is 27
-type Offset = [i32; 8];
-
-const PAWN_OFFSET: Offset = [0, 0, 0, 0, 0, 0, 0, 0];
-const KNIGHT_OFFSET: Offset = [-21, -19, -12, -8, 8, 12, 19, 21];
-const BISHOP_OFFSET: Offset = [-11, -9, 9, 11, 0, 0, 0, 0];
-const ROOK_OFFSET: Offset = [-10, -1, 1, 10, 0, 0, 0, 0];
-const QUEEN_KING_OFFSET: Offset = [-11, -10, -9, -1, 1, 9, 10, 11];
 
 // indexes for *standard* starting position
 const STD_LONG_WHITE_ROOK_START: usize = 56;
@@ -54,6 +50,7 @@ pub const NULL_SHORT_MOVE: ShortMove = ShortMove {
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     Pawn,
     Knight,
@@ -64,6 +61,7 @@ pub enum PieceType {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceColour {
     White,
     Black,
@@ -89,18 +87,20 @@ impl fmt::Display for PieceColour {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub pcolour: PieceColour,
     pub ptype: PieceType,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Square {
     Piece(Piece),
     Empty,
 }
 // todo maybe have a separate struct for starting flags instead of using movegen flags
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MovegenFlags {
     pub white_castle_short: bool,
     pub white_castle_long: bool,
@@ -145,6 +145,7 @@ impl MovegenFlags {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     pub piece: Piece,
     pub from: usize,
@@ -153,6 +154,29 @@ pub struct Move {
 }
 
 impl Move {
+    // None for NULL_MOVE, whose indexes aren't on the board
+    pub fn from_square(&self) -> Option<SquareIdx> {
+        SquareIdx::new(self.from)
+    }
+
+    pub fn to_square(&self) -> Option<SquareIdx> {
+        SquareIdx::new(self.to)
+    }
+
+    // algebraic notation of the from square e.g. "e2"
+    pub fn from_square_name(&self) -> Option<String> {
+        self.from_square().map(|square| square.to_string())
+    }
+
+    pub fn to_square_name(&self) -> Option<String> {
+        self.to_square().map(|square| square.to_string())
+    }
+
+    // long algebraic coordinate notation used by UCI, the same as Display
+    pub fn uci_string(&self) -> String {
+        self.to_string()
+    }
+
     pub const fn short_move(&self) -> ShortMove {
         ShortMove {
             from: self.from as u8,
@@ -165,14 +189,85 @@ impl Move {
     }
 }
 
+// long algebraic coordinate notation e.g. "e2e4" or "e7e8q". Castling is written as the king's from and to squares
+// ("e1g1"), also in chess960, rather than UCI_Chess960's king takes rook. Moves off the board are written as the UCI null
+// move "0000"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let promotion_ptype = match self.move_type {
+            MoveType::Promotion(ptype, _) => Some(ptype),
+            _ => None,
+        };
+        match (self.from_square(), self.to_square()) {
+            (Some(from), Some(to)) => write_coordinate_notation(f, from, to, promotion_ptype),
+            _ => write!(f, "0000"),
+        }
+    }
+}
+
+fn write_coordinate_notation(
+    f: &mut fmt::Formatter<'_>,
+    from: SquareIdx,
+    to: SquareIdx,
+    promotion_ptype: Option<PieceType>,
+) -> fmt::Result {
+    write!(f, "{}{}", from, to)?;
+    match promotion_ptype {
+        Some(PieceType::Queen) => write!(f, "q"),
+        Some(PieceType::Rook) => write!(f, "r"),
+        Some(PieceType::Bishop) => write!(f, "b"),
+        Some(PieceType::Knight) => write!(f, "n"),
+        _ => Ok(()),
+    }
+}
+
 // struct that stores enough information to identify any full sized move
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShortMove {
     pub from: u8,
     pub to: u8,
     pub promotion_ptype: Option<PieceType>,
 }
 
+impl ShortMove {
+    // parses the notation written by Display e.g. "e2e4" or "a7a8q", without checking the move is legal or making sense
+    // of castling. None if s is malformed, "0000" included
+    pub(crate) fn from_coordinate_notation(s: &str) -> Option<Self> {
+        if !s.is_ascii() || !(4..=5).contains(&s.len()) {
+            return None;
+        }
+        let from = SquareIdx::from_notation(&s[0..2])?;
+        let to = SquareIdx::from_notation(&s[2..4])?;
+        let promotion_ptype = match &s[4..] {
+            "" => None,
+            "q" => Some(PieceType::Queen),
+            "r" => Some(PieceType::Rook),
+            "b" => Some(PieceType::Bishop),
+            "n" => Some(PieceType::Knight),
+            _ => return None,
+        };
+        Some(Self {
+            from: from.index() as u8,
+            to: to.index() as u8,
+            promotion_ptype,
+        })
+    }
+}
+
+// same notation as Move
+impl fmt::Display for ShortMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (
+            SquareIdx::new(self.from as usize),
+            SquareIdx::new(self.to as usize),
+        ) {
+            (Some(from), Some(to)) => write_coordinate_notation(f, from, to, self.promotion_ptype),
+            _ => write!(f, "0000"),
+        }
+    }
+}
+
 impl PartialEq<ShortMove> for Move {
     fn eq(&self, other: &ShortMove) -> bool {
         let result = self.from == other.from as usize && self.to == other.to as usize;
@@ -200,12 +295,14 @@ impl PartialEq<Move> for ShortMove {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CastleSide {
     Short,
     Long,
 }
 // TODO king squares include to and from indexes, which are already in the move struct. Maybe change this
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CastleMove {
     pub rook_from: usize,
     pub rook_to: usize,
@@ -219,6 +316,7 @@ impl CastleMove {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MoveType {
     EnPassant(usize),
     Promotion(PieceType, Option<PieceType>),
@@ -287,26 +385,6 @@ const fn mb_get_pawn_attack_offset(piece: Piece) -> [i32; 2] {
     }
 }
 
-#[inline(always)]
-const fn mb_get_offset(piece: Piece) -> Offset {
-    match piece.ptype {
-        PieceType::Pawn => PAWN_OFFSET, // not used
-        PieceType::Knight => KNIGHT_OFFSET,
-        PieceType::Bishop => BISHOP_OFFSET,
-        PieceType::Rook => ROOK_OFFSET,
-        PieceType::Queen => QUEEN_KING_OFFSET,
-        PieceType::King => QUEEN_KING_OFFSET,
-    }
-}
-
-#[inline(always)]
-const fn get_slide(piece: Piece) -> bool {
-    match piece.ptype {
-        PieceType::Pawn | PieceType::Knight | PieceType::King => false,
-        PieceType::Bishop | PieceType::Rook | PieceType::Queen => true,
-    }
-}
-
 #[inline(always)]
 const fn pawn_is_promotion_square(i: i32, piece: Piece) -> bool {
     match piece.pcolour {
@@ -362,6 +440,7 @@ fn is_castle_possible(
 // doesnt account for discovered king checks after the move
 pub(crate) fn movegen(
     pos: &position::Pos64,
+    bitboards: &Bitboards,
     movegen_flags: &MovegenFlags,
     piece: Piece,
     i: usize,
@@ -468,53 +547,22 @@ pub(crate) fn movegen(
             }
         }
     } else {
-        // move gen for other pieces
-        for j in mb_get_offset(piece) {
-            // end of offsets
-            if j == 0 {
-                break;
-            }
-
-            let mut mv = mailbox::next_mailbox_number(i, j);
-            let mut slide_idx = j;
-
-            while mv >= 0 {
-                let mv_square = &pos[mv as usize];
-                match mv_square {
-                    Square::Piece(mv_square_piece) => {
-                        if piece.pcolour != mv_square_piece.pcolour {
-                            mv_map.add_move(
-                                &(Move {
-                                    piece,
-                                    from: i,
-                                    to: mv as usize,
-                                    move_type: MoveType::Capture(mv_square_piece.ptype),
-                                }),
-                            );
-                        }
-                        break; // break the slide after encountering a piece
-                    }
-                    Square::Empty => {
-                        mv_map.add_move(
-                            &(Move {
-                                piece,
-                                from: i,
-                                to: mv as usize,
-                                move_type: MoveType::Normal,
-                            }),
-                        );
-                    }
-                }
-                // is piece a sliding type
-                if get_slide(piece) {
-                    slide_idx += j;
-                    mv = mailbox::next_mailbox_number(i, slide_idx);
-
-                    continue;
-                } else {
-                    break;
-                } // continue through rest of offsets
-            }
+        // move gen for other pieces, the squares they attack that aren't their own pieces in index order
+        let targets =
+            piece_attacks(piece, i, bitboards.occupied()) & !bitboards.colour(piece.pcolour);
+        for to in square_set_indexes(targets) {
+            let move_type = match &pos[to] {
+                Square::Piece(captured) => MoveType::Capture(captured.ptype),
+                Square::Empty => MoveType::Normal,
+            };
+            mv_map.add_move(
+                &(Move {
+                    piece,
+                    from: i,
+                    to,
+                    move_type,
+                }),
+            );
         }
     }
 
@@ -600,60 +648,119 @@ pub(crate) fn movegen(
     }
 }
 
-pub fn movegen_in_check(pos: &position::Pos64, king_idx: usize, king_colour: PieceColour) -> bool {
-    for (i, s) in pos.iter().enumerate() {
-        if let Square::Piece(piece) = s {
-            if piece.pcolour != king_colour {
-                // Move gen for pawns
-                if piece.ptype == PieceType::Pawn {
-                    // Defending moves for pawns
-                    let attack_offset = mb_get_pawn_attack_offset(*piece);
-                    for j in attack_offset {
-                        let mv = mailbox::next_mailbox_number(i, j);
-                        if mv >= 0 {
-                            if (mv as usize) == king_idx {
-                                return true;
-                            }
-                        }
-                    }
-                } else {
-                    // move gen for other pieces
-                    let mb_offset = mb_get_offset(*piece);
-                    let slide = get_slide(*piece);
-                    for j in mb_offset {
-                        // end of offsets
-                        if j == 0 {
-                            break;
-                        }
+pub(crate) fn movegen_in_check(
+    bitboards: &Bitboards,
+    king_idx: usize,
+    king_colour: PieceColour,
+) -> bool {
+    bitboards.attackers_to(king_idx, !king_colour) != 0
+}
 
-                        let mut mv = mailbox::next_mailbox_number(i, j);
-                        let mut slide_idx = j;
+// pieces pinned to the king and the checks against it, found once per position so most moves can be classified as
+// legal without testing them on the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CheckInfo {
+    // square set of the king's own pieces that are absolutely pinned
+    pub pinned: u64,
+    // square set a non king move has to land on to resolve check: every square when not in check, the checker and the
+    // squares between it and the king in single check, and no squares in double check
+    pub check_mask: u64,
+    pub checkers: u8,
+}
 
-                        while mv >= 0 {
-                            if matches!(&pos[mv as usize], Square::Piece(_)) {
-                                if mv as usize == king_idx {
-                                    return true;
-                                }
-                                break; // break the slide after encountering a piece
-                            }
-                            // repeating this code here and in the matches! is faster than just putting it on top. Don't know why
-                            if mv as usize == king_idx {
-                                return true;
-                            }
+impl CheckInfo {
+    // treats every piece as pinned, so every move has to be tested on the board
+    pub(crate) const fn all_pinned() -> Self {
+        Self {
+            pinned: u64::MAX,
+            check_mask: u64::MAX,
+            checkers: 0,
+        }
+    }
+}
 
-                            // is piece a sliding type
-                            if slide {
-                                slide_idx += j;
-                                mv = mailbox::next_mailbox_number(i, slide_idx);
-                                continue;
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
+pub(crate) fn movegen_check_info(
+    bitboards: &Bitboards,
+    king_idx: usize,
+    king_colour: PieceColour,
+) -> CheckInfo {
+    let enemy = |ptype| {
+        bitboards.pieces(Piece {
+            pcolour: !king_colour,
+            ptype,
+        })
+    };
+    // enemy pawns checking the king sit on the squares a pawn of the king's colour would attack. An adjacent enemy king
+    // also counts, as it does in movegen_in_check
+    let leapers = (PAWN_ATTACKS[colour_idx(king_colour)][king_idx] & enemy(PieceType::Pawn))
+        | (KNIGHT_ATTACKS[king_idx] & enemy(PieceType::Knight))
+        | (KING_ATTACKS[king_idx] & enemy(PieceType::King));
+    let (pinned, slider_checks, slider_checkers) =
+        slider_pins_and_checks(bitboards, king_idx, king_colour);
+    let checkers = leapers.count_ones() as u8 + slider_checkers;
+
+    CheckInfo {
+        pinned,
+        check_mask: match checkers {
+            0 => u64::MAX,
+            1 => leapers | slider_checks,
+            _ => 0,
+        },
+        checkers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+    use crate::position::Position;
+
+    fn move_strings(fen: &str) -> Vec<String> {
+        Position::try_from(fen.parse::<FEN>().unwrap())
+            .unwrap()
+            .get_legal_moves()
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_move_display() {
+        let moves = move_strings("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(moves.contains(&"e2e4".to_string()));
+        assert!(moves.contains(&"g1f3".to_string()));
+
+        // every promotion piece, pushing and capturing
+        let moves =
+            move_strings("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P1P1P/R2Q1RK1 b kq - 0 1");
+        for expected in ["b2b1q", "b2b1r", "b2b1b", "b2b1n", "b2a1q", "b2a1n"] {
+            assert!(moves.contains(&expected.to_string()), "{}", expected);
         }
+        // both castle sides are the king's from and to squares
+        let moves = move_strings("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        assert!(moves.contains(&"e8g8".to_string()));
+        assert!(moves.contains(&"e8c8".to_string()));
+
+        // en passant is the capturing pawn's move
+        let moves = move_strings("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        assert!(moves.contains(&"e5f6".to_string()));
+
+        // chess960 castling is also the king's from and to squares, not king takes rook
+        let moves = move_strings("rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1");
+        assert!(moves.contains(&"b1g1".to_string()));
+        assert!(moves.contains(&"b1c1".to_string()));
+        assert!(!moves.contains(&"b1h1".to_string()));
+
+        assert_eq!(NULL_MOVE.to_string(), "0000");
+        let mv = Move {
+            from: 12,
+            to: 4,
+            move_type: MoveType::Promotion(PieceType::Knight, None),
+            ..NULL_MOVE
+        };
+        assert_eq!(mv.uci_string(), "e7e8n");
+        assert_eq!(mv.short_move().to_string(), "e7e8n");
+        assert_eq!(NULL_SHORT_MOVE.to_string(), "0000");
     }
-    false
 }
@@ -0,0 +1,313 @@
+use std::fmt;
+
+use crate::movegen::*;
+
+// Compact 4 byte representation of a Move, used for the attack map and in the search hot loops where the full Move
+// (~48 bytes) causes a lot of memory traffic. Conversion to and from Move is lossless for any move on the board.
+//
+// Bit layout, least significant bit first:
+//   0..6   from square index
+//   6..12  to square index
+//   12..15 moving piece type
+//   15     moving piece colour (0 = white, 1 = black)
+//   16..19 move type tag (see TAG_* constants)
+//   19..32 move type payload:
+//          EnPassant:  19..25 index of the captured pawn
+//          Promotion:  19..22 promotion piece type, 22..25 captured piece type + 1 (0 = no capture)
+//          Castle:     19..25 rook from, 25..31 rook to, 31 castle side (0 = short, 1 = long)
+//          Capture:    19..22 captured piece type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct PackedMove(u32);
+
+const SQUARE_MASK: u32 = 0x3f;
+const PTYPE_MASK: u32 = 0x7;
+
+const FROM_SHIFT: u32 = 0;
+const TO_SHIFT: u32 = 6;
+const PTYPE_SHIFT: u32 = 12;
+const PCOLOUR_SHIFT: u32 = 15;
+const TAG_SHIFT: u32 = 16;
+const PAYLOAD_SHIFT: u32 = 19;
+
+const TAG_NORMAL: u32 = 0;
+const TAG_PAWN_PUSH: u32 = 1;
+const TAG_DOUBLE_PAWN_PUSH: u32 = 2;
+const TAG_CAPTURE: u32 = 3;
+const TAG_EN_PASSANT: u32 = 4;
+const TAG_PROMOTION: u32 = 5;
+const TAG_CASTLE: u32 = 6;
+const TAG_NONE: u32 = 7;
+
+#[inline(always)]
+const fn ptype_to_bits(ptype: PieceType) -> u32 {
+    ptype as u32
+}
+
+#[inline(always)]
+const fn bits_to_ptype(bits: u32) -> PieceType {
+    match bits & PTYPE_MASK {
+        0 => PieceType::Pawn,
+        1 => PieceType::Knight,
+        2 => PieceType::Bishop,
+        3 => PieceType::Rook,
+        4 => PieceType::Queen,
+        _ => PieceType::King,
+    }
+}
+
+impl PackedMove {
+    // returns None if mv can't be packed losslessly, e.g. NULL_MOVE or any other move with squares off the board
+    pub fn try_pack(mv: &Move) -> Option<Self> {
+        let packed = Self::pack(mv);
+        if packed.unpack() == *mv {
+            Some(packed)
+        } else {
+            None
+        }
+    }
+
+    // squares are masked to 6 bits, so NULL_MOVE (and other off board moves) can't be packed losslessly
+    pub const fn pack(mv: &Move) -> Self {
+        let mut bits = ((mv.from as u32 & SQUARE_MASK) << FROM_SHIFT)
+            | ((mv.to as u32 & SQUARE_MASK) << TO_SHIFT)
+            | (ptype_to_bits(mv.piece.ptype) << PTYPE_SHIFT);
+        if let PieceColour::Black = mv.piece.pcolour {
+            bits |= 1 << PCOLOUR_SHIFT;
+        }
+        let (tag, payload) = match mv.move_type {
+            MoveType::Normal => (TAG_NORMAL, 0),
+            MoveType::PawnPush => (TAG_PAWN_PUSH, 0),
+            MoveType::DoublePawnPush => (TAG_DOUBLE_PAWN_PUSH, 0),
+            MoveType::Capture(ptype) => (TAG_CAPTURE, ptype_to_bits(ptype)),
+            MoveType::EnPassant(idx) => (TAG_EN_PASSANT, idx as u32 & SQUARE_MASK),
+            MoveType::Promotion(ptype, capture) => (
+                TAG_PROMOTION,
+                ptype_to_bits(ptype)
+                    | (match capture {
+                        Some(capture) => ptype_to_bits(capture) + 1,
+                        None => 0,
+                    } << 3),
+            ),
+            MoveType::Castle(cm) => (
+                TAG_CASTLE,
+                (cm.rook_from as u32 & SQUARE_MASK)
+                    | ((cm.rook_to as u32 & SQUARE_MASK) << 6)
+                    | (match cm.side {
+                        CastleSide::Short => 0,
+                        CastleSide::Long => 1,
+                    } << 12),
+            ),
+            MoveType::None => (TAG_NONE, 0),
+        };
+        Self(bits | (tag << TAG_SHIFT) | (payload << PAYLOAD_SHIFT))
+    }
+
+    pub const fn unpack(self) -> Move {
+        Move {
+            piece: self.piece(),
+            from: self.from(),
+            to: self.to(),
+            move_type: self.move_type(),
+        }
+    }
+
+    #[inline(always)]
+    pub const fn from(self) -> usize {
+        ((self.0 >> FROM_SHIFT) & SQUARE_MASK) as usize
+    }
+
+    #[inline(always)]
+    pub const fn to(self) -> usize {
+        ((self.0 >> TO_SHIFT) & SQUARE_MASK) as usize
+    }
+
+    #[inline(always)]
+    pub const fn ptype(self) -> PieceType {
+        bits_to_ptype(self.0 >> PTYPE_SHIFT)
+    }
+
+    #[inline(always)]
+    pub const fn pcolour(self) -> PieceColour {
+        if (self.0 >> PCOLOUR_SHIFT) & 1 == 0 {
+            PieceColour::White
+        } else {
+            PieceColour::Black
+        }
+    }
+
+    #[inline(always)]
+    pub const fn piece(self) -> Piece {
+        Piece {
+            pcolour: self.pcolour(),
+            ptype: self.ptype(),
+        }
+    }
+
+    #[inline(always)]
+    const fn tag(self) -> u32 {
+        (self.0 >> TAG_SHIFT) & 0x7
+    }
+
+    #[inline(always)]
+    const fn payload(self) -> u32 {
+        self.0 >> PAYLOAD_SHIFT
+    }
+
+    // captured piece type for normal captures only, same as matching on MoveType::Capture
+    #[inline(always)]
+    pub const fn capture_ptype(self) -> Option<PieceType> {
+        if self.tag() == TAG_CAPTURE {
+            Some(bits_to_ptype(self.payload()))
+        } else {
+            None
+        }
+    }
+
+    // promotion piece type, if the move is a promotion
+    #[inline(always)]
+    pub const fn promotion_ptype(self) -> Option<PieceType> {
+        if self.tag() == TAG_PROMOTION {
+            Some(bits_to_ptype(self.payload()))
+        } else {
+            None
+        }
+    }
+
+    // index of the pawn captured en passant, if the move is an en passant capture
+    #[inline(always)]
+    pub const fn en_passant_capture(self) -> Option<usize> {
+        if self.tag() == TAG_EN_PASSANT {
+            Some((self.payload() & SQUARE_MASK) as usize)
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub const fn is_castle(self) -> bool {
+        self.tag() == TAG_CASTLE
+    }
+
+    pub const fn move_type(self) -> MoveType {
+        let payload = self.payload();
+        match self.tag() {
+            TAG_NORMAL => MoveType::Normal,
+            TAG_PAWN_PUSH => MoveType::PawnPush,
+            TAG_DOUBLE_PAWN_PUSH => MoveType::DoublePawnPush,
+            TAG_CAPTURE => MoveType::Capture(bits_to_ptype(payload)),
+            TAG_EN_PASSANT => MoveType::EnPassant((payload & SQUARE_MASK) as usize),
+            TAG_PROMOTION => MoveType::Promotion(
+                bits_to_ptype(payload),
+                match (payload >> 3) & PTYPE_MASK {
+                    0 => None,
+                    capture => Some(bits_to_ptype(capture - 1)),
+                },
+            ),
+            TAG_CASTLE => MoveType::Castle(CastleMove {
+                rook_from: (payload & SQUARE_MASK) as usize,
+                rook_to: ((payload >> 6) & SQUARE_MASK) as usize,
+                side: if (payload >> 12) & 1 == 0 {
+                    CastleSide::Short
+                } else {
+                    CastleSide::Long
+                },
+            }),
+            _ => MoveType::None,
+        }
+    }
+
+    #[inline(always)]
+    pub const fn short_move(self) -> ShortMove {
+        ShortMove {
+            from: self.from() as u8,
+            to: self.to() as u8,
+            promotion_ptype: self.promotion_ptype(),
+        }
+    }
+}
+
+impl From<PackedMove> for Move {
+    fn from(mv: PackedMove) -> Self {
+        mv.unpack()
+    }
+}
+
+// same semantics as PartialEq<ShortMove> for Move
+impl PartialEq<ShortMove> for PackedMove {
+    fn eq(&self, other: &ShortMove) -> bool {
+        let result = self.from() == other.from as usize && self.to() == other.to as usize;
+        if let (Some(other_ptype), Some(self_ptype)) =
+            (other.promotion_ptype, self.promotion_ptype())
+        {
+            return result && self_ptype == other_ptype;
+        }
+        result
+    }
+}
+
+impl fmt::Display for PackedMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.unpack())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+    use crate::position::Position;
+
+    #[test]
+    fn test_packed_move_size() {
+        assert_eq!(std::mem::size_of::<PackedMove>(), 4);
+    }
+
+    #[test]
+    fn test_packed_move_round_trip() {
+        // positions with every move type, including chess960 castling and capturing promotions
+        let fens = [
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P1P1P/R2Q1RK1 b kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "nbbqrnkr/pppppppp/8/8/8/5N2/PPPPPPPP/NBBQR1KR w KQkq - 0 1",
+            "qnrbbk1r/pppppppp/8/8/8/8/PPPPPPPP/QNRBBK1R w KQkq - 0 1",
+        ];
+        let mut count = 0;
+        for fen in fens {
+            let pos = Position::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            for packed in pos.get_pseudo_legal_moves() {
+                let mv = packed.unpack();
+                assert_eq!(PackedMove::pack(&mv), *packed);
+                assert_eq!(Move::from(*packed), mv);
+                assert_eq!(packed.short_move(), mv.short_move());
+                count += 1;
+            }
+        }
+        assert!(count > 100);
+
+        let castle = Move {
+            piece: Piece {
+                pcolour: PieceColour::Black,
+                ptype: PieceType::King,
+            },
+            from: 4,
+            to: 2,
+            move_type: MoveType::Castle(CastleMove {
+                rook_from: 0,
+                rook_to: 3,
+                side: CastleSide::Long,
+            }),
+        };
+        assert_eq!(PackedMove::pack(&castle).unpack(), castle);
+        let promotion = Move {
+            piece: Piece {
+                pcolour: PieceColour::White,
+                ptype: PieceType::Pawn,
+            },
+            from: 8,
+            to: 1,
+            move_type: MoveType::Promotion(PieceType::Knight, Some(PieceType::Queen)),
+        };
+        assert_eq!(PackedMove::pack(&promotion).unpack(), promotion);
+    }
+}
@@ -1,6 +1,18 @@
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
-use crate::{board, engine, movegen::*, position::Position, transposition, BoardState};
+use crate::errors::FenParseError;
+use crate::fen::FEN;
+use crate::zobrist::{self, PositionHash};
+use crate::{
+    board, engine, log_and_return_error,
+    movegen::*,
+    position::{AttackMap, Position},
+    transposition, BoardState,
+};
+
+// size of the node count cache used by perft_hashed
+const PERFT_HASH_SIZE_MB: usize = 32;
 
 #[derive(Debug, Default)]
 pub struct PerftNodes {
@@ -51,7 +63,7 @@ pub fn pos_perft(pos: &Position, depth: u8) -> PerftNodes {
 
     let start = Instant::now();
 
-    get_all_legal_positions(pos, depth, &mut nodes);
+    get_all_legal_positions(&mut pos.clone(), depth, &mut nodes);
 
     let duration = start.elapsed();
 
@@ -70,22 +82,235 @@ pub fn pos_perft(pos: &Position, depth: u8) -> PerftNodes {
     nodes
 }
 
+// total perft node count (leaf nodes at this depth) from the given state
+pub fn perft_nodes(bs: &BoardState, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    count_nodes(&mut bs.position().clone(), depth)
+}
+
+// depth must be at least 1. moves at the last ply are only counted, not made or unpacked (bulk counting), and the
+// recursion makes and unmakes moves on one Position so no BoardState or repetition bookkeeping is built along perft lines
+fn count_nodes(pos: &mut Position, depth: u8) -> u64 {
+    if depth == 1 {
+        return pos.count_legal_moves() as u64;
+    }
+    // each child's attack map is reused by the next child once its move is unmade
+    let mut attack_map = AttackMap::default();
+    let mut nodes = 0;
+    for mv in pos.get_legal_moves() {
+        let undo = pos.make_move_reusing(&mv, attack_map);
+        nodes += count_nodes(pos, depth - 1);
+        attack_map = pos.unmake_move_reusing(undo);
+    }
+    nodes
+}
+
+// one line of a perft EPD file, a FEN followed by the expected node counts for each depth e.g. "<FEN> ;D1 20 ;D2 400"
+#[derive(Debug, Clone)]
+pub struct PerftEpdEntry {
+    pub fen: FEN,
+    pub expected: Vec<(u8, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerftMismatch {
+    pub depth: u8,
+    pub expected: u64,
+    pub nodes: u64,
+}
+
+impl PerftEpdEntry {
+    // run perft at every depth with at most max_nodes expected nodes, returning the depths that didn't match
+    pub fn verify(&self, max_nodes: u64) -> Vec<PerftMismatch> {
+        let bs =
+            BoardState::try_from(self.fen).expect("position is validated when the entry is parsed");
+        self.expected
+            .iter()
+            .filter(|(_, expected)| *expected <= max_nodes)
+            .map(|&(depth, expected)| PerftMismatch {
+                depth,
+                expected,
+                nodes: perft_nodes(&bs, depth),
+            })
+            .filter(|result| result.nodes != result.expected)
+            .collect()
+    }
+}
+
+impl FromStr for PerftEpdEntry {
+    type Err = FenParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(';');
+        // split always yields at least one field
+        let fen = fields.next().unwrap().trim().parse::<FEN>()?;
+        if let Err(e) = Position::try_from(fen) {
+            log_and_return_error!(FenParseError::InvalidEpd(e.to_string()))
+        }
+        let mut expected = Vec::new();
+        for field in fields {
+            let depth_nodes = field
+                .trim()
+                .strip_prefix('D')
+                .and_then(|f| f.split_once(' '))
+                .and_then(|(depth, nodes)| Some((depth.parse().ok()?, nodes.trim().parse().ok()?)));
+            match depth_nodes {
+                Some(depth_nodes) => expected.push(depth_nodes),
+                None => log_and_return_error!(FenParseError::InvalidEpd(format!(
+                    "Invalid perft depth field: {}. Expected 'D<depth> <nodes>'",
+                    field.trim()
+                ))),
+            }
+        }
+        Ok(Self { fen, expected })
+    }
+}
+
+// parse every entry in a perft EPD file, blank lines and lines starting with '#' are skipped
+pub fn parse_perft_epd(epd: &str) -> Result<Vec<PerftEpdEntry>, FenParseError> {
+    epd.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PerftEpdEntry::from_str)
+        .collect()
+}
+
+// cached node count for a position at a remaining depth. the full position hash is kept as a verification key, as
+// only part of it is used to index the table
+#[derive(Debug, Clone, Copy, Default)]
+struct PerftHashEntry {
+    key: PositionHash,
+    depth: u8,
+    nodes: u64,
+}
+
+// (position hash, remaining depth) -> node count, kept separate from the engine's transposition table which stores evals
+struct PerftHashTable {
+    entries: Vec<PerftHashEntry>,
+}
+
+impl PerftHashTable {
+    fn new(len: usize) -> Self {
+        Self {
+            entries: vec![PerftHashEntry::default(); len.max(1)],
+        }
+    }
+
+    fn with_capacity_mb(mb: usize) -> Self {
+        Self::new(mb * 1024 * 1024 / std::mem::size_of::<PerftHashEntry>())
+    }
+
+    // depth is mixed into the index so the same position at different depths doesn't always share a slot
+    #[inline(always)]
+    fn idx(&self, key: PositionHash, depth: u8) -> usize {
+        ((key ^ (depth as u64).wrapping_mul(0x9e3779b97f4a7c15)) % self.entries.len() as u64)
+            as usize
+    }
+
+    #[inline(always)]
+    fn get(&self, key: PositionHash, depth: u8) -> Option<u64> {
+        let entry = &self.entries[self.idx(key, depth)];
+        // depth is never 0 for a stored entry, so empty entries never match
+        if entry.key == key && entry.depth == depth {
+            Some(entry.nodes)
+        } else {
+            None
+        }
+    }
+
+    // always replace, the most recent subtrees are the most likely to be transposed into again
+    #[inline(always)]
+    fn insert(&mut self, key: PositionHash, depth: u8, nodes: u64) {
+        let idx = self.idx(key, depth);
+        self.entries[idx] = PerftHashEntry { key, depth, nodes };
+    }
+}
+
+// total perft node count like perft_nodes, but subtree counts are cached by position hash and depth so transpositions
+// are only counted once
+pub fn perft_hashed(bs: &BoardState, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut table = PerftHashTable::with_capacity_mb(PERFT_HASH_SIZE_MB);
+    perft_hashed_nodes(bs.position(), bs.position_hash, depth, &mut table)
+}
+
+// depth must be at least 1. child positions are hashed and looked up before they are created, so a cache hit skips
+// generating the child position entirely
+fn perft_hashed_nodes(
+    pos: &Position,
+    hash: PositionHash,
+    depth: u8,
+    table: &mut PerftHashTable,
+) -> u64 {
+    if depth == 1 {
+        return pos.count_legal_moves() as u64;
+    }
+    let mut nodes = 0;
+    for mv in &pos.get_legal_moves() {
+        let new_hash =
+            zobrist::pos_next_hash(&pos.movegen_flags, &pos.next_movegen_flags(mv), hash, mv);
+        nodes += match table.get(new_hash, depth - 1) {
+            Some(n) => n,
+            None => {
+                let n = perft_hashed_nodes(&pos.new_position(mv), new_hash, depth - 1, table);
+                table.insert(new_hash, depth - 1, n);
+                n
+            }
+        };
+    }
+    nodes
+}
+
+// perft node counts split by root move, in legal move order. the counts sum to the total perft node count at this depth
+pub fn perft_divide(bs: &BoardState, depth: u8) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let pos = bs.position();
+    pos.get_legal_moves()
+        .into_iter()
+        .map(|mv| {
+            if depth == 1 {
+                return (mv, 1);
+            }
+            (mv, count_nodes(&mut pos.new_position(&mv), depth - 1))
+        })
+        .collect()
+}
+
+// render perft_divide results the same way as stockfish's "go perft", one "e2e4: 20" line per root move then the total
+pub fn perft_divide_to_string(divide: &[(Move, u64)]) -> String {
+    let mut output = String::new();
+    for (mv, nodes) in divide {
+        output.push_str(&format!("{}: {}\n", mv, nodes));
+    }
+    output.push_str(&format!(
+        "\nNodes searched: {}",
+        divide.iter().map(|(_, nodes)| nodes).sum::<u64>()
+    ));
+    output
+}
+
 pub fn engine_perft(bs: &BoardState, depth: u8, tt: &mut transposition::TranspositionTable) {
     // let mut tt = transposition::TranspositionTable::new(); // not included in duration
     let start = Instant::now();
-    let (eval, mv) = engine::choose_move(bs, depth, tt);
+    let result = engine::choose_move(bs, depth, tt);
     let duration = start.elapsed();
     println!(
         "Engine perft at depth {} (took {:?} to complete):",
         depth, duration
     );
-    println!(" - Eval: {}", eval);
-    println!(" - Best move: {:?}", mv);
+    println!(" - Eval: {}", result.eval);
+    println!(" - Best move: {:?}", result.best_move);
     println!();
 }
 
 #[inline]
-fn get_all_legal_positions(pos: &Position, depth: u8, nodes: &mut PerftNodes) {
+pub(crate) fn get_all_legal_positions(pos: &mut Position, depth: u8, nodes: &mut PerftNodes) {
     let moves = pos.get_legal_moves();
     if depth == 0 || moves.is_empty() {
         return;
@@ -113,9 +338,9 @@ fn get_all_legal_positions(pos: &Position, depth: u8, nodes: &mut PerftNodes) {
             }
             nodes.nodes += 1;
         } else {
-            let p = pos.new_position(mv);
-            //let unmake_move = pos.make_move(mv);
-            get_all_legal_positions(&p, depth - 1, nodes);
+            let undo = pos.make_move_in_place(&mv);
+            get_all_legal_positions(pos, depth - 1, nodes);
+            pos.unmake_move(undo);
         }
     }
 }
@@ -123,7 +348,6 @@ fn get_all_legal_positions(pos: &Position, depth: u8, nodes: &mut PerftNodes) {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::fen::FEN;
 
     #[test]
     fn test_perft() {
@@ -134,32 +358,38 @@ mod test {
         let pos2 = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos3 = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos4 = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos4mirrored = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos5 = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos6 = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10"
             .parse::<FEN>()
             .unwrap()
-            .into();
+            .try_into()
+            .unwrap();
 
         let pos1_nodes = pos_perft(&pos1, 5);
         assert_eq!(pos1_nodes.nodes, 4865609);
@@ -202,4 +432,126 @@ mod test {
         let pos6_nodes = pos_perft(&pos6, 4);
         assert_eq!(pos6_nodes.nodes, 3894594);
     }
+
+    #[test]
+    fn test_perft_divide() {
+        // per move counts must sum to the known perft totals
+        let bs = BoardState::new_starting();
+        let divide = perft_divide(&bs, 4);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), 197281);
+        assert_eq!(perft_divide(&bs, 1).len(), 20);
+        assert!(perft_divide(&bs, 0).is_empty());
+
+        // kiwipete
+        let bs = BoardState::try_from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let divide = perft_divide(&bs, 3);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), 97862);
+
+        let output = perft_divide_to_string(&divide);
+        assert!(output.contains("e1g1: "));
+        assert!(output.ends_with("\n\nNodes searched: 97862"));
+        assert_eq!(output.lines().count(), 50);
+    }
+
+    #[test]
+    fn test_coordinate_notation() {
+        let bs = BoardState::try_from(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P1P1P/R2Q1RK1 b kq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let notations = perft_divide(&bs, 1)
+            .iter()
+            .map(|(mv, _)| mv.to_string())
+            .collect::<Vec<String>>();
+        for expected in ["e8c8", "b2a1q", "b2a1n", "b2b1r", "c7c5"] {
+            assert!(notations.contains(&expected.to_string()), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn test_perft_epd_entry() {
+        let entry =
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039"
+                .parse::<PerftEpdEntry>()
+                .unwrap();
+        assert_eq!(entry.expected, vec![(1, 48), (2, 2039)]);
+        assert!(entry.verify(u64::MAX).is_empty());
+
+        let entry = "8/8/8/8/8/8/8/k6K w - - 0 1;D1 4;D2 9"
+            .parse::<PerftEpdEntry>()
+            .unwrap();
+        assert_eq!(
+            entry.verify(u64::MAX),
+            vec![PerftMismatch {
+                depth: 1,
+                expected: 4,
+                nodes: 3
+            }]
+        );
+        // depths expecting more than max_nodes are skipped
+        assert!(entry.verify(3).is_empty());
+
+        assert!(
+            parse_perft_epd("# comment\n\n8/8/8/8/8/8/8/k6K w - - 0 1 ;D1 3\n")
+                .is_ok_and(|entries| entries.len() == 1)
+        );
+        for invalid in [
+            "8/8/8/8/8/8/8/k6K w - - 0 1 ;D1",
+            "8/8/8/8/8/8/8/k6K w - - 0 1 ;1 3",
+            "8/8/8/8/8/8/8/k6K w - - 0 1 ;Dx 3",
+            "8/8/8/8/8/8/8/k6K w - - 0 1 ;D1 -3",
+            "8/8/8/8/8/8/8/k6K x - - 0 1 ;D1 3",
+        ] {
+            assert!(invalid.parse::<PerftEpdEntry>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_perft_hashed() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ];
+        for fen in fens {
+            let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            for depth in 0..=3 {
+                assert_eq!(perft_hashed(&bs, depth), perft_nodes(&bs, depth), "{}", fen);
+            }
+        }
+
+        assert_eq!(perft_hashed(&BoardState::new_starting(), 5), 4865609);
+        let bs = BoardState::try_from(fens[1].parse::<FEN>().unwrap()).unwrap();
+        assert_eq!(perft_hashed(&bs, 6), 11030083);
+        let bs = BoardState::try_from(fens[3].parse::<FEN>().unwrap()).unwrap();
+        assert_eq!(perft_hashed(&bs, 5), 8146062);
+    }
+
+    #[test]
+    fn test_perft_hashed_collisions() {
+        // a tiny table forces constant index collisions, the verification key must keep the counts exact
+        let bs = BoardState::try_from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        for len in [1, 7, 64] {
+            let mut table = PerftHashTable::new(len);
+            assert_eq!(
+                perft_hashed_nodes(bs.position(), bs.position_hash, 3, &mut table),
+                97862
+            );
+        }
+    }
 }
@@ -3,57 +3,399 @@ pub mod notation;
 pub mod tag;
 mod token;
 
+use std::collections::VecDeque;
 use std::fmt;
+use std::io::BufRead;
 use std::str::FromStr;
+use std::time::Duration;
 
-use chrono::prelude::*;
-
+use crate::engine::{checkmate_eval, get_checkmate_ply, is_eval_checkmate};
 use crate::errors::PGNParseError;
 use crate::fen::FEN;
-use crate::movegen::PieceColour;
+use crate::log_and_return_error;
 use crate::{board, GameOverState};
 use notation::*;
 use tag::*;
 use token::*;
 
-enum PGNResult {
-    WhiteWin,
-    BlackWin,
-    Draw,
-    Undecided,
+// result tag value of a game that hasn't finished
+const UNDECIDED_RESULT: &str = "*";
+
+// something in a PGN that contradicts the game its moves play out, it is still imported. See
+// Board::try_from_pgn_with_warnings
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ImportWarning {
+    // the moves end the game, but the Result tag has a different result e.g. "0-1" after white checkmates
+    ResultMismatch {
+        tag: String,
+        game: String,
+    },
+    // an "unterminated" Termination tag with a finished Result, or a "normal" one with an unfinished Result
+    TerminationMismatch {
+        termination: Termination,
+        result: String,
+    },
+    // the moves carry on after the game ended at this ply, the ones after it aren't played
+    MovesAfterGameOver {
+        ply: usize,
+    },
 }
 
-impl fmt::Display for PGNResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl fmt::Display for ImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::WhiteWin => write!(f, "1-0"),
-            Self::BlackWin => write!(f, "0-1"),
-            Self::Draw => write!(f, "1/2-1/2"),
-            Self::Undecided => write!(f, "*"),
+            Self::ResultMismatch { tag, game } => {
+                write!(f, "Result tag is {} but the moves end in {}", tag, game)
+            }
+            Self::TerminationMismatch {
+                termination,
+                result,
+            } => write!(
+                f,
+                "Termination tag is {} but the Result tag is {}",
+                termination, result
+            ),
+            Self::MovesAfterGameOver { ply } => {
+                write!(f, "Moves from ply {} continue after the game ended", ply)
+            }
+        }
+    }
+}
+
+// a move of the movetext, with the comment that follows it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MoveRecord {
+    pub notation: Notation,
+    // numeric annotation glyphs, e.g. 1 for a good move, either $1 or the '!' suffix in the movetext
+    pub nags: Vec<u8>,
+    // engine eval after the move from white's perspective, and the mover's time left on the clock. Written in the
+    // comment as lichess does e.g. {[%eval 0.35] [%clk 0:02:31]}
+    pub eval: Option<i32>,
+    pub clock: Option<Duration>,
+    pub comment: Option<String>,
+    // alternatives to this move, each starting from the position before it
+    pub variations: Vec<Variation>,
+}
+
+impl MoveRecord {
+    pub fn new(notation: Notation) -> Self {
+        Self {
+            notation,
+            nags: Vec::new(),
+            eval: None,
+            clock: None,
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+// a recursive annotation variation, a line of moves in place of a move of the line it branches off
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Variation {
+    // comment before the first move of the variation
+    pub comment: Option<String>,
+    pub moves: Vec<MoveRecord>,
+}
+
+impl Variation {
+    // variations branching off this one, where ply is the ply of the variation's first move. See PGN::variations
+    pub fn variations(&self, ply: usize) -> Vec<(usize, &Variation)> {
+        branches(&self.moves, ply)
+    }
+}
+
+// variations branching off the moves of a line starting at start_ply, with the ply of the move they replace
+fn branches(moves: &[MoveRecord], start_ply: usize) -> Vec<(usize, &Variation)> {
+    moves
+        .iter()
+        .enumerate()
+        .flat_map(|(i, record)| {
+            record
+                .variations
+                .iter()
+                .map(move |variation| (start_ply + i, variation))
+        })
+        .collect()
+}
+
+// eval in pawns, or #N for a mate in N moves with a negative N when black is mating
+fn eval_to_pgn_string(eval: i32) -> String {
+    if is_eval_checkmate(eval) {
+        let moves = (get_checkmate_ply(eval) as u32).div_ceil(2);
+        format!("#{}{}", if eval < 0 { "-" } else { "" }, moves)
+    } else {
+        format!("{:.2}", eval as f64 / 100.0)
+    }
+}
+
+// a mate in N moves is taken to be 2N - 1 plys away, as it is when the mating side is to move
+fn eval_from_pgn_str(s: &str) -> Option<i32> {
+    match s.strip_prefix('#') {
+        Some(mate) => {
+            let (sign, moves) = match mate.strip_prefix('-') {
+                Some(moves) => (-1, moves),
+                None => (1, mate),
+            };
+            let moves: u8 = moves.parse().ok()?;
+            Some(sign * checkmate_eval(moves.checked_mul(2)?.saturating_sub(1)))
+        }
+        None => {
+            let pawns: f64 = s.parse().ok()?;
+            pawns
+                .is_finite()
+                .then(|| (pawns * 100.0).round() as i32)
+                .filter(|eval| !is_eval_checkmate(*eval))
+        }
+    }
+}
+
+// H:MM:SS, with tenths of a second if there are any
+fn clock_to_pgn_string(clock: Duration) -> String {
+    let seconds = clock.as_secs();
+    let mut clock_str = format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let tenths = clock.subsec_millis() / 100;
+    if tenths > 0 {
+        clock_str.push_str(&format!(".{}", tenths));
+    }
+    clock_str
+}
+
+fn clock_from_pgn_str(s: &str) -> Option<Duration> {
+    let [hours, minutes, seconds] = s.split(':').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: f64 = seconds.parse().ok()?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    let whole = hours.checked_mul(3600)?.checked_add(minutes * 60)?;
+    Some(Duration::from_secs(whole) + Duration::from_secs_f64(seconds))
+}
+
+// takes the [%eval ...] and [%clk ...] commands out of a move's comment into its record, returning the rest of the
+// comment. Other commands, and ones with values that can't be read, are left in the comment
+fn take_comment_commands(comment: &str, record: &mut MoveRecord) -> String {
+    let mut rest = String::new();
+    let mut remaining = comment;
+    while let Some(start) = remaining.find("[%") {
+        let Some(len) = remaining[start..].find(']') else {
+            break;
+        };
+        let command = &remaining[start + 2..start + len];
+        let taken = match command.split_once(char::is_whitespace) {
+            Some(("eval", value)) => eval_from_pgn_str(value.trim())
+                .map(|eval| record.eval = Some(eval))
+                .is_some(),
+            Some(("clk", value)) => clock_from_pgn_str(value.trim())
+                .map(|clock| record.clock = Some(clock))
+                .is_some(),
+            _ => false,
+        };
+        rest.push_str(&remaining[..start]);
+        if !taken {
+            rest.push_str(&remaining[start..=start + len]);
+        }
+        remaining = &remaining[start + len + 1..];
+    }
+    rest.push_str(remaining);
+    rest
+}
+
+// movetext of a line starting at start_ply, as the words to write with spaces between them
+fn line_movetext(
+    comment: &Option<String>,
+    moves: &[MoveRecord],
+    start_ply: usize,
+    numbers_after_comments: bool,
+    words: &mut Vec<String>,
+) {
+    if let Some(comment) = comment {
+        words.push(format!("{{{}}}", comment));
+    }
+    // black's moves only need a move number at the start of a line, and with numbers_after_comments when a comment or
+    // variation comes between them and white's
+    let mut needs_number = true;
+    for (i, record) in moves.iter().enumerate() {
+        let ply = start_ply + i;
+        if ply.is_multiple_of(2) {
+            words.push(format!("{}.{}", ply / 2 + 1, record.notation));
+        } else if needs_number {
+            words.push(format!("{}...{}", ply / 2 + 1, record.notation));
+        } else {
+            words.push(record.notation.to_string());
+        }
+        needs_number = false;
+        // suffix annotations are written as their NAG, as in the export format
+        for nag in &record.nags {
+            words.push(format!("${}", nag));
+        }
+        let mut comment = Vec::new();
+        if let Some(eval) = record.eval {
+            comment.push(format!("[%eval {}]", eval_to_pgn_string(eval)));
+        }
+        if let Some(clock) = record.clock {
+            comment.push(format!("[%clk {}]", clock_to_pgn_string(clock)));
+        }
+        comment.extend(record.comment.clone());
+        if !comment.is_empty() {
+            words.push(format!("{{{}}}", comment.join(" ")));
+            needs_number = numbers_after_comments;
+        }
+        for variation in &record.variations {
+            let mut variation_words = Vec::new();
+            line_movetext(
+                &variation.comment,
+                &variation.moves,
+                ply,
+                numbers_after_comments,
+                &mut variation_words,
+            );
+            if let Some(first) = variation_words.first_mut() {
+                first.insert(0, '(');
+                variation_words.last_mut().unwrap().push(')');
+            } else {
+                variation_words.push("()".to_string());
+            }
+            words.append(&mut variation_words);
+            needs_number = numbers_after_comments;
         }
     }
 }
 
+// a mainline move whose '+' or '#' in the source text disagrees with the position it was played in, see
+// PGN::validate_against_board
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnotationMismatch {
+    pub ply: usize,
+    // as written in the PGN
+    pub found: Notation,
+    // as generated from the board
+    pub expected: Notation,
+}
+
 #[derive(Debug, Clone)]
 pub struct PGN {
     tags: Vec<Tag>,
-    moves: Vec<Notation>,
+    // comment before the first move
+    comment: Option<String>,
+    moves: Vec<MoveRecord>,
 }
 
 impl FromStr for PGN {
     type Err = PGNParseError;
 
+    // a single game, see parse_multi and PgnReader for PGN databases with more than one
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut new = Self {
-            tags: Vec::new(),
-            moves: Vec::new(),
-        };
-        let tokens = Tokens::from_pgn_str(s);
-        new.tags = tokens.get_tags()?;
-        new.moves = tokens.get_move_notations()?;
-        // set required tags to defaults if they are missing, using game termination marker as the Result tag if it is missing
-        new.set_required_tags_defaults(tokens.get_game_termination());
-        Ok(new)
+        let mut games = Tokens::from_pgn_str(s).split_games();
+        if games.len() > 1 {
+            log_and_return_error!(PGNParseError::MultipleGames(format!(
+                "Found {} games where one was expected, use PGN::parse_multi to read them all",
+                games.len()
+            )))
+        }
+        Self::from_tokens(&games.pop().unwrap_or_default())
+    }
+}
+
+// reads the games of a PGN database one at a time, so large files don't have to be read into memory first e.g.
+// PgnReader::new(BufReader::new(File::open(path)?)). Errors name the game they were found in, counting from 1
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    // tag line that started the next game, read while looking for the end of the last one
+    next_game_start: String,
+    // games split from the last text read that haven't been returned yet
+    pending: VecDeque<Tokens>,
+    games_read: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next_game_start: String::new(),
+            pending: VecDeque::new(),
+            games_read: 0,
+            finished: false,
+        }
+    }
+
+    // number of games returned so far, including ones that failed to parse
+    pub fn games_read(&self) -> usize {
+        self.games_read
+    }
+
+    // text of the next game, the lines up to the next tag section after some movetext. Tag sections can't start inside
+    // a comment, and games that aren't separated by tags are split up by split_games afterwards
+    fn read_game_text(&mut self) -> Result<Option<String>, PGNParseError> {
+        let mut text = std::mem::take(&mut self.next_game_start);
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => log_and_return_error!(PGNParseError::FileError(e.to_string())),
+            }
+            let trimmed = line.trim_start();
+            if !in_comment && trimmed.starts_with('[') {
+                if in_movetext {
+                    self.next_game_start = line;
+                    break;
+                }
+            } else if !trimmed.is_empty() {
+                in_movetext = true;
+                for c in line.chars() {
+                    match c {
+                        '{' => in_comment = true,
+                        '}' => in_comment = false,
+                        // rest of line comment
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+            }
+            text.push_str(&line);
+        }
+        Ok((!text.trim().is_empty()).then_some(text))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PGN, PGNParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tokens) = self.pending.pop_front() {
+                self.games_read += 1;
+                let game = self.games_read;
+                return Some(
+                    PGN::from_tokens(&tokens).map_err(|e| PGNParseError::InGame(game, Box::new(e))),
+                );
+            }
+            if self.finished {
+                return None;
+            }
+            match self.read_game_text() {
+                Ok(Some(text)) => self
+                    .pending
+                    .extend(Tokens::from_pgn_str(&text).split_games()),
+                Ok(None) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
     }
 }
 
@@ -61,52 +403,29 @@ impl From<&board::Board> for PGN {
     fn from(board: &board::Board) -> Self {
         let mut new = Self {
             tags: Vec::new(),
+            comment: None,
             moves: Vec::new(),
         };
 
-        new.tags.push(Tag::Event("Chess Oxide".to_string()));
-        new.tags.push(Tag::Site("chess-oxide".to_string()));
-
-        // set date tag
-        let date_time = Local::now();
-        let date = date_time.format("%Y.%m.%d").to_string();
-        new.tags.push(Tag::Date(date));
-
-        new.tags.push(Tag::Round("?".to_string()));
-
-        match &board.get_white_player().name {
-            Some(name) => new.tags.push(Tag::White(name.to_string())),
-            None => new.tags.push(Tag::White("?".to_string())),
-        }
-        match &board.get_black_player().name {
-            Some(name) => new.tags.push(Tag::Black(name.to_string())),
-            None => new.tags.push(Tag::Black("?".to_string())),
-        }
+        // metadata that isn't set on the board falls back to defaults
+        let metadata = board.metadata();
+        let or_default = |value: &Option<String>, default: &str| {
+            value.clone().unwrap_or_else(|| default.to_string())
+        };
+        new.tags
+            .push(Tag::Event(or_default(&metadata.event, "Chess Oxide")));
+        new.tags
+            .push(Tag::Site(or_default(&metadata.site, "chess-oxide")));
+        new.tags
+            .push(Tag::Date(metadata.date.unwrap_or_else(PgnDate::today)));
+        new.tags.push(Tag::Round(or_default(&metadata.round, "?")));
+        new.tags
+            .push(Tag::White(or_default(&metadata.white.name, "?")));
+        new.tags
+            .push(Tag::Black(or_default(&metadata.black.name, "?")));
 
-        // set result tag based on Board GameOverState
         new.tags
-            .push(Tag::Result(board.get_game_over_state().map_or_else(
-                || PGNResult::Undecided.to_string(),
-                |gos| match gos {
-                    GameOverState::WhiteResign => PGNResult::BlackWin.to_string(),
-                    GameOverState::BlackResign => PGNResult::WhiteWin.to_string(),
-                    GameOverState::AgreedDraw => PGNResult::Draw.to_string(),
-                    GameOverState::Forced(gs) => {
-                        if gs.is_win() {
-                            // the side to move is the loser, the last move was the winning move
-                            if board.get_side_to_move() == PieceColour::White {
-                                PGNResult::BlackWin.to_string()
-                            } else {
-                                PGNResult::WhiteWin.to_string()
-                            }
-                        } else if gs.is_draw() {
-                            PGNResult::Draw.to_string()
-                        } else {
-                            PGNResult::Undecided.to_string()
-                        }
-                    }
-                },
-            )));
+            .push(Tag::Result(board.result_string().to_string()));
 
         match board.variant() {
             board::Variant::Standard => {
@@ -122,49 +441,129 @@ impl From<&board::Board> for PGN {
             }
         }
 
-        match board.get_white_player().elo {
-            Some(elo) => new.tags.push(Tag::WhiteElo(elo.to_string())),
-            None => new.tags.push(Tag::WhiteElo("?".to_string())),
+        new.tags.push(Tag::WhiteElo(board.get_white_player().elo));
+        new.tags.push(Tag::BlackElo(board.get_black_player().elo));
+
+        new.tags.push(Tag::Termination(board.termination()));
+        new.tags
+            .push(Tag::Annotator(format!("chess-oxide {}", crate::version())));
+        new.tags.extend(metadata.extra_tags.iter().cloned());
+        new.moves = board
+            .move_history_notation()
+            .into_iter()
+            .map(MoveRecord::new)
+            .collect();
+        new.tags.push(Tag::PlyCount(new.moves.len() as u32));
+
+        new
+    }
+}
+
+// line breaks between the lines of an exported PGN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineStyle {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl NewlineStyle {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
         }
-        match board.get_black_player().elo {
-            Some(elo) => new.tags.push(Tag::BlackElo(elo.to_string())),
-            None => new.tags.push(Tag::BlackElo("?".to_string())),
+    }
+}
+
+// which tags an exported PGN has. Games without the seven tag roster are still read back, with defaults for the
+// missing tags
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TagFilter {
+    #[default]
+    All,
+    // Event, Site, Date, Round, White, Black and Result
+    SevenTagRoster,
+    // tags with these names, an empty list for none
+    Only(Vec<String>),
+}
+
+impl TagFilter {
+    fn includes(&self, tag: &Tag) -> bool {
+        match self {
+            Self::All => true,
+            Self::SevenTagRoster => matches!(
+                tag,
+                Tag::Event(_)
+                    | Tag::Site(_)
+                    | Tag::Date(_)
+                    | Tag::Round(_)
+                    | Tag::White(_)
+                    | Tag::Black(_)
+                    | Tag::Result(_)
+            ),
+            Self::Only(names) => names.iter().any(|name| name == tag.name()),
         }
+    }
+}
 
-        new.tags.push(Tag::Termination("UNIMPLEMENTED".to_string()));
-        new.tags.push(Tag::Annotator("chess-oxide".to_string()));
-        new.moves = board.move_history_notation();
+// how PGN::to_string_with writes a game, the defaults are the export format that Display writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnWriteOptions {
+    // most bytes on a line of movetext, only a single word longer than this goes over
+    pub line_width: usize,
+    // black's moves after a comment or variation are numbered e.g. "3...Nf6", the export format does this
+    pub include_move_numbers_after_comments: bool,
+    pub newline: NewlineStyle,
+    pub include_tags: TagFilter,
+}
 
-        new
+impl Default for PgnWriteOptions {
+    fn default() -> Self {
+        Self {
+            line_width: 79,
+            include_move_numbers_after_comments: true,
+            newline: NewlineStyle::Lf,
+            include_tags: TagFilter::All,
+        }
     }
 }
 
 impl fmt::Display for PGN {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut sorted_tags = self.tags.to_vec();
+        write!(f, "{}", self.to_string_with(&PgnWriteOptions::default()))
+    }
+}
+
+impl PGN {
+    pub fn tags(&self) -> &Vec<Tag> {
+        &self.tags
+    }
+
+    // the game written with the given options, Display uses the defaults
+    pub fn to_string_with(&self, opts: &PgnWriteOptions) -> String {
+        let mut sorted_tags: Vec<&Tag> = self
+            .tags
+            .iter()
+            .filter(|tag| opts.include_tags.includes(tag))
+            .collect();
         sorted_tags.sort();
 
         let mut pgn = String::new();
         for tag in &sorted_tags {
             pgn.push_str(&format!("{}\n", tag));
         }
-        pgn.push('\n');
-        // wrap lines at 80 characters
-        let mut chars_since_newline = 0;
-        for (i, mv) in self.moves.iter().enumerate() {
-            if chars_since_newline >= 80 {
-                pgn.push('\n');
-                chars_since_newline = 0;
-            }
-            if i % 2 == 0 {
-                let str = format!("{}.", i / 2 + 1);
-                pgn.push_str(&str);
-                chars_since_newline += str.len();
-            }
-            let mv_str = mv.to_string();
-            pgn.push_str(&format!("{} ", mv_str));
-            chars_since_newline += mv_str.len() + 1;
+        if !sorted_tags.is_empty() {
+            pgn.push('\n');
         }
+        let mut words = Vec::new();
+        line_movetext(
+            &self.comment,
+            &self.moves,
+            0,
+            opts.include_move_numbers_after_comments,
+            &mut words,
+        );
         // unwrap is safe, the Result tag is required and set in all constructors
         let Tag::Result(termination_indicator) = self
             .tags
@@ -174,21 +573,184 @@ impl fmt::Display for PGN {
         else {
             unreachable!("Result tag is required and set in all constructors, it will be found");
         };
-        pgn.push_str(&format!("{}\n", termination_indicator));
+        words.push(termination_indicator.clone());
 
-        write!(f, "{}", pgn)
+        // lines are broken between words, and inside comments between the words of the comment. Lengths are counted in
+        // bytes up to the next line break, as comments can have line breaks of their own
+        let mut line_len = 0;
+        for piece in words.iter().flat_map(|word| word.split(' ')) {
+            let first_line_len = piece.find('\n').unwrap_or(piece.len());
+            if line_len > 0 && line_len + 1 + first_line_len > opts.line_width {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            pgn.push_str(piece);
+            line_len = match piece.rfind('\n') {
+                Some(newline) => piece.len() - newline - 1,
+                None => line_len + piece.len(),
+            };
+        }
+        pgn.push('\n');
+
+        match opts.newline {
+            NewlineStyle::Lf => pgn,
+            newline => pgn.replace('\n', newline.as_str()),
+        }
     }
-}
 
-impl PGN {
-    pub fn tags(&self) -> &Vec<Tag> {
-        &self.tags
+    // notation of each move in the mainline
+    pub fn moves(&self) -> impl ExactSizeIterator<Item = &Notation> + '_ {
+        self.moves.iter().map(|record| &record.notation)
     }
 
-    pub fn moves(&self) -> &Vec<Notation> {
+    // moves of the mainline with their annotations and variations, which are ignored when playing the game on a Board
+    pub fn move_records(&self) -> &[MoveRecord] {
         &self.moves
     }
 
+    // for annotating moves and attaching variations to them
+    pub fn move_records_mut(&mut self) -> &mut [MoveRecord] {
+        &mut self.moves
+    }
+
+    // variations branching off the mainline, with the ply of the mainline move they replace, so a variation at ply 0
+    // starts from the initial position. Walk further down the tree with Variation::variations
+    pub fn variations(&self) -> Vec<(usize, &Variation)> {
+        branches(&self.moves, 0)
+    }
+
+    // comment before the first move
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
+
+    // PGN of the game up to the board's current state, so a board checked out at an earlier ply exports the moves up to
+    // it as an unfinished game. The same as PGN::from when the board isn't detatched
+    pub fn from_board_at_current_state(board: &board::Board) -> Self {
+        let mut new = Self::from(board);
+        if let Some(idx) = board.detatched_idx() {
+            new.moves.truncate(idx);
+            for tag in &mut new.tags {
+                match tag {
+                    Tag::Result(result) => *result = UNDECIDED_RESULT.to_string(),
+                    Tag::Termination(termination) => *termination = Termination::Unterminated,
+                    Tag::PlyCount(count) => *count = idx as u32,
+                    _ => {}
+                }
+            }
+        }
+        new
+    }
+
+    // PGN of the board with a comment after each move, comments[0] follows the first move. Moves past the end of comments
+    // have none
+    pub fn from_board_with_comments(board: &board::Board, comments: &[Option<String>]) -> Self {
+        let mut new = Self::from(board);
+        for (record, comment) in new.moves.iter_mut().zip(comments) {
+            record.comment = comment.clone();
+        }
+        new
+    }
+
+    // PGN of the board with an engine eval after each move, from white's perspective like the engine's. evals[0] is the
+    // eval after the first move, moves past the end of evals have none
+    pub fn from_board_with_evals(board: &board::Board, evals: &[i32]) -> Self {
+        let mut new = Self::from(board);
+        for (record, eval) in new.moves.iter_mut().zip(evals) {
+            record.eval = Some(*eval);
+        }
+        new
+    }
+
+    // mainline moves whose check or checkmate annotations disagree with the board the game was played on, usually one
+    // made with Board::try_from(pgn). The board's own notation is always used when exporting it, so this is for
+    // importers that want to warn about the source rather than silently correct it
+    pub fn validate_against_board(&self, board: &board::Board) -> Vec<AnnotationMismatch> {
+        self.moves()
+            .zip(board.move_history_notation())
+            .enumerate()
+            .filter(|(_, (found, expected))| {
+                found.is_check() != expected.is_check()
+                    || found.is_checkmate() != expected.is_checkmate()
+            })
+            .map(|(ply, (found, expected))| AnnotationMismatch {
+                ply,
+                found: found.clone(),
+                expected,
+            })
+            .collect()
+    }
+
+    // contradictions between the Result and Termination tags and how the game on the board ended, usually one made with
+    // Board::try_from(pgn). Moves that carry on after the game ended are found while replaying them, see
+    // Board::try_from_pgn_with_warnings
+    pub fn verify_result(&self, board: &board::Board) -> Vec<ImportWarning> {
+        let mut warnings = Vec::new();
+        let result = self.tags.iter().find_map(|tag| match tag {
+            Tag::Result(result) => Some(result.as_str()),
+            _ => None,
+        });
+        if let (Some(result), Some(GameOverState::Forced(_))) =
+            (result, board.get_game_over_state())
+        {
+            let game = board.result_string().to_string();
+            if result != game {
+                warnings.push(ImportWarning::ResultMismatch {
+                    tag: result.to_string(),
+                    game,
+                });
+            }
+        }
+        let termination = self.tags.iter().find_map(|tag| match tag {
+            Tag::Termination(termination) => Some(termination),
+            _ => None,
+        });
+        if let (Some(termination), Some(result)) = (termination, result) {
+            let unfinished = result == UNDECIDED_RESULT;
+            if matches!(termination, Termination::Unterminated) != unfinished
+                && matches!(termination, Termination::Unterminated | Termination::Normal)
+            {
+                warnings.push(ImportWarning::TerminationMismatch {
+                    termination: termination.clone(),
+                    result: result.to_string(),
+                });
+            }
+        }
+        warnings
+    }
+
+    // every game in a PGN database such as a tournament or lichess export, in order. Errors name the game they were
+    // found in, counting from 1
+    pub fn parse_multi(s: &str) -> Result<Vec<Self>, PGNParseError> {
+        Tokens::from_pgn_str(s)
+            .split_games()
+            .iter()
+            .enumerate()
+            .map(|(i, tokens)| {
+                Self::from_tokens(tokens).map_err(|e| PGNParseError::InGame(i + 1, Box::new(e)))
+            })
+            .collect()
+    }
+
+    fn from_tokens(tokens: &Tokens) -> Result<Self, PGNParseError> {
+        let (comment, moves) = tokens.get_move_records()?;
+        let mut new = Self {
+            tags: tokens.get_tags()?,
+            comment,
+            moves,
+        };
+        // set required tags to defaults if they are missing, using game termination marker as the Result tag if it is missing
+        new.set_required_tags_defaults(tokens.get_game_termination());
+        Ok(new)
+    }
+
     fn set_required_tags_defaults(&mut self, termination: Option<String>) {
         let mut missing_event = true;
         let mut missing_site = true;
@@ -218,9 +780,7 @@ impl PGN {
             self.tags.push(Tag::Site("chess-oxide".to_string()));
         }
         if missing_date {
-            let date_time = Local::now();
-            let date = date_time.format("%Y.%m.%d").to_string();
-            self.tags.push(Tag::Date(date));
+            self.tags.push(Tag::Date(PgnDate::today()));
         }
         if missing_round {
             self.tags.push(Tag::Round("?".to_string()));
@@ -233,7 +793,7 @@ impl PGN {
         }
         if missing_result {
             self.tags.push(Tag::Result(
-                termination.unwrap_or_else(|| PGNResult::Undecided.to_string()),
+                termination.unwrap_or_else(|| UNDECIDED_RESULT.to_string()),
             ));
         }
     }
@@ -242,7 +802,6 @@ impl PGN {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::log_and_return_error;
     use std::fs;
     use std::path::Path;
 
@@ -274,5 +833,793 @@ mod tests {
 
         assert_eq!(pgn.tags.len(), 10);
         assert_eq!(pgn.moves.len(), 115);
+        // exported games count their plies
+        assert!(pgn1.tags().contains(&Tag::PlyCount(115)));
+    }
+
+    const MULTI_GAME: &str = include_str!("../test_data/multi_game.pgn");
+
+    fn assert_multi_games(games: &[PGN]) {
+        assert_eq!(games.len(), 3);
+        let results: Vec<&Tag> = games
+            .iter()
+            .map(|game| {
+                game.tags()
+                    .iter()
+                    .find(|tag| matches!(tag, Tag::Result(_)))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            results,
+            [
+                &Tag::Result("1-0".to_string()),
+                &Tag::Result("0-1".to_string()),
+                &Tag::Result("1/2-1/2".to_string())
+            ]
+        );
+        // each game only has its own tags, comments and variations, which aren't part of the mainline
+        assert_eq!(
+            games
+                .iter()
+                .map(|game| game.moves().len())
+                .collect::<Vec<_>>(),
+            [33, 4, 8]
+        );
+        assert_eq!(
+            games[0].move_records()[5].comment.as_deref(),
+            Some("This is a weak move already.")
+        );
+        assert_eq!(
+            games[1].move_records()[2].comment.as_deref(),
+            Some("A comment over lines that\n[looks like a tag] and mentions 1-0")
+        );
+        let variations = games[0].variations();
+        assert_eq!(variations.len(), 1);
+        assert_eq!(variations[0].0, 7);
+        assert_eq!(variations[0].1.moves.len(), 4);
+        assert!(games[1].variations().is_empty());
+        assert!(games[1]
+            .tags()
+            .contains(&Tag::Event("Fool's mate".to_string())));
+        assert!(!games[1]
+            .tags()
+            .iter()
+            .any(|tag| matches!(tag, Tag::Other(..))));
+        assert!(!games[1]
+            .tags()
+            .iter()
+            .any(|tag| matches!(tag, Tag::WhiteElo(_))));
+
+        // every game is a board of its own
+        let boards: Vec<board::Board> = games
+            .iter()
+            .map(|game| board::Board::try_from(game.clone()).unwrap())
+            .collect();
+        assert_eq!(
+            boards[0].get_game_over_state(),
+            Some(GameOverState::Forced(crate::GameState::Checkmate))
+        );
+        assert_eq!(
+            boards[1].get_game_over_state(),
+            Some(GameOverState::Forced(crate::GameState::Checkmate))
+        );
+        assert_eq!(
+            boards[2].get_game_over_state(),
+            Some(GameOverState::AgreedDraw)
+        );
+        assert_eq!(boards[2].get_white_player().elo, Some(2000));
+    }
+
+    #[test]
+    fn test_pgn_parse_multi() {
+        let games = PGN::parse_multi(MULTI_GAME).unwrap();
+        assert_multi_games(&games);
+        // a single game, and games without tag sections between them
+        assert_eq!(PGN::parse_multi(&games[0].to_string()).unwrap().len(), 1);
+        let untagged = "[Event \"?\"]\n1.e4 e5 1-0 1.d4 d5 2.c4 * 1.c4 0-1\n\n";
+        let moves: Vec<usize> = PGN::parse_multi(untagged)
+            .unwrap()
+            .iter()
+            .map(|game| game.moves().len())
+            .collect();
+        assert_eq!(moves, [2, 3, 1]);
+        assert!(PGN::parse_multi("").unwrap().is_empty());
+
+        // from_str only reads a single game
+        assert!(matches!(
+            MULTI_GAME.parse::<PGN>(),
+            Err(PGNParseError::MultipleGames(_))
+        ));
+        assert_eq!(
+            MULTI_GAME
+                .split("\n\n[")
+                .next()
+                .unwrap()
+                .parse::<PGN>()
+                .unwrap()
+                .moves()
+                .len(),
+            33
+        );
+    }
+
+    #[test]
+    fn test_pgn_reader() {
+        let mut reader = PgnReader::new(MULTI_GAME.as_bytes());
+        let games: Vec<PGN> = reader.by_ref().map(Result::unwrap).collect();
+        assert_multi_games(&games);
+        assert_eq!(reader.games_read(), 3);
+        assert!(reader.next().is_none());
+
+        // CRLF line endings and no blank lines between games
+        let crlf = MULTI_GAME.replace("\n\n[", "\n[").replace('\n', "\r\n");
+        let games: Vec<PGN> = PgnReader::new(crlf.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_multi_games(&games);
+    }
+
+    #[test]
+    fn test_pgn_multi_malformed_game() {
+        let malformed = MULTI_GAME.replace("Qh4#", "Qh9#");
+        match PGN::parse_multi(&malformed) {
+            Err(PGNParseError::InGame(2, e)) => {
+                assert!(matches!(*e, PGNParseError::NotationParseError(_)))
+            }
+            other => panic!("expected an error in game 2, got {:?}", other),
+        }
+
+        // the reader carries on past a game that failed to parse
+        let results: Vec<Result<PGN, PGNParseError>> =
+            PgnReader::new(malformed.as_bytes()).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        let err = results[1].as_ref().unwrap_err();
+        assert!(matches!(err, PGNParseError::InGame(2, _)));
+        assert!(err.to_string().starts_with("Error in game 2:"));
+    }
+
+    #[test]
+    fn test_pgn_comments_round_trip() {
+        let annotated = "[Event \"?\"]\n[Result \"*\"]\n\n{Opening   notes} 1.e4 {King's pawn} e5 2.Nf3 ; to the end of the line\nNc6 {first} {second} 3.Bb5 *\n\n";
+        let pgn: PGN = annotated.parse().unwrap();
+        assert_eq!(pgn.comment(), Some("Opening   notes"));
+        let comments: Vec<Option<&str>> = pgn
+            .move_records()
+            .iter()
+            .map(|record| record.comment.as_deref())
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                Some("King's pawn"),
+                None,
+                Some("to the end of the line"),
+                Some("first second"),
+                None
+            ]
+        );
+
+        // comments are written back in braces, with a move number for black's move after one, wrapping lines as usual
+        let exported = pgn.to_string();
+        assert!(exported.replace('\n', " ").contains("{Opening   notes} 1.e4 {King's pawn} 1...e5 2.Nf3 {to the end of the line} 2...Nc6 {first second} 3.Bb5 *"));
+        let reparsed: PGN = exported.parse().unwrap();
+        assert_eq!(reparsed.comment(), pgn.comment());
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+
+        // the board ignores them
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(board.move_history_notation().len(), 5);
+    }
+
+    #[test]
+    fn test_pgn_from_board_with_comments() {
+        let mut board = board::Board::new();
+        for mv in ["e2e4", "e7e5", "g1f3"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let mut pgn = PGN::from_board_with_comments(&board, &[None, Some("Solid".to_string())]);
+        assert_eq!(
+            pgn.move_records()
+                .iter()
+                .map(|record| record.comment.clone())
+                .collect::<Vec<_>>(),
+            [None, Some("Solid".to_string()), None]
+        );
+        pgn.move_records_mut()[2].comment = Some("Developing".to_string());
+        pgn.set_comment(Some("Casual game".to_string()));
+        let reparsed: PGN = pgn.to_string().parse().unwrap();
+        assert_eq!(reparsed.comment(), Some("Casual game"));
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+        assert_eq!(
+            PGN::from(&board).to_string(),
+            PGN::from_board_with_comments(&board, &[]).to_string()
+        );
+    }
+
+    #[test]
+    fn test_pgn_variations() {
+        let movetext = "1.e4 e5 (1...c5 {Sicilian} 2.Nf3 (2.Nc3 Nc6 (2...d6 3.f4) 3.g3) 2...d6) (1...e6 2.d4) 2.Nf3 Nc6 {main} (2...d6 3.d4) 3.Bb5 *";
+        let pgn: PGN = format!("[Event \"?\"]\n[Result \"*\"]\n\n{}\n\n", movetext)
+            .parse()
+            .unwrap();
+        let notations = |moves: &[MoveRecord]| -> Vec<String> {
+            moves
+                .iter()
+                .map(|record| record.notation.to_string())
+                .collect()
+        };
+        assert_eq!(
+            pgn.moves().map(Notation::to_string).collect::<Vec<_>>(),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5"]
+        );
+
+        // variations are anchored at the ply of the move they replace
+        let variations = pgn.variations();
+        assert_eq!(
+            variations
+                .iter()
+                .map(|(ply, variation)| (*ply, notations(&variation.moves)))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    1,
+                    vec!["c5".to_string(), "Nf3".to_string(), "d6".to_string()]
+                ),
+                (1, vec!["e6".to_string(), "d4".to_string()]),
+                (3, vec!["d6".to_string(), "d4".to_string()]),
+            ]
+        );
+        let sicilian = variations[0].1;
+        assert_eq!(sicilian.moves[0].comment.as_deref(), Some("Sicilian"));
+        let nested = sicilian.variations(1);
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].0, 2);
+        assert_eq!(notations(&nested[0].1.moves), ["Nc3", "Nc6", "g3"]);
+        let nested = nested[0].1.variations(2);
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].0, 3);
+        assert_eq!(notations(&nested[0].1.moves), ["d6", "f4"]);
+        assert!(nested[0].1.variations(3).is_empty());
+
+        // written back as they were read
+        let exported = pgn.to_string();
+        assert!(exported.replace('\n', " ").contains(movetext));
+        let reparsed: PGN = exported.parse().unwrap();
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+        assert_eq!(reparsed.to_string(), exported);
+
+        // only the mainline is played on the board
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(board.move_history_notation().len(), 5);
+    }
+
+    #[test]
+    fn test_pgn_invalid_variations() {
+        for movetext in [
+            "1.e4 (1.d4 d5 *",
+            "1.e4 e5) 2.Nf3 *",
+            "(1.d4) 1.e4 *",
+            "1.e4 (1.d4 1-0) e5 *",
+        ] {
+            assert!(
+                matches!(
+                    format!("[Result \"*\"]\n\n{}", movetext).parse::<PGN>(),
+                    Err(PGNParseError::InvalidVariation(_))
+                ),
+                "{}",
+                movetext
+            );
+        }
+    }
+
+    #[test]
+    fn test_pgn_lenient_san() {
+        let pgn: PGN = "1.e4 d5 2.exd Nf6 3.nf3 Nxd5 4.bb5+ c6 5.0-0 cxb5 6.a4 b4 7.c4 bxc3 e.p. *"
+            .parse()
+            .unwrap();
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(
+            PGN::from(&board)
+                .moves()
+                .map(|notation| notation.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "e4", "d5", "exd5", "Nf6", "Nf3", "Nxd5", "Bb5+", "c6", "O-O", "cxb5", "a4", "b4",
+                "c4", "bxc3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pgn_long_algebraic() {
+        let san = "1.e4 e5 2.Nf3 Nc6 3.Bc4 Bc5 4.O-O Nf6 5.d4 Bxd4 6.Nxd4 Nxd4 7.c3 Nxe4 8.cxd4 Qh4 9.Re1 O-O \
+            10.Rxe4 d6 11.Bg5 Qxg5 12.Nc3 Bf5 13.Rxe5 dxe5 14.dxe5 Rad8 15.Qf3 *";
+        let lan = "1.e2-e4 e7-e5 2.Ng1-f3 Nb8-c6 3.Bf1-c4 Bf8-c5 4.O-O Ng8-f6 5.d2-d4 Bc5xd4 6.Nf3xd4 Nc6xd4 \
+            7.c2-c3 Nf6xe4 8.c3xd4 Qd8-h4 9.Rf1-e1 O-O 10.Re1xe4 d7-d6 11.Bc1-g5 Qh4xg5 12.Nb1-c3 Bc8-f5 \
+            13.Re4xe5 d6xe5 14.d4xe5 Ra8-d8 15.Qd1-f3 *";
+        let san_board = board::Board::try_from(san.parse::<PGN>().unwrap()).unwrap();
+        let lan_board = board::Board::try_from(lan.parse::<PGN>().unwrap()).unwrap();
+        assert_eq!(san_board.move_history_string_notation().len(), 29);
+        assert_eq!(
+            lan_board.get_current_state().board_hash,
+            san_board.get_current_state().board_hash
+        );
+        // the game is exported as SAN
+        assert_eq!(
+            PGN::from(&lan_board).to_string(),
+            PGN::from(&san_board).to_string()
+        );
+    }
+
+    #[test]
+    fn test_pgn_validate_against_board() {
+        // Qh5 is check without a '+'
+        let pgn: PGN = "1.e4 f6 2.Qh5 g6 *".parse().unwrap();
+        let board = board::Board::try_from(pgn.clone()).unwrap();
+        let mismatches = pgn.validate_against_board(&board);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].ply, 2);
+        assert_eq!(mismatches[0].found.to_string(), "Qh5");
+        assert_eq!(mismatches[0].expected.to_string(), "Qh5+");
+        // exporting the board writes the correct annotations
+        assert!(PGN::from(&board).to_string().contains("2.Qh5+ g6"));
+
+        // a spurious '#', and a missing one
+        let pgn: PGN = "1.e4# e5 2.Bc4 Nc6 3.Qh5 Nf6 4.Qxf7 *".parse().unwrap();
+        let board = board::Board::try_from(pgn.clone()).unwrap();
+        let mismatches = pgn.validate_against_board(&board);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].ply, 0);
+        assert!(mismatches[0].found.is_checkmate() && !mismatches[0].expected.is_checkmate());
+        assert_eq!(mismatches[1].ply, 6);
+        assert_eq!(mismatches[1].expected.to_string(), "Qxf7#");
+        assert!(PGN::from(&board).to_string().contains("4.Qxf7# 1-0"));
+
+        // annotations that agree with the board aren't reported
+        assert!(PGN::from(&board).validate_against_board(&board).is_empty());
+    }
+
+    #[test]
+    fn test_pgn_verify_result() {
+        let import =
+            |pgn: &str| board::Board::try_from_pgn_with_warnings(pgn.parse().unwrap()).unwrap();
+        let mate = "1.e4 e5 2.Bc4 Nc6 3.Qh5 Nf6 4.Qxf7#";
+
+        // checkmate for white with a black win in the tag, the board keeps the checkmate
+        let (board, found) = import(&format!("[Result \"0-1\"]\n\n{} 0-1", mate));
+        assert_eq!(
+            found,
+            [ImportWarning::ResultMismatch {
+                tag: "0-1".to_string(),
+                game: "1-0".to_string()
+            }]
+        );
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::Forced(crate::GameState::Checkmate))
+        );
+        assert!(PGN::from(&board)
+            .tags()
+            .contains(&Tag::Result("1-0".to_string())));
+
+        // matching tags
+        let (_, found) = import(&format!(
+            "[Result \"1-0\"]\n[Termination \"normal\"]\n\n{} 1-0",
+            mate
+        ));
+        assert!(found.is_empty());
+        // a decisive result without the moves ending the game is a resignation
+        let (board, found) = import("[Result \"0-1\"]\n\n1.e4 e5 0-1");
+        assert!(found.is_empty());
+        assert_eq!(
+            board.get_game_over_state(),
+            Some(GameOverState::WhiteResign)
+        );
+
+        // unfinished games, which can't have ended on the board or normally
+        let (_, found) = import("[Result \"*\"]\n[Termination \"unterminated\"]\n\n1.e4 e5 *");
+        assert!(found.is_empty());
+        let (_, found) = import(&format!("{} *", mate));
+        assert_eq!(
+            found,
+            [ImportWarning::ResultMismatch {
+                tag: "*".to_string(),
+                game: "1-0".to_string()
+            }]
+        );
+        let (_, found) = import("[Result \"*\"]\n[Termination \"normal\"]\n\n1.e4 e5 *");
+        assert_eq!(
+            found,
+            [ImportWarning::TerminationMismatch {
+                termination: Termination::Normal,
+                result: "*".to_string()
+            }]
+        );
+        let (_, found) = import("[Result \"1-0\"]\n[Termination \"unterminated\"]\n\n1.e4 e5 1-0");
+        assert_eq!(found.len(), 1);
+
+        // moves after the checkmate aren't played
+        let (board, found) = import(&format!("[Result \"1-0\"]\n\n{} Ke7 5.Qxe5 1-0", mate));
+        assert_eq!(found, [ImportWarning::MovesAfterGameOver { ply: 7 }]);
+        assert_eq!(board.move_history_string_notation().len(), 7);
+        assert!(board::Board::try_from(PGN::from_str(&format!("{} Ke7 *", mate)).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_pgn_from_board_at_current_state() {
+        use rand::SeedableRng;
+
+        let mut board = board::Board::new();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2103);
+        while board.move_history_string_notation().len() < 80 {
+            let mv = board.random_legal_move(&mut rng).unwrap();
+            if board.make_move(&mv).unwrap().is_game_over() {
+                // start again until a game lasts 40 moves
+                board = board::Board::new();
+            }
+        }
+        let full = PGN::from(&board);
+        for _ in 0..70 {
+            board.checkout_prev();
+        }
+        assert_eq!(board.detatched_idx(), Some(10));
+
+        let pgn = PGN::from_board_at_current_state(&board);
+        assert_eq!(pgn.moves().len(), 10);
+        assert!(pgn.moves().eq(full.moves().take(10)));
+        assert!(pgn.tags().contains(&Tag::Result("*".to_string())));
+        assert!(pgn
+            .tags()
+            .contains(&Tag::Termination(Termination::Unterminated)));
+        assert!(pgn.tags().contains(&Tag::PlyCount(10)));
+        assert!(pgn.tags().contains(&Tag::SetUp("0".to_string())));
+        assert!(!pgn.tags().iter().any(|tag| matches!(tag, Tag::FEN(_))));
+
+        let replayed = board::Board::try_from(pgn.to_string().parse::<PGN>().unwrap()).unwrap();
+        assert_eq!(
+            replayed.get_current_state().board_hash,
+            board.get_current_state().board_hash
+        );
+        assert!(replayed.get_game_over_state().is_none());
+
+        // the full game when the board isn't detatched
+        board.checkout_latest_state();
+        assert_eq!(
+            PGN::from_board_at_current_state(&board).to_string(),
+            full.to_string()
+        );
+
+        // a position that isn't the standard one keeps its FEN
+        let mut board = board::Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        for mv in ["e2e4", "e8d7", "e1d2"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        board.checkout_prev();
+        let pgn = PGN::from_board_at_current_state(&board);
+        assert!(pgn
+            .tags()
+            .contains(&Tag::FEN("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".to_string())));
+        let replayed = board::Board::try_from(pgn).unwrap();
+        assert_eq!(
+            replayed.get_current_state().board_hash,
+            board.get_current_state().board_hash
+        );
+    }
+
+    #[test]
+    fn test_pgn_to_string_with() {
+        // line breaks from wrapping are read back as part of the comments they're in
+        fn unwrapped(records: &[MoveRecord]) -> Vec<MoveRecord> {
+            records
+                .iter()
+                .map(|record| MoveRecord {
+                    comment: record
+                        .comment
+                        .as_ref()
+                        .map(|comment| comment.split_whitespace().collect::<Vec<_>>().join(" ")),
+                    variations: record
+                        .variations
+                        .iter()
+                        .map(|variation| Variation {
+                            comment: variation.comment.clone(),
+                            moves: unwrapped(&variation.moves),
+                        })
+                        .collect(),
+                    ..record.clone()
+                })
+                .collect()
+        }
+        let pgn: PGN = "[Event \"Options\"]\n[Annotator \"?\"]\n\n{Opening comment} 1.e4 {[%eval 0.3] A long \
+            comment about the first move, written to span several lines} e5 2.Nf3 $1 (2.f4 exf4 {King's gambit} 3.Nf3 \
+            (3.Bc4 Qh4+ 4.Kf1) g5) 2...Nc6 3.Bb5 a6 4.Ba4 Nf6 5.O-O Be7 6.Re1 b5 7.Bb3 d6 8.c3 O-O 9.h3 Nb8 10.d4 \
+            Nbd7 11.c4 c6 12.cxb5 axb5 13.Nc3 Bb7 14.Bg5 b4 15.Bxf6 Nxf6 16.Na4 Nxe4 17.Bxf7+ Rxf7 1-0"
+            .parse()
+            .unwrap();
+
+        // lines stay within the width, and the moves read back the same
+        for line_width in [20, 40, 60, 79, 120] {
+            for include_move_numbers_after_comments in [true, false] {
+                let opts = PgnWriteOptions {
+                    line_width,
+                    include_move_numbers_after_comments,
+                    ..Default::default()
+                };
+                let written = pgn.to_string_with(&opts);
+                assert!(
+                    written.lines().all(|line| line.len() <= line_width),
+                    "{}",
+                    written
+                );
+                let reparsed: PGN = written.parse().unwrap();
+                assert_eq!(unwrapped(reparsed.move_records()), pgn.move_records());
+                assert_eq!(reparsed.comment(), pgn.comment());
+            }
+        }
+        assert_eq!(
+            pgn.to_string_with(&PgnWriteOptions::default()),
+            pgn.to_string()
+        );
+
+        // black's moves after comments and variations are only numbered when asked to be
+        let opts = PgnWriteOptions {
+            line_width: usize::MAX,
+            include_move_numbers_after_comments: false,
+            ..Default::default()
+        };
+        let written = pgn.to_string_with(&opts);
+        assert!(written
+            .contains("several lines} e5 2.Nf3 $1 (2.f4 exf4 {King's gambit} 3.Nf3 (3.Bc4 Qh4+"));
+        assert!(written.contains("4.Kf1) g5) Nc6 3.Bb5"));
+        assert!(pgn.to_string().contains("several lines} 1...e5"));
+
+        // CRLF line breaks, and filtered tags
+        let opts = PgnWriteOptions {
+            newline: NewlineStyle::CrLf,
+            include_tags: TagFilter::SevenTagRoster,
+            ..Default::default()
+        };
+        let written = pgn.to_string_with(&opts);
+        assert_eq!(
+            written.matches('\n').count(),
+            written.matches("\r\n").count()
+        );
+        assert!(written.starts_with("[Event \"Options\"]\r\n"));
+        assert!(written.contains("[Result \"1-0\"]\r\n\r\n{Opening comment}"));
+        assert!(!written.contains("Annotator"));
+        assert_eq!(
+            unwrapped(written.parse::<PGN>().unwrap().move_records()),
+            pgn.move_records()
+        );
+        let opts = PgnWriteOptions {
+            include_tags: TagFilter::Only(vec!["Annotator".to_string()]),
+            ..Default::default()
+        };
+        assert!(pgn
+            .to_string_with(&opts)
+            .starts_with("[Annotator \"?\"]\n\n{Opening"));
+        let opts = PgnWriteOptions {
+            include_tags: TagFilter::Only(Vec::new()),
+            ..Default::default()
+        };
+        let written = pgn.to_string_with(&opts);
+        assert!(written.starts_with("{Opening comment} 1.e4"));
+        assert_eq!(
+            unwrapped(written.parse::<PGN>().unwrap().move_records()),
+            pgn.move_records()
+        );
+    }
+
+    #[test]
+    fn test_pgn_nags() {
+        let pgn: PGN = "[Result \"*\"]\n\n1.e4 e5 2.Qh5 Nc6?! 3.Qxf7+!! $18 (3.Bc4 $1) *"
+            .parse()
+            .unwrap();
+        let record = &pgn.move_records()[4];
+        assert_eq!(record.notation.to_string(), "Qxf7+");
+        assert_eq!(record.nags, [3, 18]);
+        assert_eq!(record.variations[0].moves[0].nags, [1]);
+
+        // suffixes are written as NAGs, and read back the same
+        let exported = pgn.to_string();
+        assert!(exported.contains("2.Qh5 Nc6 $6 3.Qxf7+ $3 $18 (3.Bc4 $1) *"));
+        let reparsed: PGN = exported.parse().unwrap();
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+
+        // and are ignored when finding the move
+        let mut board = board::Board::new();
+        for mv in ["e2e4", "e7e5", "d1h5", "b8c6"] {
+            board.make_move_uci(mv).unwrap();
+        }
+        let mv = record
+            .notation
+            .to_move_with_context(board.get_current_state())
+            .unwrap();
+        assert_eq!(
+            mv.short_move(),
+            board
+                .get_current_state()
+                .find_move_uci("h5f7")
+                .unwrap()
+                .short_move()
+        );
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(board.move_history_notation().len(), 5);
+    }
+
+    #[test]
+    fn test_pgn_evals_round_trip() {
+        let mut board = board::Board::new();
+        let mut evals = Vec::new();
+        for mv in [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5", "c2c3", "g8f6", "d2d4", "e5d4", "c3d4",
+            "c5b4", "b1c3", "f6e4", "e1g1", "e4c3", "b2c3", "b4c3", "d1b3", "d7d5",
+        ] {
+            board.make_move_uci(mv).unwrap();
+            evals.push(crate::evaluate_state(board.get_current_state()));
+        }
+        // mates in a number of moves, for either side
+        evals[18] = checkmate_eval(5);
+        evals[19] = -checkmate_eval(1);
+        assert_eq!(board.move_history_notation().len(), 20);
+
+        let exported = PGN::from_board_with_evals(&board, &evals).to_string();
+        assert!(exported.replace('\n', " ").contains("[%eval #3]"));
+        assert!(exported.replace('\n', " ").contains("[%eval #-1]"));
+        let imported: PGN = exported.parse().unwrap();
+        let imported_evals: Vec<i32> = imported
+            .move_records()
+            .iter()
+            .map(|record| record.eval.unwrap())
+            .collect();
+        assert_eq!(imported_evals, evals);
+        assert!(imported
+            .move_records()
+            .iter()
+            .all(|record| record.comment.is_none()));
+        assert_eq!(imported.to_string(), exported);
+    }
+
+    #[test]
+    fn test_pgn_eval_and_clock_comments() {
+        let lichess = "[Event \"?\"]\n[Result \"*\"]\n\n1. e4 { [%eval 0.17] [%clk 0:03:00] } 1... e5 { [%eval 0.2] [%clk 1:02:58.5] } \
+                       2. Nf3 { [%clk 0:02:59] [%csl Gf3] Developing } 2... Nc6 { [%eval -1.05] [%eval abc] } *";
+        let pgn: PGN = lichess.parse().unwrap();
+        // the brackets of commands aren't read as tags
+        assert!(!pgn.tags().iter().any(|tag| tag.name().starts_with('%')));
+        let records = pgn.move_records();
+        assert_eq!(records[0].eval, Some(17));
+        assert_eq!(records[0].clock, Some(Duration::from_secs(180)));
+        assert_eq!(records[0].comment, None);
+        assert_eq!(records[1].eval, Some(20));
+        assert_eq!(records[1].clock, Some(Duration::from_millis(3_778_500)));
+        assert_eq!(records[2].eval, None);
+        assert_eq!(records[2].clock, Some(Duration::from_secs(179)));
+        // other commands, and ones that can't be read, are kept in the comment
+        assert_eq!(records[2].comment.as_deref(), Some("[%csl Gf3] Developing"));
+        assert_eq!(records[3].eval, Some(-105));
+        assert_eq!(records[3].comment.as_deref(), Some("[%eval abc]"));
+
+        let exported = pgn.to_string();
+        assert!(exported
+            .replace('\n', " ")
+            .contains("1.e4 {[%eval 0.17] [%clk 0:03:00]} 1...e5 {[%eval 0.20] [%clk 1:02:58.5]}"));
+        let reparsed: PGN = exported.parse().unwrap();
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+    }
+
+    #[test]
+    fn test_pgn_non_ascii_and_escape_lines() {
+        let lichess = "% exported by a tool that writes escape lines\n[Event \"Rated blitz game\"]\n[Site \"https://lichess.org/abcdefgh\"]\n\
+                       [White \"Łukasz Müller\"]\n[Black \"José Raúl\"]\n[Result \"0-1\"]\n\n\
+                       1. e4 { Très bien ♟ } 1... e5 2. Nf3 ; ça va\n%2. d4 escaped\n2... Nc6 0-1\n";
+        let pgn: PGN = lichess.parse().unwrap();
+        assert!(pgn
+            .tags()
+            .contains(&Tag::White("Łukasz Müller".to_string())));
+        assert!(pgn.tags().contains(&Tag::Black("José Raúl".to_string())));
+        assert_eq!(
+            pgn.move_records()
+                .iter()
+                .map(|record| record.comment.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("Très bien ♟"), None, Some("ça va"), None]
+        );
+        let reparsed: PGN = pgn.to_string().parse().unwrap();
+        assert_eq!(reparsed.move_records(), pgn.move_records());
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(
+            board.get_white_player().name.as_deref(),
+            Some("Łukasz Müller")
+        );
+
+        // non-ASCII anywhere else is an error rather than a panic
+        for invalid in [
+            "[Event \"?\"]\n\n1. e4 é5 *",
+            "[Évent \"?\"]\n\n1. e4 *",
+            "[Event \"?\"]\n\n1. e4 $é *",
+        ] {
+            assert!(invalid.parse::<PGN>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_pgn_export_line_length() {
+        let mut board = board::Board::new();
+        let mut comments = Vec::new();
+        for mv in [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "g8f6",
+        ] {
+            board.make_move_uci(mv).unwrap();
+            comments.push(Some(
+                "a long comment that goes on for long enough to need a line of its own and more"
+                    .to_string(),
+            ));
+        }
+        comments[3] = Some(
+            "averyveryverylongwordthatcannotbebrokenanywhereatallbecauseithasnospacesinsideofit!"
+                .to_string(),
+        );
+        let pgn = PGN::from_board_with_comments(&board, &comments);
+        let exported = pgn.to_string();
+        for line in exported.lines() {
+            assert!(line.len() <= 79 || !line.contains(' '), "{}", line);
+            assert!(!line.ends_with(' '), "{:?}", line);
+        }
+        // comments broken over lines are read back with the line breaks
+        let reparsed: PGN = exported.parse().unwrap();
+        let comments = |pgn: &PGN| -> Vec<String> {
+            pgn.move_records()
+                .iter()
+                .map(|record| {
+                    let comment = record.comment.as_deref().unwrap();
+                    comment.split_whitespace().collect::<Vec<_>>().join(" ")
+                })
+                .collect()
+        };
+        assert_eq!(comments(&reparsed), comments(&pgn));
+        assert_eq!(
+            reparsed.moves().collect::<Vec<_>>(),
+            pgn.moves().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_pgn_junk_input() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(2098);
+        let valid = fs::read_to_string("test_data/multi_game.pgn").unwrap();
+        let alphabet: Vec<char> =
+            "[]{}()<>;%$!?#+=-/*.\"\n\r\t 0123456789abcdefghKQRBNOx\u{e9}\u{265f}"
+                .chars()
+                .collect();
+        for _ in 0..2000 {
+            // random bytes, random PGN-like characters and a valid game with a few characters changed or cut short
+            let junk = match rng.gen_range(0..3) {
+                0 => {
+                    let bytes: Vec<u8> = (0..rng.gen_range(0..200)).map(|_| rng.gen()).collect();
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+                1 => (0..rng.gen_range(0..200))
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                    .collect(),
+                _ => {
+                    let mut chars: Vec<char> = valid.chars().collect();
+                    for _ in 0..rng.gen_range(1..5) {
+                        let i = rng.gen_range(0..chars.len());
+                        chars[i] = alphabet[rng.gen_range(0..alphabet.len())];
+                    }
+                    chars.truncate(rng.gen_range(0..=chars.len()));
+                    chars.into_iter().collect()
+                }
+            };
+            // errors are fine, panics are not
+            let _ = junk.parse::<PGN>();
+            let _ = PGN::parse_multi(&junk);
+            let _ = PgnReader::new(junk.as_bytes()).count();
+        }
     }
 }
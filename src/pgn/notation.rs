@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use crate::errors::PGNParseError;
 use crate::{board, movegen::*};
-use crate::{hash_to_string, log_and_return_error};
+use crate::{hash_to_string, log_and_return_error, SquareIdx};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Notation {
@@ -12,7 +12,7 @@ pub struct Notation {
     dis_rank: Option<char>, // for disambiguating moves if required
     capture: bool,
     to_file: char,
-    to_rank: char,
+    to_rank: char, // ' ' for a leniently parsed pawn capture without a rank (e.g. 'exd')
     promotion: Option<char>,
     check: bool,
     checkmate: bool,
@@ -47,7 +47,9 @@ impl fmt::Display for Notation {
             notation.push('x');
         }
         notation.push(self.to_file);
-        notation.push(self.to_rank);
+        if self.to_rank != ' ' {
+            notation.push(self.to_rank);
+        }
         if let Some(promotion) = self.promotion {
             notation.push('=');
             notation.push(promotion);
@@ -114,48 +116,79 @@ impl Notation {
         // create new uninitialised Notation struct
         let mut notation = Self::new();
 
-        // check if move is legal and if it results in check or checkmate by generating a new boardstate
-        // set check and checkmate flags based off the new boardstate's gamestate
+        // check if move is legal and if it results in check, only checking moves need a new boardstate to tell if it is
+        // checkmate. set check and checkmate flags based off the new boardstate's gamestate
         if legal_moves.contains(mv) {
-            let test_bs = bs_context.next_state(mv).unwrap(); // unwrap is safe as move is legal
-            match test_bs.get_gamestate() {
-                board::GameState::Check => notation.check = true, // SET CHECK FLAG
-                board::GameState::Checkmate => notation.checkmate = true, // SET CHECKMATE FLAG
-                _ => {}
+            if bs_context.position().gives_check(mv) {
+                let test_bs = bs_context.next_state(mv).unwrap(); // unwrap is safe as move is legal
+                match test_bs.get_gamestate() {
+                    board::GameState::Checkmate => notation.checkmate = true, // SET CHECKMATE FLAG
+                    _ => notation.check = true,                               // SET CHECK FLAG
+                }
             }
         } else {
             let err = PGNParseError::NotationParseError(format!("Move not legal: {:?}", mv));
             log_and_return_error!(err);
         }
 
+        notation.set_move_squares(legal_moves, mv);
+        Ok(notation)
+    }
+
+    // from_mv_with_context for a move that has already been played, so the check and checkmate flags come from the state
+    // after it rather than a new one. Board uses this to keep the notation of its moves as they're made
+    pub(crate) fn from_mv_with_next_state(
+        bs_context: &board::BoardState,
+        mv: &Move,
+        next_state: &board::BoardState,
+    ) -> Result<Notation, PGNParseError> {
+        let legal_moves = extract_legal_moves(bs_context)?;
+        if !legal_moves.contains(mv) {
+            let err = PGNParseError::NotationParseError(format!("Move not legal: {:?}", mv));
+            log_and_return_error!(err);
+        }
+
+        let mut notation = Self::new();
+        if next_state.position().is_in_check() {
+            match next_state.get_gamestate() {
+                board::GameState::Checkmate => notation.checkmate = true,
+                _ => notation.check = true,
+            }
+        }
+        notation.set_move_squares(legal_moves, mv);
+        Ok(notation)
+    }
+
+    // sets everything but the check and checkmate flags, mv has to be one of legal_moves
+    fn set_move_squares(&mut self, legal_moves: &[Move], mv: &Move) {
         // set castling string if it is a castling move and return
         if let MoveType::Castle(cm) = mv.move_type {
-            notation.castle_str = Some(match cm.get_castle_side() {
+            self.castle_str = Some(match cm.get_castle_side() {
                 // SET CASTLE STRING
                 CastleSide::Short => "O-O".to_string(),
                 CastleSide::Long => "O-O-O".to_string(),
             });
-            return Ok(notation); // RETURN ON CASTLE MOVE
+            return; // RETURN ON CASTLE MOVE
         }
 
         // SET PIECE CHAR
-        notation.piece = ptype_to_piece_char(&mv.piece.ptype);
+        self.piece = ptype_to_piece_char(&mv.piece.ptype);
 
         // SET TO FILE AND TO RANK
-        notation.to_file = index_to_file_notation(mv.to);
-        notation.to_rank = index_to_rank_notation_unchecked(mv.to);
+        self.to_file = SquareIdx::new_unchecked(mv.to).file_char();
+        self.to_rank = SquareIdx::new_unchecked(mv.to).rank_char();
 
         // SET CAPTURE FLAG (Normal capture, en passant capture, or promotion capture)
-        notation.capture = mv.move_type.is_capture();
+        self.capture = mv.move_type.is_capture();
 
         // SET PROMOTION CHAR
-        notation.promotion = mv_type_to_promotion_char(&mv.move_type);
+        self.promotion = mv_type_to_promotion_char(&mv.move_type);
 
         // DISAMBIGUATING MOVES
         // pawn moves that are captures or en passants only need dis_file, otherwise only to_file and to_rank are needed
-        if matches!(mv.piece.ptype, PieceType::Pawn) && notation.capture {
-            // notation.capture is set above in function
-            notation.dis_file = Some(index_to_file_notation(mv.from));
+        if matches!(mv.piece.ptype, PieceType::Pawn) && self.capture {
+            // self.capture is set above in function
+            self.dis_file = Some(SquareIdx::new_unchecked(mv.from).file_char());
         } else {
             // check if there are any other pieces besides pawns that can move to the same square as the mv.piece
             let same_piece_moves: Vec<&Move> = legal_moves
@@ -165,15 +198,15 @@ impl Notation {
             // if there are other pieces that can move to same square
             if !same_piece_moves.is_empty() {
                 // store the current mv.from square file and rank
-                let mv_from_file = index_to_file_notation(mv.from);
-                let mv_from_rank = index_to_rank_notation_unchecked(mv.from);
+                let mv_from_file = SquareIdx::new_unchecked(mv.from).file_char();
+                let mv_from_rank = SquareIdx::new_unchecked(mv.from).rank_char();
                 // keep track of whether any of the other moves have the same file or rank as the current mv.from square
                 let mut same_file = false;
                 let mut same_rank = false;
                 // check if any of the other moves have the same file or rank as the current mv.from square
                 for other_mv in same_piece_moves {
-                    let other_mv_from_file = index_to_file_notation(other_mv.from);
-                    let other_mv_from_rank = index_to_rank_notation_unchecked(other_mv.from);
+                    let other_mv_from_file = SquareIdx::new_unchecked(other_mv.from).file_char();
+                    let other_mv_from_rank = SquareIdx::new_unchecked(other_mv.from).rank_char();
                     if other_mv_from_file == mv_from_file {
                         same_file = true;
                     }
@@ -183,17 +216,113 @@ impl Notation {
                 }
                 // disambiguate the move by setting the file, or setting the rank, or setting both if needed in that order
                 if !same_file {
-                    notation.dis_file = Some(mv_from_file);
+                    self.dis_file = Some(mv_from_file);
                 } else if !same_rank {
-                    notation.dis_rank = Some(mv_from_rank);
+                    self.dis_rank = Some(mv_from_rank);
                 } else {
-                    notation.dis_file = Some(mv_from_file);
-                    notation.dis_rank = Some(mv_from_rank);
+                    self.dis_file = Some(mv_from_file);
+                    self.dis_rank = Some(mv_from_rank);
                 }
             }
         }
+    }
 
-        Ok(notation)
+    // from a move without the position it's played in, so the from square is always written in full e.g. "Ng1f3" or
+    // "e7xd8=Q", and check and checkmate are given. For logging engine lines, or anything with moves but no board.
+    // from_str reads it back and to_move_with_context finds the move, but it isn't standard SAN
+    pub fn from_mv_no_context(mv: &Move, check: bool, checkmate: bool) -> Notation {
+        let mut notation = Self::new();
+        notation.check = check && !checkmate;
+        notation.checkmate = checkmate;
+
+        if let MoveType::Castle(cm) = mv.move_type {
+            notation.castle_str = Some(match cm.get_castle_side() {
+                CastleSide::Short => "O-O".to_string(),
+                CastleSide::Long => "O-O-O".to_string(),
+            });
+            return notation;
+        }
+
+        notation.piece = ptype_to_piece_char(&mv.piece.ptype);
+        notation.dis_file = Some(SquareIdx::new_unchecked(mv.from).file_char());
+        notation.dis_rank = Some(SquareIdx::new_unchecked(mv.from).rank_char());
+        notation.capture = mv.move_type.is_capture();
+        notation.to_file = SquareIdx::new_unchecked(mv.to).file_char();
+        notation.to_rank = SquareIdx::new_unchecked(mv.to).rank_char();
+        notation.promotion = mv_type_to_promotion_char(&mv.move_type);
+        notation
+    }
+
+    // parses standard SAN as well as variants found in real world PGNs, which FromStr rejects: castling written with
+    // zeros ('0-0'), an en passant suffix ('exd6e.p.'), promotion without '=' ('e8Q'), lowercase piece letters ('nf3'),
+    // pawn captures without a rank ('exd') and long algebraic notation ('Ng1-f3'). Display writes the result as standard
+    // SAN, apart from the missing rank and with the from square of long algebraic notation
+    pub fn from_str_lenient(s: &str) -> Result<Notation, PGNParseError> {
+        Self::validate_ascii(s)?;
+
+        let (body, check_suffix) = split_check_suffix(s.trim());
+        let (body, check_suffix) = match strip_en_passant_suffix(body) {
+            Some(body) => split_check_suffix(body),
+            None => (body, check_suffix),
+        };
+
+        // pawn capture without a rank, the move is found from the legal captures onto the file
+        if let [from_file, 'x', to_file] = body.chars().collect::<Vec<_>>()[..] {
+            if is_valid_file(from_file) && is_valid_file(to_file) {
+                let mut notation = Self::new();
+                notation.dis_file = Some(from_file);
+                notation.capture = true;
+                notation.to_file = to_file;
+                notation.check = check_suffix == "+";
+                notation.checkmate = check_suffix == "#";
+                if check_suffix.len() > 1 {
+                    let err = PGNParseError::NotationParseError(format!(
+                        "Invalid notation, multiple check chars ({})",
+                        s
+                    ));
+                    log_and_return_error!(err)
+                }
+                return Ok(notation);
+            }
+        }
+
+        let mut normalized =
+            if !body.is_empty() && body.chars().all(|c| matches!(c, '0' | 'o' | '-')) {
+                body.replace(['0', 'o'], "O")
+            } else {
+                let mut chars: Vec<char> = body.chars().collect();
+                if let Some(first) = chars.first_mut() {
+                    if matches!(*first, 'n' | 'r' | 'q' | 'k')
+                        || *first == 'b' && is_lowercase_bishop(body)
+                    {
+                        first.make_ascii_uppercase();
+                    }
+                }
+                // long algebraic notation like "Ng1-f3" gives the from square before a '-', it's kept as the
+                // disambiguating file and rank as it is for "Ng1f3" or "e7xd8=Q"
+                if let Some(i) = chars
+                    .windows(3)
+                    .position(|w| w[0].is_ascii_digit() && w[1] == '-' && w[2].is_ascii_lowercase())
+                {
+                    chars.remove(i + 1);
+                }
+                // promotion piece straight after the rank, or a lowercase one after '='
+                if let [.., rank, promotion] = chars[..] {
+                    if matches!(rank, '1' | '8')
+                        && matches!(promotion, 'Q' | 'R' | 'B' | 'N' | 'q' | 'r' | 'b' | 'n')
+                    {
+                        chars.pop();
+                        chars.push('=');
+                        chars.push(promotion.to_ascii_uppercase());
+                    } else if rank == '=' {
+                        chars.last_mut().unwrap().make_ascii_uppercase();
+                    }
+                }
+                chars.into_iter().collect()
+            };
+        normalized.push_str(check_suffix);
+
+        Self::from_str(&normalized)
     }
 
     fn parse_castling_string(&mut self, notation_str: &str) -> bool {
@@ -370,15 +499,15 @@ impl Notation {
         notation_str: &str,
         i: usize,
     ) -> Result<(), PGNParseError> {
-        if piece_char.is_none() {
-            *piece_char = Some(c);
-        } else {
+        // the piece char can only come first, otherwise a promotion without '=' like "e8Q" would be read as "Qe8"
+        if i != 0 {
             let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation, multiple uppercase piece chars (char: '{}' at index: {})",
+                "Invalid notation, piece char must come first (char: '{}' at index: {})",
                 notation_str, i
             ));
             log_and_return_error!(err)
         }
+        *piece_char = Some(c);
         Ok(())
     }
 
@@ -471,6 +600,14 @@ impl Notation {
 }
 
 impl Notation {
+    pub fn is_check(&self) -> bool {
+        self.check
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.checkmate
+    }
+
     // tries to find a move, and disambiguates as best as possible, for use in PGN import format so if it is missing some disambiguating information but the move can still be identified, it is fine
     pub fn to_move_with_context(
         &self,
@@ -515,12 +652,18 @@ impl Notation {
                 if possible_dis_moves.len() == 1 {
                     Ok(*possible_dis_moves[0])
                 } else {
-                    let err = PGNParseError::MoveNotFound(format!(
-                "No legal move found for notation ({}) in BoardState (hash: {}) => Could not use notation to disambiguate between multiple possible moves: {:?}",
-                self,
-                hash_to_string(bs_context.board_hash),
-                possible_moves
-                ));
+                    // the candidates are legal, so they have notation
+                    let candidates: Vec<String> = possible_moves
+                        .iter()
+                        .filter_map(|mv| Self::from_mv_with_context(bs_context, mv).ok())
+                        .map(|n| n.to_string())
+                        .collect();
+                    let err = PGNParseError::AmbiguousMove(format!(
+                        "{} could be any of {} in BoardState (hash: {})",
+                        self,
+                        candidates.join(", "),
+                        hash_to_string(bs_context.board_hash)
+                    ));
                     log_and_return_error!(err)
                 }
             }
@@ -578,10 +721,13 @@ impl Notation {
         moves
             .iter()
             .filter(|mv| {
-                if let Some(castle_side) = self.get_castle_side() {
-                    if let MoveType::Castle(cm) = mv.move_type {
-                        return cm.get_castle_side() == castle_side;
-                    }
+                // castle notation only matches castle moves, and other notation never matches castle moves.
+                // in chess960 a castle move can have the same from and to squares as a normal king move (e.g. Kf1-g1 and O-O)
+                // O-O is always the h-side rook and O-O-O the a-side rook, regardless of how far the king travels
+                if let MoveType::Castle(cm) = mv.move_type {
+                    return self.get_castle_side() == Some(cm.get_castle_side());
+                } else if self.get_castle_side().is_some() {
+                    return false;
                 }
 
                 if let Some(piece) = self.get_piece_type() {
@@ -595,8 +741,9 @@ impl Notation {
                     }
                 }
 
-                if self.to_file != index_to_file_notation(mv.to)
-                    || self.to_rank != index_to_rank_notation_unchecked(mv.to)
+                if self.to_file != SquareIdx::new_unchecked(mv.to).file_char()
+                    || self.to_rank != ' '
+                        && self.to_rank != SquareIdx::new_unchecked(mv.to).rank_char()
                 {
                     return false;
                 }
@@ -619,6 +766,32 @@ impl Notation {
     }
 }
 
+// splits off the trailing check and checkmate chars
+fn split_check_suffix(notation_str: &str) -> (&str, &str) {
+    let body = notation_str.trim_end_matches(['+', '#']);
+    (body, &notation_str[body.len()..])
+}
+
+// removes an en passant suffix after the destination rank. '.' is a PGN delimiter, so "exd6e.p." in movetext reaches
+// the notation parser as "exd6e"
+fn strip_en_passant_suffix(notation_str: &str) -> Option<&str> {
+    ["e.p.", "ep", "e"].iter().find_map(|suffix| {
+        notation_str
+            .strip_suffix(suffix)
+            .map(str::trim_end)
+            .filter(|body| body.ends_with(|c: char| c.is_ascii_digit()))
+    })
+}
+
+// a lowercase 'b' is a pawn on the b file unless that can't be a pawn move, like 'bb5' or 'bxe5'
+fn is_lowercase_bishop(notation_str: &str) -> bool {
+    match notation_str.as_bytes() {
+        [b'b', file, rank, ..] if file.is_ascii_lowercase() && rank.is_ascii_digit() => true,
+        [b'b', b'x', file, ..] => !matches!(file, b'a' | b'c'),
+        _ => false,
+    }
+}
+
 // get legal moves from BoardState, on error return BoardStateError wrapped in PGNParseError
 fn extract_legal_moves(bs: &board::BoardState) -> Result<&[Move], PGNParseError> {
     match bs.get_legal_moves() {
@@ -682,54 +855,19 @@ fn is_valid_promotion(promotion: char) -> bool {
     promotion.is_ascii_uppercase() && valid_promotions.contains(&promotion)
 }
 
-#[inline]
-fn index_to_file_notation(i: usize) -> char {
-    match i % 8 {
-        0 => 'a',
-        1 => 'b',
-        2 => 'c',
-        3 => 'd',
-        4 => 'e',
-        5 => 'f',
-        6 => 'g',
-        7 => 'h',
-        _ => ' ',
-    }
-}
-
-#[inline]
-fn index_to_rank_notation_unchecked(i: usize) -> char {
-    let rank_num = 8 - i / 8;
-    char::from_digit(rank_num.try_into().unwrap(), 10).unwrap()
-}
-
 fn rank_notation_to_indexes_unchecked(r: char) -> [usize; 8] {
-    let rank_num = r.to_digit(10).unwrap() as usize;
-    let rank_starts = [56, 48, 40, 32, 24, 16, 8, 0]; // 1st to 8th rank starting indexes
-    let mut indexes = [0; 8];
-    for (i, j) in indexes.iter_mut().enumerate() {
-        *j = rank_starts[rank_num - 1] + i;
-    }
-    indexes
+    let rank = SquareIdx::rank_from_char(r).unwrap();
+    std::array::from_fn(|file| SquareIdx::from_file_rank(file as u8, rank).unwrap().index())
 }
 
 fn file_notation_to_indexes_unchecked(f: char) -> [usize; 8] {
-    let file_offset = match f {
-        'a' => 0,
-        'b' => 1,
-        'c' => 2,
-        'd' => 3,
-        'e' => 4,
-        'f' => 5,
-        'g' => 6,
-        'h' => 7,
-        _ => unreachable!(),
-    };
-    let mut indexes = [0; 8];
-    for (i, j) in indexes.iter_mut().enumerate() {
-        *j = file_offset + i * 8;
-    }
-    indexes
+    let file = SquareIdx::file_from_char(f).unwrap();
+    // a8 to a1, the same order as the board indexes
+    std::array::from_fn(|i| {
+        SquareIdx::from_file_rank(file, 7 - i as u8)
+            .unwrap()
+            .index()
+    })
 }
 
 #[cfg(test)]
@@ -885,6 +1023,134 @@ mod test {
         assert!(notation.is_err());
     }
 
+    #[test]
+    fn test_notation_from_mv_no_context() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2106);
+        let mut tested = 0;
+        for chess960 in [false, true] {
+            for _ in 0..20 {
+                let mut board = if chess960 {
+                    board::Board::new_chess960_from_num(rng.gen_range(0..960)).unwrap()
+                } else {
+                    board::Board::new()
+                };
+                while board.get_game_over_state().is_none() {
+                    let bs = board.get_current_state().clone();
+                    for mv in bs.get_legal_moves().unwrap() {
+                        let with_context = Notation::from_mv_with_context(&bs, mv).unwrap();
+                        let notation = Notation::from_mv_no_context(
+                            mv,
+                            with_context.check,
+                            with_context.checkmate,
+                        );
+                        let reparsed = Notation::from_str(&notation.to_string()).unwrap();
+                        assert_eq!(reparsed, notation);
+                        assert_eq!(reparsed.to_move_with_context(&bs).unwrap(), *mv);
+                        tested += 1;
+                    }
+                    let mv = board.random_legal_move(&mut rng).unwrap();
+                    board.make_move(&mv).unwrap();
+                }
+            }
+        }
+        assert!(tested > 10_000);
+
+        let mv = Move {
+            piece: Piece {
+                ptype: PieceType::Knight,
+                pcolour: PieceColour::White,
+            },
+            from: 62,
+            to: 45,
+            move_type: MoveType::Normal,
+        };
+        assert_eq!(
+            Notation::from_mv_no_context(&mv, false, false).to_string(),
+            "Ng1f3"
+        );
+        assert_eq!(
+            Notation::from_mv_no_context(&mv, true, false).to_string(),
+            "Ng1f3+"
+        );
+        assert_eq!(
+            Notation::from_mv_no_context(&mv, true, true).to_string(),
+            "Ng1f3#"
+        );
+    }
+
+    #[test]
+    fn test_notation_from_str_lenient() {
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        let promotion = "8/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let open_game = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        let pawn_capture = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        // (position, lenient notation, its Display, the move in standard SAN)
+        let cases = [
+            (castling, "0-0", "O-O", "O-O"),
+            (castling, "0-0-0", "O-O-O", "O-O-O"),
+            (castling, "o-o", "O-O", "O-O"),
+            (en_passant, "exd6e.p.", "exd6", "exd6"),
+            (en_passant, "exd6 e.p.", "exd6", "exd6"),
+            (en_passant, "exd6ep", "exd6", "exd6"),
+            (en_passant, "exd6e", "exd6", "exd6"),
+            (promotion, "e8Q", "e8=Q", "e8=Q"),
+            (promotion, "e8n", "e8=N", "e8=N"),
+            (promotion, "e8=q", "e8=Q", "e8=Q"),
+            (open_game, "nf3", "Nf3", "Nf3"),
+            (open_game, "bb5", "Bb5", "Bb5"),
+            (open_game, "qh5", "Qh5", "Qh5"),
+            (open_game, "ke2", "Ke2", "Ke2"),
+            (pawn_capture, "exd", "exd", "exd5"),
+            (open_game, "Ng1-f3", "Ng1f3", "Nf3"),
+            (open_game, "d2-d4", "d2d4", "d4"),
+            (pawn_capture, "e4-d5", "e4d5", "exd5"),
+        ];
+        for (fen, lenient, display, san) in cases {
+            let bs = board::BoardState::try_from(fen.parse::<crate::fen::FEN>().unwrap()).unwrap();
+            // strict mode rejects these, or reads them as a move that isn't legal ('bb5' as a b pawn move)
+            assert!(
+                !matches!(
+                    Notation::from_str(lenient).map(|n| n.to_move_with_context(&bs)),
+                    Ok(Ok(_))
+                ),
+                "{}",
+                lenient
+            );
+            let notation = Notation::from_str_lenient(lenient).unwrap();
+            assert_eq!(notation.to_string(), display);
+            let mv = notation.to_move_with_context(&bs).unwrap();
+            assert_eq!(
+                mv,
+                Notation::from_str(san)
+                    .unwrap()
+                    .to_move_with_context(&bs)
+                    .unwrap()
+            );
+            assert_eq!(
+                Notation::from_mv_with_context(&bs, &mv)
+                    .unwrap()
+                    .to_string(),
+                san
+            );
+        }
+
+        // standard SAN parses the same in both modes, a lowercase 'b' stays a pawn when it can be one
+        for san in [
+            "e4", "Nf3", "bxc3", "b4", "O-O-O+", "exd6", "e8=Q#", "Qf3xf5+",
+        ] {
+            assert_eq!(
+                Notation::from_str_lenient(san).unwrap(),
+                Notation::from_str(san).unwrap()
+            );
+        }
+        for invalid in ["0-0-0-0", "exd+#", "e9Q", "xd", "Qf3x", "é4"] {
+            assert!(Notation::from_str_lenient(invalid).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn test_notation_to_move_with_context() {
         let bs = board::BoardState::new_starting();
@@ -905,18 +1171,86 @@ mod test {
         assert_eq!(mv.to, 36);
     }
 
+    #[test]
+    fn test_notation_chess960_castle_king_on_castle_square() {
+        // king already on g1, so O-O only moves the h1 rook
+        let bs = board::BoardState::try_from(
+            "nbbqrnkr/pppppppp/8/8/8/5N2/PPPPPPPP/NBBQR1KR w KQkq - 0 1"
+                .parse::<crate::fen::FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let mv = Notation::from_str("O-O")
+            .unwrap()
+            .to_move_with_context(&bs)
+            .unwrap();
+        assert_eq!(mv.from, 62);
+        assert_eq!(mv.to, 62);
+        assert!(matches!(
+            mv.move_type,
+            MoveType::Castle(CastleMove {
+                rook_from: 63,
+                rook_to: 61,
+                side: CastleSide::Short
+            })
+        ));
+        assert_eq!(
+            Notation::from_mv_with_context(&bs, &mv)
+                .unwrap()
+                .to_string(),
+            "O-O"
+        );
+    }
+
+    #[test]
+    fn test_notation_chess960_castle_and_king_move_same_squares() {
+        // Kf1-g1 is possible as both a normal king move and as O-O
+        let bs = board::BoardState::try_from(
+            "qnrbbk1r/pppppppp/8/8/8/8/PPPPPPPP/QNRBBK1R w KQkq - 0 1"
+                .parse::<crate::fen::FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let king_mv = Notation::from_str("Kg1")
+            .unwrap()
+            .to_move_with_context(&bs)
+            .unwrap();
+        assert_eq!(king_mv.move_type, MoveType::Normal);
+        assert_eq!((king_mv.from, king_mv.to), (61, 62));
+
+        let castle_mv = Notation::from_str("O-O")
+            .unwrap()
+            .to_move_with_context(&bs)
+            .unwrap();
+        assert!(matches!(castle_mv.move_type, MoveType::Castle(_)));
+        assert_eq!((castle_mv.from, castle_mv.to), (61, 62));
+
+        assert_eq!(
+            Notation::from_mv_with_context(&bs, &king_mv)
+                .unwrap()
+                .to_string(),
+            "Kg1"
+        );
+        assert_eq!(
+            Notation::from_mv_with_context(&bs, &castle_mv)
+                .unwrap()
+                .to_string(),
+            "O-O"
+        );
+    }
+
     #[test]
     fn test_index_to_file_notation() {
-        assert_eq!(index_to_file_notation(0), 'a');
-        assert_eq!(index_to_file_notation(7), 'h');
-        assert_eq!(index_to_file_notation(35), 'd');
+        assert_eq!(SquareIdx::new_unchecked(0).file_char(), 'a');
+        assert_eq!(SquareIdx::new_unchecked(7).file_char(), 'h');
+        assert_eq!(SquareIdx::new_unchecked(35).file_char(), 'd');
     }
 
     #[test]
     fn test_index_to_rank_notation() {
-        assert_eq!(index_to_rank_notation_unchecked(0), '8');
-        assert_eq!(index_to_rank_notation_unchecked(7), '8');
-        assert_eq!(index_to_rank_notation_unchecked(35), '4');
+        assert_eq!(SquareIdx::new_unchecked(0).rank_char(), '8');
+        assert_eq!(SquareIdx::new_unchecked(7).rank_char(), '8');
+        assert_eq!(SquareIdx::new_unchecked(35).rank_char(), '4');
     }
 
     #[test]
@@ -1,18 +1,272 @@
 use std::{fmt, str::FromStr};
 
+use chrono::prelude::*;
+
 use crate::{errors::PGNParseError, log_and_return_error};
 
-#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
-pub struct CustomTag {
-    name: String,
-    value: String,
+// value of the Date tag, "YYYY.MM.DD" where unknown parts are question marks e.g. "1858.??.??"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Default)]
+pub struct PgnDate {
+    pub year: Option<u16>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
 }
 
-impl CustomTag {
-    pub fn new(name: &str, value: &str) -> Self {
+impl PgnDate {
+    pub fn today() -> Self {
+        let today = Local::now();
         Self {
-            name: name.to_string(),
-            value: value.to_string(),
+            year: u16::try_from(today.year()).ok(),
+            month: Some(today.month() as u8),
+            day: Some(today.day() as u8),
+        }
+    }
+}
+
+impl fmt::Display for PgnDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{:04}.", year)?,
+            None => write!(f, "????.")?,
+        }
+        match self.month {
+            Some(month) => write!(f, "{:02}.", month)?,
+            None => write!(f, "??.")?,
+        }
+        match self.day {
+            Some(day) => write!(f, "{:02}", day),
+            None => write!(f, "??"),
+        }
+    }
+}
+
+impl FromStr for PgnDate {
+    type Err = PGNParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PGNParseError::InvalidTag(format!("Invalid Date value {}", s));
+        let parts: Vec<&str> = s.split('.').collect();
+        let [year, month, day] = parts[..] else {
+            log_and_return_error!(invalid())
+        };
+        // each part is either all question marks or a number of the same width
+        fn part<T: FromStr>(part: &str, width: usize) -> Result<Option<T>, ()> {
+            if part.len() != width {
+                Err(())
+            } else if part.chars().all(|c| c == '?') {
+                Ok(None)
+            } else if part.chars().all(|c| c.is_ascii_digit()) {
+                part.parse().map(Some).map_err(|_| ())
+            } else {
+                Err(())
+            }
+        }
+        let (Ok(year), Ok(month), Ok(day)) = (
+            part::<u16>(year, 4),
+            part::<u8>(month, 2),
+            part::<u8>(day, 2),
+        ) else {
+            log_and_return_error!(invalid())
+        };
+        if month.is_some_and(|month| !(1..=12).contains(&month))
+            || day.is_some_and(|day| !(1..=31).contains(&day))
+        {
+            log_and_return_error!(invalid())
+        }
+        Ok(Self { year, month, day })
+    }
+}
+
+// a period of a TimeControl, times are in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum TimeControlPeriod {
+    // "40/9000", a number of moves in the time
+    Moves { moves: u32, seconds: u32 },
+    // "300", the rest of the game in the time
+    SuddenDeath(u32),
+    // "300+5", the rest of the game with time added after each move
+    Increment { base: u32, increment: u32 },
+    // "*180", an hourglass that runs down for the side to move and up for the other
+    Sandclock(u32),
+}
+
+impl fmt::Display for TimeControlPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Moves { moves, seconds } => write!(f, "{}/{}", moves, seconds),
+            Self::SuddenDeath(seconds) => write!(f, "{}", seconds),
+            Self::Increment { base, increment } => write!(f, "{}+{}", base, increment),
+            Self::Sandclock(seconds) => write!(f, "*{}", seconds),
+        }
+    }
+}
+
+impl FromStr for TimeControlPeriod {
+    type Err = PGNParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |value: &str| -> Result<u32, PGNParseError> {
+            // u32 parsing allows a leading '+', which is a separator here
+            match value.parse() {
+                Ok(number) if value.chars().all(|c| c.is_ascii_digit()) => Ok(number),
+                _ => {
+                    let err = PGNParseError::InvalidTag(format!("Invalid TimeControl value {}", s));
+                    log_and_return_error!(err)
+                }
+            }
+        };
+        if let Some(sandclock) = s.strip_prefix('*') {
+            Ok(Self::Sandclock(number(sandclock)?))
+        } else if let Some((moves, time)) = s.split_once('/') {
+            Ok(Self::Moves {
+                moves: number(moves)?,
+                seconds: number(time)?,
+            })
+        } else if let Some((base, increment)) = s.split_once('+') {
+            Ok(Self::Increment {
+                base: number(base)?,
+                increment: number(increment)?,
+            })
+        } else {
+            Ok(Self::SuddenDeath(number(s)?))
+        }
+    }
+}
+
+// value of the TimeControl tag, periods are separated by ':' e.g. "40/7200:3600"
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub enum TimeControl {
+    // "?"
+    Unknown,
+    // "-"
+    Untimed,
+    Periods(Vec<TimeControlPeriod>),
+}
+
+impl TimeControl {
+    // base time and increment in seconds, for the common single period time controls
+    pub fn base_increment(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Periods(periods) => match periods[..] {
+                [TimeControlPeriod::SuddenDeath(base)] => Some((base, 0)),
+                [TimeControlPeriod::Increment { base, increment }] => Some((base, increment)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "?"),
+            Self::Untimed => write!(f, "-"),
+            Self::Periods(periods) => {
+                let periods: Vec<String> = periods.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", periods.join(":"))
+            }
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = PGNParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "?" => Ok(Self::Unknown),
+            "-" => Ok(Self::Untimed),
+            _ => Ok(Self::Periods(
+                s.split(':')
+                    .map(str::parse)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+        }
+    }
+}
+
+// value of the WhiteElo and BlackElo tags, "?" or "-" if the player has no rating
+fn parse_elo(s: &str) -> Result<Option<u16>, PGNParseError> {
+    match s {
+        "?" | "-" | "" => Ok(None),
+        _ => match s.parse() {
+            Ok(elo) if s.chars().all(|c| c.is_ascii_digit()) => Ok(Some(elo)),
+            _ => {
+                let err = PGNParseError::InvalidTag(format!("Invalid Elo value {}", s));
+                log_and_return_error!(err)
+            }
+        },
+    }
+}
+
+fn elo_to_string(elo: &Option<u16>) -> String {
+    elo.map_or_else(|| "?".to_string(), |elo| elo.to_string())
+}
+
+// Encyclopaedia of Chess Openings code, a letter A-E and two digits, with an optional suffix for extended codes
+// e.g. "B80" or "B80a". "?" if unknown
+fn validate_eco(s: &str) -> Result<(), PGNParseError> {
+    let bytes = s.as_bytes();
+    let valid = s == "?"
+        || (bytes.len() >= 3
+            && (b'A'..=b'E').contains(&bytes[0])
+            && bytes[1..3].iter().all(u8::is_ascii_digit)
+            && bytes[3..].iter().all(u8::is_ascii_alphanumeric));
+    if !valid {
+        let err = PGNParseError::InvalidTag(format!("Invalid ECO value {}", s));
+        log_and_return_error!(err)
+    }
+    Ok(())
+}
+
+// values of the Termination tag defined by the standard, for how the game ended rather than who won. Other keeps values
+// outside the standard that some sites use, e.g. "Unknown"
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub enum Termination {
+    Abandoned,
+    Adjudication,
+    Death,
+    Emergency,
+    Normal,
+    RulesInfraction,
+    TimeForfeit,
+    Unterminated,
+    Other(String),
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let termination_str = match self {
+            Self::Abandoned => "abandoned",
+            Self::Adjudication => "adjudication",
+            Self::Death => "death",
+            Self::Emergency => "emergency",
+            Self::Normal => "normal",
+            Self::RulesInfraction => "rules infraction",
+            Self::TimeForfeit => "time forfeit",
+            Self::Unterminated => "unterminated",
+            Self::Other(value) => value,
+        };
+        write!(f, "{}", termination_str)
+    }
+}
+
+impl FromStr for Termination {
+    type Err = PGNParseError;
+
+    // the standard uses lower case, but other capitalisations are common e.g. "Time forfeit"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "abandoned" => Ok(Self::Abandoned),
+            "adjudication" => Ok(Self::Adjudication),
+            "death" => Ok(Self::Death),
+            "emergency" => Ok(Self::Emergency),
+            "normal" => Ok(Self::Normal),
+            "rules infraction" => Ok(Self::RulesInfraction),
+            "time forfeit" => Ok(Self::TimeForfeit),
+            "unterminated" => Ok(Self::Unterminated),
+            _ => Ok(Self::Other(s.to_string())),
         }
     }
 }
@@ -22,46 +276,116 @@ pub enum Tag {
     // REQUIRED TAGS
     Event(String),
     Site(String),
-    Date(String),
+    Date(PgnDate),
     Round(String),
     White(String),
     Black(String),
     Result(String),
     // OPTIONAL TAGS
-    WhiteElo(String),
-    BlackElo(String),
+    WhiteElo(Option<u16>),
+    BlackElo(Option<u16>),
     Variant(String),
     Eco(String),
     SetUp(String),
     FEN(String),
-    Termination(String),
+    Termination(Termination),
     Annotator(String),
-    CustomTag(CustomTag),
+    TimeControl(TimeControl),
+    PlyCount(u32),
+    // any other tag, by name and value
+    Other(String, String),
 }
 
-impl fmt::Display for Tag {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl Tag {
+    pub fn name(&self) -> &str {
         match self {
-            Self::Event(value) => write!(f, "[Event \"{}\"]", value),
-            Self::Site(value) => write!(f, "[Site \"{}\"]", value),
-            Self::Date(value) => write!(f, "[Date \"{}\"]", value),
-            Self::Round(value) => write!(f, "[Round \"{}\"]", value),
-            Self::White(value) => write!(f, "[White \"{}\"]", value),
-            Self::Black(value) => write!(f, "[Black \"{}\"]", value),
-            Self::Result(value) => write!(f, "[Result \"{}\"]", value),
-            Self::WhiteElo(value) => write!(f, "[WhiteElo \"{}\"]", value),
-            Self::BlackElo(value) => write!(f, "[BlackElo \"{}\"]", value),
-            Self::Variant(value) => write!(f, "[Variant \"{}\"]", value),
-            Self::Eco(value) => write!(f, "[ECO \"{}\"]", value),
-            Self::SetUp(value) => write!(f, "[SetUp \"{}\"]", value),
-            Self::FEN(value) => write!(f, "[FEN \"{}\"]", value),
-            Self::Termination(value) => write!(f, "[Termination \"{}\"]", value),
-            Self::Annotator(value) => write!(f, "[Annotator \"{}\"]", value),
-            Self::CustomTag(ct) => write!(f, "[{} \"{}\"]", ct.name, ct.value),
+            Self::Event(_) => "Event",
+            Self::Site(_) => "Site",
+            Self::Date(_) => "Date",
+            Self::Round(_) => "Round",
+            Self::White(_) => "White",
+            Self::Black(_) => "Black",
+            Self::Result(_) => "Result",
+            Self::WhiteElo(_) => "WhiteElo",
+            Self::BlackElo(_) => "BlackElo",
+            Self::Variant(_) => "Variant",
+            Self::Eco(_) => "ECO",
+            Self::SetUp(_) => "SetUp",
+            Self::FEN(_) => "FEN",
+            Self::Termination(_) => "Termination",
+            Self::Annotator(_) => "Annotator",
+            Self::TimeControl(_) => "TimeControl",
+            Self::PlyCount(_) => "PlyCount",
+            Self::Other(name, _) => name,
+        }
+    }
+
+    // the value as it's written in the tag, without quotes
+    pub fn value(&self) -> String {
+        match self {
+            Self::Event(value)
+            | Self::Site(value)
+            | Self::Round(value)
+            | Self::White(value)
+            | Self::Black(value)
+            | Self::Result(value)
+            | Self::Variant(value)
+            | Self::Eco(value)
+            | Self::SetUp(value)
+            | Self::FEN(value)
+            | Self::Annotator(value)
+            | Self::Other(_, value) => value.clone(),
+            Self::Date(date) => date.to_string(),
+            Self::WhiteElo(elo) | Self::BlackElo(elo) => elo_to_string(elo),
+            Self::Termination(termination) => termination.to_string(),
+            Self::TimeControl(time_control) => time_control.to_string(),
+            Self::PlyCount(ply_count) => ply_count.to_string(),
+        }
+    }
+
+    // the tag with the given name, validating the value for typed tags
+    pub fn from_name_value(name: &str, value: &str) -> Result<Tag, PGNParseError> {
+        match name {
+            "Event" => Ok(Self::Event(value.to_string())),
+            "Site" => Ok(Self::Site(value.to_string())),
+            "Date" => Ok(Self::Date(value.parse()?)),
+            "Round" => Ok(Self::Round(value.to_string())),
+            "White" => Ok(Self::White(value.to_string())),
+            "Black" => Ok(Self::Black(value.to_string())),
+            "Result" => Ok(Self::Result(value.to_string())),
+            "WhiteElo" => Ok(Self::WhiteElo(parse_elo(value)?)),
+            "BlackElo" => Ok(Self::BlackElo(parse_elo(value)?)),
+            "Variant" => Ok(Self::Variant(value.to_string())),
+            "ECO" => {
+                validate_eco(value)?;
+                Ok(Self::Eco(value.to_string()))
+            }
+            "SetUp" => Ok(Self::SetUp(value.to_string())),
+            "FEN" => Ok(Self::FEN(value.to_string())),
+            "Termination" => Ok(Self::Termination(value.parse()?)),
+            "Annotator" => Ok(Self::Annotator(value.to_string())),
+            "TimeControl" => Ok(Self::TimeControl(value.parse()?)),
+            "PlyCount" => match value.parse() {
+                Ok(ply_count) if value.chars().all(|c| c.is_ascii_digit()) => {
+                    Ok(Self::PlyCount(ply_count))
+                }
+                _ => {
+                    let err =
+                        PGNParseError::InvalidTag(format!("Invalid PlyCount value {}", value));
+                    log_and_return_error!(err)
+                }
+            },
+            name => Ok(Self::Other(name.to_string(), value.to_string())),
         }
     }
 }
 
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{} \"{}\"]", self.name(), self.value())
+    }
+}
+
 impl FromStr for Tag {
     type Err = PGNParseError;
 
@@ -69,7 +393,11 @@ impl FromStr for Tag {
         let tag_str = tag.trim_matches(['[', ']']).trim();
         let mut parts = tag_str.splitn(2, ' ').map(str::trim);
 
-        let name = if let Some(name) = parts.next() {
+        // tag names are letters, digits and underscores
+        let name = if let Some(name) = parts
+            .next()
+            .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        {
             name
         } else {
             let err = PGNParseError::InvalidTag(format!("Tag {} has invalid name", tag));
@@ -83,24 +411,7 @@ impl FromStr for Tag {
             log_and_return_error!(err)
         };
 
-        match name {
-            "Event" => Ok(Self::Event(value.to_string())),
-            "Site" => Ok(Self::Site(value.to_string())),
-            "Date" => Ok(Self::Date(value.to_string())),
-            "Round" => Ok(Self::Round(value.to_string())),
-            "White" => Ok(Self::White(value.to_string())),
-            "Black" => Ok(Self::Black(value.to_string())),
-            "Result" => Ok(Self::Result(value.to_string())),
-            "WhiteElo" => Ok(Self::WhiteElo(value.to_string())),
-            "BlackElo" => Ok(Self::BlackElo(value.to_string())),
-            "Variant" => Ok(Self::Variant(value.to_string())),
-            "ECO" => Ok(Self::Eco(value.to_string())),
-            "SetUp" => Ok(Self::SetUp(value.to_string())),
-            "FEN" => Ok(Self::FEN(value.to_string())),
-            "Termination" => Ok(Self::Termination(value.to_string())),
-            "Annotator" => Ok(Self::Annotator(value.to_string())),
-            c => Ok(Self::CustomTag(CustomTag::new(c, value))),
-        }
+        Self::from_name_value(name, value)
     }
 }
 
@@ -118,4 +429,139 @@ mod test {
             _ => panic!("Parsed tag is not an Event"),
         }
     }
+
+    #[test]
+    fn test_parse_termination_tag() {
+        let tag: Tag = "[Termination \"time forfeit\"]".parse().unwrap();
+        assert_eq!(tag, Tag::Termination(Termination::TimeForfeit));
+        assert_eq!(tag.to_string(), "[Termination \"time forfeit\"]");
+        assert_eq!(
+            "[Termination \"Rules infraction\"]".parse::<Tag>().unwrap(),
+            Tag::Termination(Termination::RulesInfraction)
+        );
+        let tag: Tag = "[Termination \"Unknown\"]".parse().unwrap();
+        assert_eq!(
+            tag,
+            Tag::Termination(Termination::Other("Unknown".to_string()))
+        );
+        assert_eq!(tag.to_string(), "[Termination \"Unknown\"]");
+    }
+
+    #[test]
+    fn test_parse_date_tag() {
+        let tag: Tag = "[Date \"1858.??.??\"]".parse().unwrap();
+        assert_eq!(
+            tag,
+            Tag::Date(PgnDate {
+                year: Some(1858),
+                month: None,
+                day: None
+            })
+        );
+        assert_eq!(tag.to_string(), "[Date \"1858.??.??\"]");
+        for date in ["1999.01.20", "????.??.??", "2024.02.??"] {
+            assert_eq!(date.parse::<PgnDate>().unwrap().to_string(), date);
+        }
+        for malformed in [
+            "1999-01-20",
+            "1999.1.20",
+            "1999.13.01",
+            "1999.01.32",
+            "99.01.01",
+            "?",
+            "1999.0a.01",
+        ] {
+            assert!(
+                format!("[Date \"{}\"]", malformed).parse::<Tag>().is_err(),
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_elo_tags() {
+        assert_eq!(
+            "[WhiteElo \"2355\"]".parse::<Tag>().unwrap(),
+            Tag::WhiteElo(Some(2355))
+        );
+        assert_eq!(
+            "[BlackElo \"?\"]".parse::<Tag>().unwrap(),
+            Tag::BlackElo(None)
+        );
+        assert_eq!(
+            "[BlackElo \"-\"]".parse::<Tag>().unwrap(),
+            Tag::BlackElo(None)
+        );
+        assert_eq!(Tag::WhiteElo(Some(2355)).to_string(), "[WhiteElo \"2355\"]");
+        assert_eq!(Tag::BlackElo(None).to_string(), "[BlackElo \"?\"]");
+        for malformed in ["abc", "-5", "+5", "1500.5", "70000"] {
+            assert!(format!("[WhiteElo \"{}\"]", malformed)
+                .parse::<Tag>()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_time_control_tag() {
+        let tag: Tag = "[TimeControl \"300+5\"]".parse().unwrap();
+        let Tag::TimeControl(time_control) = &tag else {
+            panic!("Parsed tag is not a TimeControl")
+        };
+        assert_eq!(time_control.base_increment(), Some((300, 5)));
+        assert_eq!(tag.to_string(), "[TimeControl \"300+5\"]");
+
+        assert_eq!(
+            "40/7200:3600".parse::<TimeControl>().unwrap(),
+            TimeControl::Periods(vec![
+                TimeControlPeriod::Moves {
+                    moves: 40,
+                    seconds: 7200
+                },
+                TimeControlPeriod::SuddenDeath(3600)
+            ])
+        );
+        assert_eq!(
+            "600".parse::<TimeControl>().unwrap().base_increment(),
+            Some((600, 0))
+        );
+        assert_eq!("-".parse::<TimeControl>().unwrap(), TimeControl::Untimed);
+        for time_control in ["?", "-", "300+0", "*180", "40/9000:300+30"] {
+            assert_eq!(
+                time_control.parse::<TimeControl>().unwrap().to_string(),
+                time_control
+            );
+        }
+        for malformed in ["", "5 min", "300+", "+5", "40/", "300:", "*", "1:30:00x"] {
+            assert!(format!("[TimeControl \"{}\"]", malformed)
+                .parse::<Tag>()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_eco_and_ply_count_tags() {
+        assert_eq!(
+            "[ECO \"B80\"]".parse::<Tag>().unwrap(),
+            Tag::Eco("B80".to_string())
+        );
+        assert!("[ECO \"C33a\"]".parse::<Tag>().is_ok());
+        assert!("[ECO \"?\"]".parse::<Tag>().is_ok());
+        for malformed in ["F00", "B8", "b80", "B8x"] {
+            assert!(format!("[ECO \"{}\"]", malformed).parse::<Tag>().is_err());
+        }
+
+        let tag: Tag = "[PlyCount \"115\"]".parse().unwrap();
+        assert_eq!(tag, Tag::PlyCount(115));
+        assert_eq!(tag.to_string(), "[PlyCount \"115\"]");
+        assert!("[PlyCount \"many\"]".parse::<Tag>().is_err());
+
+        // unknown tags are kept as they are
+        let tag: Tag = "[WhiteFideId \"12345\"]".parse().unwrap();
+        assert_eq!(
+            tag,
+            Tag::Other("WhiteFideId".to_string(), "12345".to_string())
+        );
+        assert_eq!(tag.to_string(), "[WhiteFideId \"12345\"]");
+    }
 }
@@ -1,9 +1,10 @@
 use std::ops::Deref;
-use std::vec;
 
-use super::notation::*;
+use super::notation::Notation;
 use super::tag::*;
+use super::{take_comment_commands, MoveRecord, Variation};
 use crate::errors::PGNParseError;
+use crate::log_and_return_error;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Token {
@@ -20,7 +21,7 @@ impl Token {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Tokens {
     tokens: Vec<Token>,
 }
@@ -35,8 +36,18 @@ impl Tokens {
         let mut tags = Vec::new();
         let mut tag_str = String::new();
         let mut in_tag = false;
+        // brackets in comments aren't tags, e.g. lichess's {[%eval 0.35]}
+        let mut comment_end: Option<&str> = None;
         for token in &self.tokens {
-            if token.value == "[" {
+            if let Some(end) = comment_end {
+                if token.value == end {
+                    comment_end = None;
+                }
+            } else if !in_tag && token.value == "{" {
+                comment_end = Some("}");
+            } else if !in_tag && token.value == ";" {
+                comment_end = Some("\n");
+            } else if token.value == "[" {
                 in_tag = true;
                 tag_str += &token.value;
             } else if token.value == "]" {
@@ -60,44 +71,187 @@ impl Tokens {
         None
     }
 
-    pub fn get_move_notations(&self) -> Result<Vec<Notation>, PGNParseError> {
-        // for now trim comments, variations and move numbers from the movetext as we won't use them for now
-        let mut move_tokens = self.tokens.clone();
-        let delimiters = vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")];
-        for delimiter in delimiters {
-            let mut new_tokens = Vec::new();
-            let mut in_delimiter = false;
-            for token in move_tokens {
-                if token.value == delimiter.0 {
-                    in_delimiter = true;
-                } else if token.value == delimiter.1 {
-                    in_delimiter = false;
-                } else if !in_delimiter {
-                    new_tokens.push(token.clone());
+    // moves of the mainline with the comments and variations after them, and the comment before the first move if there
+    // is one. Move numbers and the game termination marker are skipped
+    pub fn get_move_records(&self) -> Result<(Option<String>, Vec<MoveRecord>), PGNParseError> {
+        parse_line(&mut self.tokens.iter(), false)
+    }
+
+    // splits the tokens of a PGN database into its games. A game ends at a termination marker in its movetext, outside
+    // of tags, comments and variations, and the next starts at the first token after it that isn't whitespace. Trailing
+    // tokens with no termination marker are a last game, whitespace alone is not
+    pub fn split_games(self) -> Vec<Tokens> {
+        let mut games = Vec::new();
+        let mut game = Vec::new();
+        let mut in_tag = false;
+        let mut in_comment = false;
+        let mut variation_depth: usize = 0;
+        let mut terminated = false;
+        for token in self.tokens {
+            let is_whitespace = token.value.trim().is_empty();
+            if terminated && !is_whitespace {
+                games.push(Tokens {
+                    tokens: std::mem::take(&mut game),
+                });
+                terminated = false;
+            }
+            if in_comment {
+                in_comment = token.value != "}";
+            } else if in_tag {
+                in_tag = token.value != "]";
+            } else {
+                match token.value.as_str() {
+                    "[" => in_tag = true,
+                    "{" => in_comment = true,
+                    "(" => variation_depth += 1,
+                    ")" => variation_depth = variation_depth.saturating_sub(1),
+                    _ if variation_depth == 0 && token.is_game_termination_marker() => {
+                        terminated = true
+                    }
+                    _ => {}
                 }
             }
-            move_tokens = new_tokens;
+            if !(terminated && is_whitespace) {
+                game.push(token);
+            }
         }
-        // truncate at game termination marker
-        if let Some(pos) = move_tokens
-            .iter()
-            .position(Token::is_game_termination_marker)
-        {
-            move_tokens.truncate(pos);
+        if game.iter().any(|token| !token.value.trim().is_empty()) {
+            games.push(Tokens { tokens: game });
         }
-        // remove all single character tokens that are left
-        move_tokens.retain(|token| token.value.len() > 1);
-        // remove move numbers
-        move_tokens.retain(|token| !token.value.chars().all(|c| c.is_ascii_digit()));
-        let mut notations = Vec::new();
-        for token in move_tokens {
-            let notation = token.value.parse()?;
-            notations.push(notation);
+        games
+    }
+}
+// reads a line of movetext, up to the ')' closing it for a variation. Brace comments and ';' comments to the end of the
+// line are attached to the move before them, several after the same move are joined with a space
+fn parse_line<'a>(
+    tokens: &mut impl Iterator<Item = &'a Token>,
+    in_variation: bool,
+) -> Result<(Option<String>, Vec<MoveRecord>), PGNParseError> {
+    let mut line_comment: Option<String> = None;
+    let mut records: Vec<MoveRecord> = Vec::new();
+    while let Some(token) = tokens.next() {
+        let comment = match token.value.as_str() {
+            "{" => Some(
+                tokens
+                    .by_ref()
+                    .take_while(|token| token.value != "}")
+                    // CRLF line breaks inside the comment are kept as '\n'
+                    .filter(|token| token.value != "\r")
+                    .map(|token| token.value.as_str())
+                    .collect::<String>(),
+            ),
+            ";" => Some(
+                tokens
+                    .by_ref()
+                    .take_while(|token| token.value != "\n")
+                    .map(|token| token.value.as_str())
+                    .collect::<String>(),
+            ),
+            _ => None,
+        };
+        if let Some(comment) = comment {
+            let (comment, attach_to) = match records.last_mut() {
+                Some(record) => (take_comment_commands(&comment, record), &mut record.comment),
+                None => (comment, &mut line_comment),
+            };
+            let comment = comment.trim();
+            if !comment.is_empty() {
+                match attach_to {
+                    Some(existing) => {
+                        existing.push(' ');
+                        existing.push_str(comment);
+                    }
+                    None => *attach_to = Some(comment.to_string()),
+                }
+            }
+            continue;
         }
+        match token.value.as_str() {
+            // tags, and '<' '>' which are reserved for future expansion of the standard
+            "[" => tokens
+                .by_ref()
+                .take_while(|token| token.value != "]")
+                .for_each(drop),
+            "<" => tokens
+                .by_ref()
+                .take_while(|token| token.value != ">")
+                .for_each(drop),
+            "(" => {
+                let (comment, moves) = parse_line(tokens, true)?;
+                match records.last_mut() {
+                    Some(record) => record.variations.push(Variation { comment, moves }),
+                    None => log_and_return_error!(PGNParseError::InvalidVariation(
+                        "Variation before the first move of a line".to_string()
+                    )),
+                }
+            }
+            ")" if in_variation => return Ok((line_comment, records)),
+            ")" => {
+                log_and_return_error!(PGNParseError::InvalidVariation("Unmatched ')'".to_string()))
+            }
+            _ if token.is_game_termination_marker() => {
+                if in_variation {
+                    log_and_return_error!(PGNParseError::InvalidVariation(format!(
+                        "Game termination marker {} inside a variation",
+                        token.value
+                    )))
+                }
+                break;
+            }
+            value if value.starts_with('$') => {
+                let Ok(nag) = value[1..].parse::<u8>() else {
+                    log_and_return_error!(PGNParseError::InvalidNag(value.to_string()))
+                };
+                match records.last_mut() {
+                    Some(record) => record.nags.push(nag),
+                    None => log_and_return_error!(PGNParseError::InvalidNag(format!(
+                        "{} before the first move of a line",
+                        value
+                    ))),
+                }
+            }
+            // single character tokens like whitespace and '.', move numbers, and an en passant suffix written apart
+            // from its move ("exd6 ep", "e.p." is split into single characters)
+            value
+                if value.len() <= 1
+                    || value.chars().all(|c| c.is_ascii_digit())
+                    || value == "ep" => {}
+            value => {
+                // a move can end with a suffix annotation, which is stored as its NAG
+                let (notation, suffix) =
+                    value.split_at(value.find(['!', '?']).unwrap_or(value.len()));
+                let mut record = MoveRecord::new(Notation::from_str_lenient(notation)?);
+                if !suffix.is_empty() {
+                    match suffix_nag(suffix) {
+                        Some(nag) => record.nags.push(nag),
+                        None => log_and_return_error!(PGNParseError::InvalidNag(value.to_string())),
+                    }
+                }
+                records.push(record);
+            }
+        }
+    }
+    if in_variation {
+        log_and_return_error!(PGNParseError::InvalidVariation(
+            "Variation is missing its closing ')'".to_string()
+        ))
+    }
+    Ok((line_comment, records))
+}
 
-        Ok(notations)
+// NAG of a move suffix annotation, the standard maps these to the first six
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
     }
 }
+
 // calling .iter() on Tokens will iterator over the inner Vec
 impl Deref for Tokens {
     type Target = Vec<Token>;
@@ -107,28 +261,36 @@ impl Deref for Tokens {
     }
 }
 
+// non-ASCII text is kept in the tokens it's part of, it's only valid in comments and tag values which is checked when
+// they are parsed
 fn tokenize(pgn: &str) -> Vec<Token> {
-    // input should already be checked at this point, in a public function
-    assert!(pgn.is_ascii(), "PGN must be ASCII");
-
     let mut split_vec = Vec::new();
-    let mut last = 0;
-    for (index, matched) in
-        pgn.match_indices(move |c: char| is_pgn_delimiter(pgn.chars().nth(last).unwrap(), c))
-    {
-        if last != index {
-            split_vec.push(Token::new(&pgn[last..index]));
+    // single pass over each line, tokens never span lines as newlines are delimiters
+    for line in pgn.split_inclusive('\n') {
+        // lines starting with '%' are escaped, they hold data for other programs and aren't part of the PGN
+        if line.starts_with('%') {
+            continue;
+        }
+        let mut last = 0;
+        for (index, c) in line.char_indices() {
+            if is_pgn_delimiter(c) {
+                if last != index {
+                    split_vec.push(Token::new(&line[last..index]));
+                }
+                let next = index + c.len_utf8();
+                split_vec.push(Token::new(&line[index..next]));
+                last = next;
+            }
+        }
+        if last < line.len() {
+            split_vec.push(Token::new(&line[last..]));
         }
-        split_vec.push(Token::new(matched));
-        last = index + matched.len();
-    }
-    if last < pgn.len() {
-        split_vec.push(Token::new(&pgn[last..]));
     }
     split_vec
 }
 
-const fn is_pgn_delimiter(prev_char: char, c: char) -> bool {
+// there is deliberately no rule splitting a digit from what follows it, that would break up results like "1-0"
+const fn is_pgn_delimiter(c: char) -> bool {
     c.is_ascii_whitespace()
         || c == '.'
         || c == ')'
@@ -141,12 +303,13 @@ const fn is_pgn_delimiter(prev_char: char, c: char) -> bool {
         || c == '<'
         || c == '>'
         || c == '"'
-        || prev_char.is_ascii_digit() && !c.is_ascii_digit()
+        || c == ';'
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::pgn::notation::*;
 
     #[test]
     fn test_tokenize() {
@@ -253,6 +416,37 @@ mod test {
         assert_eq!(tokens, expected_tokens);
     }
 
+    #[test]
+    fn test_tokenize_without_tags() {
+        // digits followed by other chars aren't split, so results stay whole
+        let tokens = tokenize("1.e4 e5 1-0");
+        let expected_tokens: Vec<Token> = ["1", ".", "e4", " ", "e5", " ", "1-0"]
+            .iter()
+            .map(|s| Token::new(s))
+            .collect();
+
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_tokenize_large_input() {
+        let game = include_str!("../../test_data/test.pgn");
+        let repeats = 1_000_000 / game.len() + 1;
+        let large = game.repeat(repeats);
+
+        let start = std::time::Instant::now();
+        let tokens = tokenize(&large);
+        let elapsed = start.elapsed();
+
+        assert_eq!(tokens.len(), tokenize(game).len() * repeats);
+        // linear time takes milliseconds here, even in debug builds
+        assert!(
+            elapsed < std::time::Duration::from_secs(5),
+            "took {:?}",
+            elapsed
+        );
+    }
+
     #[test]
     fn test_tokens_get_tags() {
         let tokens_vec = vec![
@@ -279,7 +473,7 @@ mod test {
     }
 
     #[test]
-    fn test_tokens_get_move_notations() {
+    fn test_tokens_get_move_records() {
         let tokens_vec = vec![
             Token::new("1"),
             Token::new("."),
@@ -292,12 +486,66 @@ mod test {
             Token::new("1-0"),
         ];
         let tokens = Tokens { tokens: tokens_vec };
-        let notations = tokens.get_move_notations().unwrap();
+        let (comment, records) = tokens.get_move_records().unwrap();
+        let notations: Vec<&Notation> = records.iter().map(|record| &record.notation).collect();
 
         assert_eq!(notations.len(), 3);
-        assert_eq!(notations[0], "e4".parse().unwrap());
-        assert_eq!(notations[1], "e5".parse().unwrap());
-        assert_eq!(notations[2], "Q1d7+".parse().unwrap());
+        assert_eq!(*notations[0], "e4".parse().unwrap());
+        assert_eq!(*notations[1], "e5".parse().unwrap());
+        assert_eq!(*notations[2], "Q1d7+".parse().unwrap());
         println!("{:?}", notations[2]);
+        assert_eq!(comment, None);
+        assert!(records.iter().all(|record| record.comment.is_none()));
+    }
+
+    #[test]
+    fn test_tokens_get_move_records_comments() {
+        let tokens = Tokens::from_pgn_str(
+            "[Event \"?\"]\n{Before the game} 1.e4 {Best by test} {, says Fischer} e5 ; rest of line\n2.Nf3 \
+             (2.f4 {King's gambit}) Nc6 {} 1-0 {after the game}",
+        );
+        let (comment, records) = tokens.get_move_records().unwrap();
+        assert_eq!(comment.as_deref(), Some("Before the game"));
+        let comments: Vec<Option<&str>> = records
+            .iter()
+            .map(|record| record.comment.as_deref())
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                Some("Best by test , says Fischer"),
+                Some("rest of line"),
+                None,
+                None
+            ]
+        );
+        assert_eq!(records[3].notation, "Nc6".parse().unwrap());
+    }
+
+    #[test]
+    fn test_tokens_get_move_records_nags() {
+        let (_, records) = Tokens::from_pgn_str("[Event \"?\"]\n12. Qxf7+!! $18")
+            .get_move_records()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].notation, "Qxf7+".parse().unwrap());
+        assert_eq!(records[0].nags, [3, 18]);
+
+        let (_, records) =
+            Tokens::from_pgn_str("[Event \"?\"]\n1.e4! e5? 2.Nf3!? Nc6?! 3.Bb5?? $146 a6 *")
+                .get_move_records()
+                .unwrap();
+        let nags: Vec<&[u8]> = records
+            .iter()
+            .map(|record| record.nags.as_slice())
+            .collect();
+        assert_eq!(nags, [&[1][..], &[2], &[5], &[6], &[4, 146], &[]]);
+
+        for invalid in ["$1 1.e4", "1.e4 $256", "1.e4 $x", "1.e4!!!"] {
+            assert!(matches!(
+                Tokens::from_pgn_str(&format!("[Event \"?\"]\n{}", invalid)).get_move_records(),
+                Err(PGNParseError::InvalidNag(_))
+            ));
+        }
     }
 }
@@ -0,0 +1,145 @@
+// players that choose moves for Board::play_game, e.g. for engine vs engine or engine vs random harnesses
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::board::BoardState;
+use crate::engine;
+use crate::movegen::{Move, NULL_MOVE};
+use crate::transposition::TranspositionTable;
+
+pub trait Player {
+    // move to play in bs, which always has a legal move. A move that isn't legal ends Board::play_game with an error
+    fn get_move(&mut self, bs: &BoardState) -> Move;
+}
+
+// plays the engine's best move at a fixed depth
+pub struct EnginePlayer {
+    pub depth: u8,
+    pub tt: TranspositionTable,
+}
+
+impl EnginePlayer {
+    pub fn new(depth: u8) -> Self {
+        Self {
+            depth,
+            tt: TranspositionTable::new(),
+        }
+    }
+
+    pub fn with_transposition_table(mut self, tt: TranspositionTable) -> Self {
+        self.tt = tt;
+        self
+    }
+}
+
+impl Player for EnginePlayer {
+    fn get_move(&mut self, bs: &BoardState) -> Move {
+        self.tt.new_search();
+        engine::choose_move(bs, self.depth, &mut self.tt)
+            .best_move
+            .unwrap_or(NULL_MOVE)
+    }
+}
+
+// plays a uniformly random legal move
+pub struct RandomPlayer {
+    rng: StdRng,
+}
+
+impl RandomPlayer {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // the same seed plays the same moves in the same positions
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for RandomPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for RandomPlayer {
+    fn get_move(&mut self, bs: &BoardState) -> Move {
+        bs.random_legal_move(&mut self.rng).unwrap_or(NULL_MOVE)
+    }
+}
+
+// a closure as a Player, e.g. one that reads moves from stdin
+pub struct FnPlayer<F: FnMut(&BoardState) -> Move>(F);
+
+impl<F: FnMut(&BoardState) -> Move> FnPlayer<F> {
+    pub fn new(f: F) -> Self {
+        Self(f)
+    }
+}
+
+impl<F: FnMut(&BoardState) -> Move> Player for FnPlayer<F> {
+    fn get_move(&mut self, bs: &BoardState) -> Move {
+        (self.0)(bs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, GameOverState, GameResult, ResultReason};
+    use crate::movegen::PieceColour;
+    use crate::pgn::PGN;
+
+    #[test]
+    fn test_engine_vs_random() {
+        for seed in 0..4 {
+            let mut board = Board::new();
+            let mut white = EnginePlayer::new(2);
+            let mut black = RandomPlayer::with_seed(seed);
+            let gos = board.play_game(&mut white, &mut black, 300).unwrap();
+            assert_eq!(board.get_game_over_state(), Some(gos));
+            assert!(board.ply_count() <= 300);
+            // a random player doesn't last long against the engine
+            assert_eq!(board.winner(), Some(PieceColour::White), "seed {}", seed);
+
+            let exported = PGN::from(&board).to_string();
+            let imported = Board::try_from(exported.parse::<PGN>().unwrap()).unwrap();
+            assert_eq!(imported.to_fen(), board.to_fen());
+            assert_eq!(imported.result(), board.result());
+        }
+    }
+
+    #[test]
+    fn test_play_game_max_plies() {
+        let mut board = Board::new();
+        let mut white = RandomPlayer::with_seed(1);
+        let mut black = RandomPlayer::with_seed(2);
+        let gos = board.play_game(&mut white, &mut black, 6).unwrap();
+        assert_eq!(board.ply_count(), 6);
+        assert_eq!(
+            gos,
+            GameOverState::Adjudication(GameResult::Draw(ResultReason::Adjudication))
+        );
+        // a finished game isn't played on
+        let gos = board.play_game(&mut white, &mut black, 6).unwrap();
+        assert_eq!(board.ply_count(), 6);
+        assert_eq!(board.get_game_over_state(), Some(gos));
+    }
+
+    #[test]
+    fn test_play_game_illegal_move() {
+        let mut board = Board::new();
+        let e2e4 = board.get_current_state().find_move_uci("e2e4").unwrap();
+        // black can't play white's move
+        let mut white = FnPlayer::new(|_: &BoardState| e2e4);
+        let mut black = FnPlayer::new(|_: &BoardState| e2e4);
+        assert!(board.play_game(&mut white, &mut black, 10).is_err());
+        assert_eq!(board.ply_count(), 1);
+        assert_eq!(board.get_game_over_state(), None);
+    }
+}
@@ -5,9 +5,15 @@ use std::vec;
 
 use rand::Rng;
 
+use crate::bitboard::Bitboards;
+use crate::engine;
+use crate::errors::PositionError;
 use crate::fen::FEN;
+use crate::log_and_return_error;
 use crate::mailbox;
 use crate::movegen::*;
+use crate::packed_move::PackedMove;
+use crate::util::SquareIdx;
 use crate::zobrist;
 use crate::zobrist::PositionHash;
 
@@ -44,7 +50,114 @@ impl Default for Pos64 {
     }
 }
 
+// serialized as an array of its 64 squares, a8 first
+#[cfg(feature = "serde")]
+impl serde::Serialize for Pos64 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+
+        let mut tuple = serializer.serialize_tuple(64)?;
+        for square in &self.0 {
+            tuple.serialize_element(square)?;
+        }
+        tuple.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Pos64 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Pos64Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Pos64Visitor {
+            type Value = Pos64;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "an array of 64 squares")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Pos64, A::Error> {
+                let mut pos64 = Pos64::default();
+                for i in 0..64 {
+                    pos64.0[i] = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<Square>()?.is_some() {
+                    return Err(serde::de::Error::invalid_length(65, &self));
+                }
+                Ok(pos64)
+            }
+        }
+
+        deserializer.deserialize_tuple(64, Pos64Visitor)
+    }
+}
+
 impl Pos64 {
+    // the piece on idx, None for empty squares and indexes off the board
+    #[inline(always)]
+    pub fn piece_at(&self, idx: usize) -> Option<Piece> {
+        match self.0.get(idx)? {
+            Square::Piece(p) => Some(*p),
+            Square::Empty => None,
+        }
+    }
+
+    // every piece on the board with its index, in index order (a8 first)
+    #[inline(always)]
+    pub fn pieces(&self) -> impl Iterator<Item = (usize, Piece)> + '_ {
+        self.0.iter().enumerate().filter_map(|(i, s)| match s {
+            Square::Piece(p) => Some((i, *p)),
+            Square::Empty => None,
+        })
+    }
+
+    #[inline(always)]
+    pub fn pieces_of(&self, colour: PieceColour) -> impl Iterator<Item = (usize, Piece)> + '_ {
+        self.pieces().filter(move |(_, p)| p.pcolour == colour)
+    }
+
+    /// Indexes of every piece of one colour and type, e.g. counting white's pawns:
+    ///
+    /// ```
+    /// use chess::{BoardState, PieceColour, PieceType};
+    ///
+    /// let bs = BoardState::new_starting();
+    /// let white_pawns = bs.get_pos64().piece_squares(PieceColour::White, PieceType::Pawn);
+    /// assert_eq!(white_pawns.count(), 8);
+    /// ```
+    #[inline(always)]
+    pub fn piece_squares(
+        &self,
+        colour: PieceColour,
+        ptype: PieceType,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let piece = Piece {
+            pcolour: colour,
+            ptype,
+        };
+        self.pieces()
+            .filter(move |(_, p)| *p == piece)
+            .map(|(i, _)| i)
+    }
+
+    // the board mirrored top to bottom with the colours of the pieces swapped, so white's pieces stand where black's did
+    pub fn flipped_vertical(&self) -> Self {
+        let mut flipped = Self::default();
+        for (i, p) in self.pieces() {
+            // i ^ 56 is the same file on the mirrored rank
+            flipped[i ^ 56] = Square::Piece(Piece {
+                pcolour: !p.pcolour,
+                ptype: p.ptype,
+            });
+        }
+        flipped
+    }
+
     // check if a pawn of colour 'pawn_colour' is on either side of square at index i, used for setting polyglot en passant flag
     #[inline(always)]
     pub fn polyglot_is_pawn_beside(&self, i: usize, pawn_colour: PieceColour) -> bool {
@@ -122,8 +235,9 @@ impl Pos64 {
     }
 }
 
+// moves are stored packed to keep the map small, it is cloned and rebuilt for every new position
 #[derive(Debug, PartialEq, Clone)]
-pub struct AttackMap(Vec<Move>);
+pub struct AttackMap(Vec<PackedMove>);
 
 impl AttackMap {
     fn new() -> Self {
@@ -140,21 +254,194 @@ impl AttackMap {
     }
 }
 
+impl Default for AttackMap {
+    fn default() -> Self {
+        Self::new_no_alloc()
+    }
+}
+
 impl MoveMap for AttackMap {
     fn add_move(&mut self, mv: &Move) {
-        self.0.push(*mv);
+        self.0.push(PackedMove::pack(mv));
+    }
+}
+
+#[inline(always)]
+pub(crate) const fn colour_idx(colour: PieceColour) -> usize {
+    match colour {
+        PieceColour::White => 0,
+        PieceColour::Black => 1,
     }
 }
 
+// indexes of the set bits in a square set, lowest index first
+#[inline(always)]
+pub(crate) fn square_set_indexes(mut set: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            return None;
+        }
+        let i = set.trailing_zeros() as usize;
+        set &= set - 1;
+        Some(i)
+    })
+}
+
+// piece locations and evaluation sums for each side, updated incrementally in new_position (the same way the zobrist
+// hash is updated) so the evaluation and king lookups don't need to scan the whole board
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) struct PieceTotals {
+    // square sets per colour and piece type, bit i is set if the piece is on index i
+    bitboards: Bitboards,
+    material: [i32; 2],
+    mg_pst: [i32; 2],
+    eg_pst: [i32; 2],
+    phase: i32,
+}
+
+impl PieceTotals {
+    pub fn from_pos64(pos64: &Pos64) -> Self {
+        let mut totals = Self::default();
+        for (i, p) in pos64.pieces() {
+            totals.add(p, i);
+        }
+        totals
+    }
+
+    #[inline(always)]
+    fn add(&mut self, piece: Piece, i: usize) {
+        let c = colour_idx(piece.pcolour);
+        self.bitboards.add(piece, i);
+        self.material[c] += engine::get_piece_value(&piece.ptype);
+        self.mg_pst[c] += engine::get_piece_pos_value(i, &piece, false);
+        self.eg_pst[c] += engine::get_piece_pos_value(i, &piece, true);
+        self.phase += engine::get_piece_phase_value(&piece.ptype);
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, piece: Piece, i: usize) {
+        let c = colour_idx(piece.pcolour);
+        self.bitboards.remove(piece, i);
+        self.material[c] -= engine::get_piece_value(&piece.ptype);
+        self.mg_pst[c] -= engine::get_piece_pos_value(i, &piece, false);
+        self.eg_pst[c] -= engine::get_piece_pos_value(i, &piece, true);
+        self.phase -= engine::get_piece_phase_value(&piece.ptype);
+    }
+
+    // totals after mv is made, mirrors zobrist::pos_next_hash
+    #[inline(always)]
+    fn after_move(&self, mv: &Move) -> Self {
+        let mut totals = *self;
+        let side = mv.piece.pcolour;
+        let mut piece = mv.piece;
+        totals.remove(mv.piece, mv.from);
+        match mv.move_type {
+            MoveType::Capture(ptype) => totals.remove(
+                Piece {
+                    pcolour: !side,
+                    ptype,
+                },
+                mv.to,
+            ),
+            MoveType::EnPassant(idx) => totals.remove(
+                Piece {
+                    pcolour: !side,
+                    ptype: PieceType::Pawn,
+                },
+                idx,
+            ),
+            MoveType::Promotion(ptype, capture) => {
+                if let Some(c) = capture {
+                    totals.remove(
+                        Piece {
+                            pcolour: !side,
+                            ptype: c,
+                        },
+                        mv.to,
+                    );
+                }
+                piece = Piece {
+                    pcolour: side,
+                    ptype,
+                };
+            }
+            MoveType::Castle(c) => {
+                let rook = Piece {
+                    pcolour: side,
+                    ptype: PieceType::Rook,
+                };
+                totals.remove(rook, c.rook_from);
+                totals.add(rook, c.rook_to);
+            }
+            _ => {}
+        }
+        totals.add(piece, mv.to);
+        totals
+    }
+
+    // square set of the given piece, bit i is set if the piece is on index i
+    #[inline(always)]
+    pub fn squares(&self, piece: Piece) -> u64 {
+        self.bitboards.pieces(piece)
+    }
+
+    #[inline(always)]
+    pub fn bitboards(&self) -> &Bitboards {
+        &self.bitboards
+    }
+
+    // material value of colour's pieces in centipawns, including the king
+    #[inline(always)]
+    pub fn material(&self, colour: PieceColour) -> i32 {
+        self.material[colour_idx(colour)]
+    }
+
+    // sum of colour's middlegame piece-square values
+    #[inline(always)]
+    pub fn mg_pst(&self, colour: PieceColour) -> i32 {
+        self.mg_pst[colour_idx(colour)]
+    }
+
+    // sum of colour's endgame piece-square values
+    #[inline(always)]
+    pub fn eg_pst(&self, colour: PieceColour) -> i32 {
+        self.eg_pst[colour_idx(colour)]
+    }
+
+    // game phase of the non-pawn material on the board, not capped so promotions can take it above the maximum
+    #[inline(always)]
+    pub fn phase(&self) -> i32 {
+        self.phase
+    }
+}
+
+// the state of a Position before Position::make_move_in_place or make_null_move, everything the move changed that can't
+// be worked back out from the move itself
 #[derive(Debug, Clone)]
+pub struct Undo {
+    squares: [(usize, Square); 4], // the squares the move changed, with what was on them before
+    movegen_flags: MovegenFlags,
+    in_check: bool,
+    check_info: CheckInfo,
+    piece_totals: PieceTotals,
+    wking_idx: usize,
+    bking_idx: usize,
+    attack_map: AttackMap,
+    attacked: Option<[u64; 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Position {
     pub pos64: Pos64,
     pub side: PieceColour,
     pub movegen_flags: MovegenFlags,
     in_check: bool,
+    check_info: CheckInfo, // pins and checks against the side to move's king
     attack_map: AttackMap, // map of moves from attacking side
+    piece_totals: PieceTotals,
     wking_idx: usize,
     bking_idx: usize,
+    attacked: Option<[u64; 2]>, // squares attacked by each side, only kept up to date when tracking attacks
 }
 
 impl Position {
@@ -248,10 +535,13 @@ impl Position {
             pos64: pos,
             side: PieceColour::White,
             in_check: false,
+            check_info: CheckInfo::all_pinned(),
             movegen_flags,
             attack_map: AttackMap::new(),
+            piece_totals: PieceTotals::from_pos64(&pos),
             wking_idx: 60,
             bking_idx: 4,
+            attacked: None,
         };
         new.gen_maps();
         new
@@ -395,10 +685,13 @@ impl Position {
             pos64: pos,
             side: PieceColour::White,
             in_check: false,
+            check_info: CheckInfo::all_pinned(),
             movegen_flags,
             attack_map: AttackMap::new(),
+            piece_totals: PieceTotals::from_pos64(&pos),
             wking_idx: 56 + king_start,
             bking_idx: king_start,
+            attacked: None,
         };
         new.gen_maps();
         new
@@ -413,51 +706,286 @@ impl Position {
             pos64,
             side,
             in_check: false,
+            check_info: CheckInfo::all_pinned(),
             movegen_flags,
             attack_map: AttackMap::new(),
+            piece_totals: PieceTotals::from_pos64(&pos64),
             wking_idx: 0,
             bking_idx: 0,
+            attacked: None,
         };
         new.update_king_idx();
         new.gen_maps();
         new
     }
 
+    // checks the position is one movegen can work with, positions from FENs aren't checked otherwise. Each side needs
+    // exactly one king, at most 16 pieces and 8 pawns, no pawns on the first or last rank, and the side not to move
+    // can't be in check
+    pub fn validate(&self) -> Result<(), PositionError> {
+        for colour in [PieceColour::White, PieceColour::Black] {
+            let kings = self.pos64.piece_squares(colour, PieceType::King).count();
+            if kings != 1 {
+                log_and_return_error!(PositionError::KingCount(format!(
+                    "{} has {} kings",
+                    colour, kings
+                )))
+            }
+            let pieces = self.pos64.pieces_of(colour).count();
+            if pieces > 16 {
+                log_and_return_error!(PositionError::TooManyPieces(format!(
+                    "{} has {} pieces",
+                    colour, pieces
+                )))
+            }
+            let pawns = self.pos64.piece_squares(colour, PieceType::Pawn).count();
+            if pawns > 8 {
+                log_and_return_error!(PositionError::TooManyPawns(format!(
+                    "{} has {} pawns",
+                    colour, pawns
+                )))
+            }
+        }
+        let back_rank_pawn = self
+            .pos64
+            .pieces()
+            .find(|(i, p)| p.ptype == PieceType::Pawn && !(8..56).contains(i));
+        if let Some((i, p)) = back_rank_pawn {
+            log_and_return_error!(PositionError::PawnOnBackRank(format!(
+                "{} pawn on {}",
+                p.pcolour,
+                SquareIdx::new_unchecked(i)
+            )))
+        }
+        let (wking, bking) = (
+            SquareIdx::new_unchecked(self.wking_idx),
+            SquareIdx::new_unchecked(self.bking_idx),
+        );
+        if wking.file().abs_diff(bking.file()) <= 1 && wking.rank().abs_diff(bking.rank()) <= 1 {
+            log_and_return_error!(PositionError::KingsAdjacent(format!(
+                "White king on {}, Black king on {}",
+                wking, bking
+            )))
+        }
+        let opponent = !self.side;
+        if let Some(ep) = self.movegen_flags.en_passant {
+            // the opponent's pawn that just moved two squares, with the square it passed over and the one it left empty
+            let ranks = match self.side {
+                PieceColour::White => 24..32,
+                PieceColour::Black => 32..40,
+            };
+            let behind = |i: usize| match self.side {
+                PieceColour::White => i - 8,
+                PieceColour::Black => i + 8,
+            };
+            let pawn = Square::Piece(Piece {
+                pcolour: opponent,
+                ptype: PieceType::Pawn,
+            });
+            if !ranks.contains(&ep)
+                || self.pos64[ep] != pawn
+                || self.pos64[behind(ep)] != Square::Empty
+                || self.pos64[behind(behind(ep))] != Square::Empty
+            {
+                log_and_return_error!(PositionError::InvalidEnPassant(format!(
+                    "no {} pawn can have just moved two squares past {}",
+                    opponent,
+                    SquareIdx::new_unchecked(behind(ep))
+                )))
+            }
+        }
+        if self.is_square_attacked(self.king_idx(opponent), self.side) {
+            log_and_return_error!(PositionError::OpponentInCheck(format!(
+                "{} is to move and can capture the {} king",
+                self.side, opponent
+            )))
+        }
+        Ok(())
+    }
+
+    // the same position with the colours swapped, see Pos64::flipped_vertical. The other side is to move, with the castling
+    // rights and en passant square of the side it swapped with
+    pub fn mirrored(&self) -> Self {
+        let flags = &self.movegen_flags;
+        let mirrored_flags = MovegenFlags {
+            white_castle_short: flags.black_castle_short,
+            white_castle_long: flags.black_castle_long,
+            black_castle_short: flags.white_castle_short,
+            black_castle_long: flags.white_castle_long,
+            en_passant: flags.en_passant.map(|i| i ^ 56),
+            polyglot_en_passant: flags.polyglot_en_passant.map(|i| i ^ 56),
+            long_white_rook_start: flags.long_black_rook_start ^ 56,
+            short_white_rook_start: flags.short_black_rook_start ^ 56,
+            long_black_rook_start: flags.long_white_rook_start ^ 56,
+            short_black_rook_start: flags.short_white_rook_start ^ 56,
+        };
+        let mirrored =
+            Self::new_from_pub_parts(self.pos64.flipped_vertical(), !self.side, mirrored_flags);
+        if self.attacked.is_some() {
+            mirrored.with_attack_tracking()
+        } else {
+            mirrored
+        }
+    }
+
+    // keeps the squares attacked by both sides up to date after every move, for anything that needs the opponent's
+    // attacks as well e.g. threat display. Positions made from this one track them too. Off by default, as the engine
+    // doesn't use them and every move would pay for generating them
+    pub fn with_attack_tracking(mut self) -> Self {
+        self.attacked = Some(Self::gen_attacked(self.piece_totals.bitboards()));
+        self
+    }
+
+    // square set of the squares attacked by colour, whatever is on them. Generated from scratch unless the position is
+    // tracking attacks
+    pub fn attacked_squares(&self, colour: PieceColour) -> u64 {
+        match self.attacked {
+            Some(attacked) => attacked[colour_idx(colour)],
+            None => self.piece_totals.bitboards().attacked_squares(colour),
+        }
+    }
+
+    // both sides' attacks are regenerated, as a move changes the attacks of every slider whose ray passes through the
+    // squares it changed, which finding would cost about as much as generating them
+    #[inline(always)]
+    fn gen_attacked(bitboards: &Bitboards) -> [u64; 2] {
+        [
+            bitboards.attacked_squares(PieceColour::White),
+            bitboards.attacked_squares(PieceColour::Black),
+        ]
+    }
+
     // Assumes a legal move, no legality checks are done, so no bounds checking is done here
     pub fn new_position(&self, mv: &Move) -> Self {
-        let mut new_pos = self.clone();
-        new_pos.set_en_passant_flag(mv);
-        new_pos.set_castle_flags(mv);
-        new_pos.set_king_position(mv);
+        let mut new_pos = Self {
+            attack_map: AttackMap::new(),
+            ..self.test_clone()
+        };
+        new_pos.apply_move(mv);
+        new_pos
+    }
 
+    // makes mv on this position instead of a copy, returning what unmake_move needs to take it back. Assumes a legal move
+    // like new_position
+    pub fn make_move_in_place(&mut self, mv: &Move) -> Undo {
+        self.make_move_reusing(mv, AttackMap::new())
+    }
+
+    // takes back the move (or null move) undo was returned for, it must be the last one made on this position that wasn't
+    // unmade
+    pub fn unmake_move(&mut self, undo: Undo) {
+        self.unmake_move_reusing(undo);
+    }
+
+    // same as make_move_in_place, but the new attack map is generated into attack_map, reusing its allocation
+    pub(crate) fn make_move_reusing(&mut self, mv: &Move, attack_map: AttackMap) -> Undo {
+        // every square the move can change, unused entries repeat mv.from so restoring them is harmless
+        let changed = match mv.move_type {
+            MoveType::EnPassant(ep_capture) => [mv.from, mv.to, ep_capture, mv.from],
+            MoveType::Castle(castle_mv) => [mv.from, mv.to, castle_mv.rook_from, castle_mv.rook_to],
+            _ => [mv.from, mv.to, mv.from, mv.from],
+        };
+        let undo = self.save_undo(changed, attack_map);
+        self.apply_move(mv);
+        undo
+    }
+
+    // passes the turn to the other side without moving anything (a null move) e.g. for null move pruning, undone with
+    // unmake_move. En passant is no longer possible afterwards. Passing while in check would leave the king to be
+    // captured, so None is returned and the position is unchanged
+    pub fn make_null_move(&mut self) -> Option<Undo> {
+        self.make_null_move_reusing(AttackMap::new())
+    }
+
+    // same as make_null_move, but the new attack map is generated into attack_map, reusing its allocation
+    pub(crate) fn make_null_move_reusing(&mut self, attack_map: AttackMap) -> Option<Undo> {
+        if self.in_check {
+            return None;
+        }
+        // no squares change, restoring a8 to itself is harmless
+        let undo = self.save_undo([0; 4], attack_map);
+        self.movegen_flags.en_passant = None;
+        self.movegen_flags.polyglot_en_passant = None;
+        self.toggle_side();
+        self.gen_maps();
+        Some(undo)
+    }
+
+    // the Undo for a move changing the changed squares, attack_map replaces the current attack map
+    #[inline(always)]
+    fn save_undo(&mut self, changed: [usize; 4], attack_map: AttackMap) -> Undo {
+        Undo {
+            squares: changed.map(|i| (i, self.pos64[i])),
+            movegen_flags: self.movegen_flags,
+            in_check: self.in_check,
+            check_info: self.check_info,
+            piece_totals: self.piece_totals,
+            wking_idx: self.wking_idx,
+            bking_idx: self.bking_idx,
+            attack_map: std::mem::replace(&mut self.attack_map, attack_map),
+            attacked: self.attacked,
+        }
+    }
+
+    // same as unmake_move, but the attack map of the position the move led to is returned so it can be reused
+    pub(crate) fn unmake_move_reusing(&mut self, undo: Undo) -> AttackMap {
+        for (i, square) in undo.squares {
+            self.pos64[i] = square;
+        }
+        self.toggle_side();
+        self.movegen_flags = undo.movegen_flags;
+        self.in_check = undo.in_check;
+        self.check_info = undo.check_info;
+        self.piece_totals = undo.piece_totals;
+        self.wking_idx = undo.wking_idx;
+        self.bking_idx = undo.bking_idx;
+        self.attacked = undo.attacked;
+        self.debug_assert_piece_totals();
+        std::mem::replace(&mut self.attack_map, undo.attack_map)
+    }
+
+    // makes mv on the position it is legal in, then regenerates the maps for the other side
+    fn apply_move(&mut self, mv: &Move) {
+        self.set_en_passant_flag(mv);
+        self.set_castle_flags(mv);
+        self.set_king_position(mv);
+        self.piece_totals = self.piece_totals.after_move(mv);
+        self.move_pieces(mv);
+
+        self.toggle_side();
+        self.gen_maps();
+        self.debug_assert_piece_totals();
+    }
+
+    // moves the pieces of mv on pos64 only, the side to move must be the side of the moving piece
+    #[inline(always)]
+    fn move_pieces(&mut self, mv: &Move) {
         match mv.move_type {
             MoveType::EnPassant(ep_capture) => {
                 // en passant, 'to' square is different from the captured square
-                new_pos.pos64[ep_capture] = Square::Empty;
+                self.pos64[ep_capture] = Square::Empty;
             }
             MoveType::Castle(castle_mv) => {
                 if castle_mv.rook_from != castle_mv.rook_to {
-                    new_pos.pos64[castle_mv.rook_to] = Square::Piece(Piece {
+                    self.pos64[castle_mv.rook_to] = Square::Piece(Piece {
                         pcolour: self.side,
                         ptype: PieceType::Rook,
                     });
-                    new_pos.pos64[castle_mv.rook_from] = Square::Empty;
+                    self.pos64[castle_mv.rook_from] = Square::Empty;
                 }
 
                 if mv.from != mv.to {
-                    new_pos.pos64[mv.to] = Square::Piece(Piece {
+                    self.pos64[mv.to] = Square::Piece(Piece {
                         pcolour: self.side,
                         ptype: PieceType::King,
                     });
                     if castle_mv.rook_to != mv.from {
-                        new_pos.pos64[mv.from] = Square::Empty;
+                        self.pos64[mv.from] = Square::Empty;
                     }
                 }
-                new_pos.toggle_side();
-                new_pos.gen_maps();
-                return new_pos;
+                return;
             }
-            MoveType::Promotion(ptype, _) => match &mut new_pos.pos64[mv.from] {
+            MoveType::Promotion(ptype, _) => match &mut self.pos64[mv.from] {
                 Square::Piece(p) => {
                     p.ptype = ptype;
                 }
@@ -468,12 +996,27 @@ impl Position {
             _ => {}
         }
 
-        new_pos.pos64[mv.to] = new_pos.pos64[mv.from];
-        new_pos.pos64[mv.from] = Square::Empty;
+        self.pos64[mv.to] = self.pos64[mv.from];
+        self.pos64[mv.from] = Square::Empty;
+    }
 
-        new_pos.toggle_side();
-        new_pos.gen_maps();
-        new_pos
+    // incremental totals must always match a full recount of the board
+    #[cfg(debug_assertions)]
+    fn debug_assert_piece_totals(&self) {
+        assert_eq!(
+            self.piece_totals,
+            PieceTotals::from_pos64(&self.pos64),
+            "incremental piece totals don't match the board"
+        );
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn debug_assert_piece_totals(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn piece_totals(&self) -> &PieceTotals {
+        &self.piece_totals
     }
 
     #[inline(always)]
@@ -483,16 +1026,20 @@ impl Position {
 
     #[inline(always)]
     fn update_king_idx(&mut self) {
-        for (i, s) in self.pos64.iter().enumerate() {
-            if let Square::Piece(p) = s {
-                if p.ptype == PieceType::King {
-                    if p.pcolour == PieceColour::White {
-                        self.wking_idx = i;
-                    } else {
-                        self.bking_idx = i;
-                    }
-                }
-            }
+        let white_king = self.piece_totals.squares(Piece {
+            pcolour: PieceColour::White,
+            ptype: PieceType::King,
+        });
+        let black_king = self.piece_totals.squares(Piece {
+            pcolour: PieceColour::Black,
+            ptype: PieceType::King,
+        });
+        // positions without a king leave the index unchanged
+        if white_king != 0 {
+            self.wking_idx = white_king.trailing_zeros() as usize;
+        }
+        if black_king != 0 {
+            self.bking_idx = black_king.trailing_zeros() as usize;
         }
     }
 
@@ -514,81 +1061,120 @@ impl Position {
             pos64: self.pos64,
             side: self.side,
             in_check: self.in_check,
+            check_info: self.check_info,
             movegen_flags: self.movegen_flags,
             // create new attack map with empty vec, because it's not needed for testing legality.
             attack_map: AttackMap::new_no_alloc(),
+            piece_totals: self.piece_totals,
             wking_idx: self.wking_idx,
             bking_idx: self.bking_idx,
+            attacked: self.attacked,
         }
     }
 
-    pub fn is_move_legal(&self, mv: &Move) -> bool {
-        if mv.piece.ptype == PieceType::King {
-            if let MoveType::Castle(castle_mv) = mv.move_type {
+    // whether mv checks the opponent's king, found from the square sets after the move without generating the new
+    // position's maps. Discovered checks are covered too, including through the en passant captured pawn, and checks from
+    // the rook when castling
+    pub fn gives_check(&self, mv: &Move) -> bool {
+        let bitboards = *self.piece_totals.after_move(mv).bitboards();
+        movegen_in_check(&bitboards, self.king_idx(!self.side), !self.side)
+    }
+
+    // movegen flags of the position after mv is made, without generating the new position's maps
+    pub(crate) fn next_movegen_flags(&self, mv: &Move) -> MovegenFlags {
+        let mut test_pos = self.test_clone();
+        test_pos.set_en_passant_flag(mv);
+        test_pos.set_castle_flags(mv);
+        test_pos.movegen_flags
+    }
+
+    pub(crate) fn is_move_legal(&self, mv: PackedMove) -> bool {
+        if let Some(legal) = self.pin_move_legality(mv) {
+            self.debug_assert_move_legality(mv, legal);
+            return legal;
+        }
+        self.test_move_legality(mv)
+    }
+
+    // legality of a move from the pins and checks found in gen_maps, without making it. King moves, castling,
+    // en passant and moves of pinned pieces can expose the king in ways the masks don't show, so they return None
+    #[inline(always)]
+    fn pin_move_legality(&self, mv: PackedMove) -> Option<bool> {
+        if mv.ptype() == PieceType::King
+            || mv.is_castle()
+            || mv.en_passant_capture().is_some()
+            || self.check_info.pinned & (1 << mv.from()) != 0
+        {
+            return None;
+        }
+        Some(self.check_info.check_mask & (1 << mv.to()) != 0)
+    }
+
+    // moves classified from the pins and checks must agree with testing them on the board
+    #[cfg(debug_assertions)]
+    fn debug_assert_move_legality(&self, mv: PackedMove, legal: bool) {
+        assert_eq!(
+            legal,
+            self.test_move_legality(mv),
+            "pin and check legality of {:?} is wrong in {}",
+            mv.unpack(),
+            FEN::from(self)
+        );
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn debug_assert_move_legality(&self, _mv: PackedMove, _legal: bool) {}
+
+    // whether the king is attacked after the move, found from the square sets with the moving pieces taken off their
+    // squares and put on the new ones
+    fn test_move_legality(&self, mv: PackedMove) -> bool {
+        let bitboards = self.piece_totals.bitboards();
+        let enemy = !self.side;
+        let (from, to) = (mv.from(), mv.to());
+        if mv.is_castle() {
+            if let MoveType::Castle(castle_mv) = mv.move_type() {
                 // can't castle out of check
                 if self.in_check {
                     return false;
                 }
 
-                let king_square = Square::Piece(Piece {
-                    pcolour: self.side,
-                    ptype: PieceType::King,
-                });
-
-                let rook_square = Square::Piece(Piece {
-                    pcolour: self.side,
-                    ptype: PieceType::Rook,
-                });
-
+                // the squares the king passes through can't be attacked, seen without the king on its starting square.
                 // range ignoring starting king square as it is checked above
-                let king_squares = if mv.from < mv.to {
-                    // mv.to +1 for inclusive range while having same type in both branches
-                    mv.from + 1..mv.to + 1
+                let occupied = bitboards.occupied() & !(1 << from);
+                let mut king_squares = if from < to {
+                    // to +1 for inclusive range while having same type in both branches
+                    from + 1..to + 1
                 } else {
-                    // non inclusive range ignoring mv.from
-                    mv.to..mv.from
+                    // non inclusive range ignoring from
+                    to..from
                 };
-
-                let mut test_pos = self.test_clone();
-
-                if mv.from != mv.to {
-                    for i in king_squares {
-                        test_pos.pos64[i] = king_square;
-                        let last_king_idx = test_pos.get_king_idx();
-                        test_pos.pos64[last_king_idx] = Square::Empty;
-                        test_pos.set_king_idx(i);
-
-                        if movegen_in_check(&test_pos.pos64, i, self.side) {
-                            return false;
-                        }
-                    }
+                if king_squares.any(|i| bitboards.attackers_to_with(i, enemy, occupied) != 0) {
+                    return false;
                 }
 
                 // only needed for chess960 positions where moving your rook can open a discovered check
                 // example position: (wKe1, wRb1, bKe8, bRa1) white castles a-side (long)
-                test_pos.pos64[castle_mv.rook_to] = rook_square;
-                if castle_mv.rook_from != mv.to {
-                    test_pos.pos64[castle_mv.rook_from] = Square::Empty;
-                }
-                if movegen_in_check(&test_pos.pos64, test_pos.get_king_idx(), self.side) {
-                    return false;
-                }
-
-                return true;
+                let occupied =
+                    occupied & !(1 << castle_mv.rook_from) | 1 << to | 1 << castle_mv.rook_to;
+                return bitboards.attackers_to_with(to, enemy, occupied) == 0;
             }
         }
 
-        let mut test_pos = self.test_clone();
-        test_pos.set_king_position(mv);
-
-        if let MoveType::EnPassant(ep_capture) = mv.move_type {
-            test_pos.pos64[ep_capture] = Square::Empty;
+        let mut occupied = bitboards.occupied() & !(1 << from) | 1 << to;
+        // captured pieces can't attack, en passant captures a pawn that isn't on the to square
+        let mut captured = 1 << to;
+        if let Some(ep_capture) = mv.en_passant_capture() {
+            occupied &= !(1 << ep_capture);
+            captured |= 1 << ep_capture;
         }
-
-        test_pos.pos64[mv.to] = test_pos.pos64[mv.from];
-        test_pos.pos64[mv.from] = Square::Empty;
-
-        !movegen_in_check(&test_pos.pos64, test_pos.get_king_idx(), self.side)
+        // side to move is always the side of the moving piece
+        let king_idx = if mv.ptype() == PieceType::King {
+            to
+        } else {
+            self.get_king_idx()
+        };
+        bitboards.attackers_to_with(king_idx, enemy, occupied) & !captured == 0
     }
 
     #[inline(always)]
@@ -602,20 +1188,29 @@ impl Position {
 
     #[inline(always)]
     fn get_king_idx(&self) -> usize {
-        if self.side == PieceColour::White {
+        self.king_idx(self.side)
+    }
+
+    #[inline(always)]
+    pub fn king_idx(&self, colour: PieceColour) -> usize {
+        if colour == PieceColour::White {
             self.wking_idx
         } else {
             self.bking_idx
         }
     }
 
-    #[inline(always)]
-    fn set_king_idx(&mut self, idx: usize) {
-        if self.side == PieceColour::White {
-            self.wking_idx = idx;
-        } else {
-            self.bking_idx = idx;
-        }
+    // the square behind the pawn that just made a double push, where a pawn capturing it en passant lands. Set after
+    // every double push like the FEN en passant field, even when no pawn can capture it
+    pub fn en_passant_square(&self) -> Option<usize> {
+        // the pawn belongs to the side that isn't to move, and its square behind is towards its own back rank
+        self.movegen_flags.en_passant.map(|idx| {
+            if self.side == PieceColour::White {
+                idx - 8
+            } else {
+                idx + 8
+            }
+        })
     }
 
     #[inline(always)]
@@ -623,15 +1218,83 @@ impl Position {
         self.in_check
     }
 
-    pub fn get_pseudo_legal_moves(&self) -> &Vec<Move> {
+    #[inline(always)]
+    pub fn is_square_attacked(&self, idx: usize, by: PieceColour) -> bool {
+        self.piece_totals.bitboards().attackers_to(idx, by) != 0
+    }
+
+    // every piece of colour 'by' attacking the square at idx, as (index, piece) pairs in board order
+    pub fn attackers_to(&self, idx: usize, by: PieceColour) -> Vec<(usize, Piece)> {
+        square_set_indexes(self.piece_totals.bitboards().attackers_to(idx, by))
+            .filter_map(|i| self.pos64.piece_at(i).map(|p| (i, p)))
+            .collect()
+    }
+
+    pub(crate) fn get_pseudo_legal_moves(&self) -> &[PackedMove] {
         &self.attack_map.0
     }
 
-    pub fn get_legal_moves(&self) -> Vec<&Move> {
+    // legal moves of the side to move's piece on idx, only generating moves for that piece. Empty for empty squares, the
+    // opponent's pieces and indexes off the board
+    pub fn moves_from(&self, idx: usize) -> Vec<Move> {
+        let mut moves = AttackMap::new_no_alloc();
+        match self.pos64.get(idx) {
+            Some(Square::Piece(p)) if p.pcolour == self.side => movegen(
+                &self.pos64,
+                self.piece_totals.bitboards(),
+                &self.movegen_flags,
+                *p,
+                idx,
+                &mut moves,
+            ),
+            _ => return Vec::new(),
+        }
+        moves
+            .0
+            .into_iter()
+            .filter(|mv| self.is_move_legal(*mv))
+            .map(|mv| mv.unpack())
+            .collect()
+    }
+
+    // the legal move short_mv was made from, matched the same way as PartialEq<ShortMove> for Move. A short move without a
+    // promotion piece matches every promotion on its squares, the queen promotion is returned for it. None if no legal
+    // move matches e.g. a transposition table move stored for another position
+    pub fn expand_short_move(&self, short_mv: ShortMove) -> Option<Move> {
+        let mut found = None;
+        for mv in &self.attack_map.0 {
+            if *mv == short_mv && self.is_move_legal(*mv) {
+                found = Some(*mv);
+                if mv
+                    .promotion_ptype()
+                    .is_none_or(|ptype| ptype == PieceType::Queen)
+                {
+                    break;
+                }
+            }
+        }
+        found.map(|mv| mv.unpack())
+    }
+
+    // number of legal moves, without allocating or unpacking them
+    pub(crate) fn count_legal_moves(&self) -> usize {
+        self.attack_map
+            .0
+            .iter()
+            .filter(|mv| self.is_move_legal(**mv))
+            .count()
+    }
+
+    // whether any legal move exists, stopping at the first one found
+    pub(crate) fn has_legal_move(&self) -> bool {
+        self.attack_map.0.iter().any(|mv| self.is_move_legal(*mv))
+    }
+
+    pub fn get_legal_moves(&self) -> Vec<Move> {
         let mut legal_moves = Vec::with_capacity(self.attack_map.0.len());
         for mv in &self.attack_map.0 {
-            if self.is_move_legal(mv) {
-                legal_moves.push(mv);
+            if self.is_move_legal(*mv) {
+                legal_moves.push(mv.unpack());
             }
         }
         legal_moves
@@ -650,6 +1313,7 @@ impl Position {
             self.movegen_flags.en_passant = Some(mv.to);
         } else {
             self.movegen_flags.en_passant = None;
+            self.movegen_flags.polyglot_en_passant = None;
         }
     }
 
@@ -688,23 +1352,761 @@ impl Position {
 
     pub(crate) fn gen_maps(&mut self) {
         self.attack_map.clear();
+        if self.attacked.is_some() {
+            self.attacked = Some(Self::gen_attacked(self.piece_totals.bitboards()));
+        }
 
         let pos64 = &self.pos64;
+        let bitboards = self.piece_totals.bitboards();
         let movegen_flags = &self.movegen_flags;
-        for (i, s) in pos64.iter().enumerate() {
-            if let Square::Piece(p) = s {
-                if p.pcolour != self.side {
-                    continue;
-                }
-                movegen(pos64, movegen_flags, *p, i, &mut self.attack_map);
+        for i in square_set_indexes(bitboards.colour(self.side)) {
+            if let Square::Piece(p) = pos64[i] {
+                movegen(pos64, bitboards, movegen_flags, p, i, &mut self.attack_map);
             }
         }
-        self.in_check = movegen_in_check(&self.pos64, self.get_king_idx(), self.side);
+        let king_idx = self.get_king_idx();
+        let king = Square::Piece(Piece {
+            pcolour: self.side,
+            ptype: PieceType::King,
+        });
+        if self.pos64[king_idx] == king {
+            self.check_info =
+                movegen_check_info(self.piece_totals.bitboards(), king_idx, self.side);
+            self.in_check = self.check_info.checkers > 0;
+        } else {
+            // positions without a king can't use pins, so every move is tested on the board
+            self.check_info = CheckInfo::all_pinned();
+            self.in_check = movegen_in_check(self.piece_totals.bitboards(), king_idx, self.side);
+        }
+    }
+}
+
+impl TryFrom<FEN> for Position {
+    type Error = PositionError;
+
+    fn try_from(fen: FEN) -> Result<Self, Self::Error> {
+        let pos = Self::new_from_pub_parts(fen.pos64(), fen.side(), fen.movegen_flags());
+        pos.validate()?;
+        Ok(pos)
     }
 }
 
-impl From<FEN> for Position {
-    fn from(fen: FEN) -> Self {
-        Self::new_from_pub_parts(fen.pos64(), fen.side(), fen.movegen_flags())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::FenParseError;
+    use crate::perft::{get_all_legal_positions, PerftNodes};
+
+    #[test]
+    fn test_piece_totals_starting_position() {
+        let pos = Position::new_starting();
+        let totals = pos.piece_totals();
+        assert_eq!(totals.phase(), 24);
+        assert_eq!(
+            totals.material(PieceColour::White),
+            totals.material(PieceColour::Black)
+        );
+        assert_eq!(
+            totals.mg_pst(PieceColour::White),
+            totals.mg_pst(PieceColour::Black)
+        );
+        let black_pawns = Piece {
+            pcolour: PieceColour::Black,
+            ptype: PieceType::Pawn,
+        };
+        assert_eq!(totals.squares(black_pawns), 0xff00);
+        assert_eq!(
+            square_set_indexes(totals.squares(black_pawns)).collect::<Vec<_>>(),
+            (8..16).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_pos64_pieces() {
+        let pos64 = Position::new_starting().pos64;
+        assert_eq!(pos64.pieces().count(), 32);
+        assert_eq!(pos64.pieces_of(PieceColour::Black).count(), 16);
+        assert!(pos64
+            .pieces_of(PieceColour::White)
+            .all(|(i, p)| p.pcolour == PieceColour::White && pos64.piece_at(i) == Some(p)));
+        assert_eq!(
+            pos64
+                .piece_squares(PieceColour::Black, PieceType::Rook)
+                .collect::<Vec<usize>>(),
+            [0, 7]
+        );
+        assert_eq!(
+            pos64
+                .piece_squares(PieceColour::White, PieceType::King)
+                .collect::<Vec<usize>>(),
+            [60]
+        );
+        assert_eq!(pos64.piece_at(32), None);
+        assert_eq!(pos64.piece_at(64), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        // FEN parsing validates the position, so take its error
+        let validate = |fen: &str| match fen.parse::<FEN>() {
+            Ok(fen) => Position::try_from(fen),
+            Err(FenParseError::InvalidPosition(e)) => Err(e),
+            Err(e) => panic!("{}", e),
+        };
+        // FEN parsing already rejects the wrong number of kings, so remove one from a valid position
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap();
+        let mut pos64 = fen.pos64();
+        pos64[60] = Square::Empty;
+        let pos = Position::new_from_pub_parts(pos64, fen.side(), fen.movegen_flags());
+        assert!(matches!(pos.validate(), Err(PositionError::KingCount(_))));
+        assert!(matches!(
+            validate("4k3/8/8/8/8/NNN5/PPPPPPPP/RNBQKBNR w KQ - 0 1"),
+            Err(PositionError::TooManyPieces(_))
+        ));
+        assert!(matches!(
+            validate("4k3/8/8/8/8/P7/PPPPPPPP/4K3 w - - 0 1"),
+            Err(PositionError::TooManyPawns(_))
+        ));
+        assert!(matches!(
+            validate("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"),
+            Err(PositionError::PawnOnBackRank(_))
+        ));
+        assert!(matches!(
+            validate("4k3/8/8/8/8/8/8/p3K3 w - - 0 1"),
+            Err(PositionError::PawnOnBackRank(_))
+        ));
+        assert!(matches!(
+            validate("8/8/8/3kK3/8/8/8/8 w - - 0 1"),
+            Err(PositionError::KingsAdjacent(_))
+        ));
+        assert!(matches!(
+            validate("4k3/8/8/8/8/8/8/4K2r b - - 0 1"),
+            Err(PositionError::OpponentInCheck(_))
+        ));
+        // the side to move being in check is fine
+        assert!(validate("4k3/8/8/8/8/8/8/4K2r w - - 0 1").is_ok());
+
+        // unusual but legal, nine queens after promoting every pawn and a double check
+        let pos = validate("QQQQkQQQ/8/8/8/8/8/8/QK6 b - - 0 1").unwrap();
+        assert!(pos.is_in_check());
+        assert!(validate("3k4/8/8/8/8/5n2/8/3qK3 w - - 0 1").is_ok());
+
+        // conversions to BoardState and Board give the same error, for a FEN of a position that wasn't validated
+        let fen = "8/8/8/3k4/8/8/8/4K3 w - - 0 1".parse::<FEN>().unwrap();
+        let mut pos64 = fen.pos64();
+        // move the white king next to the black one
+        pos64[36] = pos64[60];
+        pos64[60] = Square::Empty;
+        let fen = FEN::from(&Position::new_from_pub_parts(
+            pos64,
+            fen.side(),
+            fen.movegen_flags(),
+        ));
+        assert!(matches!(
+            crate::board::BoardState::try_from(fen),
+            Err(PositionError::KingsAdjacent(_))
+        ));
+        assert!(matches!(
+            crate::board::Board::try_from(fen),
+            Err(PositionError::KingsAdjacent(_))
+        ));
+    }
+
+    #[test]
+    fn test_mirrored() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let pos = Position::try_from(entry.fen).unwrap();
+            let mirrored = pos.mirrored();
+            assert_eq!(mirrored.mirrored(), pos);
+            assert_eq!(mirrored.is_in_check(), pos.is_in_check());
+            // every move has a mirrored move, in the mirrored position
+            let mut moves: Vec<(usize, usize)> = pos
+                .get_legal_moves()
+                .iter()
+                .map(|mv| (mv.from ^ 56, mv.to ^ 56))
+                .collect();
+            let mut mirrored_moves: Vec<(usize, usize)> = mirrored
+                .get_legal_moves()
+                .iter()
+                .map(|mv| (mv.from, mv.to))
+                .collect();
+            moves.sort();
+            mirrored_moves.sort();
+            assert_eq!(moves, mirrored_moves, "{}", FEN::from(&pos));
+        }
+
+        let mirrored_fen = |fen: &str| {
+            FEN::from(
+                &Position::try_from(fen.parse::<FEN>().unwrap())
+                    .unwrap()
+                    .mirrored(),
+            )
+            .to_string()
+        };
+        assert_eq!(
+            mirrored_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1"
+        );
+        assert_eq!(
+            mirrored_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1"),
+            "r3k2r/8/8/8/8/8/8/R3K2R b Qk - 0 1"
+        );
+        assert_eq!(
+            mirrored_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w Gb - 0 1"),
+            "1r2k1r1/8/8/8/8/8/8/1R2K1R1 b Qk - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_piece_totals_incremental() {
+        // making and unmaking a move recounts the totals in debug builds and panics on a mismatch, so walking the tree
+        // covers captures, en passant, promotions and (chess960) castling
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+            "nbbqrnkr/pppppppp/8/8/8/5N2/PPPPPPPP/NBBQR1KR w KQkq - 0 1",
+            "qnrbbk1r/pppppppp/8/8/8/8/PPPPPPPP/QNRBBK1R w KQkq - 0 1",
+        ];
+        for fen in fens {
+            let mut pos = Position::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            get_all_legal_positions(&mut pos, 3, &mut PerftNodes::default());
+        }
+    }
+
+    // walks every position to depth 2 from each FEN, checking the allocation free counts against get_legal_moves
+    fn assert_legal_move_counts(pos: &Position, depth: u8) {
+        let legal_moves = pos.get_legal_moves();
+        assert_eq!(
+            pos.count_legal_moves(),
+            legal_moves.len(),
+            "{}",
+            FEN::from(pos)
+        );
+        assert_eq!(
+            pos.has_legal_move(),
+            !legal_moves.is_empty(),
+            "{}",
+            FEN::from(pos)
+        );
+        if depth > 0 {
+            for mv in &legal_moves {
+                assert_legal_move_counts(&pos.new_position(mv), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_legal_moves() {
+        let mut fens = crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd"))
+            .unwrap()
+            .iter()
+            .map(|entry| entry.fen)
+            .collect::<Vec<FEN>>();
+        // no legal moves: checkmate, stalemate and a smothered mate
+        for fen in [
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+            "6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1",
+        ] {
+            let fen = fen.parse::<FEN>().unwrap();
+            let pos = Position::try_from(fen).unwrap();
+            assert_eq!(pos.count_legal_moves(), 0);
+            assert!(!pos.has_legal_move());
+            fens.push(fen);
+        }
+        for fen in fens {
+            assert_legal_move_counts(&Position::try_from(fen).unwrap(), 2);
+        }
+    }
+
+    fn attacker_squares(fen: &str, square: &str, by: PieceColour) -> Vec<usize> {
+        let pos = Position::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        let idx = crate::util::square_index(square).unwrap();
+        let attackers = pos.attackers_to(idx, by);
+        assert_eq!(pos.is_square_attacked(idx, by), !attackers.is_empty());
+        attackers.iter().map(|(i, _)| *i).collect()
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        let idx = |square| crate::util::square_index(square).unwrap();
+        // pawns only attack diagonally forwards, d5 is attacked by the c4 pawn but not the d4 or e6 pawns
+        let fen = "4k3/8/4P3/8/2PP4/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            attacker_squares(fen, "d5", PieceColour::White),
+            vec![idx("c4")]
+        );
+        assert!(attacker_squares(fen, "d5", PieceColour::Black).is_empty());
+        assert_eq!(
+            attacker_squares(fen, "d7", PieceColour::White),
+            vec![idx("e6")]
+        );
+        // knights jump over pieces, squares count as attacked whatever is on them
+        let fen = "4k3/8/8/8/3p4/2ppp3/3p1N2/4K3 w - - 0 1";
+        assert_eq!(
+            attacker_squares(fen, "d3", PieceColour::White),
+            vec![idx("f2")]
+        );
+        assert_eq!(
+            attacker_squares(fen, "e2", PieceColour::Black),
+            vec![idx("d3")]
+        );
+        // sliders are blocked by the first piece in the way, of either colour
+        let fen = "4k3/8/8/1B6/8/3P4/8/R2K1Q1r b - - 0 1";
+        assert_eq!(
+            attacker_squares(fen, "e1", PieceColour::White),
+            vec![idx("d1"), idx("f1")]
+        );
+        assert_eq!(
+            attacker_squares(fen, "a8", PieceColour::White),
+            vec![idx("a1")]
+        );
+        assert!(attacker_squares(fen, "f1", PieceColour::Black).contains(&idx("h1")));
+        assert!(!attacker_squares(fen, "e1", PieceColour::Black).contains(&idx("h1")));
+        assert!(!attacker_squares(fen, "e2", PieceColour::White).contains(&idx("b5")));
+        assert!(attacker_squares(fen, "e8", PieceColour::White).contains(&idx("b5")));
+        // the d3 pawn blocks the f1 queen's diagonal through e2
+        assert!(!attacker_squares(fen, "c4", PieceColour::White).contains(&idx("f1")));
+        assert!(attacker_squares(fen, "e2", PieceColour::White).contains(&idx("f1")));
+        // kings attack adjacent squares, e8 is attacked by the e2 rook through the open file
+        let fen = "4k3/8/8/8/8/8/4R3/4K3 b - - 0 1";
+        assert_eq!(
+            attacker_squares(fen, "d2", PieceColour::White),
+            vec![idx("e2"), idx("e1")]
+        );
+        assert_eq!(
+            attacker_squares(fen, "e8", PieceColour::White),
+            vec![idx("e2")]
+        );
+        assert!(attacker_squares(fen, "c3", PieceColour::White).is_empty());
+        let pos = Position::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        assert!(pos.is_in_check());
+        assert!(pos.is_square_attacked(idx("d7"), PieceColour::Black));
+    }
+
+    // attacked squares found by probing every square with is_square_attacked
+    fn assert_attacked_squares(pos: &Position) {
+        for colour in [PieceColour::White, PieceColour::Black] {
+            let probed = (0..64)
+                .filter(|&i| pos.is_square_attacked(i, colour))
+                .fold(0u64, |attacked, i| attacked | 1 << i);
+            assert_eq!(pos.attacked_squares(colour), probed, "{}", FEN::from(pos));
+        }
+    }
+
+    #[test]
+    fn test_attacked_squares() {
+        // every square on the second and third ranks, and the first rank except the corners
+        let pos = Position::new_starting();
+        assert_eq!(pos.attacked_squares(PieceColour::White).count_ones(), 22);
+        assert_attacked_squares(&pos);
+
+        // the tracked attacks stay up to date through en passant, castling and promotion, and are restored by unmake
+        let mut pos = Position::try_from(
+            "r3k2r/pppp1ppp/8/8/4p3/8/PPPPPPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap()
+        .with_attack_tracking();
+        assert_attacked_squares(&pos);
+        let mut history = Vec::new();
+        for mv_str in [
+            "d2d4", "e4d3", "e1g1", "e8c8", "a2a3", "d3c2", "a3a4", "c2c1q",
+        ] {
+            let mv = pos
+                .get_legal_moves()
+                .into_iter()
+                .find(|mv| mv.to_string() == mv_str)
+                .unwrap();
+            let before = pos.clone();
+            history.push((before, pos.make_move_in_place(&mv)));
+            assert_attacked_squares(&pos);
+            for child_mv in pos.get_legal_moves() {
+                assert_attacked_squares(&pos.new_position(&child_mv));
+            }
+        }
+        assert_attacked_squares(&pos.mirrored());
+        while let Some((before, undo)) = history.pop() {
+            pos.unmake_move(undo);
+            assert_eq!(pos, before);
+            assert_attacked_squares(&pos);
+        }
+    }
+
+    #[test]
+    fn test_is_square_attacked_matches_attackers_to() {
+        // is_square_attacked probes outward from the square while attackers_to scans every piece, so they should agree on
+        // every square of every position one move from the perft suite
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let root = Position::try_from(entry.fen).unwrap();
+            for mv in root.get_legal_moves() {
+                let pos = root.new_position(&mv);
+                for idx in 0..64 {
+                    for by in [PieceColour::White, PieceColour::Black] {
+                        assert_eq!(
+                            pos.is_square_attacked(idx, by),
+                            !pos.attackers_to(idx, by).is_empty(),
+                            "{} {} {:?}",
+                            FEN::from(&pos),
+                            idx,
+                            by
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_info() {
+        let squares = |squares: &[&str]| {
+            squares.iter().fold(0u64, |set, square| {
+                set | 1 << crate::util::square_index(square).unwrap()
+            })
+        };
+        let check_info = |fen: &str| {
+            Position::try_from(fen.parse::<FEN>().unwrap())
+                .unwrap()
+                .check_info
+        };
+
+        // the e2 bishop is pinned by the e8 rook and the d2 knight by the b4 queen
+        let info = check_info("4r1k1/8/8/8/1q6/8/3NB3/4K3 w - - 0 1");
+        assert_eq!(info.pinned, squares(&["e2", "d2"]));
+        assert_eq!(info.checkers, 0);
+        assert_eq!(info.check_mask, u64::MAX);
+        // two own pieces on the ray means neither is pinned
+        let info = check_info("4r1k1/8/8/8/8/4N3/4B3/4K3 w - - 0 1");
+        assert_eq!(info.pinned, 0);
+        // sliding check can be blocked or captured, pawn and knight checks can only be captured
+        let info = check_info("4k3/8/8/8/1b6/8/8/4K3 w - - 0 1");
+        assert_eq!(info.checkers, 1);
+        assert_eq!(info.check_mask, squares(&["b4", "c3", "d2"]));
+        let info = check_info("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1");
+        assert_eq!(info.check_mask, squares(&["d2"]));
+        let info = check_info("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1");
+        assert_eq!(info.check_mask, squares(&["f3"]));
+        // only the king can move out of double check
+        let info = check_info("4k3/8/8/8/1b6/8/8/4K2r w - - 0 1");
+        assert_eq!(info.checkers, 2);
+        assert_eq!(info.check_mask, 0);
+    }
+
+    fn assert_gives_check(pos: &Position, depth: u8) {
+        for mv in pos.get_legal_moves() {
+            let new_pos = pos.new_position(&mv);
+            assert_eq!(
+                pos.gives_check(&mv),
+                new_pos.is_in_check(),
+                "{:?} in {}",
+                mv,
+                FEN::from(pos)
+            );
+            if depth > 1 {
+                assert_gives_check(&new_pos, depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gives_check() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            assert_gives_check(&Position::try_from(entry.fen).unwrap(), 2);
+        }
+
+        let gives_check = |fen: &str, from: &str, to: &str, promotion_ptype: Option<PieceType>| {
+            let pos = Position::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+            let short_mv = ShortMove {
+                from: crate::util::square_index(from).unwrap() as u8,
+                to: crate::util::square_index(to).unwrap() as u8,
+                promotion_ptype,
+            };
+            let mv = pos
+                .get_legal_moves()
+                .into_iter()
+                .find(|mv| mv.short_move() == short_mv)
+                .unwrap();
+            pos.gives_check(&mv)
+        };
+        // direct
+        assert!(gives_check(
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "a1",
+            "a8",
+            None
+        ));
+        assert!(!gives_check(
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "a1",
+            "a2",
+            None
+        ));
+        // discovered, by a knight and by the king stepping off the file
+        assert!(gives_check(
+            "4k3/8/8/8/8/4N3/8/4R1K1 w - - 0 1",
+            "e3",
+            "c4",
+            None
+        ));
+        assert!(gives_check(
+            "4k3/8/8/8/8/8/4K3/4R3 w - - 0 1",
+            "e2",
+            "d3",
+            None
+        ));
+        assert!(!gives_check(
+            "4k3/8/8/8/8/8/4K3/4R3 w - - 0 1",
+            "e2",
+            "e3",
+            None
+        ));
+        // en passant removes both pawns from the rook's rank
+        assert!(gives_check(
+            "8/8/8/k2pP2R/8/8/8/4K3 w - d6 0 2",
+            "e5",
+            "d6",
+            None
+        ));
+        assert!(!gives_check(
+            "8/8/8/k2pP2R/8/8/8/4K3 w - d6 0 2",
+            "e5",
+            "e6",
+            None
+        ));
+        // promotion checks depend on the piece promoted to
+        assert!(gives_check(
+            "1k6/4P3/8/8/8/8/8/4K3 w - - 0 1",
+            "e7",
+            "e8",
+            Some(PieceType::Queen)
+        ));
+        assert!(!gives_check(
+            "1k6/4P3/8/8/8/8/8/4K3 w - - 0 1",
+            "e7",
+            "e8",
+            Some(PieceType::Knight)
+        ));
+        assert!(gives_check(
+            "8/4P3/3k4/8/8/8/8/4K3 w - - 0 1",
+            "e7",
+            "e8",
+            Some(PieceType::Knight)
+        ));
+        assert!(!gives_check(
+            "8/4P3/3k4/8/8/8/8/4K3 w - - 0 1",
+            "e7",
+            "e8",
+            Some(PieceType::Queen)
+        ));
+        // the castling rook checks
+        assert!(gives_check(
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "e1",
+            "g1",
+            None
+        ));
+        assert!(gives_check(
+            "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            "e1",
+            "c1",
+            None
+        ));
+        assert!(!gives_check(
+            "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            "e1",
+            "c1",
+            None
+        ));
+    }
+
+    #[test]
+    fn test_moves_from() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let pos = Position::try_from(entry.fen).unwrap();
+            let legal_moves = pos.get_legal_moves();
+            for idx in 0..64 {
+                let from_idx: Vec<Move> = legal_moves
+                    .iter()
+                    .filter(|mv| mv.from == idx)
+                    .copied()
+                    .collect();
+                assert_eq!(pos.moves_from(idx), from_idx);
+            }
+        }
+
+        let to_squares = |pos: &Position, square: &str| {
+            let mut squares: Vec<String> = pos
+                .moves_from(crate::util::square_index(square).unwrap())
+                .iter()
+                .map(|mv| mv.to_square_name().unwrap())
+                .collect();
+            squares.sort();
+            squares
+        };
+        // the e2 rook is pinned on the e file and the d2 knight can't move at all
+        let pos = Position::try_from(
+            "4r1k1/8/8/8/1q6/8/3NR3/4K3 w - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(to_squares(&pos, "e2"), ["e3", "e4", "e5", "e6", "e7", "e8"]);
+        assert!(to_squares(&pos, "d2").is_empty());
+        // empty squares, the opponent's pieces and off the board
+        assert!(to_squares(&pos, "e4").is_empty());
+        assert!(to_squares(&pos, "b4").is_empty());
+        assert!(pos.moves_from(64).is_empty());
+
+        // in check from the b4 bishop, the knight can only block and the king has to step off the diagonal
+        let pos = Position::try_from("4k3/8/8/8/1b6/8/8/1N2K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        assert_eq!(to_squares(&pos, "b1"), ["c3", "d2"]);
+        assert_eq!(to_squares(&pos, "e1"), ["d1", "e2", "f1", "f2"]);
+    }
+
+    #[test]
+    fn test_expand_short_move() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            let pos = Position::try_from(entry.fen).unwrap();
+            for mv in pos.get_legal_moves() {
+                assert_eq!(pos.expand_short_move(mv.short_move()), Some(mv));
+            }
+        }
+
+        let pos = Position::try_from(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P1P1P/R2Q1RK1 b kq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let short_mv = |from: usize, to: usize, promotion_ptype: Option<PieceType>| ShortMove {
+            from: from as u8,
+            to: to as u8,
+            promotion_ptype,
+        };
+        // b2a1 is ambiguous without a promotion piece, it is the queen promotion
+        assert_eq!(
+            pos.expand_short_move(short_mv(49, 56, None))
+                .unwrap()
+                .move_type,
+            MoveType::Promotion(PieceType::Queen, Some(PieceType::Rook))
+        );
+        assert_eq!(
+            pos.expand_short_move(short_mv(49, 56, Some(PieceType::Knight)))
+                .unwrap()
+                .move_type,
+            MoveType::Promotion(PieceType::Knight, Some(PieceType::Rook))
+        );
+        // a white move stored for another position, and a move that doesn't exist
+        assert_eq!(pos.expand_short_move(short_mv(52, 36, None)), None);
+        assert_eq!(pos.expand_short_move(short_mv(49, 41, None)), None);
+        assert_eq!(pos.expand_short_move(NULL_SHORT_MOVE), None);
+
+        // pseudo legal but the knight is pinned to the king
+        let pos = Position::try_from("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1".parse::<FEN>().unwrap())
+            .unwrap();
+        let pinned = short_mv(51, 36, None);
+        assert!(pos.get_pseudo_legal_moves().iter().any(|mv| *mv == pinned));
+        assert_eq!(pos.expand_short_move(pinned), None);
+    }
+
+    // makes and unmakes every move to depth, each move must lead to the same position as new_position and unmaking it
+    // must restore the position exactly
+    fn assert_make_unmake(pos: &mut Position, depth: u8) {
+        if depth == 0 {
+            return;
+        }
+        let before = pos.clone();
+        for mv in before.get_legal_moves() {
+            let undo = pos.make_move_in_place(&mv);
+            assert_eq!(
+                *pos,
+                before.new_position(&mv),
+                "{:?} in {}",
+                mv,
+                FEN::from(&before)
+            );
+            assert_make_unmake(pos, depth - 1);
+            pos.unmake_move(undo);
+            assert_eq!(*pos, before, "{:?} in {}", mv, FEN::from(&before));
+        }
+    }
+
+    #[test]
+    fn test_make_unmake_move() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            assert_make_unmake(&mut Position::try_from(entry.fen).unwrap(), 2);
+        }
+        // chess960 castling where the king lands on its own rook's square, or the rook on the king's
+        for fen in [
+            "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1",
+            "rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1",
+        ] {
+            assert_make_unmake(
+                &mut Position::try_from(fen.parse::<FEN>().unwrap()).unwrap(),
+                3,
+            );
+        }
+    }
+
+    fn assert_null_move(pos: &mut Position) {
+        let original = pos.clone();
+        if pos.in_check {
+            assert!(pos.make_null_move().is_none());
+            assert_eq!(*pos, original);
+            return;
+        }
+        let undo = pos.make_null_move().unwrap();
+        // the same as building the position for the other side to move directly, without en passant
+        let mut flags = original.movegen_flags;
+        flags.en_passant = None;
+        flags.polyglot_en_passant = None;
+        let direct = Position::new_from_pub_parts(original.pos64, !original.side, flags);
+        assert_eq!(*pos, direct);
+        assert_eq!(pos.get_legal_moves(), direct.get_legal_moves());
+        assert_eq!(
+            zobrist::null_move_hash(&original.movegen_flags, original.pos_hash()),
+            pos.pos_hash()
+        );
+
+        // passing back only loses the en passant square
+        let back_hash = zobrist::null_move_hash(&pos.movegen_flags, pos.pos_hash());
+        let undo_back = pos.make_null_move().unwrap();
+        assert_eq!(back_hash, pos.pos_hash());
+        let mut without_ep = original.clone();
+        without_ep.movegen_flags = flags;
+        assert_eq!(back_hash, without_ep.pos_hash());
+        assert_eq!(
+            back_hash == original.pos_hash(),
+            original.movegen_flags.polyglot_en_passant.is_none()
+        );
+
+        pos.unmake_move(undo_back);
+        pos.unmake_move(undo);
+        assert_eq!(*pos, original);
+    }
+
+    #[test]
+    fn test_null_move() {
+        for entry in crate::perft::parse_perft_epd(include_str!("../test_data/perft.epd")).unwrap()
+        {
+            assert_null_move(&mut Position::try_from(entry.fen).unwrap());
+        }
+        for fen in [
+            // en passant capture available
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            // in check
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+        ] {
+            assert_null_move(&mut Position::try_from(fen.parse::<FEN>().unwrap()).unwrap());
+        }
     }
 }
@@ -0,0 +1,479 @@
+use std::io::{self, Read, Write};
+
+use crate::board::{GameOverState, GameResult, GameState, ResultReason, Variant};
+use crate::errors::SaveGameError;
+use crate::log_and_return_error;
+use crate::movegen::{PieceColour, PieceType, ShortMove};
+use crate::version;
+use crate::zobrist;
+
+// native save format for a Board, which is loaded by replaying its moves from the starting position. Numbers are big
+// endian like polyglot books, strings are utf8 after their length:
+// - header: the magic "COXS", format version (u16), library version and zobrist scheme (u8 length each)
+// - variant (u8), starting FEN (u16 length)
+// - tags as a count (u16) of name and value pairs (u16 length each), e.g. White and WhiteElo
+// - moves as a count (u32) of ShortMoves packed into a u16 each: from, to (6 bits each), then the promotion
+// - game over state (u8), followed by the GameState (u8) if it was forced or a claimed draw, the side (u8) that ran
+//   out of time for a timeout, or the result and its reason (u8 each) for an adjudication
+const SAVE_MAGIC: [u8; 4] = *b"COXS";
+// bumped whenever the layout changes, files with a different version are rejected
+pub(crate) const SAVE_FORMAT_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SavedGame {
+    pub variant: Variant,
+    pub starting_fen: String,
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<ShortMove>,
+    pub game_over_state: Option<GameOverState>,
+}
+
+impl SavedGame {
+    pub fn write(&self, w: &mut impl Write) -> Result<(), SaveGameError> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&SAVE_MAGIC);
+        bytes.extend_from_slice(&SAVE_FORMAT_VERSION.to_be_bytes());
+        push_short_str(&mut bytes, version::version())?;
+        push_short_str(&mut bytes, zobrist::ZOBRIST_SCHEME)?;
+
+        bytes.push(variant_to_byte(self.variant));
+        push_str(&mut bytes, &self.starting_fen)?;
+        bytes.extend_from_slice(&count_to_u16(self.tags.len(), "tags")?.to_be_bytes());
+        for (name, value) in &self.tags {
+            push_str(&mut bytes, name)?;
+            push_str(&mut bytes, value)?;
+        }
+
+        let move_count = match u32::try_from(self.moves.len()) {
+            Ok(count) => count,
+            Err(_) => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+                "Too many moves to save: {}",
+                self.moves.len()
+            ))),
+        };
+        bytes.extend_from_slice(&move_count.to_be_bytes());
+        for mv in &self.moves {
+            bytes.extend_from_slice(&pack_short_move(mv).to_be_bytes());
+        }
+
+        match self.game_over_state {
+            None => bytes.push(0),
+            Some(GameOverState::WhiteResign) => bytes.push(1),
+            Some(GameOverState::BlackResign) => bytes.push(2),
+            Some(GameOverState::AgreedDraw) => bytes.push(3),
+            Some(GameOverState::Forced(gs)) => bytes.extend_from_slice(&[4, gamestate_to_byte(gs)]),
+            Some(GameOverState::DrawClaimed(gs)) => {
+                bytes.extend_from_slice(&[5, gamestate_to_byte(gs)])
+            }
+            Some(GameOverState::Timeout(side)) => {
+                bytes.extend_from_slice(&[6, colour_to_byte(side)])
+            }
+            Some(GameOverState::Adjudication(result)) => {
+                bytes.push(7);
+                bytes.extend_from_slice(&result_to_bytes(result));
+            }
+        }
+
+        w.write_all(&bytes).map_err(io_error)?;
+        w.flush().map_err(io_error)
+    }
+
+    pub fn read(r: &mut impl Read) -> Result<Self, SaveGameError> {
+        let mut reader = SaveReader(r);
+        if reader.bytes::<4>()? != SAVE_MAGIC {
+            log_and_return_error!(SaveGameError::InvalidFormat(
+                "Not a chess-oxide save file".to_string()
+            ))
+        }
+        let format_version = u16::from_be_bytes(reader.bytes()?);
+        let library_version = reader.short_str()?;
+        if format_version != SAVE_FORMAT_VERSION {
+            log_and_return_error!(SaveGameError::VersionMismatch(format!(
+                "Save format version {} (written by chess-oxide {}) is not supported, expected version {}",
+                format_version, library_version, SAVE_FORMAT_VERSION
+            )))
+        }
+        let zobrist_scheme = reader.short_str()?;
+        if zobrist_scheme != zobrist::ZOBRIST_SCHEME {
+            log_and_return_error!(SaveGameError::VersionMismatch(format!(
+                "Zobrist scheme {} (written by chess-oxide {}) does not match {}",
+                zobrist_scheme,
+                library_version,
+                zobrist::ZOBRIST_SCHEME
+            )))
+        }
+
+        let variant = variant_from_byte(reader.byte()?)?;
+        let starting_fen = reader.str()?;
+        let tag_count = u16::from_be_bytes(reader.bytes()?);
+        let mut tags = Vec::with_capacity(tag_count as usize);
+        for _ in 0..tag_count {
+            tags.push((reader.str()?, reader.str()?));
+        }
+
+        let move_count = u32::from_be_bytes(reader.bytes()?);
+        // capacity is capped so a corrupt count can't allocate gigabytes before the data runs out
+        let mut moves = Vec::with_capacity((move_count as usize).min(1024));
+        for _ in 0..move_count {
+            moves.push(unpack_short_move(u16::from_be_bytes(reader.bytes()?))?);
+        }
+
+        let game_over_state = match reader.byte()? {
+            0 => None,
+            1 => Some(GameOverState::WhiteResign),
+            2 => Some(GameOverState::BlackResign),
+            3 => Some(GameOverState::AgreedDraw),
+            4 => Some(GameOverState::Forced(gamestate_from_byte(reader.byte()?)?)),
+            5 => Some(GameOverState::DrawClaimed(gamestate_from_byte(
+                reader.byte()?,
+            )?)),
+            6 => Some(GameOverState::Timeout(colour_from_byte(reader.byte()?)?)),
+            7 => Some(GameOverState::Adjudication(result_from_bytes(
+                reader.bytes()?,
+            )?)),
+            b => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+                "Invalid game over state: {}",
+                b
+            ))),
+        };
+
+        Ok(Self {
+            variant,
+            starting_fen,
+            tags,
+            moves,
+            game_over_state,
+        })
+    }
+}
+
+struct SaveReader<'a, R: Read>(&'a mut R);
+
+impl<R: Read> SaveReader<'_, R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], SaveGameError> {
+        let mut buf = [0; N];
+        self.fill(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn byte(&mut self) -> Result<u8, SaveGameError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), SaveGameError> {
+        match self.0.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => log_and_return_error!(
+                SaveGameError::InvalidFormat("Unexpected end of save file".to_string())
+            ),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, SaveGameError> {
+        let mut buf = vec![0; len];
+        self.fill(&mut buf)?;
+        match String::from_utf8(buf) {
+            Ok(s) => Ok(s),
+            Err(e) => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+                "Invalid string: {}",
+                e
+            ))),
+        }
+    }
+
+    fn short_str(&mut self) -> Result<String, SaveGameError> {
+        let len = self.byte()? as usize;
+        self.string(len)
+    }
+
+    fn str(&mut self) -> Result<String, SaveGameError> {
+        let len = u16::from_be_bytes(self.bytes()?) as usize;
+        self.string(len)
+    }
+}
+
+fn io_error(e: io::Error) -> SaveGameError {
+    let err = SaveGameError::Io(e.to_string());
+    log::error!("{}", err);
+    err
+}
+
+fn count_to_u16(len: usize, what: &str) -> Result<u16, SaveGameError> {
+    match u16::try_from(len) {
+        Ok(len) => Ok(len),
+        Err(_) => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Too many {} to save: {}",
+            what, len
+        ))),
+    }
+}
+
+fn push_short_str(bytes: &mut Vec<u8>, s: &str) -> Result<(), SaveGameError> {
+    match u8::try_from(s.len()) {
+        Ok(len) => bytes.push(len),
+        Err(_) => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "String too long to save: {}",
+            s
+        ))),
+    }
+    bytes.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn push_str(bytes: &mut Vec<u8>, s: &str) -> Result<(), SaveGameError> {
+    bytes.extend_from_slice(&count_to_u16(s.len(), "bytes in a string")?.to_be_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn pack_short_move(mv: &ShortMove) -> u16 {
+    let promotion = match mv.promotion_ptype {
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(PieceType::Queen) => 4,
+        _ => 0,
+    };
+    (mv.from as u16 & 0x3f) | (mv.to as u16 & 0x3f) << 6 | promotion << 12
+}
+
+fn unpack_short_move(packed: u16) -> Result<ShortMove, SaveGameError> {
+    let promotion_ptype = match packed >> 12 {
+        0 => None,
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        p => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid promotion in move {:#06x}: {}",
+            packed, p
+        ))),
+    };
+    Ok(ShortMove {
+        from: (packed & 0x3f) as u8,
+        to: (packed >> 6 & 0x3f) as u8,
+        promotion_ptype,
+    })
+}
+
+fn variant_to_byte(variant: Variant) -> u8 {
+    match variant {
+        Variant::Standard => 0,
+        Variant::Chess960 => 1,
+        Variant::FromPosition => 2,
+    }
+}
+
+fn variant_from_byte(b: u8) -> Result<Variant, SaveGameError> {
+    match b {
+        0 => Ok(Variant::Standard),
+        1 => Ok(Variant::Chess960),
+        2 => Ok(Variant::FromPosition),
+        _ => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid variant: {}",
+            b
+        ))),
+    }
+}
+
+fn gamestate_to_byte(gs: GameState) -> u8 {
+    match gs {
+        GameState::Active => 0,
+        GameState::Check => 1,
+        GameState::Checkmate => 2,
+        GameState::Stalemate => 3,
+        GameState::Repetition => 4,
+        GameState::FiftyMove => 5,
+        GameState::InsufficientMaterial => 6,
+        GameState::FivefoldRepetition => 7,
+        GameState::SeventyFiveMove => 8,
+    }
+}
+
+fn gamestate_from_byte(b: u8) -> Result<GameState, SaveGameError> {
+    match b {
+        0 => Ok(GameState::Active),
+        1 => Ok(GameState::Check),
+        2 => Ok(GameState::Checkmate),
+        3 => Ok(GameState::Stalemate),
+        4 => Ok(GameState::Repetition),
+        5 => Ok(GameState::FiftyMove),
+        6 => Ok(GameState::InsufficientMaterial),
+        7 => Ok(GameState::FivefoldRepetition),
+        8 => Ok(GameState::SeventyFiveMove),
+        _ => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid game state: {}",
+            b
+        ))),
+    }
+}
+
+fn colour_to_byte(colour: PieceColour) -> u8 {
+    match colour {
+        PieceColour::White => 0,
+        PieceColour::Black => 1,
+    }
+}
+
+fn colour_from_byte(b: u8) -> Result<PieceColour, SaveGameError> {
+    match b {
+        0 => Ok(PieceColour::White),
+        1 => Ok(PieceColour::Black),
+        _ => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid colour: {}",
+            b
+        ))),
+    }
+}
+
+fn result_to_bytes(result: GameResult) -> [u8; 2] {
+    let kind = match result {
+        GameResult::WhiteWins(_) => 0,
+        GameResult::BlackWins(_) => 1,
+        GameResult::Draw(_) => 2,
+    };
+    let reason = match result.reason() {
+        ResultReason::Checkmate => 0,
+        ResultReason::Resignation => 1,
+        ResultReason::Stalemate => 2,
+        ResultReason::InsufficientMaterial => 3,
+        ResultReason::Agreement => 4,
+        ResultReason::Repetition => 5,
+        ResultReason::FiftyMove => 6,
+        ResultReason::FivefoldRepetition => 7,
+        ResultReason::SeventyFiveMove => 8,
+        ResultReason::Timeout => 9,
+        ResultReason::TimeoutVsInsufficientMaterial => 10,
+        ResultReason::Adjudication => 11,
+    };
+    [kind, reason]
+}
+
+fn result_from_bytes([kind, reason]: [u8; 2]) -> Result<GameResult, SaveGameError> {
+    let reason = match reason {
+        0 => ResultReason::Checkmate,
+        1 => ResultReason::Resignation,
+        2 => ResultReason::Stalemate,
+        3 => ResultReason::InsufficientMaterial,
+        4 => ResultReason::Agreement,
+        5 => ResultReason::Repetition,
+        6 => ResultReason::FiftyMove,
+        7 => ResultReason::FivefoldRepetition,
+        8 => ResultReason::SeventyFiveMove,
+        9 => ResultReason::Timeout,
+        10 => ResultReason::TimeoutVsInsufficientMaterial,
+        11 => ResultReason::Adjudication,
+        _ => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid result reason: {}",
+            reason
+        ))),
+    };
+    match kind {
+        0 => Ok(GameResult::WhiteWins(reason)),
+        1 => Ok(GameResult::BlackWins(reason)),
+        2 => Ok(GameResult::Draw(reason)),
+        _ => log_and_return_error!(SaveGameError::InvalidFormat(format!(
+            "Invalid result: {}",
+            kind
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_game() -> SavedGame {
+        SavedGame {
+            variant: Variant::FromPosition,
+            starting_fen: "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string(),
+            tags: vec![("White".to_string(), "Carlsen".to_string())],
+            moves: vec![ShortMove {
+                from: 9,
+                to: 1,
+                promotion_ptype: Some(PieceType::Knight),
+            }],
+            game_over_state: Some(GameOverState::Forced(GameState::Stalemate)),
+        }
+    }
+
+    fn to_bytes(saved: &SavedGame) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        saved.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_saved_game_round_trip() {
+        let saved = saved_game();
+        let bytes = to_bytes(&saved);
+        assert_eq!(bytes[0..4], SAVE_MAGIC);
+        assert_eq!(SavedGame::read(&mut bytes.as_slice()).unwrap(), saved);
+
+        for gos in [
+            GameOverState::Timeout(PieceColour::Black),
+            GameOverState::Adjudication(GameResult::WhiteWins(ResultReason::Adjudication)),
+        ] {
+            let saved = SavedGame {
+                game_over_state: Some(gos),
+                ..saved_game()
+            };
+            let bytes = to_bytes(&saved);
+            assert_eq!(SavedGame::read(&mut bytes.as_slice()).unwrap(), saved);
+        }
+
+        for packed in 0..0x5000 {
+            assert_eq!(pack_short_move(&unpack_short_move(packed).unwrap()), packed);
+        }
+    }
+
+    #[test]
+    fn test_saved_game_header_mismatch() {
+        let bytes = to_bytes(&saved_game());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(
+            SavedGame::read(&mut wrong_magic.as_slice()),
+            Err(SaveGameError::InvalidFormat(_))
+        ));
+
+        let mut newer_version = bytes.clone();
+        newer_version[4..6].copy_from_slice(&(SAVE_FORMAT_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            SavedGame::read(&mut newer_version.as_slice()),
+            Err(SaveGameError::VersionMismatch(_))
+        ));
+
+        // the zobrist scheme follows the library version string
+        let scheme_start = 7 + version::version().len() + 1;
+        let mut other_scheme = bytes.clone();
+        other_scheme[scheme_start..scheme_start + zobrist::ZOBRIST_SCHEME.len()]
+            .copy_from_slice(&b"polyglot-v9"[..zobrist::ZOBRIST_SCHEME.len()]);
+        let err = SavedGame::read(&mut other_scheme.as_slice()).unwrap_err();
+        assert!(matches!(err, SaveGameError::VersionMismatch(_)));
+        assert!(err.to_string().contains("Zobrist scheme"));
+    }
+
+    #[test]
+    fn test_saved_game_corrupt() {
+        let bytes = to_bytes(&saved_game());
+        // every truncation is an error rather than a panic or a shorter game
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                SavedGame::read(&mut &bytes[..len]),
+                Err(SaveGameError::InvalidFormat(_))
+            ));
+        }
+
+        let mut bad_game_over = bytes.clone();
+        let len = bad_game_over.len();
+        bad_game_over[len - 2] = 9;
+        assert!(SavedGame::read(&mut bad_game_over.as_slice()).is_err());
+
+        let mut bad_promotion = bytes.clone();
+        bad_promotion[len - 4] |= 0x70;
+        assert!(SavedGame::read(&mut bad_promotion.as_slice()).is_err());
+    }
+}
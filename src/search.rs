@@ -0,0 +1,151 @@
+// engine searches run on their own thread, so a GUI can keep reading the board while the engine thinks. See
+// Board::start_engine_search and Board::apply_engine_result
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::board::{white_eval_for, BoardState};
+use crate::engine::{self, SearchResult};
+use crate::movegen::Move;
+use crate::transposition::TranspositionTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: u8,
+    // no deeper iteration is started once this much time has passed. The iteration running at the time still completes
+    // (see SearchHandle::stop to cut it short)
+    pub movetime: Option<Duration>,
+}
+
+impl SearchLimits {
+    pub fn new(depth: u8) -> Self {
+        Self {
+            depth,
+            movetime: None,
+        }
+    }
+
+    pub fn with_movetime(mut self, movetime: Duration) -> Self {
+        self.movetime = Some(movetime);
+        self
+    }
+}
+
+// result of the deepest completed iteration. eval is in centipawns from white's perspective, like EngineResult
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchProgress {
+    pub depth: u8,
+    pub eval: i32,
+    pub best_move: Option<Move>,
+}
+
+pub struct SearchHandle {
+    // board_hash of the searched state, the result is only applied to the same state
+    pub(crate) board_hash: u64,
+    stop: Arc<AtomicBool>,
+    progress: Arc<Mutex<SearchProgress>>,
+    thread: Option<thread::JoinHandle<SearchResult>>,
+    result: Option<SearchResult>,
+}
+
+impl SearchHandle {
+    // searches bs to limits.depth one depth at a time. The search takes the table out of tt and puts it back when it's
+    // done, so the mutex is only held for the swaps. Anything else using tt meanwhile gets an empty table of the same
+    // size, and its entries are dropped when the search's table is put back
+    pub(crate) fn spawn(
+        bs: BoardState,
+        limits: SearchLimits,
+        tt: Arc<Mutex<TranspositionTable>>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(Mutex::new(SearchProgress::default()));
+        let board_hash = bs.board_hash;
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let progress = Arc::clone(&progress);
+            thread::spawn(move || {
+                let started = Instant::now();
+                let mut table = {
+                    let mut tt = tt.lock().unwrap_or_else(PoisonError::into_inner);
+                    let empty = TranspositionTable::with_capacity_mb(tt.size_mb());
+                    std::mem::replace(&mut *tt, empty)
+                };
+                // once for the whole search, so the entries of earlier iterations aren't replaced first as stale ones
+                table.new_search();
+                let report = |result: &SearchResult| {
+                    *progress.lock().unwrap_or_else(PoisonError::into_inner) = SearchProgress {
+                        depth: result.depth,
+                        eval: white_eval_for(result.eval, bs.side_to_move),
+                        best_move: result.best_move,
+                    };
+                };
+                // depth 1 is too quick to be worth stopping, and there has to be a move to play
+                let mut result = engine::choose_move(&bs, 1, &mut table);
+                report(&result);
+                for depth in 2..=limits.depth {
+                    let out_of_time = limits
+                        .movetime
+                        .is_some_and(|movetime| started.elapsed() >= movetime);
+                    if out_of_time {
+                        break;
+                    }
+                    match engine::choose_move_until_stopped(&bs, depth, &mut table, &stop) {
+                        Some(iteration) => result = iteration,
+                        None => break,
+                    }
+                    report(&result);
+                }
+                *tt.lock().unwrap_or_else(PoisonError::into_inner) = table;
+                result
+            })
+        };
+        Self {
+            board_hash,
+            stop,
+            progress,
+            thread: Some(thread),
+            result: None,
+        }
+    }
+
+    pub fn progress(&self) -> SearchProgress {
+        *self.progress.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // stops the search straight away. The iteration it was in is abandoned and the best move of the deepest completed
+    // one is applied
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    // blocks until the search is finished and returns its result. Board::apply_engine_result waits too, this is for
+    // waiting without holding a lock on the board
+    pub fn wait(&mut self) -> SearchProgress {
+        self.join();
+        self.progress()
+    }
+
+    pub(crate) fn join(&mut self) -> SearchResult {
+        if let Some(thread) = self.thread.take() {
+            // a panic in the search thread is a bug in the engine, pass it on
+            let result = thread
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e));
+            self.result = Some(result);
+        }
+        // either the thread was just joined or it was joined by an earlier call
+        self.result.unwrap()
+    }
+}
+
+impl Drop for SearchHandle {
+    // a dropped search isn't waited for, it stops in the background
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,10 +1,14 @@
 // https://github.com/mvanthoor/rustic/blob/4.0-beta/src/engine/transposition.rs
 // Based on this author's work, mainly to understand generic types. Only used for type TableEntry currently.
 
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::vec;
 
 use crate::zobrist::PositionHash;
-use crate::{util, ShortMove, NULL_SHORT_MOVE};
+use crate::{util, PieceType, ShortMove, NULL_SHORT_MOVE};
 
 const DEFAULT_TABLE_SIZE_MB: usize = 200; // in MiB
 const NUM_BUCKETS: usize = 3;
@@ -14,6 +18,7 @@ const UNINIT_ENTRY: TableEntry = TableEntry {
     ply: 0,
     eval: 0,
     mv: NULL_SHORT_MOVE,
+    generation: 0,
 };
 
 // TT with generic type T as TableEntry
@@ -24,6 +29,8 @@ pub trait TTData {
     fn new() -> Self;
     fn get_depth(&self) -> u8;
     fn is_empty(&self) -> bool;
+    fn get_generation(&self) -> u8;
+    fn set_generation(&mut self, generation: u8);
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,13 +42,15 @@ pub enum BoundType {
 }
 
 // TODO detect checkmate distance
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TableEntry {
     pub bound_type: BoundType,
     pub depth: u8,
     pub ply: u8,
     pub eval: i32,
     pub mv: ShortMove,
+    // search generation the entry was stored in, set by TT::insert
+    pub generation: u8,
 }
 impl TTData for TableEntry {
     fn new() -> Self {
@@ -55,13 +64,139 @@ impl TTData for TableEntry {
     fn is_empty(&self) -> bool {
         self.bound_type == BoundType::Invalid
     }
+
+    fn get_generation(&self) -> u8 {
+        self.generation
+    }
+
+    fn set_generation(&mut self, generation: u8) {
+        self.generation = generation;
+    }
+}
+impl AtomicTTData for TableEntry {
+    // words[0] bytes from least significant: bound type, depth, ply, generation, move from, move to, promotion.
+    // words[1] is the eval
+    fn to_words(&self) -> [u64; 2] {
+        let bound_type = match self.bound_type {
+            BoundType::Invalid => 0,
+            BoundType::Exact => 1,
+            BoundType::Lower => 2,
+            BoundType::Upper => 3,
+        };
+        let promotion = match self.mv.promotion_ptype {
+            Some(ptype) => ptype as u64 + 1,
+            None => 0,
+        };
+        [
+            bound_type
+                | (self.depth as u64) << 8
+                | (self.ply as u64) << 16
+                | (self.generation as u64) << 24
+                | (self.mv.from as u64) << 32
+                | (self.mv.to as u64) << 40
+                | promotion << 48,
+            self.eval as u32 as u64,
+        ]
+    }
+
+    fn from_words(words: [u64; 2]) -> Self {
+        let byte = |i: u32| (words[0] >> (i * 8)) as u8;
+        Self {
+            bound_type: match byte(0) {
+                1 => BoundType::Exact,
+                2 => BoundType::Lower,
+                3 => BoundType::Upper,
+                _ => BoundType::Invalid,
+            },
+            depth: byte(1),
+            ply: byte(2),
+            generation: byte(3),
+            mv: ShortMove {
+                from: byte(4),
+                to: byte(5),
+                promotion_ptype: match byte(6) {
+                    1 => Some(PieceType::Pawn),
+                    2 => Some(PieceType::Knight),
+                    3 => Some(PieceType::Bishop),
+                    4 => Some(PieceType::Rook),
+                    5 => Some(PieceType::Queen),
+                    6 => Some(PieceType::King),
+                    _ => None,
+                },
+            },
+            eval: words[1] as u32 as i32,
+        }
+    }
+}
+
+// TT usage counters since the table was created or TT::reset_stats was called, and the current contents of the table
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TTStats {
+    pub probes: u64,
+    pub hits: u64,
+    // inserts that were stored, including updates to an entry for the same position
+    pub stores: u64,
+    // stores that overwrote an entry for a different position
+    pub replacements: u64,
+    // inserts that weren't stored because of the replacement policy
+    pub rejections: u64,
+    pub entries: usize,
+    pub size: usize,
+    // average depth of the entries in the table
+    pub average_depth: f64,
+}
+impl TTStats {
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.probes as f64
+        }
+    }
+
+    // fraction of the table that is filled
+    pub fn occupancy(&self) -> f64 {
+        if self.size == 0 {
+            0.0
+        } else {
+            self.entries as f64 / self.size as f64
+        }
+    }
+}
+impl fmt::Display for TTStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Probes -> {}, Hits -> {} ({:.1}%), Stores -> {}, Replacements -> {}, Rejections -> {}, Entries -> {}/{} ({:.1}%), Average depth -> {:.2}",
+            self.probes,
+            self.hits,
+            self.hit_rate() * 100.0,
+            self.stores,
+            self.replacements,
+            self.rejections,
+            self.entries,
+            self.size,
+            self.occupancy() * 100.0,
+            self.average_depth
+        )
+    }
 }
 
+// the table is allocated on the first insert, so creating (or resizing) a TT that is never searched with is free
 #[derive(Debug, Clone)]
 pub struct TT<T> {
     table: Vec<Entry<T>>,
     entry_count: usize,
     size_mb: usize,
+    generation: u8,
+    // get only has &self, so its counters are Cells
+    probes: Cell<u64>,
+    hits: Cell<u64>,
+    stores: u64,
+    replacements: u64,
+    rejections: u64,
+    // sum of the depths of all entries in the table
+    depth_total: u64,
 }
 impl<T: TTData + Copy + Clone> Default for TT<T> {
     fn default() -> Self {
@@ -70,43 +205,112 @@ impl<T: TTData + Copy + Clone> Default for TT<T> {
 }
 impl<T: TTData + Copy + Clone> TT<T> {
     pub fn new() -> Self {
-        Self::with_size(DEFAULT_TABLE_SIZE_MB)
+        Self::with_capacity_mb(DEFAULT_TABLE_SIZE_MB)
     }
 
-    pub fn with_size(size_mb: usize) -> Self {
-        let table = vec![Entry::<T>::new(); Self::mb_to_len(size_mb)];
+    // size_mb of 0 disables the table, nothing is stored and every lookup misses
+    pub fn with_capacity_mb(size_mb: usize) -> Self {
         Self {
-            table,
+            table: Vec::new(),
             entry_count: 0,
             size_mb,
+            generation: 0,
+            probes: Cell::new(0),
+            hits: Cell::new(0),
+            stores: 0,
+            replacements: 0,
+            rejections: 0,
+            depth_total: 0,
         }
     }
 
+    // call before each search, entries from previous searches are replaced before entries from the current one
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
     pub fn get(&self, hash: PositionHash) -> Option<&T> {
-        if self.size_mb != 0 {
+        self.probes.set(self.probes.get() + 1);
+        let result = if !self.table.is_empty() {
             self.table[self.get_idx(hash)].get(self.get_bucket_hash(hash))
         } else {
             None
+        };
+        if result.is_some() {
+            self.hits.set(self.hits.get() + 1);
         }
+        result
     }
 
-    pub fn insert(&mut self, hash: PositionHash, data: T) {
-        if self.size_mb != 0 {
+    pub fn insert(&mut self, hash: PositionHash, mut data: T) {
+        if self.table.is_empty() && self.size_mb != 0 {
+            self.table = vec![Entry::<T>::new(); Self::mb_to_len(self.size_mb)];
+        }
+        if !self.table.is_empty() {
             let idx = self.get_idx(hash);
             let bucket_hash = self.get_bucket_hash(hash);
-            // returns true if the bucket was empty, so we can increment entry_count
-            if self.table[idx].insert(bucket_hash, data) {
-                self.entry_count += 1;
+            data.set_generation(self.generation);
+            let old = match self.table[idx].insert(bucket_hash, data, self.generation) {
+                InsertOutcome::Empty => {
+                    self.entry_count += 1;
+                    None
+                }
+                InsertOutcome::Updated(old) => Some(old),
+                InsertOutcome::Replaced(old) => {
+                    self.replacements += 1;
+                    Some(old)
+                }
+                InsertOutcome::Rejected => {
+                    self.rejections += 1;
+                    return;
+                }
+            };
+            self.stores += 1;
+            self.depth_total += data.get_depth() as u64;
+            if let Some(old) = old {
+                self.depth_total -= old.get_depth() as u64;
             }
         }
     }
 
+    pub fn stats(&self) -> TTStats {
+        TTStats {
+            probes: self.probes.get(),
+            hits: self.hits.get(),
+            stores: self.stores,
+            replacements: self.replacements,
+            rejections: self.rejections,
+            entries: self.entry_count,
+            size: self.size(),
+            average_depth: if self.entry_count == 0 {
+                0.0
+            } else {
+                self.depth_total as f64 / self.entry_count as f64
+            },
+        }
+    }
+
+    // resets the usage counters, the entries in the table are kept
+    pub fn reset_stats(&mut self) {
+        self.probes.set(0);
+        self.hits.set(0);
+        self.stores = 0;
+        self.replacements = 0;
+        self.rejections = 0;
+    }
+
+    // number of entries the table can hold
     pub fn size(&self) -> usize {
-        self.table.len() * NUM_BUCKETS
+        Self::mb_to_len(self.size_mb) * NUM_BUCKETS
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.size_mb
     }
 
+    // bytes currently allocated for the table, 0 until the first insert
     pub fn heap_alloc_size(&self) -> usize {
-        self.table.len() * std::mem::size_of::<Entry<T>>()
+        self.table.capacity() * std::mem::size_of::<Entry<T>>()
     }
 
     pub fn len(&self) -> usize {
@@ -117,8 +321,10 @@ impl<T: TTData + Copy + Clone> TT<T> {
         self.entry_count == 0
     }
 
+    // removes all entries, the table stays allocated
     pub fn clear(&mut self) {
         self.entry_count = 0;
+        self.depth_total = 0;
         self.table.iter_mut().for_each(|entry| {
             *entry = Entry::new();
         });
@@ -129,8 +335,7 @@ impl<T: TTData + Copy + Clone> TT<T> {
     }
 
     fn get_idx(&self, hash: PositionHash) -> usize {
-        let idx_hash = util::high_bits(hash); // use high bits for index, and low bits for bucket collision handling
-        (idx_hash as usize) % self.table.len()
+        table_idx(hash, self.table.len())
     }
 
     const fn get_bucket_hash(&self, hash: PositionHash) -> u32 {
@@ -152,6 +357,14 @@ impl<T: TTData> Bucket<T> {
     }
 }
 
+// what Entry::insert did with the data, with the entry it overwrote
+enum InsertOutcome<T> {
+    Empty,
+    Updated(T),
+    Replaced(T),
+    Rejected,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Entry<T> {
     buckets: [Bucket<T>; NUM_BUCKETS],
@@ -163,31 +376,523 @@ impl<T: TTData + Copy + Clone> Entry<T> {
         }
     }
 
-    // returns true if the bucket was empty before data was inserted
-    fn insert(&mut self, hash: u32, data: T) -> bool {
-        let mut idx = 0;
-        for i in 1..self.buckets.len() {
-            // skip first bucket as we will start by comparing idx 0
-            // replacement strategy is removing lowest depth entry, uninitialised entries are depth 0
-            if self.buckets[i].data.get_depth() < self.buckets[idx].data.get_depth() {
-                idx = i;
-            }
-        }
-        let was_empty = self.buckets[idx].hash == 0;
-        self.buckets[idx].hash = hash;
-        self.buckets[idx].data = data;
-        if !was_empty {
+    // see replacement_idx for which bucket is used
+    fn insert(&mut self, hash: u32, data: T, generation: u8) -> InsertOutcome<T> {
+        let same_position = self
+            .buckets
+            .map(|bucket| bucket.hash == hash && !bucket.data.is_empty());
+        let Some(idx) = replacement_idx(
+            &self.buckets.map(|bucket| bucket.data),
+            same_position,
+            &data,
+            generation,
+        ) else {
+            return InsertOutcome::Rejected;
+        };
+        let old = self.buckets[idx].data;
+        self.buckets[idx] = Bucket { hash, data };
+        if old.is_empty() {
+            InsertOutcome::Empty
+        } else if same_position[idx] {
+            InsertOutcome::Updated(old)
+        } else {
             log::trace!("TT bucket collision");
+            InsertOutcome::Replaced(old)
         }
-        was_empty
     }
 
     fn get(&self, hash: u32) -> Option<&T> {
-        for bucket in &self.buckets {
-            if bucket.hash == hash {
-                return Some(&bucket.data);
+        self.buckets
+            .iter()
+            .find(|bucket| bucket.hash == hash && !bucket.data.is_empty())
+            .map(|bucket| &bucket.data)
+    }
+}
+
+// use high bits for index, and low bits for bucket collision handling. table_len must not be 0
+fn table_idx(hash: PositionHash, table_len: usize) -> usize {
+    (util::high_bits(hash) as usize) % table_len
+}
+
+// index of the bucket data should be stored in, or None if it shouldn't be stored. same_position[i] is true if bucket i
+// holds an entry for the same position as data. An entry for the same position is only replaced by a deeper one, or
+// if it is from an older generation. Otherwise data replaces an empty bucket, then the shallowest entry from an older
+// generation, then the shallowest entry from the current generation if it isn't deeper than data
+fn replacement_idx<T: TTData>(
+    buckets: &[T; NUM_BUCKETS],
+    same_position: [bool; NUM_BUCKETS],
+    data: &T,
+    generation: u8,
+) -> Option<usize> {
+    if let Some(idx) = same_position.iter().position(|&same| same) {
+        let old = &buckets[idx];
+        return (data.get_depth() >= old.get_depth() || old.get_generation() != generation)
+            .then_some(idx);
+    }
+    let idx = (0..NUM_BUCKETS)
+        .min_by_key(|&i| {
+            let old = &buckets[i];
+            (
+                !old.is_empty(),
+                old.get_generation() == generation,
+                old.get_depth(),
+            )
+        })
+        .unwrap(); // NUM_BUCKETS > 0
+    let old = &buckets[idx];
+    if !old.is_empty() && old.get_generation() == generation && old.get_depth() > data.get_depth() {
+        None
+    } else {
+        Some(idx)
+    }
+}
+
+// AtomicTTData must be implemented for any type used in an AtomicTT. Entries are stored as two u64 words, and all
+// zero words must unpack to an empty entry
+pub trait AtomicTTData: TTData {
+    fn to_words(&self) -> [u64; 2];
+    fn from_words(words: [u64; 2]) -> Self;
+}
+
+// Lock free TT that can be shared between search threads, get and insert only need &self. Same buckets and
+// replacement policy as TT, but buckets are written as separate atomic words with no lock, so a reader can see half of
+// one write and half of another. Each bucket stores the full key XORed with its data words, so a torn bucket no longer
+// unpacks to the key it is looked up with, and is treated as a miss. Unlike TT, the table is allocated up front
+pub type SharedTranspositionTable<T = TableEntry> = AtomicTT<T>;
+
+#[derive(Debug)]
+pub struct AtomicTT<T> {
+    table: Vec<AtomicEntry>,
+    entry_count: AtomicUsize,
+    size_mb: usize,
+    generation: AtomicU8,
+    data: PhantomData<fn() -> T>,
+}
+impl<T: AtomicTTData> Default for AtomicTT<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: AtomicTTData> AtomicTT<T> {
+    pub fn new() -> Self {
+        Self::with_capacity_mb(DEFAULT_TABLE_SIZE_MB)
+    }
+
+    // size_mb of 0 disables the table, nothing is stored and every lookup misses
+    pub fn with_capacity_mb(size_mb: usize) -> Self {
+        let table = (0..Self::mb_to_len(size_mb))
+            .map(|_| AtomicEntry::default())
+            .collect();
+        Self {
+            table,
+            entry_count: AtomicUsize::new(0),
+            size_mb,
+            generation: AtomicU8::new(0),
+            data: PhantomData,
+        }
+    }
+
+    // call before each search, entries from previous searches are replaced before entries from the current one
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, hash: PositionHash) -> Option<T> {
+        if self.table.is_empty() {
+            return None;
+        }
+        self.table[table_idx(hash, self.table.len())]
+            .buckets
+            .iter()
+            .map(AtomicBucket::load::<T>)
+            .find(|(key, data)| *key == hash && !data.is_empty())
+            .map(|(_, data)| data)
+    }
+
+    pub fn insert(&self, hash: PositionHash, mut data: T) {
+        if self.table.is_empty() {
+            return;
+        }
+        let generation = self.generation.load(Ordering::Relaxed);
+        data.set_generation(generation);
+        let entry = &self.table[table_idx(hash, self.table.len())];
+        let buckets = entry.buckets.each_ref().map(AtomicBucket::load::<T>);
+        let same_position = buckets
+            .each_ref()
+            .map(|(key, old)| *key == hash && !old.is_empty());
+        let olds = buckets.map(|(_, old)| old);
+        if let Some(idx) = replacement_idx(&olds, same_position, &data, generation) {
+            entry.buckets[idx].store(hash, &data);
+            if olds[idx].is_empty() {
+                self.entry_count.fetch_add(1, Ordering::Relaxed);
             }
         }
-        None
+    }
+
+    // number of entries the table can hold
+    pub fn size(&self) -> usize {
+        self.table.len() * NUM_BUCKETS
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.size_mb
+    }
+
+    pub fn heap_alloc_size(&self) -> usize {
+        self.table.capacity() * std::mem::size_of::<AtomicEntry>()
+    }
+
+    // approximate while other threads are inserting
+    pub fn len(&self) -> usize {
+        self.entry_count.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // removes all entries, the table stays allocated
+    pub fn clear(&mut self) {
+        *self.entry_count.get_mut() = 0;
+        self.table.iter_mut().for_each(|entry| {
+            *entry = AtomicEntry::default();
+        });
+    }
+
+    const fn mb_to_len(mb_size: usize) -> usize {
+        (mb_size * 1024 * 1024) / std::mem::size_of::<AtomicEntry>()
+    }
+}
+
+#[derive(Debug, Default)]
+struct AtomicBucket {
+    check: AtomicU64, // key ^ words[0] ^ words[1]
+    words: [AtomicU64; 2],
+}
+impl AtomicBucket {
+    // the key the bucket's data was stored with, if the bucket wasn't torn by concurrent writes
+    fn load<T: AtomicTTData>(&self) -> (PositionHash, T) {
+        let words = [
+            self.words[0].load(Ordering::Relaxed),
+            self.words[1].load(Ordering::Relaxed),
+        ];
+        let key = self.check.load(Ordering::Relaxed) ^ words[0] ^ words[1];
+        (key, T::from_words(words))
+    }
+
+    fn store<T: AtomicTTData>(&self, key: PositionHash, data: &T) {
+        let words = data.to_words();
+        self.words[0].store(words[0], Ordering::Relaxed);
+        self.words[1].store(words[1], Ordering::Relaxed);
+        self.check
+            .store(key ^ words[0] ^ words[1], Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+struct AtomicEntry {
+    buckets: [AtomicBucket; NUM_BUCKETS],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine;
+    use crate::fen::FEN;
+    use crate::BoardState;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_entry_size() {
+        // heap_alloc_size and mb_to_len assume this layout, keep entries small so more fit in the table
+        assert_eq!(std::mem::size_of::<Bucket<TableEntry>>(), 16);
+        assert_eq!(std::mem::size_of::<Entry<TableEntry>>(), 16 * NUM_BUCKETS);
+    }
+
+    #[test]
+    fn test_tt_capacity() {
+        let bs = BoardState::try_from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        for size_mb in [1, 64] {
+            let mut tt = TranspositionTable::with_capacity_mb(size_mb);
+            assert_eq!(tt.heap_alloc_size(), 0);
+            for mv in bs.get_legal_moves().unwrap() {
+                engine::choose_move(&bs.next_state(mv).unwrap(), 3, &mut tt);
+            }
+            assert!(!tt.is_empty());
+            assert!(tt.len() <= tt.size(), "{}MB", size_mb);
+            assert!(
+                tt.heap_alloc_size() <= size_mb * 1024 * 1024,
+                "{}MB",
+                size_mb
+            );
+            assert_eq!(
+                tt.heap_alloc_size() / std::mem::size_of::<Entry<TableEntry>>() * NUM_BUCKETS,
+                tt.size()
+            );
+
+            // the search doesn't fill the 1MB table, so fill the rest with entries for made up positions
+            let entry = TableEntry {
+                bound_type: BoundType::Exact,
+                depth: 1,
+                ply: 1,
+                eval: 0,
+                mv: NULL_SHORT_MOVE,
+                generation: 0,
+            };
+            for i in 0..(2 * 1024 * 1024 / 16) as u64 {
+                tt.insert(i.wrapping_mul(0x9e3779b97f4a7c15) | 1, entry);
+            }
+            assert!(tt.len() <= tt.size(), "{}MB", size_mb);
+            if size_mb == 1 {
+                assert!(tt.len() > tt.size() * 9 / 10);
+            }
+            assert!(
+                tt.heap_alloc_size() <= size_mb * 1024 * 1024,
+                "{}MB",
+                size_mb
+            );
+        }
+    }
+
+    #[test]
+    fn test_tt_clear() {
+        let bs = BoardState::try_from(
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R b KQ - 3 9"
+                .parse::<FEN>()
+                .unwrap(),
+        )
+        .unwrap();
+        let expected = engine::choose_move(&bs, 3, &mut TranspositionTable::with_capacity_mb(1));
+
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        // fill the table from a different position first
+        engine::choose_move(&BoardState::new_starting(), 3, &mut tt);
+        let heap_alloc_size = tt.heap_alloc_size();
+        tt.clear();
+        assert!(tt.is_empty());
+        assert_eq!(tt.len(), 0);
+        assert_eq!(tt.heap_alloc_size(), heap_alloc_size);
+        assert_eq!(tt.size_mb(), 1);
+        assert_eq!(engine::choose_move(&bs, 3, &mut tt), expected);
+    }
+
+    #[test]
+    fn test_table_entry_words_round_trip() {
+        let entries = [
+            TableEntry {
+                bound_type: BoundType::Lower,
+                depth: 7,
+                ply: 3,
+                eval: -99_999_995, // mated in 5 ply
+                mv: ShortMove {
+                    from: 8,
+                    to: 0,
+                    promotion_ptype: Some(PieceType::Knight),
+                },
+                generation: 255,
+            },
+            TableEntry {
+                bound_type: BoundType::Upper,
+                depth: u8::MAX,
+                ply: 0,
+                eval: i32::MIN,
+                mv: NULL_SHORT_MOVE,
+                generation: 1,
+            },
+            depth_entry(3),
+        ];
+        for entry in entries {
+            assert_eq!(TableEntry::from_words(entry.to_words()), entry);
+        }
+        assert!(TableEntry::from_words([0, 0]).is_empty());
+    }
+
+    #[test]
+    fn test_shared_tt_torn_bucket() {
+        let tt = SharedTranspositionTable::with_capacity_mb(1);
+        let (key_a, key_b) = (entry_key(1), entry_key(2));
+        tt.insert(key_a, depth_entry(3));
+        assert_eq!(tt.get(key_a).unwrap().depth, 3);
+
+        // a write of key_b interrupted after its first word
+        let bucket = tt.table[table_idx(key_a, tt.table.len())]
+            .buckets
+            .iter()
+            .find(|bucket| bucket.load::<TableEntry>().0 == key_a)
+            .unwrap();
+        bucket.words[0].store(depth_entry(5).to_words()[0], Ordering::Relaxed);
+        assert!(tt.get(key_a).is_none());
+        assert!(tt.get(key_b).is_none());
+    }
+
+    #[test]
+    fn test_shared_tt_concurrent_access() {
+        // expected entry for each key, so an entry from one key read back with another would be detected
+        fn key_entry(key: PositionHash) -> TableEntry {
+            TableEntry {
+                bound_type: BoundType::Exact,
+                depth: (key >> 8) as u8,
+                ply: key as u8,
+                eval: (key >> 16) as i32,
+                mv: ShortMove {
+                    from: (key as u8) & 63,
+                    to: (key >> 6) as u8 & 63,
+                    promotion_ptype: None,
+                },
+                generation: 0,
+            }
+        }
+
+        let tt = SharedTranspositionTable::with_capacity_mb(1);
+        let hits = AtomicUsize::new(0);
+        let start = std::sync::Barrier::new(4);
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (tt, hits, start) = (&tt, &hits, &start);
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(thread);
+                    start.wait();
+                    for i in 0..1_000_000 {
+                        // every key is in the same table entry, so threads are constantly writing over each other's
+                        // buckets
+                        let key = (1 << 32) | rng.gen_range(1..16u64);
+                        if i % 2 == 0 {
+                            tt.insert(key, key_entry(key));
+                        } else if let Some(entry) = tt.get(key) {
+                            assert_eq!(
+                                entry,
+                                TableEntry {
+                                    generation: entry.generation,
+                                    ..key_entry(key)
+                                }
+                            );
+                            hits.fetch_add(1, Ordering::Relaxed);
+                        }
+                        if i % 100_000 == 0 {
+                            tt.new_search();
+                        }
+                    }
+                });
+            }
+        });
+        assert!(hits.load(Ordering::Relaxed) > 0);
+        assert!(tt.len() <= tt.size());
+    }
+
+    #[test]
+    fn test_tt_stats() {
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        assert_eq!(
+            tt.stats(),
+            TTStats {
+                size: tt.size(),
+                ..Default::default()
+            }
+        );
+        engine::choose_move(&BoardState::new_starting(), 4, &mut tt);
+        let stats = tt.stats();
+        assert!(stats.probes > 0);
+        assert!(stats.hits > 0);
+        assert!(stats.hits <= stats.probes);
+        assert!(stats.stores >= stats.entries as u64);
+        assert_eq!(stats.entries, tt.len());
+        assert_eq!(stats.occupancy(), tt.len() as f64 / tt.size() as f64);
+        assert!(stats.average_depth > 0.0 && stats.average_depth <= 3.0);
+
+        tt.reset_stats();
+        let reset = tt.stats();
+        assert_eq!(reset.probes, 0);
+        assert_eq!(reset.hits, 0);
+        assert_eq!(reset.stores, 0);
+        assert_eq!(reset.entries, stats.entries);
+        assert_eq!(reset.average_depth, stats.average_depth);
+    }
+
+    #[test]
+    fn test_tt_stats_replacements() {
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        for i in 1..=NUM_BUCKETS as u32 {
+            tt.insert(entry_key(i), depth_entry(4));
+        }
+        // rejected, then updates the same position, then replaces a shallower entry
+        tt.insert(entry_key(100), depth_entry(2));
+        tt.insert(entry_key(1), depth_entry(6));
+        tt.insert(entry_key(101), depth_entry(5));
+        let stats = tt.stats();
+        assert_eq!(stats.rejections, 1);
+        assert_eq!(stats.replacements, 1);
+        assert_eq!(stats.stores, NUM_BUCKETS as u64 + 2);
+        assert_eq!(stats.entries, NUM_BUCKETS);
+        // depths 6, 4 and 5
+        assert_eq!(stats.average_depth, 5.0);
+    }
+
+    // keys with the same high bits are stored in the same table entry
+    fn entry_key(low_bits: u32) -> PositionHash {
+        (1 << 32) | low_bits as PositionHash
+    }
+
+    fn depth_entry(depth: u8) -> TableEntry {
+        TableEntry {
+            bound_type: BoundType::Exact,
+            depth,
+            ply: 0,
+            eval: depth as i32,
+            mv: NULL_SHORT_MOVE,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn test_tt_keeps_deep_entries() {
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        for i in 1..=NUM_BUCKETS as u32 {
+            tt.insert(entry_key(i), depth_entry(10));
+        }
+        // a full entry of deep entries from this search isn't replaced by shallower ones
+        tt.insert(entry_key(100), depth_entry(2));
+        assert!(tt.get(entry_key(100)).is_none());
+        // same position at a lower depth keeps the deeper result, a deeper one replaces it
+        tt.insert(entry_key(1), depth_entry(5));
+        assert_eq!(tt.get(entry_key(1)).unwrap().depth, 10);
+        tt.insert(entry_key(1), depth_entry(12));
+        assert_eq!(tt.get(entry_key(1)).unwrap().depth, 12);
+        for i in 1..=NUM_BUCKETS as u32 {
+            assert!(tt.get(entry_key(i)).is_some());
+        }
+        assert_eq!(tt.len(), NUM_BUCKETS);
+    }
+
+    #[test]
+    fn test_tt_reclaims_old_generations() {
+        let mut tt = TranspositionTable::with_capacity_mb(1);
+        for i in 1..=NUM_BUCKETS as u32 {
+            tt.insert(entry_key(i), depth_entry(20));
+        }
+        for _ in 0..10 {
+            tt.new_search();
+        }
+        // old entries are still returned until they are replaced
+        assert_eq!(tt.get(entry_key(1)).unwrap().depth, 20);
+        tt.insert(entry_key(1), depth_entry(1));
+        assert_eq!(tt.get(entry_key(1)).unwrap().depth, 1);
+
+        // shallow entries from the current search replace the remaining deep old ones
+        for i in 100..100 + NUM_BUCKETS as u32 - 1 {
+            tt.insert(entry_key(i), depth_entry(1));
+        }
+        for i in 100..100 + NUM_BUCKETS as u32 - 1 {
+            assert_eq!(tt.get(entry_key(i)).unwrap().generation, 10);
+        }
+        for i in 2..=NUM_BUCKETS as u32 {
+            assert!(tt.get(entry_key(i)).is_none());
+        }
+        assert_eq!(tt.len(), NUM_BUCKETS);
     }
 }
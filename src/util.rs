@@ -1,79 +1,145 @@
+use std::fmt;
+use std::str::FromStr;
+
 use crate::engine::{get_checkmate_ply, is_eval_checkmate};
-use crate::movegen::{PieceColour, PieceType, Square};
+use crate::errors::SquareParseError;
+use crate::log_and_return_error;
+use crate::movegen::PieceColour;
 use crate::BoardState;
 
-#[allow(dead_code)]
-pub fn print_board(bs: &BoardState) {
-    let pawn = " ♙ ";
-    let knight = " ♘ ";
-    let bishop = " ♗ ";
-    let rook = " ♖ ";
-    let queen = " ♕ ";
-    let king = " ♔ ";
-
-    let bking = " ♚ ";
-    let bqueen = " ♛ ";
-    let brook = " ♜ ";
-    let bbishop = " ♝ ";
-    let bknight = " ♞ ";
-    let bpawn = " ♟︎ ";
-
-    for (num, j) in bs.get_pos64().iter().enumerate() {
-        match j {
-            Square::Piece(p) => match p.pcolour {
-                PieceColour::White => match p.ptype {
-                    PieceType::Pawn => {
-                        print!("{}", pawn);
-                    }
-                    PieceType::Knight => {
-                        print!("{}", knight);
-                    }
-                    PieceType::Bishop => {
-                        print!("{}", bishop);
-                    }
-                    PieceType::Rook => {
-                        print!("{}", rook);
-                    }
-                    PieceType::Queen => {
-                        print!("{}", queen);
-                    }
-                    PieceType::King => {
-                        print!("{}", king);
-                    }
-                },
-                PieceColour::Black => match p.ptype {
-                    PieceType::Pawn => {
-                        print!("{}", bpawn);
-                    }
-                    PieceType::Knight => {
-                        print!("{}", bknight);
-                    }
-                    PieceType::Bishop => {
-                        print!("{}", bbishop);
-                    }
-                    PieceType::Rook => {
-                        print!("{}", brook);
-                    }
-                    PieceType::Queen => {
-                        print!("{}", bqueen);
-                    }
-                    PieceType::King => {
-                        print!("{}", bking);
-                    }
-                },
-            },
-            Square::Empty => {
-                print!(" - ");
-            }
+// a square of the board by its index, the same index used by Pos64 and Move (0 = a8, 63 = h1). Parses from and displays
+// as algebraic notation e.g. "e4"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SquareIdx(u8);
+
+impl SquareIdx {
+    // file 0 is the a file and rank 0 is the 1st rank
+    pub const fn from_file_rank(file: u8, rank: u8) -> Option<Self> {
+        if file < 8 && rank < 8 {
+            Some(Self((7 - rank) * 8 + file))
+        } else {
+            None
+        }
+    }
+
+    // None for indexes off the board
+    pub const fn new(index: usize) -> Option<Self> {
+        if index < 64 {
+            Some(Self(index as u8))
+        } else {
+            None
+        }
+    }
+
+    // for indexes that come from the board or its moves, which are always on the board
+    #[inline(always)]
+    pub(crate) const fn new_unchecked(index: usize) -> Self {
+        debug_assert!(index < 64);
+        Self(index as u8)
+    }
+
+    #[inline(always)]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    // 0 is the a file
+    #[inline(always)]
+    pub const fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    // 0 is the 1st rank
+    #[inline(always)]
+    pub const fn rank(self) -> u8 {
+        7 - self.0 / 8
+    }
+
+    #[inline(always)]
+    pub const fn file_char(self) -> char {
+        (b'a' + self.file()) as char
+    }
+
+    #[inline(always)]
+    pub const fn rank_char(self) -> char {
+        (b'1' + self.rank()) as char
+    }
+
+    // algebraic notation e.g. "e4" without logging an error, for parsing notations that contain squares
+    pub(crate) fn from_notation(s: &str) -> Option<Self> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => Self::file_from_char(file)
+                .zip(Self::rank_from_char(rank))
+                .and_then(|(file, rank)| Self::from_file_rank(file, rank)),
+            _ => None,
+        }
+    }
+
+    // file of a file letter 'a' to 'h', see file()
+    pub(crate) fn file_from_char(c: char) -> Option<u8> {
+        ('a'..='h').contains(&c).then(|| c as u8 - b'a')
+    }
+
+    // rank of a rank digit '1' to '8', see rank()
+    pub(crate) fn rank_from_char(c: char) -> Option<u8> {
+        ('1'..='8').contains(&c).then(|| c as u8 - b'1')
+    }
+}
+
+impl TryFrom<usize> for SquareIdx {
+    type Error = SquareParseError;
+
+    fn try_from(index: usize) -> Result<Self, Self::Error> {
+        match Self::new(index) {
+            Some(square) => Ok(square),
+            None => log_and_return_error!(SquareParseError::InvalidIndex(index)),
         }
+    }
+}
 
-        // new rank
-        if (num + 1) % 8 == 0 {
-            println!();
+impl From<SquareIdx> for usize {
+    fn from(square: SquareIdx) -> Self {
+        square.index()
+    }
+}
+
+impl FromStr for SquareIdx {
+    type Err = SquareParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::from_notation(s) {
+            Some(square) => Ok(square),
+            None => log_and_return_error!(SquareParseError::InvalidNotation(s.to_string())),
         }
     }
 }
 
+impl fmt::Display for SquareIdx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file_char(), self.rank_char())
+    }
+}
+
+// algebraic notation of the square on idx (0 = a8, 63 = h1) e.g. "e4"
+pub fn square_name(idx: usize) -> Result<String, SquareParseError> {
+    SquareIdx::try_from(idx).map(|square| square.to_string())
+}
+
+// index of a square in algebraic notation e.g. 36 for "e4"
+pub fn square_index(notation: &str) -> Result<usize, SquareParseError> {
+    notation.parse::<SquareIdx>().map(SquareIdx::index)
+}
+
+// square_name for indexes that come from the board or its moves, which are always on the board
+pub(crate) fn square_name_unchecked(idx: usize) -> String {
+    SquareIdx::new_unchecked(idx).to_string()
+}
+
+pub fn print_board(bs: &BoardState) {
+    println!("{}", bs.to_unicode_string(false));
+}
+
 #[inline]
 pub fn bytes_to_str(size: usize) -> String {
     let units = ["B", "KiB", "MiB", "GiB", "TiB"];
@@ -108,12 +174,17 @@ pub fn hash_to_string(hash: u64) -> String {
     format!("{:016x}", hash)
 }
 
-// Display engine eval in pawn units or handle checkmate evals as Mate in x ply/Checkmate
+// Display white perspective engine eval in pawn units or handle checkmate evals as White/Black mates in x ply/Checkmate
 pub fn eval_to_string(eval: i32) -> String {
     if is_eval_checkmate(eval) {
+        let mating_side = if eval > 0 {
+            PieceColour::White
+        } else {
+            PieceColour::Black
+        };
         match get_checkmate_ply(eval) {
             0 => "Checkmate".to_string(),
-            x => format!("Mate in {} ply", x),
+            x => format!("{} mates in {} ply", mating_side, x),
         }
     } else {
         let eval = eval as f64 / 100.0; // convert centipawns to pawns
@@ -144,6 +215,82 @@ mod tests {
         assert_eq!(low_bits(0xFFFFFFFFFFFFFFFF), 0xFFFFFFFF);
     }
 
+    #[test]
+    fn test_eval_to_string() {
+        assert_eq!(eval_to_string(150), "+1.50");
+        assert_eq!(eval_to_string(-25), "-0.25");
+        assert_eq!(eval_to_string(100_000_000 - 3), "White mates in 3 ply");
+        assert_eq!(eval_to_string(-(100_000_000 - 3)), "Black mates in 3 ply");
+    }
+
+    #[test]
+    fn test_square_idx() {
+        let files = "abcdefgh";
+        for index in 0..64 {
+            let square = SquareIdx::try_from(index).unwrap();
+            assert_eq!(square.index(), index);
+            assert_eq!(usize::from(square), index);
+            // index 0 is a8, counting along each rank down the board
+            let name = format!("{}{}", files.chars().nth(index % 8).unwrap(), 8 - index / 8);
+            assert_eq!(square.to_string(), name);
+            assert_eq!(name.parse::<SquareIdx>().unwrap(), square);
+            assert_eq!(
+                SquareIdx::from_file_rank(square.file(), square.rank()),
+                Some(square)
+            );
+        }
+        let e4 = "e4".parse::<SquareIdx>().unwrap();
+        assert_eq!((e4.file(), e4.rank()), (4, 3));
+        assert_eq!((e4.file_char(), e4.rank_char()), ('e', '4'));
+        assert_eq!(e4.index(), 36);
+
+        assert!(SquareIdx::try_from(64).is_err());
+        assert_eq!(SquareIdx::new(64), None);
+        assert_eq!(SquareIdx::from_file_rank(8, 0), None);
+        assert_eq!(SquareIdx::from_file_rank(0, 8), None);
+        for invalid in ["", "e", "e44", "i1", "a0", "a9", "E4", "4e"] {
+            assert!(invalid.parse::<SquareIdx>().is_err(), "{}", invalid);
+        }
+
+        let mv = crate::movegen::Move {
+            from: 52,
+            to: 36,
+            ..crate::movegen::NULL_MOVE
+        };
+        assert_eq!(mv.from_square_name().as_deref(), Some("e2"));
+        assert_eq!(mv.to_square(), Some(e4));
+        assert_eq!(crate::movegen::NULL_MOVE.from_square(), None);
+        assert_eq!(crate::movegen::NULL_MOVE.to_square_name(), None);
+    }
+
+    #[test]
+    fn test_square_name_and_index() {
+        for idx in 0..64 {
+            let name = square_name(idx).unwrap();
+            assert_eq!(square_name_unchecked(idx), name);
+            assert_eq!(square_index(&name).unwrap(), idx);
+        }
+        assert_eq!(square_index("a1").unwrap(), 56);
+        assert_eq!(square_index("h8").unwrap(), 7);
+        assert_eq!(square_index("d4").unwrap(), 35);
+        assert_eq!(square_name(36).unwrap(), "e4");
+
+        assert!(matches!(
+            square_name(64),
+            Err(SquareParseError::InvalidIndex(64))
+        ));
+        for invalid in ["", "e", "e44", "i9", "a9", "z1", "a0", "E4", "4e", " e4"] {
+            assert!(
+                matches!(
+                    square_index(invalid),
+                    Err(SquareParseError::InvalidNotation(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
     #[test]
     fn test_hash_to_string() {
         assert_eq!(hash_to_string(0x123456789ABCDEF0), "123456789abcdef0");
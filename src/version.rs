@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::zobrist;
+
+// enabled crate features, compiled in so they can be reported at runtime
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "debug_engine_logging")]
+    "debug_engine_logging",
+];
+
+// version and build metadata of the library, used to stamp analysis and files produced by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>, // only set if git was available when building
+    pub zobrist_scheme: &'static str,
+    pub features: &'static [&'static str],
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chess-oxide {}", self.version)?;
+        if let Some(git_hash) = self.git_hash {
+            write!(f, " ({})", git_hash)?;
+        }
+        write!(f, " [zobrist: {}]", self.zobrist_scheme)?;
+        if !self.features.is_empty() {
+            write!(f, " [features: {}]", self.features.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+pub const fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+pub const fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: version(),
+        git_hash: option_env!("CHESS_OXIDE_GIT_HASH"),
+        zobrist_scheme: zobrist::ZOBRIST_SCHEME,
+        features: FEATURES,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(build_info().version, version());
+    }
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.zobrist_scheme, "polyglot-v2");
+        assert!(info
+            .to_string()
+            .starts_with(&format!("chess-oxide {}", version())));
+    }
+}
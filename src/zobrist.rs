@@ -2,6 +2,7 @@ use rand::Rng;
 
 use static_init::dynamic;
 
+use crate::board::BoardState;
 use crate::magic;
 use crate::movegen::*;
 use crate::position::Position;
@@ -13,11 +14,25 @@ static ZOBRIST_HASH_TABLE: ZobristHashTable = ZobristHashTable::with_polyglot_ma
 // using 64 bit hashes
 pub type PositionHash = u64;
 
+// halfmove counts 0 to 99, one hash for 100 to 149 and one for 150 or more
+const HALFMOVE_COUNT_HASHES: usize = 102;
+// occurrences 1 to 4, and one hash for 5 or more (fivefold repetition)
+const OCCURRENCES_HASHES: usize = 5;
+
+// identifies the hashing scheme, hashes (and anything storing them) are only compatible if this matches
+pub const ZOBRIST_SCHEME: &str = "polyglot-v2";
+
 // zobrist hash of full Position, used to initialise a position hash
 pub fn pos_hash(pos: &Position) -> PositionHash {
     ZOBRIST_HASH_TABLE.polyglot_full_position_hash(pos)
 }
 
+// polyglot zobrist hash of the current position, byte-compatible with the keys used in polyglot opening books. hashed
+// from scratch, so it always equals BoardState::position_hash (which is updated incrementally as moves are made)
+pub fn polyglot_hash(bs: &BoardState) -> u64 {
+    pos_hash(bs.position())
+}
+
 // increment the zobrist hash of a Position, can be used when moves are made instead of calling pos_hash on the whole position every move
 pub fn pos_next_hash(
     last_movegen_flags: &MovegenFlags,
@@ -28,6 +43,12 @@ pub fn pos_next_hash(
     ZOBRIST_HASH_TABLE.polyglot_next_hash(last_movegen_flags, new_movegen_flags, last_hash, mv)
 }
 
+// zobrist hash of a Position after a null move (the side to move passes) from the hash of the position it was made in,
+// only the side to move and the en passant index change
+pub fn null_move_hash(last_movegen_flags: &MovegenFlags, last_hash: PositionHash) -> PositionHash {
+    ZOBRIST_HASH_TABLE.polyglot_null_move_hash(last_movegen_flags, last_hash)
+}
+
 // add BoardState information into a zobrist Position hash
 pub fn board_state_hash(
     current_hash: PositionHash,
@@ -45,8 +66,8 @@ struct ZobristHashTable {
     black_castle_long: PositionHash,
     white_castle_short: PositionHash,
     black_castle_short: PositionHash,
-    halfmove_count: [PositionHash; 100],
-    occurrences: [PositionHash; 3],
+    halfmove_count: [PositionHash; HALFMOVE_COUNT_HASHES],
+    occurrences: [PositionHash; OCCURRENCES_HASHES],
 }
 impl ZobristHashTable {
     #[allow(dead_code)]
@@ -67,11 +88,11 @@ impl ZobristHashTable {
         let black_castle_long = rng.gen();
         let white_castle_short = rng.gen();
         let black_castle_short = rng.gen();
-        let mut halfmove_count: [PositionHash; 100] = [0; 100];
+        let mut halfmove_count: [PositionHash; HALFMOVE_COUNT_HASHES] = [0; HALFMOVE_COUNT_HASHES];
         for i in &mut halfmove_count {
             *i = rng.gen();
         }
-        let mut occurrences: [PositionHash; 3] = [0; 3];
+        let mut occurrences: [PositionHash; OCCURRENCES_HASHES] = [0; OCCURRENCES_HASHES];
         for i in &mut occurrences {
             *i = rng.gen();
         }
@@ -166,64 +187,43 @@ impl ZobristHashTable {
             hash ^= self.en_passant_table[new_movegen_flags.polyglot_en_passant.unwrap() % 8];
         }
 
-        if last_movegen_flags.black_castle_long
-            && (mv.from == last_movegen_flags.long_black_rook_start
-                || mv.to == last_movegen_flags.long_black_rook_start)
-        {
+        // remove castling rights lost on this move. new_movegen_flags already accounts for king moves, castling and rooks
+        // moving from or being captured on their (chess960) start squares. checking the move squares here instead would
+        // toggle a right twice when a chess960 king castles onto its own rook's start square
+        if last_movegen_flags.black_castle_long != new_movegen_flags.black_castle_long {
             hash ^= self.black_castle_long;
         }
-        if last_movegen_flags.black_castle_short
-            && (mv.from == last_movegen_flags.short_black_rook_start
-                || mv.to == last_movegen_flags.short_black_rook_start)
-        {
+        if last_movegen_flags.black_castle_short != new_movegen_flags.black_castle_short {
             hash ^= self.black_castle_short;
         }
-        if last_movegen_flags.white_castle_long
-            && (mv.from == last_movegen_flags.long_white_rook_start
-                || mv.to == last_movegen_flags.long_white_rook_start)
-        {
+        if last_movegen_flags.white_castle_long != new_movegen_flags.white_castle_long {
             hash ^= self.white_castle_long;
         }
-        if last_movegen_flags.white_castle_short
-            && (mv.from == last_movegen_flags.short_white_rook_start
-                || mv.to == last_movegen_flags.short_white_rook_start)
-        {
+        if last_movegen_flags.white_castle_short != new_movegen_flags.white_castle_short {
             hash ^= self.white_castle_short;
         }
-        // reset castling flags on first king move (including castling which sets both flags false for the moving side)
-        if piece.ptype == PieceType::King {
-            if piece.pcolour == PieceColour::White {
-                if last_movegen_flags.white_castle_long {
-                    hash ^= self.white_castle_long
-                }
-                if last_movegen_flags.white_castle_short {
-                    hash ^= self.white_castle_short
-                }
-            } else {
-                if last_movegen_flags.black_castle_long {
-                    hash ^= self.black_castle_long
-                }
-                if last_movegen_flags.black_castle_short {
-                    hash ^= self.black_castle_short
-                }
-            }
-        }
         hash ^= self.get_piece_hash(piece, mv.to); // set moving piece in new position
         hash ^= self.white_to_move; // switch sides
         hash
     }
 
+    fn polyglot_null_move_hash(
+        &self,
+        last_movegen_flags: &MovegenFlags,
+        last_hash: PositionHash,
+    ) -> PositionHash {
+        let mut hash = last_hash;
+        // en passant is only possible straight after the double pawn push, so a null move removes it
+        if let Some(idx) = last_movegen_flags.polyglot_en_passant {
+            hash ^= self.en_passant_table[idx % 8]
+        }
+        hash ^ self.white_to_move // switch sides
+    }
+
     fn polyglot_full_position_hash(&self, pos: &Position) -> PositionHash {
         let mut hash = 0;
-        for (i, s) in pos.pos64.iter().enumerate() {
-            match s {
-                Square::Piece(p) => {
-                    hash ^= self.get_piece_hash(*p, i);
-                }
-                Square::Empty => {
-                    continue;
-                }
-            }
+        for (i, p) in pos.pos64.pieces() {
+            hash ^= self.get_piece_hash(p, i);
         }
         if pos.movegen_flags.white_castle_long {
             hash ^= self.white_castle_long;
@@ -357,15 +357,8 @@ impl ZobristHashTable {
     #[allow(dead_code)]
     fn full_position_hash(&self, pos: &Position) -> PositionHash {
         let mut hash = 0;
-        for (i, s) in pos.pos64.iter().enumerate() {
-            match s {
-                Square::Piece(p) => {
-                    hash ^= self.get_piece_hash(*p, i);
-                }
-                Square::Empty => {
-                    continue;
-                }
-            }
+        for (i, p) in pos.pos64.pieces() {
+            hash ^= self.get_piece_hash(p, i);
         }
         if pos.movegen_flags.white_castle_long {
             hash ^= self.white_castle_long;
@@ -402,16 +395,26 @@ impl ZobristHashTable {
 
     #[inline(always)]
     const fn get_halfmove_count_hash(&self, halfmove_count: u32) -> PositionHash {
-        self.halfmove_count[halfmove_count as usize]
+        // the fifty move rule can be claimed from 100 and the seventy-five move rule applies from 150, so the counts in
+        // between share a hash, as do all the counts from 150
+        if halfmove_count >= 150 {
+            self.halfmove_count[HALFMOVE_COUNT_HASHES - 1]
+        } else if halfmove_count >= 100 {
+            self.halfmove_count[HALFMOVE_COUNT_HASHES - 2]
+        } else {
+            self.halfmove_count[halfmove_count as usize]
+        }
     }
 
     #[inline(always)]
     const fn get_occurrences_hash(&self, occurrences: u8) -> PositionHash {
-        match occurrences {
-            1 => self.occurrences[0],
-            2 => self.occurrences[1],
-            3 => self.occurrences[2],
-            _ => unreachable!(),
+        // positions have always occurred at least once. Searches don't stop at repetitions, so counts can go past 5,
+        // which all share the last hash
+        let idx = occurrences.saturating_sub(1) as usize;
+        if idx >= OCCURRENCES_HASHES {
+            self.occurrences[OCCURRENCES_HASHES - 1]
+        } else {
+            self.occurrences[idx]
         }
     }
 
@@ -452,7 +455,7 @@ mod tests {
 
     fn compute_zobrist_hash_from_fen(fen_str: &str) -> u64 {
         // BoardState uses this module to compute the hash
-        let bs = crate::BoardState::from(fen_str.parse::<crate::fen::FEN>().unwrap());
+        let bs = crate::BoardState::try_from(fen_str.parse::<crate::fen::FEN>().unwrap()).unwrap();
 
         ZOBRIST_HASH_TABLE.polyglot_full_position_hash(bs.position())
     }
@@ -503,4 +506,104 @@ mod tests {
             assert_eq!(computed_hash, expected_hash, "FEN: {}", fen);
         }
     }
+
+    #[test]
+    fn test_zobrist_incremental_hashes() {
+        // same game as the polyglot reference keys above, with the hash updated incrementally as each move is made
+        let moves = [
+            ("e4", 0x823c9b50fd114196),
+            ("d5", 0x0756b94461c50fb0),
+            ("e5", 0x662fafb965db29d4),
+            ("f5", 0x22a48b5a8e47ff78),
+            // en passant was possible on the last move, but not any more
+            ("Ke2", 0x652a607ca3f242c1),
+            ("Kf7", 0x00fdd303c946bdd9),
+        ];
+        let mut bs = crate::BoardState::new_starting();
+        for (notation, expected_hash) in moves {
+            let mv = notation
+                .parse::<crate::pgn::notation::Notation>()
+                .unwrap()
+                .to_move_with_context(&bs)
+                .unwrap();
+            bs = bs.next_state(&mv).unwrap();
+            assert_eq!(bs.position_hash, expected_hash, "{}", notation);
+        }
+    }
+
+    #[test]
+    fn test_zobrist_incremental_hashes_random_games() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // random legal games from the standard and chess960 starts, checking every ply against a full rehash
+        let mut rng = StdRng::seed_from_u64(0);
+        for game in 0..2000 {
+            let mut bs = if game % 2 == 0 {
+                crate::BoardState::new_starting()
+            } else {
+                crate::BoardState::new_chess960_from_num(game % 960).unwrap()
+            };
+            let mut plies = 0;
+            while let Some(mv) = bs.random_legal_move(&mut rng) {
+                bs = bs.next_state(&mv).unwrap();
+                assert_eq!(
+                    bs.position_hash,
+                    pos_hash(bs.position()),
+                    "game {}, move {:?}, FEN: {}",
+                    game,
+                    mv,
+                    crate::fen::FEN::from(&bs)
+                );
+                plies += 1;
+                if plies == 300 {
+                    break;
+                }
+            }
+        }
+    }
+
+    // plays each move from the FEN, checking the incremental hash against a full rehash after every move
+    fn assert_incremental_hashes(fen_str: &str, moves: &[&str]) {
+        let mut bs =
+            crate::BoardState::try_from(fen_str.parse::<crate::fen::FEN>().unwrap()).unwrap();
+        for notation in moves {
+            let mv = notation
+                .parse::<crate::pgn::notation::Notation>()
+                .unwrap()
+                .to_move_with_context(&bs)
+                .unwrap();
+            bs = bs.next_state(&mv).unwrap();
+            assert_eq!(
+                bs.position_hash,
+                pos_hash(bs.position()),
+                "{} after {}",
+                fen_str,
+                notation
+            );
+        }
+    }
+
+    #[test]
+    fn test_zobrist_incremental_hashes_chess960() {
+        // kings castle onto their own short rook's start square
+        assert_incremental_hashes(
+            "bqnnrkrb/pppppppp/8/8/8/8/PPPPPPPP/BQNNRKRB w KQkq - 0 1",
+            &["O-O", "O-O"],
+        );
+        assert_incremental_hashes(
+            "1r3kr1/pppppppp/8/8/8/8/PPPPPPPP/1R3KR1 w KQkq - 0 1",
+            &["O-O-O", "O-O"],
+        );
+        // rooks leaving their chess960 start squares
+        assert_incremental_hashes(
+            "1r3kr1/p6p/8/8/8/8/P6P/1R3KR1 w KQkq - 0 1",
+            &["Rb2", "Rg7", "Rg3", "Rb6"],
+        );
+        // rooks captured on their chess960 start squares
+        assert_incremental_hashes(
+            "1r3kr1/p6p/8/8/8/8/P6P/1R3KR1 w KQkq - 0 1",
+            &["Rxb8+", "Ke7", "Rgxg8"],
+        );
+    }
 }
@@ -0,0 +1,33 @@
+// Perft correctness suite: every position in test_data/perft.epd is checked against its expected node counts. Depths
+// expecting more than SHORT_MAX_NODES nodes only run with `cargo test --release -- --ignored`
+use std::fs;
+
+use chess::{parse_perft_epd, PerftEpdEntry};
+
+const PERFT_EPD: &str = "test_data/perft.epd";
+const SHORT_MAX_NODES: u64 = 200_000;
+
+fn perft_suite() -> Vec<PerftEpdEntry> {
+    parse_perft_epd(&fs::read_to_string(PERFT_EPD).unwrap()).unwrap()
+}
+
+fn assert_perft_suite(max_nodes: u64) {
+    let mut failures = Vec::new();
+    for entry in perft_suite() {
+        for mismatch in entry.verify(max_nodes) {
+            failures.push(format!("{} {:?}", entry.fen, mismatch));
+        }
+    }
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn test_perft_epd_suite() {
+    assert_perft_suite(SHORT_MAX_NODES);
+}
+
+#[test]
+#[ignore = "deep perft, run with --release -- --ignored"]
+fn test_perft_epd_suite_full() {
+    assert_perft_suite(u64::MAX);
+}
@@ -0,0 +1,241 @@
+// End to end conformance suite: every game in the corpus is imported from PGN, checked against the expected final FEN,
+// then exported, re-imported and checked again. Expected FENs were generated independently of chess-oxide.
+// The Chess960, fifty move and fivefold games are chess-oxide self-play games, the others are played or published games
+// and opening lines.
+use std::fs;
+use std::str::FromStr;
+
+use chess::fen::FEN;
+use chess::pgn::notation::Notation;
+use chess::pgn::PGN;
+use chess::{Board, GameOverState, GameState, Variant};
+
+const CORPUS_DIR: &str = "test_data/pgn_corpus";
+
+// (file, expected FEN after the last move)
+const CORPUS: [(&str, &str); 22] = [
+    (
+        "../test.pgn",
+        "3k4/n5R1/P2BB1p1/1p2p2p/3bK2P/6P1/5r2/8 b - - 3 58",
+    ),
+    (
+        "../test_lichess_export.pgn",
+        "3k4/n5R1/P2BB1p1/1p2p2p/3bK2P/6P1/5r2/8 b - - 3 58",
+    ),
+    (
+        "opera_game.pgn",
+        "1n1Rkb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2K5 b k - 1 17",
+    ),
+    (
+        "immortal_game.pgn",
+        "r1bk3r/p2pBpNp/n4n2/1p1NP2P/6P1/3P4/P1P1K3/q5b1 b - - 1 23",
+    ),
+    (
+        "evergreen_game.pgn",
+        "1r3kr1/pbpBBp1p/1b3P2/8/8/2P2q2/P4PPP/3R2K1 b - - 0 24",
+    ),
+    (
+        "game_of_the_century.pgn",
+        "1Q6/5pk1/2p3p1/1p2N2p/1b5P/1bn5/2r3P1/2K5 w - - 16 42",
+    ),
+    (
+        "kasparov_topalov_1999.pgn",
+        "8/Q6p/6p1/5p2/5P2/2p3P1/3r3P/2K1k3 b - - 3 44",
+    ),
+    (
+        "loyd_stalemate.pgn",
+        "5bnr/4p1pq/4Qpkr/7p/7P/4P3/PPPP1PP1/RNB1KBNR b KQ - 2 10",
+    ),
+    (
+        "adams_torre_1920.pgn",
+        "2r1r1k1/1Q3ppp/3p1b2/pq1P4/4R3/5N2/1P3PPP/4R1K1 b - - 0 23",
+    ),
+    (
+        "botvinnik_capablanca_1938.pgn",
+        "6k1/p3P2p/1p3Q2/3p3K/2pP2P1/1nP5/7P/4q3 b - - 2 41",
+    ),
+    (
+        "carlsen_nepomniachtchi_2021.pgn",
+        "3k4/5RN1/4P3/5P2/7K/8/8/6q1 b - - 2 136",
+    ),
+    (
+        "deep_blue_kasparov_1997.pgn",
+        "r1k4r/p2nb1p1/2b4p/1p1n1p2/2PP4/3Q1NB1/1P3PPP/R5K1 b - c3 0 19",
+    ),
+    (
+        "lasker_thomas_1912.pgn",
+        "rn3r2/pbppq1p1/1p2pN2/8/3P2NP/6P1/PPPKBP1R/R5k1 b - - 6 18",
+    ),
+    (
+        "short_timman_1991.pgn",
+        "2b1rrk1/2pR1p2/1pq1pQp1/p3P1Kp/P1PR3P/5N2/2P2PP1/8 b - - 7 34",
+    ),
+    (
+        "steinitz_von_bardeleben_1895.pgn",
+        "r1r4k/pp1q3R/5pp1/3p2N1/6Q1/8/PP3PPP/2R3K1 b - - 0 25",
+    ),
+    ("saavedra_position.pgn", "2R5/8/8/8/r7/1K6/8/k7 b - - 2 7"),
+    (
+        "lasker_trap.pgn",
+        "r1b1k1nr/ppp2ppp/2n5/4P3/1BP4q/8/PP1K2PP/RN1Q1BnR w kq - 4 10",
+    ),
+    (
+        "fischer_spassky_1972_from_move_20.pgn",
+        "4q2k/2r1r3/4PR1p/p1p5/P1Bp1Q1P/1P6/6P1/6K1 b - - 4 41",
+    ),
+    (
+        "rotlewi_rubinstein_1907_from_move_21.pgn",
+        "6k1/5ppp/pb2p3/1p2P3/1P2bPnP/P6r/1B4QP/R4R1K w - - 2 26",
+    ),
+    ("chess960_1.pgn", "8/8/8/5p1p/3pk3/8/r7/3K2q1 w - - 0 61"),
+    (
+        "chess960_2.pgn",
+        "1R6/R7/k5p1/5p1p/3B4/8/PP4r1/3K4 b - - 11 45",
+    ),
+    (
+        "threefold_repetition.pgn",
+        "r2q1rk1/1bp1bppp/p1np1n2/1p2p3/3PP3/1BP2N1P/PP3PP1/RNBQR1K1 b - - 8 14",
+    ),
+];
+
+fn replay_corpus_game(file: &str, expected_fen: &str) -> Board {
+    let path = format!("{}/{}", CORPUS_DIR, file);
+    let pgn_str = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+
+    let pgn = PGN::from_str(&pgn_str).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let board = Board::try_from(pgn).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    assert_eq!(
+        FEN::from(board.get_current_state()).to_string(),
+        expected_fen,
+        "{}: final position",
+        path
+    );
+
+    // round trip through our own PGN export
+    let exported = PGN::from(&board).to_string();
+    let reimported_pgn = PGN::from_str(&exported).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let reimported = Board::try_from(reimported_pgn).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    assert_eq!(
+        FEN::from(reimported.get_current_state()).to_string(),
+        expected_fen,
+        "{}: final position after export",
+        path
+    );
+    assert_eq!(
+        board.get_current_state().board_hash,
+        reimported.get_current_state().board_hash,
+        "{}: board hash after export",
+        path
+    );
+    assert_eq!(
+        board.get_current_state().position_hash,
+        reimported.get_current_state().position_hash,
+        "{}: position hash after export",
+        path
+    );
+    board
+}
+
+#[test]
+fn test_pgn_corpus() {
+    for (file, expected_fen) in CORPUS {
+        replay_corpus_game(file, expected_fen);
+    }
+}
+
+#[test]
+fn test_pgn_corpus_fifty_move_draw() {
+    let board = replay_corpus_game(
+        "fifty_move_draw.pgn",
+        "8/1B6/8/2k1K3/3N4/8/8/8 w - - 100 51",
+    );
+    // the fifty move rule is a claim, so the drawn result is taken as one
+    assert_eq!(
+        board.get_game_over_state(),
+        Some(GameOverState::DrawClaimed(GameState::FiftyMove))
+    );
+}
+
+#[test]
+fn test_pgn_corpus_fivefold_repetition() {
+    // threefold repetition and the fifty move rule are only claims, so the game goes on until the fifth occurrence ends it
+    let board = replay_corpus_game(
+        "fivefold_repetition.pgn",
+        "3N1k2/8/4K3/8/4B3/8/8/8 b - - 117 59",
+    );
+    assert_eq!(
+        board.get_game_over_state(),
+        Some(GameOverState::Forced(GameState::FivefoldRepetition))
+    );
+}
+
+#[test]
+fn test_pgn_corpus_chess960_variant() {
+    // the rooks start on the e and g files, black castles long and white short
+    let path = format!("{}/chess960_1.pgn", CORPUS_DIR);
+    let pgn_str = fs::read_to_string(&path).unwrap();
+    let start_fen = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1";
+    let full_game = Board::try_from(PGN::from_str(&pgn_str).unwrap()).unwrap();
+    assert_eq!(full_game.variant(), Variant::Chess960);
+
+    // import the opening only, as an unfinished game, with other spellings of the Variant tag or none at all, and castle
+    // in the continuation
+    let (tags, _) = pgn_str.split_once("\n\n").unwrap();
+    let opening_moves = "1. e4 Ng6 2. Nd3 e6 3. Ng3 Bg5 4. Nc5 Nb6 5. d4 d6 6. Nb3 a5 7. Nxa5 Bd2 8. Re2 Bxa5 \
+        9. b4 Nf4 10. bxa5 Nc4 11. Qb4 Nxe2 12. Bxe2 b5 13. a4 c5 14. dxc5 Bc6 15. axb5 Qxb5 16. Qxb5 Bxb5 \
+        17. cxd6 Nxd6 18. Be5 Nc4";
+    for variant_tag in ["[Variant \"Chess960\"]", "[Variant \"chess 960\"]", ""] {
+        let opening = format!(
+            "{}\n\n{} *",
+            tags.replace("[Variant \"Chess960\"]", variant_tag)
+                .replace("[Result \"0-1\"]", "[Result \"*\"]"),
+            opening_moves
+        );
+        let mut board = Board::try_from(PGN::from_str(&opening).unwrap()).unwrap();
+        assert_eq!(board.variant(), Variant::Chess960, "{}", variant_tag);
+        for san in ["Bc3", "O-O-O", "O-O"] {
+            let mv = san
+                .parse::<Notation>()
+                .unwrap()
+                .to_move_with_context(board.get_current_state())
+                .unwrap();
+            board.make_move(&mv).unwrap();
+        }
+        // kings on g1 and c8 with the rooks next to them on f1 and d8, as in the full game
+        assert_eq!(board.to_fen(), full_game.get_state_history()[39].to_fen());
+
+        let exported = PGN::from(&board).to_string();
+        assert!(exported.contains("[Variant \"Chess960\"]"));
+        assert!(exported.contains("[SetUp \"1\"]"));
+        assert!(exported.contains(&format!("[FEN \"{}\"]", start_fen)));
+        assert!(exported.contains("19.Bc3 O-O-O 20.O-O"), "{}", exported);
+        let reimported = Board::try_from(PGN::from_str(&exported).unwrap()).unwrap();
+        assert_eq!(reimported.variant(), Variant::Chess960);
+        assert_eq!(reimported.get_starting_state().to_fen(), start_fen);
+        assert_eq!(reimported.to_fen(), board.to_fen());
+        assert_eq!(
+            reimported.move_history_string_notation(),
+            board.move_history_string_notation()
+        );
+    }
+
+    // a standard game stays standard, and one from a standard looking position isn't Chess960
+    let board = Board::try_from(
+        PGN::from_str(&fs::read_to_string(format!("{}/opera_game.pgn", CORPUS_DIR)).unwrap())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(board.variant(), Variant::Standard);
+    let board = Board::try_from(
+        PGN::from_str(
+            &fs::read_to_string(format!(
+                "{}/fischer_spassky_1972_from_move_20.pgn",
+                CORPUS_DIR
+            ))
+            .unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(board.variant(), Variant::FromPosition);
+}
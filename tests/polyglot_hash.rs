@@ -0,0 +1,75 @@
+// Polyglot reference keys (from the polyglot book format specification), checked through the public API
+use chess::fen::FEN;
+use chess::pgn::notation::Notation;
+use chess::{hash_to_string, polyglot_hash, BoardState};
+
+// (FEN, expected polyglot key)
+const REFERENCE_KEYS: [(&str, u64); 9] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0x463b96181691fc9c,
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        0x823c9b50fd114196,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        0x0756b94461c50fb0,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+        0x662fafb965db29d4,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        0x22a48b5a8e47ff78,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+        0x652a607ca3f242c1,
+    ),
+    (
+        "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+        0x00fdd303c946bdd9,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+        0x3c8123ea7b067637,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+        0x5c3f9b829b279560,
+    ),
+];
+
+#[test]
+fn test_polyglot_reference_keys() {
+    for (fen, expected_key) in REFERENCE_KEYS {
+        let bs = BoardState::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+        assert_eq!(polyglot_hash(&bs), expected_key, "FEN: {}", fen);
+        assert_eq!(bs.position_hash, expected_key, "FEN: {}", fen);
+    }
+    assert_eq!(
+        hash_to_string(polyglot_hash(&BoardState::new_starting())),
+        "463b96181691fc9c"
+    );
+}
+
+#[test]
+fn test_polyglot_keys_after_moves() {
+    // the reference game, reaching the positions above by making moves instead of parsing FENs
+    let mut bs = BoardState::new_starting();
+    for (notation, (_, expected_key)) in ["e4", "d5", "e5", "f5", "Ke2", "Kf7"]
+        .iter()
+        .zip(REFERENCE_KEYS.iter().skip(1))
+    {
+        let mv = notation
+            .parse::<Notation>()
+            .unwrap()
+            .to_move_with_context(&bs)
+            .unwrap();
+        bs = bs.next_state(&mv).unwrap();
+        assert_eq!(polyglot_hash(&bs), *expected_key, "{}", notation);
+    }
+}
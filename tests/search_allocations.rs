@@ -0,0 +1,71 @@
+// Engine searches reuse one BoardState and move buffer per ply, so the number of heap allocations a search makes
+// shouldn't grow with the number of nodes it searches. Counted with a global allocator that counts per thread, so
+// anything else running in parallel doesn't affect the counts
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use chess::fen::FEN;
+use chess::Board;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // the thread local can already be gone while a thread is being torn down
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations() -> u64 {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+// allocations made by a search of fen to depth, not counting setting up the board
+fn search_allocations(fen: &str, depth: u8) -> u64 {
+    let mut board = Board::try_from(fen.parse::<FEN>().unwrap()).unwrap();
+    let before = allocations();
+    board.engine_analyse(depth);
+    allocations() - before
+}
+
+#[test]
+fn test_search_allocations_dont_grow_with_depth() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+    for fen in fens {
+        let shallow = search_allocations(fen, 2);
+        let deep = search_allocations(fen, 5);
+        // the extra plies only add their scratch states and the buffers growing to fit, never an allocation per node
+        assert!(
+            deep < shallow + 200,
+            "{}: depth 2 made {} allocations, depth 5 made {}",
+            fen,
+            shallow,
+            deep
+        );
+    }
+}
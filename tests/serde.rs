@@ -0,0 +1,261 @@
+// Round trips through JSON and bincode with the serde feature, run with `cargo test --features serde`
+#![cfg(feature = "serde")]
+
+use chess::fen::FEN;
+use chess::{Board, BoardState, GameOverState, Move, PieceColour, PlayerData, ShortMove, Variant};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn json_round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+fn bincode_round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    bincode::deserialize(&bincode::serialize(value).unwrap()).unwrap()
+}
+
+// deserializes value as the type of like, for types that can't be named outside the crate
+fn from_value_like<T: serde::de::DeserializeOwned>(
+    _like: &T,
+    value: serde_json::Value,
+) -> serde_json::Result<T> {
+    serde_json::from_value(value)
+}
+
+fn assert_boards_match(board: &Board, restored: &Board) {
+    assert_eq!(
+        restored.get_current_state().board_hash,
+        board.get_current_state().board_hash
+    );
+    assert_eq!(restored.to_fen(), board.to_fen());
+    let hashes = |b: &Board| -> Vec<u64> {
+        b.get_state_history()
+            .iter()
+            .map(|state| state.board_hash)
+            .collect()
+    };
+    assert_eq!(hashes(restored), hashes(board));
+    assert_eq!(
+        restored.move_history_string_notation(),
+        board.move_history_string_notation()
+    );
+    assert_eq!(restored.variant(), board.variant());
+    assert_eq!(restored.get_game_over_state(), board.get_game_over_state());
+    assert_eq!(restored.detatched_idx(), board.detatched_idx());
+    assert_eq!(restored.auto_claim_draws(), board.auto_claim_draws());
+    assert_eq!(
+        restored.get_white_player().name,
+        board.get_white_player().name
+    );
+    assert_eq!(
+        restored.get_black_player().elo,
+        board.get_black_player().elo
+    );
+    assert_eq!(restored.metadata(), board.metadata());
+}
+
+fn test_boards() -> Vec<Board> {
+    let mut boards = Vec::new();
+
+    // en passant, castling on both sides and a promotion, then a repeated position
+    let mut board = Board::from_fen("r3k2r/pP4pp/8/3pP3/8/8/6PP/R3K2R w KQkq d6 0 1").unwrap();
+    for mv in [
+        "e5d6", "e8g8", "b7a8q", "f8a8", "e1c1", "g8f8", "d1d2", "f8g8", "d2d1", "g8f8", "d1d2",
+    ] {
+        board.make_move_uci(mv).unwrap();
+    }
+    assert_eq!(
+        board
+            .get_current_state()
+            .get_occurences_of_current_position(),
+        2
+    );
+    boards.push(board);
+
+    // a chess960 game played out with random moves, checked out a few moves back
+    let mut board = Board::new_chess960_from_num(518 + 100).unwrap();
+    let mut rng = StdRng::seed_from_u64(2086);
+    for _ in 0..60 {
+        let Some(mv) = board.random_legal_move(&mut rng) else {
+            break;
+        };
+        if board.make_move(&mv).is_err() {
+            break;
+        }
+    }
+    board.checkout_prev();
+    board.checkout_prev();
+    assert!(board.is_detatched());
+    boards.push(board);
+
+    // a resigned game with players
+    let mut board = Board::new();
+    for mv in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+        board.make_move_uci(mv).unwrap();
+    }
+    board.set_resign(PieceColour::Black);
+    board.metadata_mut().white.name = Some("White".to_string());
+    board.metadata_mut().event = Some("Casual game".to_string());
+    board.metadata_mut().date = Some("2024.05.??".parse().unwrap());
+    boards.push(board);
+
+    // a game ending in checkmate
+    let mut board = Board::new();
+    for mv in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+        board.make_move_uci(mv).unwrap();
+    }
+    assert!(board.get_game_over_state().is_some());
+    boards.push(board);
+
+    // threefold repetition, claimed and ended automatically
+    let repetition = [
+        "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+    ];
+    let mut board = Board::new();
+    for mv in repetition {
+        board.make_move_uci(mv).unwrap();
+    }
+    board.claim_draw().unwrap();
+    boards.push(board);
+    let mut board = Board::new().with_auto_claim_draws(true);
+    for mv in repetition {
+        board.make_move_uci(mv).unwrap();
+    }
+    assert!(board.get_game_over_state().is_some());
+    boards.push(board);
+
+    boards
+}
+
+#[test]
+fn test_board_round_trip() {
+    for board in test_boards() {
+        assert_boards_match(&board, &json_round_trip(&board));
+        assert_boards_match(&board, &bincode_round_trip(&board));
+    }
+}
+
+#[test]
+fn test_board_round_trip_keeps_players() {
+    let board: Board = serde_json::from_str(
+        r#"{
+            "variant": "Standard",
+            "white_player": { "name": "White", "elo": 2000 },
+            "black_player": { "name": null, "elo": 1500 },
+            "starting_fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "moves": [],
+            "game_over_state": "AgreedDraw",
+            "detatched_idx": null
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(board.variant(), Variant::Standard);
+    assert_eq!(board.get_white_player().name.as_deref(), Some("White"));
+    assert_eq!(board.get_black_player().elo, Some(1500));
+    assert_eq!(board.get_game_over_state(), Some(GameOverState::AgreedDraw));
+    assert_eq!(
+        board.get_current_state().board_hash,
+        Board::new().get_current_state().board_hash
+    );
+
+    let json = serde_json::to_string(&board).unwrap();
+    let restored: Board = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.get_white_player().elo, Some(2000));
+    assert_eq!(
+        serde_json::to_value(PlayerData::default()).unwrap(),
+        serde_json::json!({ "name": null, "elo": null })
+    );
+}
+
+#[test]
+fn test_board_deserialize_invalid() {
+    let board = Board::new();
+    let mut value = serde_json::to_value(&board).unwrap();
+
+    // moves are replayed, so illegal ones are rejected
+    let illegal = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")
+        .unwrap()
+        .get_current_state()
+        .find_move_uci("a1a8")
+        .unwrap();
+    value["moves"] = serde_json::to_value([illegal]).unwrap();
+    assert!(serde_json::from_value::<Board>(value.clone()).is_err());
+
+    // the starting FEN is validated like any other
+    value["moves"] = serde_json::json!([]);
+    value["starting_fen"] = serde_json::json!("8/8/8/8/8/8/8/8 w - - 0 1");
+    assert!(serde_json::from_value::<Board>(value.clone()).is_err());
+
+    // a detatched index has to be before the latest state
+    value["starting_fen"] = serde_json::to_value(board.to_fen()).unwrap();
+    value["detatched_idx"] = serde_json::json!(0);
+    assert!(serde_json::from_value::<Board>(value).is_err());
+}
+
+#[test]
+fn test_board_state_round_trip() {
+    for board in test_boards() {
+        for state in board.get_state_history() {
+            for restored in [json_round_trip(state), bincode_round_trip(state)] {
+                assert_eq!(restored.board_hash, state.board_hash);
+                assert_eq!(restored.position_hash, state.position_hash);
+                assert_eq!(restored.last_move, state.last_move);
+                assert_eq!(restored.to_fen(), state.to_fen());
+                assert_eq!(
+                    restored.get_occurences_of_current_position(),
+                    state.get_occurences_of_current_position()
+                );
+                assert_eq!(restored.get_gamestate(), state.get_gamestate());
+            }
+        }
+    }
+
+    // the occurences have to include the current position
+    let mut value = serde_json::to_value(BoardState::new_starting()).unwrap();
+    value["position_occurences"] = serde_json::json!([]);
+    assert!(serde_json::from_value::<BoardState>(value).is_err());
+}
+
+#[test]
+fn test_value_types_round_trip() {
+    let fen: FEN = "r3k2r/pP4pp/8/3pP3/8/8/6PP/R3K2R w KQkq d6 0 1"
+        .parse()
+        .unwrap();
+    // FEN serializes as its string
+    assert_eq!(
+        serde_json::to_value(fen).unwrap(),
+        serde_json::json!(fen.to_string())
+    );
+    assert_eq!(json_round_trip(&fen).to_string(), fen.to_string());
+    assert_eq!(bincode_round_trip(&fen).to_string(), fen.to_string());
+    assert!(serde_json::from_str::<FEN>("\"8/8/8/8/8/8/8/8 w - - 0 1\"").is_err());
+
+    let board = &test_boards()[0];
+    let state = board.get_starting_state();
+    let moves: Vec<Move> = state.get_legal_moves().unwrap().to_vec();
+    assert_eq!(json_round_trip(&moves), moves);
+    assert_eq!(bincode_round_trip(&moves), moves);
+    let short_moves: Vec<ShortMove> = moves.iter().map(Move::short_move).collect();
+    assert_eq!(json_round_trip(&short_moves), short_moves);
+
+    // Pos64 serializes as its 64 squares, a8 first
+    let pos64 = state.get_pos64();
+    let value = serde_json::to_value(pos64).unwrap();
+    assert_eq!(value.as_array().unwrap().len(), 64);
+    assert_eq!(
+        value[0],
+        serde_json::json!({ "Piece": { "pcolour": "Black", "ptype": "Rook" } })
+    );
+    assert_eq!(value[1], serde_json::json!("Empty"));
+    assert_eq!(&json_round_trip(pos64), pos64);
+    assert_eq!(&bincode_round_trip(pos64), pos64);
+    let mut short = value.clone();
+    short.as_array_mut().unwrap().pop();
+    assert!(from_value_like(pos64, short).is_err());
+
+    for variant in [Variant::Standard, Variant::Chess960, Variant::FromPosition] {
+        assert_eq!(bincode_round_trip(&variant), variant);
+    }
+    let gos = Some(GameOverState::WhiteResign);
+    assert_eq!(json_round_trip(&gos), gos);
+}